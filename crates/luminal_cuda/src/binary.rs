@@ -35,9 +35,9 @@ impl<T: CudaFloat> CudaSub<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx, a_valid) = get_idx_valid_exps(a_shape);
-        let (b_idx, b_valid) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape]);
+        let (a_idx, a_valid) = get_idx_valid_exps(&a_shape);
+        let (b_idx, b_valid) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()]);
         let type_name = T::type_name();
         let mut code = format!(
             "
@@ -198,8 +198,8 @@ where
             }
             let sub = graph
                 .add_op(CudaSub::<T>::new(
-                    a_edge.2,
-                    b_edge.2,
+                    a_edge.2.clone(),
+                    b_edge.2.clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ))
@@ -233,9 +233,9 @@ impl<T: CudaFloat> CudaEqual<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx, a_valid) = get_idx_valid_exps(a_shape);
-        let (b_idx, b_valid) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape]);
+        let (a_idx, a_valid) = get_idx_valid_exps(&a_shape);
+        let (b_idx, b_valid) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()]);
         let type_name = T::type_name();
         let mut code = format!(
             "
@@ -425,8 +425,8 @@ where
                 .unwrap();
             let equals = graph
                 .add_op(CudaEqual::<T>::new(
-                    a_edge.2,
-                    b_edge.2,
+                    a_edge.2.clone(),
+                    b_edge.2.clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ))
@@ -128,7 +128,7 @@ fn expr_to_cuda_string(expr: BigExpression) -> String {
     symbols.pop().unwrap()
 }
 
-fn get_idx_valid_exps(shape: ShapeTracker) -> (String, String) {
+fn get_idx_valid_exps(shape: &ShapeTracker) -> (String, String) {
     (
         expr_to_cuda_string(shape.index_expression()),
         expr_to_cuda_string(shape.valid_expression()),
@@ -141,12 +141,8 @@ fn render_dyn_dim_inputs(shapes: &[ShapeTracker]) -> (Vec<char>, String) {
         .flat_map(|st| {
             st.shape()
                 .into_iter()
-                .chain(
-                    st.padding
-                        .into_iter()
-                        .flat_map(|i| [i.0.into(), i.1.into()]),
-                )
-                .chain(st.slices.into_iter().flat_map(|i| [i.0.into(), i.1.into()]))
+                .chain(st.padding.iter().flat_map(|i| [i.0.into(), i.1.into()]))
+                .chain(st.slices.iter().flat_map(|i| [i.0.into(), i.1.into()]))
         })
         .flat_map(|d| d.to_symbols())
         .unique()
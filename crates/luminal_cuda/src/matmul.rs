@@ -255,8 +255,8 @@ where
                     dev.clone(),
                     Default::default(),
                 ))
-                .input(srcs[0].0, 0, srcs[0].2)
-                .input(srcs[1].0, 0, srcs[1].2)
+                .input(srcs[0].0, 0, srcs[0].2.clone())
+                .input(srcs[1].0, 0, srcs[1].2.clone())
                 .finish();
 
             // Create edges to dests
@@ -324,8 +324,8 @@ where
                     dev.clone(),
                     Default::default(),
                 ))
-                .input(srcs[0].0, 0, srcs[0].2)
-                .input(srcs[1].0, 0, srcs[1].2)
+                .input(srcs[0].0, 0, srcs[0].2.clone())
+                .input(srcs[1].0, 0, srcs[1].2.clone())
                 .finish();
 
             // Create edges to dests
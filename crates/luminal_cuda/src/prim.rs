@@ -162,8 +162,8 @@ impl<T: CudaFloat> CudaContiguous<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (idx, valid) = get_idx_valid_exps(shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape]);
+        let (idx, valid) = get_idx_valid_exps(&shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape.clone()]);
 
         let mut code = format!(
             "
@@ -637,9 +637,9 @@ impl<T: CudaFloat> CudaAdd<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx, a_valid) = get_idx_valid_exps(a_shape);
-        let (b_idx, b_valid) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape]);
+        let (a_idx, a_valid) = get_idx_valid_exps(&a_shape);
+        let (b_idx, b_valid) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()]);
         let mut code = format!(
             "
 #include \"cuda_fp16.h\"
@@ -765,9 +765,9 @@ impl<T: CudaFloat> CudaMul<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx, a_valid) = get_idx_valid_exps(a_shape);
-        let (b_idx, b_valid) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape]);
+        let (a_idx, a_valid) = get_idx_valid_exps(&a_shape);
+        let (b_idx, b_valid) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()]);
         let mut code = format!("#include \"cuda_fp16.h\"
 extern \"C\" __global__ void kernel({} *out, const {} *inp_a, const {} *inp_b, int numel{rendered}) {{
     int idx = blockIdx.x * blockDim.x + threadIdx.x;
@@ -876,9 +876,9 @@ impl<T: CudaFloat> CudaMod<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx, a_valid) = get_idx_valid_exps(a_shape);
-        let (b_idx, b_valid) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape]);
+        let (a_idx, a_valid) = get_idx_valid_exps(&a_shape);
+        let (b_idx, b_valid) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()]);
         let mut code = format!(
             "#include \"cuda_fp16.h\"
 extern \"C\" __global__ void kernel({} *out, const {} *inp_a, const {} *inp_b, int numel{rendered}) {{
@@ -1004,9 +1004,9 @@ impl<T: CudaFloat> CudaLessThan<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx, a_valid) = get_idx_valid_exps(a_shape);
-        let (b_idx, b_valid) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape]);
+        let (a_idx, a_valid) = get_idx_valid_exps(&a_shape);
+        let (b_idx, b_valid) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()]);
         let type_name = T::type_name();
         let mut code = format!("#include \"cuda_fp16.h\"
 extern \"C\" __global__ void kernel({type_name} *out, const {type_name} *inp_a, const {type_name} *inp_b, int numel{rendered}) {{
@@ -1130,8 +1130,8 @@ impl<T: CudaFloat> CudaSumReduce<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (idx, valid) = get_idx_valid_exps(shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape]);
+        let (idx, valid) = get_idx_valid_exps(&shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape.clone()]);
         let type_name = T::type_name();
         let mut code = format!("#include \"cuda_fp16.h\"
 extern \"C\" __global__ void kernel({type_name} *out, const {type_name} *inp, const int front_size, const int back_size, const int dim_size, int numel{rendered}) {{
@@ -1185,7 +1185,7 @@ where
     CudaData<T>: Data,
 {
     fn process(&mut self, tensors: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let mut shape = tensors[0].1;
+        let mut shape = tensors[0].1.clone();
         shape.remove_dim(self.2);
         let inp_size = shape.n_elements().to_usize().unwrap();
         let inp = tensors[0]
@@ -1256,8 +1256,8 @@ impl<T: CudaFloat> CudaMaxReduce<T> {
         dev: Arc<CudaDevice>,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (idx, valid) = get_idx_valid_exps(shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape]);
+        let (idx, valid) = get_idx_valid_exps(&shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape.clone()]);
         let type_name = T::type_name();
         let mut code = format!("#include \"cuda_fp16.h\"
 extern \"C\" __global__ void kernel({type_name} *out, const {type_name} *inp, const int front_size, const int back_size, const int dim_size, int numel{rendered}) {{
@@ -1316,7 +1316,7 @@ where
     CudaData<T>: Data,
 {
     fn process(&mut self, tensors: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let mut shape = tensors[0].1;
+        let mut shape = tensors[0].1.clone();
         shape.remove_dim(self.2);
         let inp_size = shape.n_elements().to_usize().unwrap();
         let inp = tensors[0]
@@ -1406,7 +1406,7 @@ where
             for (edge_id, weight, dest) in graph
                 .graph
                 .edges_directed(function_node, petgraph::Direction::Outgoing)
-                .map(|e| (e.id(), *e.weight(), e.target()))
+                .map(|e| (e.id(), e.weight().clone(), e.target()))
                 .filter(|(_, _, trg)| *trg != copy_node)
                 .collect::<Vec<_>>()
             {
@@ -1422,7 +1422,7 @@ where
             for (source, edge, edge_weight) in graph
                 .graph
                 .edges_directed(function_node, petgraph::Direction::Incoming)
-                .map(|e| (e.source(), e.id(), *e.weight()))
+                .map(|e| (e.source(), e.id(), e.weight().clone()))
                 .collect::<Vec<_>>()
             {
                 let copy_from_node = graph
@@ -1523,8 +1523,9 @@ where
             );
             let copy_node = graph
                 .add_op(CudaCopyFromDevice::<T>::new(dev.clone()))
-                .input(source, 0, shape)
+                .input(source, 0, shape.clone())
                 .finish();
+            let dtype = graph.dtype(source);
             graph.graph.add_edge(
                 copy_node,
                 output_node,
@@ -1532,6 +1533,7 @@ where
                     shape,
                     input_order: 0,
                     output_order: 0,
+                    dtype,
                 },
             );
             graph.graph.remove_edge(edge);
@@ -1570,49 +1572,49 @@ where
                 *op_ref = Box::new(CudaSqrt::<T>::new(dev.clone()));
             } else if is::<Add>(op) {
                 *op_ref = Box::new(CudaAdd::<T>::new(
-                    shapes[0],
-                    shapes[1],
+                    shapes[0].clone(),
+                    shapes[1].clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ));
             } else if is::<Mul>(op) {
                 *op_ref = Box::new(CudaMul::<T>::new(
-                    shapes[0],
-                    shapes[1],
+                    shapes[0].clone(),
+                    shapes[1].clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ));
             } else if is::<Mod>(op) {
                 *op_ref = Box::new(CudaMod::<T>::new(
-                    shapes[0],
-                    shapes[1],
+                    shapes[0].clone(),
+                    shapes[1].clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ));
             } else if is::<LessThan>(op) {
                 *op_ref = Box::new(CudaLessThan::<T>::new(
-                    shapes[0],
-                    shapes[1],
+                    shapes[0].clone(),
+                    shapes[1].clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ));
             } else if is::<Contiguous>(op) {
                 *op_ref = Box::new(CudaContiguous::<T>::new(
-                    shapes[0],
+                    shapes[0].clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ));
             } else if let Some(SumReduce(dim)) = op_ref.as_any().downcast_ref() {
                 *op_ref = Box::new(CudaSumReduce::<T>::new(
                     *dim,
-                    shapes[0],
+                    shapes[0].clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ));
             } else if let Some(MaxReduce(dim)) = op_ref.as_any().downcast_ref() {
                 *op_ref = Box::new(CudaMaxReduce::<T>::new(
                     *dim,
-                    shapes[0],
+                    shapes[0].clone(),
                     dev.clone(),
                     &graph.dyn_map,
                 ));
@@ -1685,7 +1687,7 @@ impl<T: 'static> Compiler for CopyCompiler<T> {
             {
                 continue;
             }
-            let source = graph.get_sources(first)[0];
+            let source = graph.get_sources(first).remove(0);
             move_outgoing_edge(second, source.0, &mut graph.graph);
             move_references(
                 &mut remap,
@@ -43,9 +43,9 @@ impl<T: MetalFloat> MetalSub<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(a_shape);
-        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape], 4);
+        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(&a_shape);
+        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()], 4);
         let type_name = T::type_name();
         let code = format!(
             "
@@ -116,8 +116,8 @@ impl<T: MetalFloat> Operator for MetalSub<T> {
 
             self.metal_forward(
                 &[
-                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1),
-                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1),
+                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone()),
+                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1.clone()),
                 ],
                 command_buffer,
                 &[],
@@ -147,8 +147,8 @@ impl<T: MetalFloat> Operator for MetalSub<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
-                    input_shapes[1],
+                    input_shapes[0].clone(),
+                    input_shapes[1].clone(),
                     self.device.clone(),
                     self.queue.clone(),
                     self.dyn_map,
@@ -210,8 +210,8 @@ impl<T: MetalFloat> Compiler for MetalSubtractionCompiler<T> {
             }
             let sub = graph
                 .add_op(MetalSub::<T>::new(
-                    a_edge.2,
-                    b_edge.2,
+                    a_edge.2.clone(),
+                    b_edge.2.clone(),
                     dev.clone(),
                     queue.clone(),
                     &graph.dyn_map,
@@ -248,9 +248,9 @@ impl<T: MetalFloat> MetalEqual<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(a_shape);
-        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape], 4);
+        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(&a_shape);
+        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()], 4);
         let type_name = T::type_name();
         let code = format!(
             "
@@ -322,8 +322,8 @@ impl<T: MetalFloat> Operator for MetalEqual<T> {
 
             self.metal_forward(
                 &[
-                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1),
-                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1),
+                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone()),
+                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1.clone()),
                 ],
                 command_buffer,
                 &[],
@@ -353,8 +353,8 @@ impl<T: MetalFloat> Operator for MetalEqual<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
-                    input_shapes[1],
+                    input_shapes[0].clone(),
+                    input_shapes[1].clone(),
                     self.device.clone(),
                     self.queue.clone(),
                     self.dyn_map,
@@ -445,8 +445,8 @@ impl<T: MetalFloat> Compiler for MetalEqualCompiler<T> {
                 .unwrap();
             let equals = graph
                 .add_op(MetalEqual::<T>::new(
-                    a_edge.2,
-                    b_edge.2,
+                    a_edge.2.clone(),
+                    b_edge.2.clone(),
                     dev.clone(),
                     queue.clone(),
                     &graph.dyn_map,
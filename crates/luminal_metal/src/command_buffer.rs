@@ -273,7 +273,7 @@ impl Operator for CommandBufferWrapper {
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
         self.without_storage_buffers(
             &inp.iter()
-                .map(|(t, sh)| (get_buffer_from_tensor(t).deref(), *sh))
+                .map(|(t, sh)| (get_buffer_from_tensor(t).deref(), sh.clone()))
                 .collect::<Vec<_>>(),
             unsafe { &*self.buffer.get() },
             unsafe { self.dyn_map.as_ref().unwrap() },
@@ -141,6 +141,7 @@ impl<T: MetalFloat> Compiler for ElementwiseFusionCompiler<T> {
                         }
                     }
                     // Add edge
+                    let dtype = graph.dtype(input_edge.0);
                     graph.graph.add_edge(
                         input_edge.0,
                         b,
@@ -148,6 +149,7 @@ impl<T: MetalFloat> Compiler for ElementwiseFusionCompiler<T> {
                             input_order: curr_input,
                             output_order: input_edge.2,
                             shape: input_edge.3,
+                            dtype,
                         },
                     );
                     curr_input += 1;
@@ -258,11 +260,11 @@ impl<T: MetalFloat> Compiler for ElementwiseFusionCompiler<T> {
                 .downcast_mut::<FusedElementwiseOp<T>>()
             {
                 let (dyn_chars, rendered) = render_dyn_dim_inputs(
-                    &edges.iter().map(|i| i.2).collect_vec(),
+                    &edges.iter().map(|i| i.2.clone()).collect_vec(),
                     edges.len() + 2,
                 );
                 for (inp_ind, _, sh) in &edges {
-                    let (ind, val) = get_idx_valid_exps(*sh);
+                    let (ind, val) = get_idx_valid_exps(sh);
                     if (sh.is_contiguous() && !sh.is_sliced() && !sh.is_padded())
                         || (!sh.is_sliced() && !sh.is_padded())
                     {
@@ -392,7 +394,7 @@ impl<T: MetalFloat> Operator for FusedElementwiseOp<T> {
         autoreleasepool(|| {
             let command_buffer = self.queue.new_command_buffer();
             let out = self.device.new_buffer(
-                self.output_buffer_sizes(&tensors.iter().map(|(_, s)| *s).collect_vec())[0]
+                self.output_buffer_sizes(&tensors.iter().map(|(_, s)| s.clone()).collect_vec())[0]
                     .exec(unsafe { self.dyn_map.as_ref().unwrap() })
                     .unwrap() as u64,
                 MTLResourceOptions::StorageModeShared,
@@ -401,7 +403,7 @@ impl<T: MetalFloat> Operator for FusedElementwiseOp<T> {
             self.metal_forward(
                 &tensors
                     .iter()
-                    .map(|(t, s)| (get_buffer_from_tensor(t).deref(), *s))
+                    .map(|(t, s)| (get_buffer_from_tensor(t).deref(), s.clone()))
                     .collect_vec(),
                 command_buffer,
                 &[],
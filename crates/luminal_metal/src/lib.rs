@@ -176,7 +176,7 @@ pub trait MetalKernel: Debug {
     ) -> Vec<Buffer> {
         let dev = Device::system_default().unwrap();
         // Allocate storage buffers
-        let inp_shapes = inputs.iter().map(|(_, s)| *s).collect::<Vec<_>>();
+        let inp_shapes = inputs.iter().map(|(_, s)| s.clone()).collect::<Vec<_>>();
         let intermediate_buffers = self
             .intermediate_buffer_sizes(&inp_shapes)
             .into_iter()
@@ -369,12 +369,8 @@ fn render_dyn_dim_inputs(shapes: &[ShapeTracker], offset: usize) -> (Vec<char>,
         .flat_map(|st| {
             st.shape()
                 .into_iter()
-                .chain(
-                    st.padding
-                        .into_iter()
-                        .flat_map(|i| [i.0.into(), i.1.into()]),
-                )
-                .chain(st.slices.into_iter().flat_map(|i| [i.0.into(), i.1.into()]))
+                .chain(st.padding.iter().flat_map(|i| [i.0.into(), i.1.into()]))
+                .chain(st.slices.iter().flat_map(|i| [i.0.into(), i.1.into()]))
         })
         .flat_map(|d| d.to_symbols())
         .unique()
@@ -424,7 +420,7 @@ fn expr_to_metal_string(expr: BigExpression) -> String {
     symbols.pop().unwrap()
 }
 
-fn get_idx_valid_exps(shape: ShapeTracker) -> (String, String) {
+fn get_idx_valid_exps(shape: &ShapeTracker) -> (String, String) {
     (
         expr_to_metal_string(shape.index_expression()),
         expr_to_metal_string(shape.valid_expression()),
@@ -168,8 +168,8 @@ impl<T: 'static + Clone> Operator for Matmul<T> {
 
             self.metal_forward(
                 &[
-                    (get_buffer_from_tensor(&inp[0].0), inp[0].1),
-                    (get_buffer_from_tensor(&inp[1].0), inp[1].1),
+                    (get_buffer_from_tensor(&inp[0].0), inp[0].1.clone()),
+                    (get_buffer_from_tensor(&inp[1].0), inp[1].1.clone()),
                 ],
                 command_buffer,
                 &[],
@@ -317,8 +317,8 @@ impl<T: MetalFloat> Compiler for MetalMatMulCompiler<T> {
             }
             // Insert Matmul op
             let srcs = graph.get_sources(mul);
-            let (mut src1, mut src1_shape) = (srcs[0].0, srcs[0].2);
-            let (mut src2, mut src2_shape) = (srcs[1].0, srcs[1].2);
+            let (mut src1, mut src1_shape) = (srcs[0].0, srcs[0].2.clone());
+            let (mut src2, mut src2_shape) = (srcs[1].0, srcs[1].2.clone());
             // Undo expansions and permute
             src1_shape.remove_dim(src1_shape.len() - 2);
             src2_shape.remove_dim(src2_shape.len() - 3);
@@ -337,12 +337,12 @@ impl<T: MetalFloat> Compiler for MetalMatMulCompiler<T> {
             {
                 src1 = graph
                     .add_op(MetalContiguous::<T>::new(
-                        src1_shape,
+                        src1_shape.clone(),
                         dev.clone(),
                         queue.clone(),
                         &graph.dyn_map,
                     ))
-                    .input(src1, 0, src1_shape)
+                    .input(src1, 0, src1_shape.clone())
                     .finish();
                 src1_shape = src1_shape.contiguous();
             }
@@ -359,12 +359,12 @@ impl<T: MetalFloat> Compiler for MetalMatMulCompiler<T> {
             {
                 src2 = graph
                     .add_op(MetalContiguous::<T>::new(
-                        src2_shape,
+                        src2_shape.clone(),
                         dev.clone(),
                         queue.clone(),
                         &graph.dyn_map,
                     ))
-                    .input(src2, 0, src2_shape)
+                    .input(src2, 0, src2_shape.clone())
                     .finish();
                 src2_shape = src2_shape.contiguous();
             }
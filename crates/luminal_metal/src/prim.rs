@@ -153,8 +153,8 @@ impl<T: MetalFloat> MetalContiguous<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (idx_exp, valid_exp) = get_idx_valid_exps(shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape], 3);
+        let (idx_exp, valid_exp) = get_idx_valid_exps(&shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape.clone()], 3);
         let type_name = T::type_name();
         let code = format!("
 #include <metal_stdlib>
@@ -222,7 +222,7 @@ impl<T: MetalFloat> Operator for MetalContiguous<T> {
 
             // Schedule op on the command buffer
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -249,7 +249,7 @@ impl<T: MetalFloat> Operator for MetalContiguous<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
+                    input_shapes[0].clone(),
                     self.device.clone(),
                     self.queue.clone(),
                     self.dyn_map,
@@ -329,7 +329,7 @@ impl<T: MetalFloat> Operator for MetalLog2<T> {
             );
 
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -424,7 +424,7 @@ impl<T: MetalFloat> Operator for MetalExp2<T> {
                 MTLResourceOptions::StorageModeShared,
             );
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -519,7 +519,7 @@ impl<T: MetalFloat> Operator for MetalSin<T> {
             );
 
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -614,7 +614,7 @@ impl<T: MetalFloat> Operator for MetalSqrt<T> {
             );
 
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -709,7 +709,7 @@ impl<T: MetalFloat> Operator for MetalRecip<T> {
             );
 
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -757,9 +757,9 @@ impl<T: MetalFloat> MetalAdd<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(a_shape);
-        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape], 4);
+        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(&a_shape);
+        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()], 4);
         let type_name = T::type_name();
         let code = format!("
 #include <metal_stdlib>
@@ -828,8 +828,8 @@ impl<T: MetalFloat> Operator for MetalAdd<T> {
 
             self.metal_forward(
                 &[
-                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1),
-                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1),
+                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone()),
+                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1.clone()),
                 ],
                 command_buffer,
                 &[],
@@ -856,8 +856,8 @@ impl<T: MetalFloat> Operator for MetalAdd<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
-                    input_shapes[1],
+                    input_shapes[0].clone(),
+                    input_shapes[1].clone(),
                     self.device.clone(),
                     self.queue.clone(),
                     self.dyn_map,
@@ -889,9 +889,9 @@ impl<T: MetalFloat> MetalMul<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(a_shape);
-        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape], 4);
+        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(&a_shape);
+        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()], 4);
         let type_name = T::type_name();
         let code = format!("
 #include <metal_stdlib>
@@ -961,8 +961,8 @@ impl<T: MetalFloat> Operator for MetalMul<T> {
 
             self.metal_forward(
                 &[
-                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1),
-                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1),
+                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone()),
+                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1.clone()),
                 ],
                 command_buffer,
                 &[],
@@ -989,8 +989,8 @@ impl<T: MetalFloat> Operator for MetalMul<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
-                    input_shapes[1],
+                    input_shapes[0].clone(),
+                    input_shapes[1].clone(),
                     self.device.clone(),
                     self.queue.clone(),
                     self.dyn_map,
@@ -1022,10 +1022,10 @@ impl<T: MetalFloat> MetalLessThan<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(a_shape);
-        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(b_shape);
+        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(&a_shape);
+        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(&b_shape);
         let type_name = T::type_name();
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape], 4);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()], 4);
         let code = format!("
 #include <metal_stdlib>
 using namespace metal;
@@ -1106,8 +1106,8 @@ impl<T: MetalFloat> Operator for MetalLessThan<T> {
 
             self.metal_forward(
                 &[
-                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1),
-                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1),
+                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone()),
+                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1.clone()),
                 ],
                 command_buffer,
                 &[],
@@ -1134,8 +1134,8 @@ impl<T: MetalFloat> Operator for MetalLessThan<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
-                    input_shapes[1],
+                    input_shapes[0].clone(),
+                    input_shapes[1].clone(),
                     self.device.clone(),
                     self.queue.clone(),
                     self.dyn_map,
@@ -1167,9 +1167,9 @@ impl<T: MetalFloat> MetalMod<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(a_shape);
-        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(b_shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape, b_shape], 4);
+        let (a_idx_exp, a_valid_exp) = get_idx_valid_exps(&a_shape);
+        let (b_idx_exp, b_valid_exp) = get_idx_valid_exps(&b_shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[a_shape.clone(), b_shape.clone()], 4);
         let type_name = T::type_name();
         let code = format!(
             "
@@ -1237,8 +1237,8 @@ impl<T: MetalFloat> Operator for MetalMod<T> {
 
             self.metal_forward(
                 &[
-                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1),
-                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1),
+                    (get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone()),
+                    (get_buffer_from_tensor(&tensors[1].0), tensors[1].1.clone()),
                 ],
                 command_buffer,
                 &[],
@@ -1265,8 +1265,8 @@ impl<T: MetalFloat> Operator for MetalMod<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
-                    input_shapes[1],
+                    input_shapes[0].clone(),
+                    input_shapes[1].clone(),
                     self.device.clone(),
                     self.queue.clone(),
                     self.dyn_map,
@@ -1305,8 +1305,8 @@ impl<T: MetalFloat> MetalSumReduce<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (idx_exp, valid_exp) = get_idx_valid_exps(shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape], 6);
+        let (idx_exp, valid_exp) = get_idx_valid_exps(&shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape.clone()], 6);
         let type_name = T::type_name();
         let code = format!("
 #include <metal_stdlib>
@@ -1340,7 +1340,7 @@ kernel void mkernel(device {type_name} *inp [[buffer(0)]], device {type_name} *o
 
 impl<T> MetalKernel for MetalSumReduce<T> {
     fn output_buffer_sizes(&self, input_shapes: &[ShapeTracker]) -> Vec<BigExpression> {
-        let mut sh = input_shapes[0];
+        let mut sh = input_shapes[0].clone();
         sh.remove_dim(self.dim);
         vec![sh.n_elements() * size_of::<T>()]
     }
@@ -1351,7 +1351,7 @@ impl<T> MetalKernel for MetalSumReduce<T> {
         _: &[&Buffer],
         output_buffers: &[&Buffer],
     ) {
-        let mut sh = inputs[0].1;
+        let mut sh = inputs[0].1.clone();
         sh.remove_dim(self.dim);
         let inp_size = sh.n_elements().to_usize().unwrap();
         let front_size: usize = inputs[0]
@@ -1399,7 +1399,7 @@ impl<T: MetalFloat> Operator for MetalSumReduce<T> {
         autoreleasepool(|| {
             // Setup command queue / command buffer / encoder
             let command_buffer = self.queue.new_command_buffer();
-            let mut sh = tensors[0].1;
+            let mut sh = tensors[0].1.clone();
             sh.remove_dim(self.dim);
             let inp_size = sh.n_elements().to_usize().unwrap();
             let out = self.device.new_buffer(
@@ -1408,7 +1408,7 @@ impl<T: MetalFloat> Operator for MetalSumReduce<T> {
             );
 
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -1434,7 +1434,7 @@ impl<T: MetalFloat> Operator for MetalSumReduce<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
+                    input_shapes[0].clone(),
                     self.dim,
                     self.device.clone(),
                     self.queue.clone(),
@@ -1471,9 +1471,9 @@ impl<T: MetalFloat> MetalMaxReduce<T> {
         queue: CommandQueue,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (idx_exp, valid_exp) = get_idx_valid_exps(shape);
+        let (idx_exp, valid_exp) = get_idx_valid_exps(&shape);
         let type_name = T::type_name();
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape], 6);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape.clone()], 6);
         let code = format!("
 #include <metal_stdlib>
 using namespace metal;
@@ -1507,7 +1507,7 @@ kernel void mkernel(device {type_name} *inp [[buffer(0)]], device {type_name} *o
 }
 impl<T> MetalKernel for MetalMaxReduce<T> {
     fn output_buffer_sizes(&self, input_shapes: &[ShapeTracker]) -> Vec<BigExpression> {
-        let mut sh = input_shapes[0];
+        let mut sh = input_shapes[0].clone();
         sh.remove_dim(self.dim);
         vec![sh.n_elements() * size_of::<T>()]
     }
@@ -1518,7 +1518,7 @@ impl<T> MetalKernel for MetalMaxReduce<T> {
         _: &[&Buffer],
         output_buffers: &[&Buffer],
     ) {
-        let mut sh = inputs[0].1;
+        let mut sh = inputs[0].1.clone();
         sh.remove_dim(self.dim);
         let inp_size = sh.contiguous().n_elements().to_usize().unwrap();
         let front_size: usize = inputs[0]
@@ -1574,7 +1574,7 @@ impl<T: MetalFloat> Operator for MetalMaxReduce<T> {
 
             // Setup command queue / command buffer / encoder
             let command_buffer = self.queue.new_command_buffer();
-            let mut sh = tensors[0].1;
+            let mut sh = tensors[0].1.clone();
             sh.remove_dim(self.dim);
             let inp_size = sh.n_elements().to_usize().unwrap();
             let out = self.device.new_buffer(
@@ -1604,7 +1604,7 @@ impl<T: MetalFloat> Operator for MetalMaxReduce<T> {
         if key == "recompile_shapes" {
             if let Some(input_shapes) = input.downcast_ref::<Vec<ShapeTracker>>() {
                 *self = Self::new(
-                    input_shapes[0],
+                    input_shapes[0].clone(),
                     self.dim,
                     self.device.clone(),
                     self.queue.clone(),
@@ -1648,7 +1648,7 @@ impl<T: MetalFloat + 'static> Compiler for PrimitiveCompiler<T> {
             for (edge_id, weight, dest) in graph
                 .graph
                 .edges_directed(function_node, petgraph::Direction::Outgoing)
-                .map(|e| (e.id(), *e.weight(), e.target()))
+                .map(|e| (e.id(), e.weight().clone(), e.target()))
                 .filter(|(_, _, trg)| *trg != copy_node)
                 .collect::<Vec<_>>()
             {
@@ -1664,7 +1664,7 @@ impl<T: MetalFloat + 'static> Compiler for PrimitiveCompiler<T> {
             for (source, edge, edge_weight) in graph
                 .graph
                 .edges_directed(function_node, petgraph::Direction::Incoming)
-                .map(|e| (e.source(), e.id(), *e.weight()))
+                .map(|e| (e.source(), e.id(), e.weight().clone()))
                 .collect::<Vec<_>>()
             {
                 let copy_from_node = graph
@@ -1768,8 +1768,9 @@ impl<T: MetalFloat + 'static> Compiler for PrimitiveCompiler<T> {
             );
             let copy_node = graph
                 .add_op(MetalCopyFromDevice::<T>::new(dev.clone()))
-                .input(source, 0, shape)
+                .input(source, 0, shape.clone())
                 .finish();
+            let dtype = graph.dtype(source);
             graph.graph.add_edge(
                 copy_node,
                 output_node,
@@ -1777,6 +1778,7 @@ impl<T: MetalFloat + 'static> Compiler for PrimitiveCompiler<T> {
                     input_order: 0,
                     output_order: 0,
                     shape,
+                    dtype,
                 },
             );
             graph.graph.remove_edge(edge);
@@ -1812,39 +1814,39 @@ impl<T: MetalFloat + 'static> Compiler for PrimitiveCompiler<T> {
                 *op_ref = Box::new(MetalRecip::<T>::new(dev.clone(), queue.clone()));
             } else if is::<Add>(op) {
                 *op_ref = Box::new(MetalAdd::<T>::new(
-                    src_shapes[0],
-                    src_shapes[1],
+                    src_shapes[0].clone(),
+                    src_shapes[1].clone(),
                     dev.clone(),
                     queue.clone(),
                     &graph.dyn_map,
                 ));
             } else if is::<Mul>(op) {
                 *op_ref = Box::new(MetalMul::<T>::new(
-                    src_shapes[0],
-                    src_shapes[1],
+                    src_shapes[0].clone(),
+                    src_shapes[1].clone(),
                     dev.clone(),
                     queue.clone(),
                     &graph.dyn_map,
                 ));
             } else if is::<LessThan>(op) {
                 *op_ref = Box::new(MetalLessThan::<T>::new(
-                    src_shapes[0],
-                    src_shapes[1],
+                    src_shapes[0].clone(),
+                    src_shapes[1].clone(),
                     dev.clone(),
                     queue.clone(),
                     &graph.dyn_map,
                 ));
             } else if is::<Mod>(op) {
                 *op_ref = Box::new(MetalMod::<T>::new(
-                    src_shapes[0],
-                    src_shapes[1],
+                    src_shapes[0].clone(),
+                    src_shapes[1].clone(),
                     dev.clone(),
                     queue.clone(),
                     &graph.dyn_map,
                 ));
             } else if let Some(SumReduce(dim)) = op_ref.as_any().downcast_ref() {
                 *op_ref = Box::new(MetalSumReduce::<T>::new(
-                    src_shapes[0],
+                    src_shapes[0].clone(),
                     *dim,
                     dev.clone(),
                     queue.clone(),
@@ -1852,7 +1854,7 @@ impl<T: MetalFloat + 'static> Compiler for PrimitiveCompiler<T> {
                 ));
             } else if let Some(MaxReduce(dim)) = op_ref.as_any().downcast_ref() {
                 *op_ref = Box::new(MetalMaxReduce::<T>::new(
-                    src_shapes[0],
+                    src_shapes[0].clone(),
                     *dim,
                     dev.clone(),
                     queue.clone(),
@@ -1860,7 +1862,7 @@ impl<T: MetalFloat + 'static> Compiler for PrimitiveCompiler<T> {
                 ));
             } else if is::<Contiguous>(op) {
                 *op_ref = Box::new(MetalContiguous::<T>::new(
-                    src_shapes[0],
+                    src_shapes[0].clone(),
                     dev.clone(),
                     queue.clone(),
                     &graph.dyn_map,
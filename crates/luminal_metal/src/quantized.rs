@@ -206,8 +206,8 @@ impl<T: 'static + Clone> Operator for QuantizedMatmul<T> {
 
             self.metal_forward(
                 &[
-                    (get_buffer_from_tensor(&inp[0].0), inp[0].1),
-                    (get_buffer_from_tensor(&inp[1].0), inp[1].1),
+                    (get_buffer_from_tensor(&inp[0].0), inp[0].1.clone()),
+                    (get_buffer_from_tensor(&inp[1].0), inp[1].1.clone()),
                 ],
                 command_buffer,
                 &[],
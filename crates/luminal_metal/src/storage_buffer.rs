@@ -370,7 +370,7 @@ impl Operator for StorageBufferWrapper {
             .collect::<Vec<_>>();
         self.wrapper.0.without_command_buffer(
             &inp.iter()
-                .map(|(t, sh)| (get_buffer_from_tensor(t).deref(), *sh))
+                .map(|(t, sh)| (get_buffer_from_tensor(t).deref(), sh.clone()))
                 .collect::<Vec<_>>(),
             &intermediate_buffers,
             &output_buffers,
@@ -47,8 +47,8 @@ impl<T: MetalFloat> MetalMeanReduce<T> {
         shape: ShapeTracker,
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
-        let (idx_exp, valid_exp) = get_idx_valid_exps(shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape], 6);
+        let (idx_exp, valid_exp) = get_idx_valid_exps(&shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape.clone()], 6);
         let type_name = T::type_name();
         let mut code = format!("
 #include <metal_stdlib>
@@ -83,7 +83,7 @@ kernel void mkernel(device {type_name} *inp [[buffer(0)]], device {type_name} *o
 
 impl<T> MetalKernel for MetalMeanReduce<T> {
     fn output_buffer_sizes(&self, input_shapes: &[ShapeTracker]) -> Vec<BigExpression> {
-        let mut sh = input_shapes[0];
+        let mut sh = input_shapes[0].clone();
         sh.remove_dim(self.3);
         vec![sh.n_elements() * size_of::<T>()]
     }
@@ -94,7 +94,7 @@ impl<T> MetalKernel for MetalMeanReduce<T> {
         _: &[&Buffer],
         output_buffers: &[&Buffer],
     ) {
-        let mut sh = inputs[0].1;
+        let mut sh = inputs[0].1.clone();
         sh.remove_dim(self.3);
         let inp_size = sh.n_elements().to_usize().unwrap();
 
@@ -137,7 +137,7 @@ impl<T: MetalFloat> Operator for MetalMeanReduce<T> {
     fn process(&mut self, tensors: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
         autoreleasepool(|| {
             // Setup buffers
-            let mut sh = tensors[0].1;
+            let mut sh = tensors[0].1.clone();
             sh.remove_dim(self.3);
             let inp_size = sh.n_elements().to_usize().unwrap();
             let out = self.2.new_buffer(
@@ -149,7 +149,7 @@ impl<T: MetalFloat> Operator for MetalMeanReduce<T> {
             let command_buffer = self.1.new_command_buffer();
 
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -179,7 +179,7 @@ impl<T: MetalFloat> Operator for MetalMeanReduce<T> {
                     self.2.clone(),
                     self.1.clone(),
                     self.3,
-                    input_shapes[0],
+                    input_shapes[0].clone(),
                     self.5,
                 );
             }
@@ -234,13 +234,13 @@ impl<T: MetalFloat> Compiler for MeanReduceCompiler<T> {
                 .unwrap()
                 .dim;
             // Insert MeanReduce op
-            let src = graph.get_sources(sum_reduce)[0];
+            let src = graph.get_sources(sum_reduce).remove(0);
             let mean_reduce = graph
                 .add_op(MetalMeanReduce::<T>::new(
                     dev.clone(),
                     queue.clone(),
                     dim,
-                    src.2,
+                    src.2.clone(),
                     &graph.dyn_map,
                 ))
                 .input(src.0, 0, src.2)
@@ -493,7 +493,7 @@ impl<T: MetalFloat> Compiler for StdNormCompiler<T> {
             else {
                 continue;
             };
-            let (mut x, _, mut sh) = graph.get_sources(square)[0];
+            let (mut x, _, mut sh) = graph.get_sources(square).remove(0);
             if let Some(mean_reduce) = graph
                 .graph
                 .node_weight(mean)
@@ -526,12 +526,12 @@ impl<T: MetalFloat> Compiler for StdNormCompiler<T> {
             if !sh.is_contiguous() || sh.is_sliced() || sh.is_padded() {
                 x = graph
                     .add_op(MetalContiguous::<T>::new(
-                        sh,
+                        sh.clone(),
                         dev.clone(),
                         queue.clone(),
                         &graph.dyn_map,
                     ))
-                    .input(x, 0, sh)
+                    .input(x, 0, sh.clone())
                     .finish();
                 sh = sh.contiguous();
             }
@@ -964,7 +964,7 @@ impl<T: MetalFloat> Operator for MetalSoftmax<T> {
             let command_buffer = self.queue.new_command_buffer();
 
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -1029,7 +1029,7 @@ impl<T: MetalFloat> Compiler for SoftmaxCompiler<T> {
                 continue;
             }
             // Insert Softmax op
-            let src = graph.get_sources(max_reduce)[0];
+            let src = graph.get_sources(max_reduce).remove(0);
             let mean_reduce = graph
                 .add_op(MetalSoftmax::<T> {
                     device: dev.clone(),
@@ -1093,8 +1093,8 @@ impl<T: MetalFloat> MetalRope<T> {
         dyn_map: *const FxHashMap<char, usize>,
     ) -> Self {
         let type_name = T::type_name();
-        let (index, valid) = get_idx_valid_exps(shape);
-        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape], 3);
+        let (index, valid) = get_idx_valid_exps(&shape);
+        let (dyn_symbols, rendered) = render_dyn_dim_inputs(&[shape.clone()], 3);
         Self {
             pipeline: compile_function(
                 "mkernel",
@@ -1198,7 +1198,7 @@ impl<T: MetalFloat> Operator for MetalRope<T> {
             let command_buffer = self.queue.new_command_buffer();
 
             self.metal_forward(
-                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1)],
+                &[(get_buffer_from_tensor(&tensors[0].0), tensors[0].1.clone())],
                 command_buffer,
                 &[],
                 &[&out],
@@ -1227,7 +1227,7 @@ impl<T: MetalFloat> Operator for MetalRope<T> {
                 *self = Self::new(
                     self.axis_size,
                     self.seq_offset.clone(),
-                    input_shapes[0],
+                    input_shapes[0].clone(),
                     self.device.clone(),
                     self.queue.clone(),
                     self.dyn_map,
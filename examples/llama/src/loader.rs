@@ -1,4 +1,7 @@
-use luminal::{op::Function, prelude::*};
+use luminal::{
+    op::{Function, OpError},
+    prelude::*,
+};
 
 /// Load the model in the same way dfdx-llama does
 pub struct DfdxDeferredLoader {
@@ -36,7 +39,8 @@ impl Loader for DfdxDeferredLoader {
                 let path = self.path.clone();
                 inp_func.1 = Box::new(move |_| {
                     // Get memmapped tensor
-                    let bytes = std::fs::read(format!("{path}/{s}")).unwrap();
+                    let bytes = std::fs::read(format!("{path}/{s}"))
+                        .map_err(|e| OpError::Io(format!("{path}/{s}: {e}")))?;
                     let data: Vec<f32> = if bytes.len() == n_elements * 2 {
                         // Half-precision
                         bytes
@@ -56,17 +60,17 @@ impl Loader for DfdxDeferredLoader {
                             })
                             .collect()
                     } else {
-                        panic!(
+                        return Err(OpError::Other(format!(
                             "Expected {} or {} bytes, got {} when loading {path}/{s}",
                             n_elements * 2,
                             n_elements * 4,
                             bytes.len(),
-                        )
+                        )));
                     };
 
-                    vec![Tensor {
+                    Ok(vec![Tensor {
                         data: Box::new(data),
-                    }]
+                    }])
                 });
             };
         }
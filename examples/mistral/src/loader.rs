@@ -4,7 +4,10 @@ use std::{
 };
 
 use itertools::Itertools;
-use luminal::{op::Function, prelude::*};
+use luminal::{
+    op::{Function, OpError},
+    prelude::*,
+};
 
 use crate::gguf::*;
 
@@ -57,8 +60,10 @@ impl Loader for MetalQ8Loader {
                     _ => panic!("Unsupported dtype: {data_type:?}"),
                 };
                 loading_node.1 = Box::new(move |_| {
-                    let mmap_buffer =
-                        unsafe { Mmap::map(&File::open(&file_path).unwrap()).unwrap() };
+                    let file = File::open(&file_path)
+                        .map_err(|e| OpError::Io(format!("{file_path}: {e}")))?;
+                    let mmap_buffer = unsafe { Mmap::map(&file) }
+                        .map_err(|e| OpError::Io(format!("{file_path}: {e}")))?;
                     let buffer = Device::system_default().unwrap().new_buffer_with_data(
                         unsafe {
                             mmap_buffer
@@ -69,9 +74,9 @@ impl Loader for MetalQ8Loader {
                         n_bytes as u64,
                         MTLResourceOptions::StorageModeShared,
                     );
-                    vec![Tensor {
+                    Ok(vec![Tensor {
                         data: Box::new(MetalBuffer(buffer)),
-                    }]
+                    }])
                 });
             }
         }
@@ -130,12 +135,13 @@ impl Loader for Q8Loader {
                 loading_node.1 = Box::new(move |_| {
                     // Load all bytes
                     let mut bytes = vec![0; n_bytes];
-                    let mut file = File::open(&file_path).unwrap();
+                    let io_error = |e: std::io::Error| OpError::Io(format!("{file_path}: {e}"));
+                    let mut file = File::open(&file_path).map_err(io_error)?;
                     file.seek(std::io::SeekFrom::Start(
                         buffer_offset as u64 + tensor_data_offset,
                     ))
-                    .unwrap();
-                    file.read_exact(&mut bytes).unwrap();
+                    .map_err(io_error)?;
+                    file.read_exact(&mut bytes).map_err(io_error)?;
                     // Dequantize into f32
                     let data: Vec<f32> = match data_type {
                         GgmlDType::F32 => bytes
@@ -164,7 +170,7 @@ impl Loader for Q8Loader {
                             .collect(),
                         _ => panic!("Unsupported dtype: {data_type:?}"),
                     };
-                    vec![Tensor::new(data)]
+                    Ok(vec![Tensor::new(data)])
                 });
             }
         }
@@ -0,0 +1,531 @@
+use std::{any::TypeId, cell::RefCell};
+
+use itertools::Itertools;
+use petgraph::{visit::EdgeRef, Direction};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    op::{self, Constant, ConstantValue, Operator},
+    prelude::{symbolic::Expression, *},
+};
+
+/// A tensor view used while building the gradient graph: (node, output index, shape)
+type View = (NodeIndex, u8, ShapeTracker);
+
+/// Reverse-mode automatic differentiation over the primitive ops.
+///
+/// Given a scalar loss and a set of parameters, this appends the subgraphs computing the gradient of the loss with
+/// respect to each parameter. Views (permutes, expands, slices and pads) on the edges are transposed back onto the
+/// source tensors, so gradients always come out contiguous in the shape of the parameter.
+///
+/// Since only the primops are differentiable, this should be ran before any other compilers.
+/// ```rust
+/// use luminal::prelude::*;
+/// let mut cx = Graph::new();
+/// let a = cx.tensor::<R1<3>>().set(vec![1., 2., 3.]);
+/// let loss = (a * a).sum_reduce::<_, Axis<0>>();
+/// let autograd = Autograd::new(a, loss);
+/// cx.compile(&autograd, ());
+/// let grads = autograd.grads();
+/// grads[0].retrieve();
+/// cx.execute();
+/// assert_eq!(grads[0].data(), vec![2., 4., 6.]);
+/// ```
+#[derive(Debug)]
+pub struct Autograd {
+    params: Vec<NodeIndex>,
+    loss: NodeIndex,
+    grads: RefCell<Vec<GraphTensor<()>>>,
+}
+
+impl Autograd {
+    /// Differentiate `loss` with respect to `params`
+    pub fn new<T: ToIds>(params: T, loss: GraphTensor<R0>) -> Self {
+        Self {
+            params: params.to_ids(),
+            loss: loss.id,
+            grads: Default::default(),
+        }
+    }
+
+    /// The gradient of each parameter, in the order the parameters were given. Empty until the compiler has been ran.
+    pub fn grads(&self) -> Vec<GraphTensor<()>> {
+        self.grads.borrow().clone()
+    }
+}
+
+impl Compiler for Autograd {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, _: T) {
+        // Only nodes on a path from a parameter to the loss need gradients
+        let downstream = reachable(graph, &self.params, Direction::Outgoing);
+        let upstream = reachable(graph, &[self.loss], Direction::Incoming);
+        let valid = downstream
+            .intersection(&upstream)
+            .copied()
+            .collect::<FxHashSet<_>>();
+
+        // Gradient of each node's output, stored contiguously in the physical layout of the output
+        let mut grads: FxHashMap<NodeIndex, View> = FxHashMap::default();
+        if valid.contains(&self.loss) {
+            grads.insert(self.loss, constant(graph, 1.0, &ShapeTracker::new(&[])));
+        }
+        for node in petgraph::algo::toposort(&graph.graph, None)
+            .unwrap()
+            .into_iter()
+            .rev()
+            .filter(|n| valid.contains(n))
+        {
+            let Some(grad) = grads.get(&node).cloned() else {
+                continue;
+            };
+            let inputs = graph.get_sources(node);
+            if inputs.is_empty() {
+                continue;
+            }
+            let local_grads = local_gradients(graph, node, &inputs, grad);
+            for ((src, _, st), local) in inputs.into_iter().zip(local_grads) {
+                if !valid.contains(&src) {
+                    continue;
+                }
+                let g = match local {
+                    Some(InputGrad::Logical(g)) => to_physical(graph, g, &st),
+                    Some(InputGrad::Physical(g)) => g,
+                    None => continue,
+                };
+                let g = if let Some(prev) = grads.get(&src).cloned() {
+                    // Both are contiguous with the same number of elements, so view them the same way
+                    let g = (g.0, g.1, prev.2.clone());
+                    binary(graph, op::Add, prev, g)
+                } else {
+                    g
+                };
+                grads.insert(src, g);
+            }
+        }
+
+        let graph_ref: *mut Graph = graph;
+        *self.grads.borrow_mut() = self
+            .params
+            .iter()
+            .map(|param| {
+                let (id, _, shape) = grads
+                    .get(param)
+                    .cloned()
+                    .unwrap_or_else(|| zeros_like(graph, *param));
+                GraphTensor::from_id(id, shape, graph_ref)
+            })
+            .collect();
+    }
+}
+
+/// All nodes reachable from a set of nodes along data edges in a direction, including the nodes themselves
+fn reachable(graph: &Graph, nodes: &[NodeIndex], direction: Direction) -> FxHashSet<NodeIndex> {
+    let mut seen = nodes.iter().copied().collect::<FxHashSet<_>>();
+    let mut stack = nodes.to_vec();
+    while let Some(node) = stack.pop() {
+        for edge in graph
+            .graph
+            .edges_directed(node, direction)
+            .filter(|e| !e.weight().is_schedule())
+        {
+            let next = if direction == Direction::Outgoing {
+                edge.target()
+            } else {
+                edge.source()
+            };
+            if seen.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+    seen
+}
+
+/// The shape of a view, as seen by the op consuming it
+fn logical_dims(shape: &ShapeTracker) -> Vec<Expression> {
+    shape.shape().into_iter().map(|e| e.into()).collect()
+}
+
+/// A contiguous tracker over the logical shape of a view
+fn output_shape(shape: &ShapeTracker) -> ShapeTracker {
+    ShapeTracker::new(&logical_dims(shape))
+}
+
+fn is_plain(shape: &ShapeTracker) -> bool {
+    shape.is_contiguous() && !shape.is_sliced() && !shape.is_padded()
+}
+
+/// Unary ops map over the whole source buffer, so the output is viewed the same way as the input
+fn unary<O: Operator + 'static>(graph: &mut Graph, op: O, a: View) -> View {
+    let id = graph.add_op(op).input(a.0, a.1, a.2.clone()).finish();
+    (id, 0, a.2)
+}
+
+fn contiguous(graph: &mut Graph, a: View) -> View {
+    let shape = output_shape(&a.2);
+    let id = graph.add_op(op::Contiguous).input(a.0, a.1, a.2).finish();
+    (id, 0, shape)
+}
+
+fn binary<O: Operator + 'static>(graph: &mut Graph, op: O, a: View, b: View) -> View {
+    let shape = output_shape(&a.2);
+    let id = graph
+        .add_op(op)
+        .input(a.0, a.1, a.2)
+        .input(b.0, b.1, b.2)
+        .finish();
+    (id, 0, shape)
+}
+
+/// A constant broadcasted to the logical shape of `like`
+fn constant(graph: &mut Graph, value: f32, like: &ShapeTracker) -> View {
+    let dyn_map = &graph.dyn_map as *const _;
+    let id = graph
+        .add_op(Constant(ConstantValue::Float(value), dyn_map))
+        .finish();
+    (id, 0, ShapeTracker::fake(&logical_dims(like)))
+}
+
+fn mul(graph: &mut Graph, a: View, b: View) -> View {
+    binary(graph, op::Mul, a, b)
+}
+
+fn scale(graph: &mut Graph, a: View, value: f32) -> View {
+    let c = constant(graph, value, &a.2);
+    mul(graph, a, c)
+}
+
+/// A contiguous tensor of zeros in the shape of a node's output
+fn zeros_like(graph: &mut Graph, node: NodeIndex) -> View {
+    let st = graph
+        .graph
+        .edges_directed(node, Direction::Outgoing)
+        .find_map(|e| e.weight().as_data())
+        .map(|(_, _, st)| st)
+        .unwrap_or_else(|| panic!("Parameter {node:?} isn't used anywhere in the graph"));
+    let zeros = constant(graph, 0.0, &ShapeTracker::new(&physical_dims(&st)));
+    contiguous(graph, zeros)
+}
+
+/// The dimensions of the tensor a view is looking at, in memory order
+fn physical_dims(shape: &ShapeTracker) -> Vec<Expression> {
+    shape
+        .dims
+        .iter()
+        .zip(&shape.fake)
+        .filter(|(_, fake)| !**fake)
+        .map(|(d, _)| *d)
+        .collect()
+}
+
+/// A gradient with respect to one of an op's inputs
+enum InputGrad {
+    /// In the logical shape the input is viewed in
+    Logical(View),
+    /// Already in the physical layout of the input tensor
+    Physical(View),
+}
+
+/// Gradients of a node with respect to each of its inputs, given the gradient of its output.
+fn local_gradients(
+    graph: &mut Graph,
+    node: NodeIndex,
+    inputs: &[View],
+    grad: View,
+) -> Vec<Option<InputGrad>> {
+    let op = graph.graph.node_weight(node).unwrap().as_any();
+    if op.is::<op::Log2>()
+        || op.is::<op::Exp2>()
+        || op.is::<op::Sin>()
+        || op.is::<op::Sqrt>()
+        || op.is::<op::Recip>()
+    {
+        // Unary ops run over the whole source buffer, so their gradients are computed in the physical layout
+        let is = |t| op.type_id() == t;
+        let (log2, exp2, sin, sqrt) = (
+            is(TypeId::of::<op::Log2>()),
+            is(TypeId::of::<op::Exp2>()),
+            is(TypeId::of::<op::Sin>()),
+            is(TypeId::of::<op::Sqrt>()),
+        );
+        let x = (inputs[0].0, inputs[0].1, grad.2.clone());
+        let out = (node, 0, grad.2.clone());
+        let local = if log2 {
+            let recip = unary(graph, op::Recip, x);
+            scale(graph, recip, 1. / std::f32::consts::LN_2)
+        } else if exp2 {
+            scale(graph, out, std::f32::consts::LN_2)
+        } else if sin {
+            // cos(x) = sin(x + pi / 2)
+            let half_pi = constant(graph, std::f32::consts::FRAC_PI_2, &x.2);
+            let shifted = binary(graph, op::Add, x, half_pi);
+            unary(graph, op::Sin, shifted)
+        } else if sqrt {
+            let recip = unary(graph, op::Recip, out);
+            scale(graph, recip, 0.5)
+        } else {
+            let sq = mul(graph, out.clone(), out);
+            scale(graph, sq, -1.)
+        };
+        return vec![Some(InputGrad::Physical(mul(graph, grad, local)))];
+    }
+
+    let out_shape = output_shape(&inputs[0].2);
+    // The output gradient viewed in the shape of the elementwise output
+    let g = (grad.0, grad.1, out_shape.clone());
+    let out = (node, 0, out_shape);
+    let grads = if op.is::<op::Add>() {
+        vec![Some(g.clone()), Some(g)]
+    } else if op.is::<op::Mul>() {
+        let (a, b) = (inputs[0].clone(), inputs[1].clone());
+        vec![Some(mul(graph, g.clone(), b)), Some(mul(graph, g, a))]
+    } else if op.is::<op::Mod>() {
+        // a % b = a - b * floor(a / b), so d/db = -floor(a / b) = -(a - out) / b
+        let (a, b) = (inputs[0].clone(), inputs[1].clone());
+        let neg_out = scale(graph, out, -1.);
+        let a_floor = binary(graph, op::Add, a, neg_out);
+        let b_recip = unary(graph, op::Recip, b);
+        let floor = mul(graph, a_floor, b_recip);
+        let g_b = mul(graph, g.clone(), floor);
+        vec![Some(g), Some(scale(graph, g_b, -1.))]
+    } else if op.is::<op::LessThan>() {
+        // Comparisons are piecewise constant
+        vec![None, None]
+    } else if op.is::<op::Contiguous>() {
+        vec![Some(g)]
+    } else if let Some(op::SumReduce(dim)) = op.downcast_ref::<op::SumReduce>() {
+        vec![Some(expand_reduced(g.0, g.1, &inputs[0].2, *dim))]
+    } else if let Some(op::MaxReduce(dim)) = op.downcast_ref::<op::MaxReduce>() {
+        // Gradient flows to every element equal to the max
+        let dim = *dim;
+        let g = expand_reduced(g.0, g.1, &inputs[0].2, dim);
+        let max = expand_reduced(node, 0, &inputs[0].2, dim);
+        let x = inputs[0].clone();
+        let lt = binary(graph, op::LessThan, x.clone(), max.clone());
+        let gt = binary(graph, op::LessThan, max, x);
+        let not_equal = binary(graph, op::Add, lt, gt);
+        let neg_not_equal = scale(graph, not_equal, -1.);
+        let one = constant(graph, 1., &neg_not_equal.2);
+        let equal = binary(graph, op::Add, neg_not_equal, one);
+        vec![Some(mul(graph, g, equal))]
+    } else {
+        panic!(
+            "Autograd doesn't support {:?}, it should be ran before other compilers",
+            graph.graph.node_weight(node).unwrap()
+        )
+    };
+    grads
+        .into_iter()
+        .map(|g| g.map(InputGrad::Logical))
+        .collect()
+}
+
+/// View the output of a reduction along `dim` as the shape of its input by broadcasting it back out
+fn expand_reduced(id: NodeIndex, output: u8, input: &ShapeTracker, dim: usize) -> View {
+    let mut dims = logical_dims(input);
+    let reduced = dims.remove(dim);
+    let mut shape = ShapeTracker::new(&dims);
+    shape.expand(dim, reduced);
+    (id, output, shape)
+}
+
+/// Transpose a gradient in the logical shape of a view back onto the physical layout of the viewed tensor
+fn to_physical(graph: &mut Graph, grad: View, view: &ShapeTracker) -> View {
+    let mut grad = if is_plain(&grad.2) {
+        grad
+    } else {
+        contiguous(graph, grad)
+    };
+    let is_zero = |e: Expression| e.to_usize() == Some(0);
+
+    // Undo slices by padding them back out
+    let pads = view
+        .indexes
+        .iter()
+        .copied()
+        .map(|d| {
+            // Slices are applied after padding, so they're in padded coordinates
+            let (start, end) = view.slices[d];
+            let full = view.dims[d] + view.padding[d].0 + view.padding[d].1;
+            let end = if end.to_usize() == Some(i32::MAX as usize) {
+                0.into()
+            } else {
+                full - full.min(end)
+            };
+            (start, end)
+        })
+        .collect::<Vec<_>>();
+    if pads.iter().any(|(s, e)| !is_zero(*s) || !is_zero(*e)) {
+        grad.2.pad(&pads);
+        grad = contiguous(graph, grad);
+    }
+
+    // Undo padding by slicing it back off
+    let slices = view
+        .indexes
+        .iter()
+        .copied()
+        .map(|d| (view.padding[d].0, view.padding[d].0 + view.dims[d]))
+        .collect::<Vec<_>>();
+    if view
+        .padding
+        .iter()
+        .any(|(s, e)| !is_zero(*s) || !is_zero(*e))
+    {
+        grad.2.slice(&slices);
+    }
+
+    // Sum over broadcasted dimensions
+    for axis in (0..view.len()).rev() {
+        if view.fake[view.indexes[axis]] {
+            let mut dims = logical_dims(&grad.2);
+            dims.remove(axis);
+            let id = graph
+                .add_op(op::SumReduce(axis))
+                .input(grad.0, grad.1, grad.2)
+                .finish();
+            grad = (id, 0, ShapeTracker::new(&dims));
+        }
+    }
+
+    // Undo permutes
+    let remaining = view
+        .indexes
+        .iter()
+        .copied()
+        .filter(|d| !view.fake[*d])
+        .collect::<Vec<_>>();
+    let order = (0..remaining.len())
+        .sorted_by_key(|i| remaining[*i])
+        .collect::<Vec<_>>();
+    grad.2.permute(&order);
+    if !is_plain(&grad.2) {
+        grad = contiguous(graph, grad);
+    }
+    (grad.0, grad.1, ShapeTracker::new(&physical_dims(view)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::{symbolic::Expression, *};
+    crate::test_imports!();
+
+    #[test]
+    fn test_autograd_unary() {
+        let mut cx = Graph::new();
+        let a_data = vec![0.3, 1.2, 2.5, 0.7];
+        let a = cx.tensor::<R1<4>>().set(a_data.clone());
+        let b = (a.log2() + a.exp2() + a.sin() + a.sqrt() + a.recip()) * a;
+        let loss = b.sum_reduce::<_, LAxis<0>>();
+        let autograd = Autograd::new(a, loss);
+        cx.compile(&autograd, ());
+        let grads = autograd.grads();
+        grads.retrieve();
+        cx.execute();
+
+        let d_dev = Cpu::default();
+        let d_a = d_dev.tensor_from_vec(a_data, (DConst::<4>,));
+        let d_b = ((d_a.leaky_trace().ln() / 2_f32.ln())
+            + (d_a.leaky_trace() * 2_f32.ln()).exp()
+            + d_a.leaky_trace().sin()
+            + d_a.leaky_trace().sqrt()
+            + d_a.leaky_trace().recip())
+            * d_a.leaky_trace();
+        let d_grads = d_b.sum().backward();
+        assert_close(&grads[0].data(), &d_grads.get(&d_a).as_vec());
+    }
+
+    #[test]
+    fn test_autograd_matmul() {
+        let mut cx = Graph::new();
+        let (input_data, w1_data, w2_data) = (random_vec(6), random_vec(12), random_vec(8));
+        let input = cx.tensor::<R2<2, 3>>().set(input_data.clone());
+        let w1 = cx.tensor::<R2<3, 4>>().set(w1_data.clone());
+        let w2 = cx.tensor::<R2<4, 2>>().set(w2_data.clone());
+        let loss = input
+            .matmul(w1)
+            .relu()
+            .matmul(w2)
+            .mean_reduce::<_, LAxes2<0, 1>>();
+        let autograd = Autograd::new((w1, w2), loss);
+        cx.compile(&autograd, ());
+        let grads = autograd.grads();
+        grads.retrieve();
+        cx.execute();
+
+        let d_dev = Cpu::default();
+        let d_input = d_dev.tensor_from_vec(input_data, (DConst::<2>, DConst::<3>));
+        let d_w1 = d_dev.tensor_from_vec(w1_data, (DConst::<3>, DConst::<4>));
+        let d_w2 = d_dev.tensor_from_vec(w2_data, (DConst::<4>, DConst::<2>));
+        let d_grads = d_input
+            .leaky_trace()
+            .matmul(d_w1.leaky_trace())
+            .relu()
+            .matmul(d_w2.leaky_trace())
+            .mean()
+            .backward();
+        assert_close(&grads[0].data(), &d_grads.get(&d_w1).as_vec());
+        assert_close(&grads[1].data(), &d_grads.get(&d_w2).as_vec());
+    }
+
+    /// Compare against central finite differences of the forward graph
+    fn check_numerical(data: Vec<f32>, f: fn(GraphTensor<R2<3, 4>>) -> GraphTensor<R0>) {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R2<3, 4>>().set(data.clone());
+        let autograd = Autograd::new(a, f(a));
+        cx.compile(&autograd, ());
+        let grad = autograd.grads()[0].retrieve();
+        cx.execute();
+
+        let eval = |data: Vec<f32>| {
+            let mut cx = Graph::new();
+            let out = f(cx.tensor::<R2<3, 4>>().set(data)).retrieve();
+            cx.execute();
+            out.data()[0]
+        };
+        let eps = 1e-2;
+        let numerical = (0..data.len())
+            .map(|i| {
+                let (mut plus, mut minus) = (data.clone(), data.clone());
+                plus[i] += eps;
+                minus[i] -= eps;
+                (eval(plus) - eval(minus)) / (2. * eps)
+            })
+            .collect::<Vec<_>>();
+        assert_close_precision(&grad.data(), &numerical, 2);
+    }
+
+    #[test]
+    fn test_autograd_views() {
+        let data = (0..12).map(|i| (i as f32 * 0.37).sin()).collect::<Vec<_>>();
+        // Slices and pads
+        check_numerical(data.clone(), |a| {
+            let b: GraphTensor<R2<2, 3>> = a
+                .slice((..Expression::from(2), Expression::from(1)..))
+                .realize();
+            let c: GraphTensor<R2<4, 3>> = b.pad(&[(1, 1), (0, 0)]);
+            (c * c).sum_reduce::<_, LAxes2<0, 1>>()
+        });
+        // Pads then slices
+        check_numerical(data.clone(), |a| {
+            let mut b: GraphTensor<R2<3, 6>> = a.pad(&[(0, 0), (2, 0)]);
+            // Slice the padded view directly, since the slice op would make it contiguous first
+            b.shape
+                .slice(&[(0.into(), i32::MAX.into()), (0.into(), 4.into())]);
+            let c: GraphTensor<R2<3, 4>> = b.realize();
+            (c * c.exp()).sum_reduce::<_, LAxes2<0, 1>>()
+        });
+        // Permutes and expands
+        check_numerical(data.clone(), |a| {
+            let b = a.permute::<R2<4, 3>, _>();
+            let c = b.expand::<R3<2, 4, 3>, LAxis<0>>() * a.sum_reduce::<R1<4>, _>().expand();
+            c.sin().sum_reduce::<_, LAxes3<0, 1, 2>>()
+        });
+        // Softmax and max reductions
+        check_numerical(data, |a| {
+            let b = a.softmax::<1>() * a.max_reduce::<R1<3>, _>().expand();
+            let c = b.concat_along::<R2<3, 8>, LAxis<1>, _>(a * a);
+            c.sum_reduce::<_, LAxes2<0, 1>>()
+        });
+    }
+}
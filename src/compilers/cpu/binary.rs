@@ -1,41 +1,64 @@
 use crate::{
     op::*,
     prelude::{petgraph::visit::EdgeRef, *},
+    shape::symbolic::Symbol,
 };
 use itertools::Itertools;
+use rustc_hash::FxHashMap;
 
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct Sub;
 
 impl Operator for Sub {
     fn process(&mut self, tensors: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &tensors)
+    }
+
+    fn try_process(
+        &mut self,
+        tensors: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        try_process_with_new_output(self, &tensors)
+    }
+
+    fn process_into(
+        &mut self,
+        tensors: &[(InputTensor, ShapeTracker)],
+        out: &mut [Tensor],
+    ) -> bool {
+        if !inputs_are_f32(tensors) {
+            return false;
+        }
         let (a_data, b_data) = (
             get_vec_from_tensor(&tensors[0].0),
             get_vec_from_tensor(&tensors[1].0),
         );
-        let (a_ind, a_val, b_ind, b_val) = (
-            tensors[0].1.index_expression(),
-            tensors[0].1.valid_expression(),
-            tensors[1].1.index_expression(),
-            tensors[1].1.valid_expression(),
+        let (a_ind, b_ind) = (
+            Indexer::cached(&tensors[0].1),
+            Indexer::cached(&tensors[1].1),
         );
-        let mut data = vec![0.; tensors[0].1.n_elements().to_usize().unwrap()];
-        for i in 0..data.len() {
-            let lhs = if a_val.exec_single_var(i) != 0 {
-                a_data[a_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
-            let rhs = if b_val.exec_single_var(i) != 0 {
-                b_data[b_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
-            data[i] = lhs - rhs;
+        let n = tensors[0].1.n_elements().to_usize().unwrap();
+        let data = prepare_output(out, n, 0.);
+        for (r, (a, b)) in data.iter_mut().zip(a_ind.iter(n).zip(b_ind.iter(n))) {
+            let lhs = a.map(|i| a_data[i]).unwrap_or(0.);
+            let rhs = b.map(|i| b_data[i]).unwrap_or(0.);
+            *r = lhs - rhs;
         }
-        vec![Tensor {
-            data: Box::new(data),
-        }]
+        true
+    }
+
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(elementwise_cost(inp))
+    }
+}
+
+impl SerializeOp for Sub {
+    const NAME: &'static str = "Sub";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(String::new())
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        args.is_empty().then_some(Sub)
     }
 }
 
@@ -117,33 +140,54 @@ pub struct Equal;
 
 impl Operator for Equal {
     fn process(&mut self, tensors: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &tensors)
+    }
+
+    fn try_process(
+        &mut self,
+        tensors: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        try_process_with_new_output(self, &tensors)
+    }
+
+    fn process_into(
+        &mut self,
+        tensors: &[(InputTensor, ShapeTracker)],
+        out: &mut [Tensor],
+    ) -> bool {
+        if !inputs_are_f32(tensors) {
+            return false;
+        }
         let (a_data, b_data) = (
             get_vec_from_tensor(&tensors[0].0),
             get_vec_from_tensor(&tensors[1].0),
         );
-        let mut data = vec![0.; tensors[0].1.n_elements().to_usize().unwrap()];
-        let (a_ind, a_val, b_ind, b_val) = (
-            tensors[0].1.index_expression(),
-            tensors[0].1.valid_expression(),
-            tensors[1].1.index_expression(),
-            tensors[1].1.valid_expression(),
+        let n = tensors[0].1.n_elements().to_usize().unwrap();
+        let data = prepare_output(out, n, 0.);
+        let (a_ind, b_ind) = (
+            Indexer::cached(&tensors[0].1),
+            Indexer::cached(&tensors[1].1),
         );
-        for i in 0..data.len() {
-            let a = if a_val.exec_single_var(i) != 0 {
-                a_data[a_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
-            let b = if b_val.exec_single_var(i) != 0 {
-                b_data[b_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
-            data[i] = if a < b { 1. } else { 0. };
+        for (r, (a, b)) in data.iter_mut().zip(a_ind.iter(n).zip(b_ind.iter(n))) {
+            let a = a.map(|i| a_data[i]).unwrap_or(0.);
+            let b = b.map(|i| b_data[i]).unwrap_or(0.);
+            *r = if a < b { 1. } else { 0. };
         }
-        vec![Tensor {
-            data: Box::new(data),
-        }]
+        true
+    }
+
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(elementwise_cost(inp))
+    }
+}
+
+impl SerializeOp for Equal {
+    const NAME: &'static str = "Equal";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(String::new())
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        args.is_empty().then_some(Equal)
     }
 }
 
@@ -252,48 +296,24 @@ impl Compiler for EqualCompiler {
     }
 }
 
-#[derive(LuminalPrint, Clone, LuminalEqFalse)]
-pub struct Gather {
-    pub embed_dim: usize,
-}
-
-impl Operator for Gather {
-    fn process(&mut self, tensors: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        // Inp 1 should be Vec<f32> and inp 2 should be a CudaSlice<T>
-        let indexes = tensors[0]
-            .0
-            .borrowed()
-            .data
-            .as_any()
-            .downcast_ref::<Vec<f32>>()
-            .unwrap();
-        let weights = tensors[1]
-            .0
-            .borrowed()
-            .data
-            .as_any()
-            .downcast_ref::<Vec<f32>>()
-            .unwrap();
-
-        let mut out = vec![0.; indexes.len() * self.embed_dim];
-        for token in 0..indexes.len() {
-            let e = indexes[token] as usize;
-            for dim in 0..self.embed_dim {
-                out[token * self.embed_dim + dim] = weights[e * self.embed_dim + dim];
-            }
-        }
-
-        vec![Tensor {
-            data: Box::new(out),
-        }]
+/// An [`ARange`](super::other::ARange), or one [`ConstantFolding`] already evaluated
+fn is_arange(op: &dyn Operator) -> bool {
+    if op.as_any().is::<super::other::ARange>() {
+        return true;
     }
+    op.as_any()
+        .downcast_ref::<ConstantTensor>()
+        .and_then(|c| c.0.data.as_any().downcast_ref::<Vec<f32>>())
+        .map(|v| v.iter().enumerate().all(|(i, x)| *x == i as f32))
+        .unwrap_or_default()
 }
 
+/// Replace one-hot gathers, which `gather` builds for float indexes, with a direct [`Gather`]
 #[derive(LuminalPrint, Default)]
 pub struct GatherCompiler;
 
 impl Compiler for GatherCompiler {
-    fn compile<To: ToIdsMut>(&self, graph: &mut Graph, _: To) {
+    fn compile<To: ToIdsMut>(&self, graph: &mut Graph, mut remap: To) {
         let (mut arange, mut equal, mut mul, mut sum_reduce) = (
             NodeIndex::default(),
             NodeIndex::default(),
@@ -301,35 +321,63 @@ impl Compiler for GatherCompiler {
             NodeIndex::default(),
         );
         let s = SelectOp::new()
-            .ty::<super::other::ARange>()
+            .check(|o, _| is_arange(o))
             .ptr(&mut arange)
             .edge(SelectOp::new().ty::<Equal>().ptr(&mut equal))
             .edge(SelectOp::new().ty::<Mul>().ptr(&mut mul))
             .edge(SelectOp::new().ty::<SumReduce>().ptr(&mut sum_reduce));
         let mut searcher = s.search(graph);
         while searcher.next_match() {
-            if check_no_delete(graph, &[arange, equal, mul, sum_reduce]) {
+            if check_no_delete(graph, &[arange, equal, mul]) {
                 continue;
             }
-            let embed_dim = graph
-                .graph
-                .edges_directed(mul, petgraph::Direction::Incoming)
-                .find(|e| e.source() != equal && !e.weight().is_schedule())
-                .unwrap()
-                .weight()
-                .as_data()
-                .unwrap()
-                .2
-                .shape()[2]
-                .to_usize()
-                .unwrap();
-            let gather = graph.add_op(Gather { embed_dim }).finish();
-            move_incoming_edge(equal, gather, &mut graph.graph);
-            graph.safe_remove_node(equal, 1);
-            move_incoming_edge(mul, gather, &mut graph.graph);
+            // The indexes are expanded over the rows of the mask, and the weights over the indexes
+            let (
+                Some((indexes, indexes_out, mut indexes_shape)),
+                Some((weights, weights_out, mut weights_shape)),
+            ) = (
+                graph
+                    .get_sources(equal)
+                    .into_iter()
+                    .find(|(s, _, _)| *s != arange),
+                graph
+                    .get_sources(mul)
+                    .into_iter()
+                    .find(|(s, _, _)| *s != equal),
+            )
+            else {
+                continue;
+            };
+            let is_fake = |st: &ShapeTracker, axis: usize| st.fake[st.indexes[axis]];
+            if graph.graph[sum_reduce].as_any().downcast_ref::<SumReduce>() != Some(&SumReduce(1))
+                || indexes_shape.len() != 2
+                || weights_shape.len() != 3
+                || !is_fake(&indexes_shape, 1)
+                || !is_fake(&weights_shape, 0)
+            {
+                continue;
+            }
+            indexes_shape.remove_dim(1);
+            weights_shape.remove_dim(0);
+            let Some(embed_dim) = weights_shape.shape()[1].to_usize() else {
+                continue;
+            };
+            let gather = graph
+                .add_op(Gather { embed_dim })
+                .input(indexes, indexes_out, indexes_shape)
+                .input(weights, weights_out, weights_shape)
+                .finish();
             move_outgoing_edge(sum_reduce, gather, &mut graph.graph);
+            move_references(
+                &mut remap,
+                &mut graph.no_delete,
+                &mut graph.to_retrieve,
+                sum_reduce,
+                gather,
+            );
             graph.graph.remove_node(sum_reduce);
             graph.safe_remove_node(mul, 0);
+            graph.safe_remove_node(equal, 0);
             graph.safe_remove_node(arange, 0);
         }
     }
@@ -7,9 +7,15 @@ use itertools::Itertools;
 use petgraph::{stable_graph::NodeIndex, visit::EdgeRef};
 
 use crate::{
-    op::{Exp2, InputTensor, Log2, Mul, Operator, Recip, Sin, SumReduce},
+    op::{
+        check_cpu_inputs, get_vec_from_tensor, inputs_are_f32, prepare_output,
+        process_with_new_output, try_process_with_new_output, Exp2, InputTensor, Log2, Mul, OpCost,
+        OpError, Operator, Recip, Sin, SumReduce,
+    },
     prelude::*,
+    shape::symbolic::Symbol,
 };
+use rustc_hash::FxHashMap;
 
 // Ops and compilers specific to CPU execution
 
@@ -20,8 +26,21 @@ pub type CPUCompiler = (
     other::ARangeCompiler,
     binary::GatherCompiler,
     UnaryFusionCompiler,
+    MemoryPlanner,
 );
 
+/// Plan the memory of the final graph, so [`Graph::execute_planned`] can reuse buffers between ops.
+/// This should run after every other compiler, since any change to the graph invalidates the plan.
+#[derive(Debug, Default)]
+pub struct MemoryPlanner;
+
+impl Compiler for MemoryPlanner {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, _: T) {
+        graph.toposort();
+        graph.memory_plan = Some(MemoryPlan::new(graph));
+    }
+}
+
 pub type MatMulCompiler = (MatMul2DCompiler, BatchMatMul2DCompiler);
 
 #[derive(Debug, Default)]
@@ -61,8 +80,8 @@ impl Compiler for MatMul2DCompiler {
             srcs[1].2.permute(&[1, 0]);
             let new_op = graph
                 .add_op(MatMul2D)
-                .input(srcs[0].0, 0, srcs[0].2)
-                .input(srcs[1].0, 0, srcs[1].2)
+                .input(srcs[0].0, 0, srcs[0].2.clone())
+                .input(srcs[1].0, 0, srcs[1].2.clone())
                 .finish();
 
             // Create edges to dests
@@ -94,6 +113,20 @@ pub struct MatMul2D;
 
 impl Operator for MatMul2D {
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &inp)
+    }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        try_process_with_new_output(self, &inp)
+    }
+
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        if !inputs_are_f32(inp) {
+            return false;
+        }
         let (a_shape, b_shape) = (inp[0].1.shape(), inp[1].1.shape());
         let (a_strides, b_strides) = (inp[0].1.strides(), inp[1].1.strides());
         let a_data = inp[0]
@@ -110,7 +143,11 @@ impl Operator for MatMul2D {
             .as_any()
             .downcast_ref::<Vec<f32>>()
             .unwrap();
-        let mut c = vec![0.; a_shape[0].to_usize().unwrap() * b_shape[1].to_usize().unwrap()];
+        let c = prepare_output(
+            out,
+            a_shape[0].to_usize().unwrap() * b_shape[1].to_usize().unwrap(),
+            0.,
+        );
         unsafe {
             matrixmultiply::sgemm(
                 a_shape[0].to_usize().unwrap(),
@@ -130,7 +167,20 @@ impl Operator for MatMul2D {
             );
         }
 
-        vec![Tensor::new(c)]
+        true
+    }
+
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        let (a_shape, b_shape) = (inp[0].shape(), inp[1].shape());
+        let (m, k, n) = (
+            a_shape[0].to_usize().unwrap(),
+            a_shape[1].to_usize().unwrap(),
+            b_shape[1].to_usize().unwrap(),
+        );
+        Some(OpCost {
+            flops: 2 * m * k * n,
+            output_elements: m * n,
+        })
     }
 }
 
@@ -172,8 +222,8 @@ impl Compiler for BatchMatMul2DCompiler {
             srcs[1].2.permute(&[1, 0]);
             let new_op = graph
                 .add_op(BatchedMatMul2D)
-                .input(srcs[0].0, 0, srcs[0].2)
-                .input(srcs[1].0, 0, srcs[1].2)
+                .input(srcs[0].0, 0, srcs[0].2.clone())
+                .input(srcs[1].0, 0, srcs[1].2.clone())
                 .finish();
 
             // Create edges to dests
@@ -206,6 +256,20 @@ pub struct BatchedMatMul2D;
 // ABCxCD -> ABD
 impl Operator for BatchedMatMul2D {
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &inp)
+    }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        try_process_with_new_output(self, &inp)
+    }
+
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        if !inputs_are_f32(inp) {
+            return false;
+        }
         let (a_shape, b_shape) = (inp[0].1.shape(), inp[1].1.shape());
         let (a_strides, b_strides) = (inp[0].1.strides(), inp[1].1.strides());
         let a_data = inp[0]
@@ -222,12 +286,13 @@ impl Operator for BatchedMatMul2D {
             .as_any()
             .downcast_ref::<Vec<f32>>()
             .unwrap();
-        let mut c = vec![
-            0.;
+        let c = prepare_output(
+            out,
             a_shape[0].to_usize().unwrap()
                 * a_shape[1].to_usize().unwrap()
-                * b_shape[1].to_usize().unwrap()
-        ];
+                * b_shape[1].to_usize().unwrap(),
+            0.,
+        );
 
         let mat_size = a_shape[1].to_usize().unwrap() * b_shape[1].to_usize().unwrap();
         for i in 0..a_shape[0].to_usize().unwrap() {
@@ -251,7 +316,21 @@ impl Operator for BatchedMatMul2D {
             }
         }
 
-        vec![Tensor::new(c)]
+        true
+    }
+
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        let (a_shape, b_shape) = (inp[0].shape(), inp[1].shape());
+        let (batch, m, k, n) = (
+            a_shape[0].to_usize().unwrap(),
+            a_shape[1].to_usize().unwrap(),
+            a_shape[2].to_usize().unwrap(),
+            b_shape[1].to_usize().unwrap(),
+        );
+        Some(OpCost {
+            flops: 2 * batch * m * k * n,
+            output_elements: batch * m * n,
+        })
     }
 }
 
@@ -261,15 +340,15 @@ pub struct UnaryFusionCompiler;
 
 impl Compiler for UnaryFusionCompiler {
     fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
-        fn is_unary(op: &dyn Any) -> Option<fn(f32) -> f32> {
+        fn is_unary(op: &dyn Any) -> Option<UnaryFn> {
             if op.is::<Exp2>() {
-                Some(|i| i.exp2())
+                unary_fn("Exp2")
             } else if op.is::<Log2>() {
-                Some(|i| i.log2())
+                unary_fn("Log2")
             } else if op.is::<Recip>() {
-                Some(|i| i.recip())
+                unary_fn("Recip")
             } else if op.is::<Sin>() {
-                Some(|i| i.sin())
+                unary_fn("Sin")
             } else {
                 None
             }
@@ -338,12 +417,39 @@ impl Compiler for UnaryFusionCompiler {
     }
 }
 
+/// A named unary function that can be fused
+type UnaryFn = (&'static str, fn(f32) -> f32);
+
+/// Look up a fusable unary function by the name of its op
+fn unary_fn(name: &str) -> Option<UnaryFn> {
+    match name {
+        "Exp2" => Some(("Exp2", |i| i.exp2())),
+        "Log2" => Some(("Log2", |i| i.log2())),
+        "Recip" => Some(("Recip", |i| i.recip())),
+        "Sin" => Some(("Sin", |i| i.sin())),
+        _ => None,
+    }
+}
+
 /// Multiple unary ops applied in sequence
-#[derive(Debug, Clone, PartialEq)]
-pub struct FusedUnary(Vec<fn(f32) -> f32>);
+#[derive(Clone, PartialEq)]
+pub struct FusedUnary(Vec<UnaryFn>);
+
+impl std::fmt::Debug for FusedUnary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "FusedUnary({})",
+            self.0.iter().map(|(n, _)| n).join(", ")
+        )
+    }
+}
 
 impl Operator for FusedUnary {
     fn process(&mut self, mut inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        if !inputs_are_f32(&inp) {
+            return process_with_new_output(self, &inp);
+        }
         let mut t = inp.pop().unwrap().0.cloned();
         for a in t
             .data
@@ -353,12 +459,98 @@ impl Operator for FusedUnary {
             .iter_mut()
         {
             for f in &self.0 {
-                *a = (f)(*a);
+                *a = (f.1)(*a);
             }
         }
 
         vec![t]
     }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        check_cpu_inputs(&inp)?;
+        Ok(self.process(inp))
+    }
+
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        if !inputs_are_f32(inp) {
+            return false;
+        }
+        let src = get_vec_from_tensor(&inp[0].0);
+        for (o, a) in prepare_output(out, src.len(), 0.).iter_mut().zip(src) {
+            *o = self.0.iter().fold(*a, |a, (_, f)| (f)(a));
+        }
+        true
+    }
+
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        let n = inp[0].n_physical_elements().to_usize().unwrap();
+        Some(OpCost {
+            flops: n * self.0.len(),
+            output_elements: n,
+        })
+    }
+}
+
+/// Register every CPU op with an IR registry
+pub(crate) fn register_cpu_ops(registry: &mut OpRegistry) {
+    registry
+        .register::<MatMul2D>()
+        .register::<BatchedMatMul2D>()
+        .register::<FusedUnary>()
+        .register::<binary::Sub>()
+        .register::<binary::Equal>()
+        .register::<other::ARange>();
+}
+
+/// Register every CPU compiler with a compiler registry
+pub(crate) fn register_cpu_compilers(registry: &mut CompilerRegistry) {
+    registry
+        .register_as::<CPUCompiler>("CPUCompiler")
+        .register_as::<MatMulCompiler>("MatMulCompiler")
+        .register::<MatMul2DCompiler>()
+        .register::<BatchMatMul2DCompiler>()
+        .register::<binary::SubtractionCompiler>()
+        .register::<binary::EqualCompiler>()
+        .register::<other::ARangeCompiler>()
+        .register::<binary::GatherCompiler>()
+        .register::<UnaryFusionCompiler>()
+        .register::<MemoryPlanner>();
+}
+
+impl SerializeOp for MatMul2D {
+    const NAME: &'static str = "MatMul2D";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(String::new())
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        args.is_empty().then_some(MatMul2D)
+    }
+}
+
+impl SerializeOp for BatchedMatMul2D {
+    const NAME: &'static str = "BatchedMatMul2D";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(String::new())
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        args.is_empty().then_some(BatchedMatMul2D)
+    }
+}
+
+impl SerializeOp for FusedUnary {
+    const NAME: &'static str = "FusedUnary";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(self.0.iter().map(|(n, _)| n).join(" "))
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        args.split(' ')
+            .map(unary_fn)
+            .collect::<Option<_>>()
+            .map(Self)
+    }
 }
 
 #[cfg(test)]
@@ -1,25 +1,55 @@
 use crate::{
     op::*,
     prelude::{petgraph::visit::EdgeRef, *},
-    shape::symbolic::BigExpression,
+    shape::symbolic::{BigExpression, Symbol},
 };
 use rustc_hash::FxHashMap;
 
 #[derive(LuminalPrint, Clone, LuminalEqFalse)]
 pub struct ARange {
     pub size: BigExpression,
-    dyn_map: *const FxHashMap<char, usize>,
+    dyn_map: *const FxHashMap<Symbol, usize>,
 }
 
+// SAFETY: `dyn_map` points to the owning graph's dyn map and is only read in `process_into`. The parallel executor
+// borrows the graph mutably for the whole run, so the map isn't written to while workers read it. See the impl for
+// `Constant`.
+unsafe impl Send for ARange {}
+
 impl Operator for ARange {
-    fn process(&mut self, _: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &inp)
+    }
+
+    fn process_into(&mut self, _: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
         let n_elements = self
             .size
             .exec(unsafe { self.dyn_map.as_ref().unwrap() })
             .unwrap();
-        vec![Tensor {
-            data: Box::new((0..n_elements).map(|i| i as f32).collect::<Vec<_>>()),
-        }]
+        for (i, o) in prepare_output(out, n_elements, 0.).iter_mut().enumerate() {
+            *o = i as f32;
+        }
+        true
+    }
+
+    fn cost(&self, _: &[ShapeTracker], dyn_map: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        self.size.exec(dyn_map).map(|n| OpCost {
+            flops: 0,
+            output_elements: n,
+        })
+    }
+}
+
+impl SerializeOp for ARange {
+    const NAME: &'static str = "ARange";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        write_expression(&self.size.terms)
+    }
+    fn deserialize_op(args: &str, graph: &mut Graph) -> Option<Self> {
+        Some(Self {
+            size: parse_expression(args)?,
+            dyn_map: &graph.dyn_map,
+        })
     }
 }
 
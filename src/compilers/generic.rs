@@ -12,20 +12,41 @@ use petgraph::{
 
 use crate::{
     op::{
-        Add, Constant, ConstantValue, Exp2, Function, Log2, MaxReduce, Mul, Operator, Recip,
-        SumReduce,
+        Add, Cast, Constant, ConstantTensor, ConstantValue, Contiguous, Exp2, Function, Gather,
+        InputTensor, LessThan, Log2, MaxReduce, Mod, Mul, Operator, Recip, Sin, Sqrt, SumReduce,
     },
     prelude::*,
 };
 
 /// Generic platform-agnostic optimizations. It's a good idea to use these all the time.
+///
+/// [`ConstantFolding`] leaves CPU-resident [`ConstantTensor`]s in the graph, so backends running after it need to
+/// copy them to their device, or move their compilers before it.
 pub type GenericCompiler = (
+    RemoveUnusedSlices,
     RemoveSingleReductions,
     ArithmeticElimination,
     UnarySequentialElimination,
+    AlgebraicSimplification,
+    ConstantFolding,
     CSE,
 );
 
+/// Drop slices that can't cut into their dimension given the declared ranges of dynamic dims, so views like
+/// `x.slice(..max_len)` count as contiguous in the compilers after this. See [`Graph::set_dyn_dim_range`].
+#[derive(Debug, Default)]
+pub struct RemoveUnusedSlices;
+
+impl Compiler for RemoveUnusedSlices {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, _: T) {
+        for edge in graph.graph.edge_weights_mut() {
+            if let Dependency::Data { shape, .. } = edge {
+                shape.remove_unused_slices(&graph.dyn_ranges);
+            }
+        }
+    }
+}
+
 /// Eliminate complementary unary sequential operations like `x.log().exp()`
 #[derive(Debug, Default)]
 pub struct UnarySequentialElimination;
@@ -52,6 +73,12 @@ impl Compiler for UnarySequentialElimination {
         {
             let mut searcher = selector_graph.search(graph);
             while searcher.next_match() {
+                // The reversed searches match the ops the other way around
+                let (first, last) = if graph.graph.contains_edge(first, last) {
+                    (first, last)
+                } else {
+                    (last, first)
+                };
                 if graph.no_delete.contains(&first)
                     || graph
                         .graph
@@ -201,10 +228,14 @@ impl Compiler for RemoveSingleReductions {
                         e.weight()
                             .as_data()
                             .map(|w| {
-                                w.2.dims[w.2.indexes[dim]]
-                                    .to_usize()
-                                    .map(|i| i == 1)
-                                    .unwrap_or_default()
+                                // Consumers don't see the input's slices or padding, so it can't be a view
+                                w.2.is_contiguous()
+                                    && !w.2.is_sliced()
+                                    && !w.2.is_padded()
+                                    && w.2.dims[w.2.indexes[dim]]
+                                        .to_usize()
+                                        .map(|i| i == 1)
+                                        .unwrap_or_default()
                             })
                             .unwrap_or_default()
                     })
@@ -365,9 +396,180 @@ fn is_from_set(node: NodeIndex, graph: &Graph, set: &HashSet<NodeIndex>) -> bool
     true
 }
 
+/// Lower [`Gather`] ops into the one-hot formulation of [`GraphTensor::gather_one_hot`], for backends without a
+/// gather kernel. Autograd only differentiates primops, so this also needs to run before it.
+///
+/// Indexes are compared as i64, so they stay exact whatever type they're stored in.
+#[derive(Debug, Default)]
+pub struct GatherToOneHot;
+
+impl Compiler for GatherToOneHot {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
+        let graph_ref: *mut Graph = graph;
+        for node in graph.graph.node_indices().collect::<Vec<_>>() {
+            if !graph.graph[node].as_any().is::<Gather>() {
+                continue;
+            }
+            let [(indexes, 0, ref indexes_shape), (weights, 0, ref weights_shape)] =
+                graph.get_sources(node)[..]
+            else {
+                continue;
+            };
+            let indexes =
+                GraphTensor::<(Dyn<'-'>,)>::from_id(indexes, indexes_shape.clone(), graph_ref);
+            let weights = GraphTensor::<(Dyn<'-'>, Dyn<'-'>)>::from_id(
+                weights,
+                weights_shape.clone(),
+                graph_ref,
+            );
+            let gathered = weights.gather_one_hot(indexes.cast(DType::I64));
+            move_outgoing_edge(node, gathered.id, &mut graph.graph);
+            move_references(
+                &mut remap,
+                &mut graph.no_delete,
+                &mut graph.to_retrieve,
+                node,
+                gathered.id,
+            );
+            graph.graph.remove_node(node);
+        }
+    }
+}
+
+/// Evaluate subgraphs that only depend on constants at compile time, replacing them with [`ConstantTensor`]s.
+///
+/// Only primops are evaluated, and anything touching a dyn dim that isn't known yet is left alone.
+///
+/// The folded values are computed on the CPU and stay there: a [`ConstantTensor`] outputs a CPU [`Tensor`] like a
+/// `Function` load does, so non-CPU backends have to treat it as an input to copy to the device.
+#[derive(Debug, Default)]
+pub struct ConstantFolding;
+
+impl Compiler for ConstantFolding {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
+        let foldable = [
+            TypeId::of::<Log2>(),
+            TypeId::of::<Exp2>(),
+            TypeId::of::<Sin>(),
+            TypeId::of::<Sqrt>(),
+            TypeId::of::<Recip>(),
+            TypeId::of::<Add>(),
+            TypeId::of::<Mul>(),
+            TypeId::of::<Mod>(),
+            TypeId::of::<LessThan>(),
+            TypeId::of::<Contiguous>(),
+            TypeId::of::<Cast>(),
+            TypeId::of::<Gather>(),
+            TypeId::of::<SumReduce>(),
+            TypeId::of::<MaxReduce>(),
+        ];
+        let mut values: HashMap<NodeIndex, Vec<Tensor>> = HashMap::new();
+        for node in petgraph::algo::toposort(&graph.graph, None).unwrap() {
+            let op = graph.graph.node_weight(node).unwrap();
+            let sources = graph.get_sources(node);
+            let evaluate = if let Some(Constant(value, _)) = op.as_any().downcast_ref() {
+                match value {
+                    ConstantValue::Float(_) => true,
+                    ConstantValue::Expression(e) => e.to_symbols().is_empty(),
+                }
+            } else {
+                op.as_any().is::<ConstantTensor>()
+                    || (foldable.contains(&op.as_any().type_id())
+                        && !sources.is_empty()
+                        && sources.iter().all(|(n, o, sh)| {
+                            values
+                                .get(n)
+                                .map(|v| v.len() > *o as usize)
+                                .unwrap_or_default()
+                                && sh.symbols().is_empty()
+                        }))
+            };
+            if !evaluate {
+                continue;
+            }
+            let inputs = sources
+                .iter()
+                .map(|(n, o, sh)| (InputTensor::Borrowed(&values[n][*o as usize]), sh.clone()))
+                .collect();
+            let outputs = graph.graph.node_weight_mut(node).unwrap().process(inputs);
+            values.insert(node, outputs);
+        }
+
+        // Replace the evaluated nodes that are still needed by the rest of the graph
+        for &node in values.keys() {
+            let op = graph.graph.node_weight(node).unwrap().as_any();
+            if op.is::<Constant>() || op.is::<ConstantTensor>() {
+                continue;
+            }
+            if !graph.no_delete.contains(&node)
+                && !graph.to_retrieve.contains(&node)
+                && graph
+                    .graph
+                    .neighbors_directed(node, Direction::Outgoing)
+                    .all(|n| values.contains_key(&n))
+            {
+                continue;
+            }
+            let constant = graph
+                .add_op(ConstantTensor(values[&node][0].clone()))
+                .finish();
+            move_outgoing_edge(node, constant, &mut graph.graph);
+            move_references(
+                &mut remap,
+                &mut graph.no_delete,
+                &mut graph.to_retrieve,
+                node,
+                constant,
+            );
+        }
+
+        // Remove what was folded away, along with any constants no longer used
+        for &node in values.keys() {
+            let op = graph.graph.node_weight(node).unwrap().as_any();
+            if !op.is::<Constant>() && !op.is::<ConstantTensor>() {
+                graph.graph.remove_node(node);
+            }
+        }
+        for &node in values.keys() {
+            if graph.graph.contains_node(node)
+                && !graph.no_delete.contains(&node)
+                && graph
+                    .graph
+                    .edges_directed(node, Direction::Outgoing)
+                    .next()
+                    .is_none()
+            {
+                graph.graph.remove_node(node);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::prelude::*;
+    use crate::{
+        op::ConstantTensor,
+        prelude::{symbolic::Expression, *},
+        tests::assert_exact,
+    };
+    #[test]
+    fn test_remove_unused_slices() {
+        for declared in [true, false] {
+            let mut cx = Graph::new();
+            if declared {
+                cx.set_dyn_dim_range('s', 1..=16);
+            }
+            let a = cx.tensor::<(Dyn<'s'>, Const<2>)>();
+            let mut b = (a.slice((..Expression::from(16), ..)) * 1.).retrieve();
+            cx.compile(GenericCompiler::default(), &mut b);
+            // If `s` is at most 16 the slice never cuts, so the multiply is left with a contiguous view
+            assert_eq!(cx.graph.node_count() == 1, declared);
+            a.set_dyn(vec![1., 2., 3., 4.], &[2, 2]);
+            cx.execute();
+            assert_exact(&b.data(), &[1., 2., 3., 4.]);
+        }
+    }
+
     #[test]
     fn test_log_exp() {
         let mut cx = Graph::new();
@@ -377,12 +579,102 @@ mod tests {
         cx.compile(GenericCompiler::default(), ());
         assert_eq!(cx.graph.node_count(), 1);
     }
+
+    #[test]
+    fn test_exp_log_consumed() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<2>>().set(vec![1., 2.]);
+        let mut b = (a.exp2().log2() * 2.).retrieve();
+
+        cx.compile(UnarySequentialElimination, &mut b);
+        cx.execute();
+        assert_exact(&b.data(), &[2., 4.]);
+    }
+
+    #[test]
+    fn test_single_reduction_of_view() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R2<1, 2>>().set(vec![1., 2.]);
+        // The reduced dim is size 1 before slicing, but empty after
+        let mut b = a
+            .slice((Expression::from(1)..Expression::from(1), ..))
+            .realize::<R2<0, 2>>()
+            .sum_reduce::<_, Axis<0>>()
+            .retrieve();
+
+        cx.compile(RemoveSingleReductions, &mut b);
+        cx.execute();
+        assert_exact(&b.data(), &[0., 0.]);
+    }
+
+    #[test]
+    fn test_kept_arithmetic_on_view() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<2>>().set(vec![1., 2.]);
+        let mut b = (a.pad::<R1<4>, _, _>(&[(1, 1)]) * 1.).retrieve();
+
+        cx.compile(ArithmeticElimination, &mut b);
+        cx.execute();
+        assert_exact(&b.data(), &[0., 1., 2., 0.]);
+    }
+
+    #[test]
+    fn test_gather_to_one_hot() {
+        let mut cx = Graph::new();
+        let weights = cx.tensor::<R2<3, 2>>().set(vec![1., 2., 3., 4., 5., 6.]);
+        let indexes = cx.tensor::<R1<3>>().set(vec![2i32, 2, 0]);
+        let mut out = weights.gather(indexes).retrieve();
+
+        cx.compile(GatherToOneHot, &mut out);
+        assert!(!cx
+            .graph
+            .node_indices()
+            .any(|n| cx.graph[n].as_any().is::<crate::op::Gather>()));
+        cx.execute();
+
+        assert_eq!(out.data(), vec![5., 6., 5., 6., 1., 2.]);
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R2<3, 3>>().set(vec![1.; 9]);
+        let mut b = (a * cx.tril::<Const<3>>(0)).retrieve();
+        let mut c = (cx.arange::<Const<3>>() * 2.).exp2().retrieve();
+
+        cx.compile(ConstantFolding, (&mut b, &mut c));
+        // The tril mask and c are precomputed
+        assert_eq!(cx.graph.node_count(), 4);
+        assert!(cx.graph[c.id].as_any().is::<ConstantTensor>());
+        cx.execute();
+
+        assert_eq!(b.data(), vec![1., 0., 0., 1., 1., 0., 1., 1., 1.]);
+        assert_eq!(c.data(), vec![1., 4., 16.]);
+    }
+
+    #[test]
+    fn test_constant_folding_skips_unknown() {
+        let mut cx = Graph::new();
+        let mut a = cx.arange::<Dyn<'s'>>().retrieve();
+        let mut b = (cx.rand::<R1<3>>() + 1.).retrieve();
+        let nodes = cx.graph.node_count();
+
+        cx.compile(ConstantFolding, (&mut a, &mut b));
+        // Dyn dims aren't known until execution, and random values change every run
+        assert_eq!(cx.graph.node_count(), nodes);
+        cx.set_dyn_dim('s', 4);
+        cx.execute();
+
+        assert_eq!(a.data(), vec![0., 1., 2., 3.]);
+        assert!(b.data().iter().all(|v| (1. ..2.).contains(v)));
+    }
 }
 
 /// **Reduces arithmetic expressions**
 ///
-/// - Current: x + 0 => x, x * 1 => x
-/// - TODO: x / x => 1, x - x => 0, x * 0 => 0, x - 0 => x, x * 0 => 0, 0 / x => 0
+/// - x + 0 => x, x * 1 => x, keeping the op if it makes a non-contiguous input contiguous
+///
+/// Everything else (x / x => 1, x - x => 0, x * 0 => 0, ...) is handled by [`AlgebraicSimplification`]
 #[derive(Debug, Default)]
 pub struct ArithmeticElimination;
 
@@ -435,28 +727,32 @@ impl Compiler for ArithmeticElimination {
                 .2;
             if !input_shape.is_contiguous() || input_shape.is_padded() || input_shape.is_sliced() {
                 // If any output shape is non-contiguous, we need to keep the op for it's contiguous functionality TODO: replace with explicit contiguous op here
-                if graph
-                    .graph
-                    .edges_directed(add, Direction::Outgoing)
-                    .filter_map(|e| e.weight().as_data())
-                    .any(|(_, _, sh)| !sh.is_contiguous() || sh.is_padded() || sh.is_sliced())
+                // Kept tensors are read back as contiguous too
+                if graph.no_delete.contains(&add)
+                    || graph
+                        .graph
+                        .edges_directed(add, Direction::Outgoing)
+                        .filter_map(|e| e.weight().as_data())
+                        .any(|(_, _, sh)| !sh.is_contiguous() || sh.is_padded() || sh.is_sliced())
                 {
                     continue;
                 }
                 for (weight, target) in graph
                     .graph
                     .edges_directed(add, petgraph::Direction::Outgoing)
-                    .map(|e| (*e.weight(), e.target()))
+                    .map(|e| (e.weight().clone(), e.target()))
                     .collect::<Vec<_>>()
                 {
                     if let Some(weight) = weight.as_data() {
+                        let dtype = graph.dtype(x);
                         graph.graph.add_edge(
                             x,
                             target,
                             Dependency::Data {
                                 input_order: weight.0,
                                 output_order: weight.1,
-                                shape: input_shape,
+                                shape: input_shape.clone(),
+                                dtype,
                             },
                         );
                     }
@@ -528,28 +824,32 @@ impl Compiler for ArithmeticElimination {
                 .2;
             if !input_shape.is_contiguous() || input_shape.is_padded() || input_shape.is_sliced() {
                 // If any output shape is non-contiguous, we need to keep the op for it's contiguous functionality TODO: replace with explicit contiguous op here
-                if graph
-                    .graph
-                    .edges_directed(mul, Direction::Outgoing)
-                    .filter_map(|e| e.weight().as_data())
-                    .any(|(_, _, sh)| !sh.is_contiguous() || sh.is_padded() || sh.is_sliced())
+                // Kept tensors are read back as contiguous too
+                if graph.no_delete.contains(&mul)
+                    || graph
+                        .graph
+                        .edges_directed(mul, Direction::Outgoing)
+                        .filter_map(|e| e.weight().as_data())
+                        .any(|(_, _, sh)| !sh.is_contiguous() || sh.is_padded() || sh.is_sliced())
                 {
                     continue;
                 }
                 for (weight, target) in graph
                     .graph
                     .edges_directed(mul, petgraph::Direction::Outgoing)
-                    .map(|e| (*e.weight(), e.target()))
+                    .map(|e| (e.weight().clone(), e.target()))
                     .collect::<Vec<_>>()
                 {
                     if let Some(weight) = weight.as_data() {
+                        let dtype = graph.dtype(a);
                         graph.graph.add_edge(
                             a,
                             target,
                             Dependency::Data {
                                 input_order: weight.0,
                                 output_order: weight.1,
-                                shape: input_shape,
+                                shape: input_shape.clone(),
+                                dtype,
                             },
                         );
                     }
@@ -0,0 +1,445 @@
+//! A small e-graph for equality saturation over elementwise expressions
+
+use std::str::FromStr;
+
+use rustc_hash::FxHashMap;
+
+/// The id of an equivalence class
+pub type Id = usize;
+
+/// The elementwise primops an expression is built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Op {
+    Add,
+    Mul,
+    Mod,
+    LessThan,
+    Log2,
+    Exp2,
+    Sin,
+    Sqrt,
+    Recip,
+}
+
+impl Op {
+    const ALL: [Op; 9] = [
+        Op::Add,
+        Op::Mul,
+        Op::Mod,
+        Op::LessThan,
+        Op::Log2,
+        Op::Exp2,
+        Op::Sin,
+        Op::Sqrt,
+        Op::Recip,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Mul => "*",
+            Op::Mod => "%",
+            Op::LessThan => "<",
+            Op::Log2 => "log2",
+            Op::Exp2 => "exp2",
+            Op::Sin => "sin",
+            Op::Sqrt => "sqrt",
+            Op::Recip => "recip",
+        }
+    }
+
+    pub fn is_unary(&self) -> bool {
+        !matches!(self, Op::Add | Op::Mul | Op::Mod | Op::LessThan)
+    }
+
+    /// Evaluate the op on constants. Unary ops ignore `b`
+    fn eval(&self, a: f32, b: f32) -> f32 {
+        match self {
+            Op::Add => a + b,
+            Op::Mul => a * b,
+            Op::Mod => a % b,
+            Op::LessThan => (a < b) as u8 as f32,
+            Op::Log2 => a.log2(),
+            Op::Exp2 => a.exp2(),
+            Op::Sin => a.sin(),
+            Op::Sqrt => a.sqrt(),
+            Op::Recip => a.recip(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ENode {
+    /// An input to the expression. What it refers to is up to the caller
+    Leaf(usize),
+    /// A constant, stored as its bits so nodes can be hashed
+    Const(u32),
+    Unary(Op, Id),
+    Binary(Op, Id, Id),
+}
+
+impl ENode {
+    pub fn children(&self) -> Vec<Id> {
+        match self {
+            ENode::Leaf(_) | ENode::Const(_) => vec![],
+            ENode::Unary(_, a) => vec![*a],
+            ENode::Binary(_, a, b) => vec![*a, *b],
+        }
+    }
+}
+
+/// An expression with `?` variables, written as an s-expression like `(* ?a (recip ?a))`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Var(usize),
+    Const(f32),
+    Unary(Op, Box<Pattern>),
+    Binary(Op, Box<Pattern>, Box<Pattern>),
+}
+
+/// The variables bound by a match, indexed by letter (`?a` is 0)
+type Subst = [Option<Id>; 4];
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = s.replace('(', " ( ").replace(')', " ) ");
+        let mut tokens = tokens.split_whitespace();
+        let pattern = parse_pattern(&mut tokens)?;
+        match tokens.next() {
+            Some(t) => Err(format!("Unexpected {t}")),
+            None => Ok(pattern),
+        }
+    }
+}
+
+fn parse_pattern<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Result<Pattern, String> {
+    match tokens.next().ok_or("Unexpected end of pattern")? {
+        "(" => {
+            let name = tokens.next().ok_or("Expected an op")?;
+            let op = Op::ALL
+                .into_iter()
+                .find(|o| o.name() == name)
+                .ok_or(format!("Unknown op {name}"))?;
+            let a = Box::new(parse_pattern(tokens)?);
+            let pattern = if op.is_unary() {
+                Pattern::Unary(op, a)
+            } else {
+                Pattern::Binary(op, a, Box::new(parse_pattern(tokens)?))
+            };
+            match tokens.next() {
+                Some(")") => Ok(pattern),
+                _ => Err(format!("Expected ) after {name}")),
+            }
+        }
+        t if t.starts_with('?') => match t.as_bytes() {
+            [_, v @ b'a'..=b'd'] => Ok(Pattern::Var((v - b'a') as usize)),
+            _ => Err(format!("Bad variable {t}")),
+        },
+        t => t
+            .parse()
+            .map(Pattern::Const)
+            .map_err(|_| format!("Bad constant {t}")),
+    }
+}
+
+/// Anything matching `lhs` is equal to `rhs`
+#[derive(Debug, Clone)]
+pub struct Rewrite {
+    lhs: Pattern,
+    rhs: Pattern,
+}
+
+impl Rewrite {
+    /// Panics if either pattern doesn't parse
+    pub fn new(lhs: &str, rhs: &str) -> Self {
+        Self {
+            lhs: lhs.parse().unwrap(),
+            rhs: rhs.parse().unwrap(),
+        }
+    }
+}
+
+/// Equivalence classes of expressions, with the constant value of each class tracked as it's merged
+#[derive(Debug, Default)]
+pub struct EGraph {
+    parents: Vec<Id>,
+    /// The nodes of each class. Only canonical ids have any
+    nodes: Vec<Vec<ENode>>,
+    constants: Vec<Option<f32>>,
+    memo: FxHashMap<ENode, Id>,
+}
+
+impl EGraph {
+    pub fn find(&self, mut id: Id) -> Id {
+        while self.parents[id] != id {
+            id = self.parents[id];
+        }
+        id
+    }
+
+    pub fn n_nodes(&self) -> usize {
+        self.memo.len()
+    }
+
+    /// The canonical class ids
+    pub fn classes(&self) -> impl Iterator<Item = Id> + '_ {
+        (0..self.parents.len()).filter(|i| self.parents[*i] == *i)
+    }
+
+    pub fn nodes(&self, id: Id) -> &[ENode] {
+        &self.nodes[self.find(id)]
+    }
+
+    pub fn constant(&self, id: Id) -> Option<f32> {
+        self.constants[self.find(id)]
+    }
+
+    fn canonicalize(&self, node: ENode) -> ENode {
+        match node {
+            ENode::Unary(op, a) => ENode::Unary(op, self.find(a)),
+            ENode::Binary(op, a, b) => ENode::Binary(op, self.find(a), self.find(b)),
+            n => n,
+        }
+    }
+
+    /// The value of a node, if all its inputs are constant
+    fn fold(&self, node: &ENode) -> Option<f32> {
+        match node {
+            ENode::Leaf(_) => None,
+            ENode::Const(c) => Some(f32::from_bits(*c)),
+            ENode::Unary(op, a) => Some(op.eval(self.constant(*a)?, 0.)),
+            ENode::Binary(op, a, b) => Some(op.eval(self.constant(*a)?, self.constant(*b)?)),
+        }
+    }
+
+    pub fn add(&mut self, node: ENode) -> Id {
+        let node = self.canonicalize(node);
+        if let Some(id) = self.memo.get(&node) {
+            return self.find(*id);
+        }
+        let id = self.parents.len();
+        let constant = self.fold(&node);
+        self.parents.push(id);
+        self.nodes.push(vec![node]);
+        self.constants.push(constant);
+        self.memo.insert(node, id);
+        if let (Some(c), false) = (constant, matches!(node, ENode::Const(_))) {
+            let c = self.add(ENode::Const(c.to_bits()));
+            self.union(id, c);
+        }
+        self.find(id)
+    }
+
+    /// Merge two classes, returning false if they were already the same. Call [`EGraph::rebuild`] afterwards.
+    pub fn union(&mut self, a: Id, b: Id) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        if self.nodes[a].len() < self.nodes[b].len() {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b] = a;
+        let nodes = std::mem::take(&mut self.nodes[b]);
+        self.nodes[a].extend(nodes);
+        self.constants[a] = self.constants[a].or(self.constants[b]);
+        true
+    }
+
+    /// Restore the invariants broken by unions: nodes point at canonical classes, equal nodes share a class, and
+    /// every constant class holds its constant
+    pub fn rebuild(&mut self) {
+        loop {
+            self.memo.clear();
+            let mut merges = vec![];
+            let mut folded = vec![];
+            for id in self.classes().collect::<Vec<_>>() {
+                let mut nodes = std::mem::take(&mut self.nodes[id]);
+                for node in nodes.iter_mut() {
+                    *node = self.canonicalize(*node);
+                }
+                nodes.sort_unstable();
+                nodes.dedup();
+                for node in &nodes {
+                    if let Some(other) = self.memo.insert(*node, id) {
+                        merges.push((other, id));
+                    }
+                    if self.constants[id].is_none() {
+                        if let Some(c) = self.fold(node) {
+                            self.constants[id] = Some(c);
+                        }
+                    }
+                }
+                if let Some(c) = self.constants[id] {
+                    if !nodes.iter().any(|n| matches!(n, ENode::Const(_))) {
+                        folded.push((id, c));
+                    }
+                }
+                self.nodes[id] = nodes;
+            }
+            if merges.is_empty() && folded.is_empty() {
+                return;
+            }
+            for (a, b) in merges {
+                self.union(a, b);
+            }
+            for (id, c) in folded {
+                let c = self.add(ENode::Const(c.to_bits()));
+                self.union(id, c);
+            }
+        }
+    }
+
+    fn ematch(&self, pattern: &Pattern, id: Id, subst: Subst, matches: &mut Vec<Subst>) {
+        let id = self.find(id);
+        match pattern {
+            Pattern::Var(v) => match subst[*v] {
+                Some(bound) if self.find(bound) != id => {}
+                _ => {
+                    let mut subst = subst;
+                    subst[*v] = Some(id);
+                    matches.push(subst);
+                }
+            },
+            Pattern::Const(c) => {
+                if self.constants[id] == Some(*c) {
+                    matches.push(subst);
+                }
+            }
+            Pattern::Unary(op, a) => {
+                for node in &self.nodes[id] {
+                    if let ENode::Unary(o, x) = node {
+                        if o == op {
+                            self.ematch(a, *x, subst, matches);
+                        }
+                    }
+                }
+            }
+            Pattern::Binary(op, a, b) => {
+                for node in &self.nodes[id] {
+                    if let ENode::Binary(o, x, y) = node {
+                        if o == op {
+                            let mut partial = vec![];
+                            self.ematch(a, *x, subst, &mut partial);
+                            for subst in partial {
+                                self.ematch(b, *y, subst, matches);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn instantiate(&mut self, pattern: &Pattern, subst: &Subst) -> Id {
+        match pattern {
+            Pattern::Var(v) => subst[*v].expect("Unbound variable in rewrite"),
+            Pattern::Const(c) => self.add(ENode::Const(c.to_bits())),
+            Pattern::Unary(op, a) => {
+                let a = self.instantiate(a, subst);
+                self.add(ENode::Unary(*op, a))
+            }
+            Pattern::Binary(op, a, b) => {
+                let (a, b) = (self.instantiate(a, subst), self.instantiate(b, subst));
+                self.add(ENode::Binary(*op, a, b))
+            }
+        }
+    }
+
+    /// Apply the rewrites until nothing changes, `iterations` rounds have run or the graph has more than
+    /// `node_limit` nodes
+    pub fn saturate(&mut self, rewrites: &[Rewrite], iterations: usize, node_limit: usize) {
+        for _ in 0..iterations {
+            let mut matches = vec![];
+            for id in self.classes() {
+                for rewrite in rewrites {
+                    let mut substs = vec![];
+                    self.ematch(&rewrite.lhs, id, [None; 4], &mut substs);
+                    matches.extend(substs.into_iter().map(|s| (id, &rewrite.rhs, s)));
+                }
+            }
+            let mut changed = false;
+            for (id, rhs, subst) in matches {
+                let new = self.instantiate(rhs, &subst);
+                changed |= self.union(id, new);
+                if self.n_nodes() > node_limit {
+                    break;
+                }
+            }
+            self.rebuild();
+            if !changed || self.n_nodes() > node_limit {
+                return;
+            }
+        }
+    }
+
+    /// Pick the cheapest node of every class, by the total cost of the expression tree under it
+    pub fn extract(&self, cost: impl Fn(&ENode) -> usize) -> FxHashMap<Id, (usize, ENode)> {
+        let mut best = FxHashMap::<Id, (usize, ENode)>::default();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in self.classes() {
+                for node in &self.nodes[id] {
+                    let Some(children) = node
+                        .children()
+                        .into_iter()
+                        .map(|c| best.get(&self.find(c)).map(|(c, _)| *c))
+                        .sum::<Option<usize>>()
+                    else {
+                        continue;
+                    };
+                    let total = cost(node).saturating_add(children);
+                    if best.get(&id).map(|(c, _)| total < *c).unwrap_or(true) {
+                        best.insert(id, (total, *node));
+                        changed = true;
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saturate() {
+        let mut egraph = EGraph::default();
+        let x = egraph.add(ENode::Leaf(0));
+        let two = egraph.add(ENode::Const(2f32.to_bits()));
+        let three = egraph.add(ENode::Const(3f32.to_bits()));
+        // (x * 2) * 3
+        let a = egraph.add(ENode::Binary(Op::Mul, x, two));
+        let b = egraph.add(ENode::Binary(Op::Mul, a, three));
+        egraph.saturate(
+            &[
+                Rewrite::new("(* (* ?a ?b) ?c)", "(* ?a (* ?b ?c))"),
+                Rewrite::new("(* ?a 1)", "?a"),
+            ],
+            4,
+            100,
+        );
+        let best = egraph.extract(|n| match n {
+            ENode::Leaf(_) | ENode::Const(_) => 0,
+            _ => 1,
+        });
+        let six = egraph.add(ENode::Const(6f32.to_bits()));
+        assert_eq!(
+            best[&egraph.find(b)],
+            (1, ENode::Binary(Op::Mul, egraph.find(x), six))
+        );
+        assert_eq!(egraph.constant(six), Some(6.));
+
+        assert!("(* ?a".parse::<Pattern>().is_err());
+        assert!("(pow ?a 2)".parse::<Pattern>().is_err());
+        assert_eq!(
+            "(recip -1.5)".parse::<Pattern>(),
+            Ok(Pattern::Unary(Op::Recip, Box::new(Pattern::Const(-1.5))))
+        );
+    }
+}
@@ -0,0 +1,649 @@
+mod egraph;
+
+use petgraph::{
+    stable_graph::NodeIndex,
+    unionfind::UnionFind,
+    visit::{EdgeRef, NodeIndexable},
+    Direction,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    op::{self, Constant, ConstantValue, Operator},
+    prelude::*,
+};
+
+use egraph::{EGraph, ENode, Id, Op, Rewrite};
+
+/// Rounds of rewriting to run on each subgraph
+const ITERATIONS: usize = 8;
+/// Stop rewriting a subgraph once its e-graph holds this many nodes. Commutativity and associativity grow it quickly
+const NODE_LIMIT: usize = 2_000;
+
+/// **Simplifies elementwise arithmetic by [equality saturation](https://egraphs-good.github.io/)**
+///
+/// Each connected subgraph of elementwise primops is loaded into an e-graph, rewritten with the rules below until nothing
+/// changes, and replaced by the cheapest equivalent expression if that's cheaper than the original. Constants are
+/// folded along the way, so constants broadcast into a chain of adds or muls collapse into one.
+///
+/// Only ops that are (or may be) f32 are rewritten, since the constants this introduces are f32 and some of the
+/// identities, like `x / x => 1`, don't hold for integers. Like [`UnarySequentialElimination`], identities that only
+/// hold on part of the domain (`exp2(log2(x)) => x`) are applied anyway.
+#[derive(Debug, Default)]
+pub struct AlgebraicSimplification;
+
+/// Subtraction is `a + b * -1` and division is `a * recip(b)`
+fn rules() -> Vec<Rewrite> {
+    vec![
+        Rewrite::new("(+ ?a ?b)", "(+ ?b ?a)"),
+        Rewrite::new("(* ?a ?b)", "(* ?b ?a)"),
+        Rewrite::new("(+ (+ ?a ?b) ?c)", "(+ ?a (+ ?b ?c))"),
+        Rewrite::new("(* (* ?a ?b) ?c)", "(* ?a (* ?b ?c))"),
+        Rewrite::new("(+ ?a 0)", "?a"),
+        Rewrite::new("(* ?a 1)", "?a"),
+        Rewrite::new("(* ?a 0)", "0"),
+        Rewrite::new("(+ ?a (* ?a -1))", "0"),
+        Rewrite::new("(* ?a (recip ?a))", "1"),
+        Rewrite::new("(< ?a ?a)", "0"),
+        Rewrite::new("(* (+ ?a ?b) ?c)", "(+ (* ?a ?c) (* ?b ?c))"),
+        Rewrite::new("(+ (* ?a ?b) (* ?a ?c))", "(* ?a (+ ?b ?c))"),
+        Rewrite::new("(+ (* ?a ?b) ?a)", "(* ?a (+ ?b 1))"),
+        Rewrite::new("(+ ?a ?a)", "(* ?a 2)"),
+        Rewrite::new("(recip (recip ?a))", "?a"),
+        Rewrite::new("(* (recip ?a) (recip ?b))", "(recip (* ?a ?b))"),
+        Rewrite::new("(exp2 (log2 ?a))", "?a"),
+        Rewrite::new("(log2 (exp2 ?a))", "?a"),
+        Rewrite::new("(* (exp2 ?a) (exp2 ?b))", "(exp2 (+ ?a ?b))"),
+        Rewrite::new("(+ (log2 ?a) (log2 ?b))", "(log2 (* ?a ?b))"),
+        Rewrite::new("(* (sqrt ?a) (sqrt ?a))", "?a"),
+    ]
+}
+
+/// The cost model used to pick between equivalent expressions. Transcendental ops cost more than arithmetic
+fn op_cost(op: Op) -> usize {
+    match op {
+        Op::Recip | Op::Sqrt => 2,
+        Op::Exp2 | Op::Log2 | Op::Sin => 4,
+        Op::Add | Op::Mul | Op::Mod | Op::LessThan => 1,
+    }
+}
+
+fn cost(node: &ENode) -> usize {
+    match node {
+        ENode::Leaf(_) | ENode::Const(_) => 0,
+        ENode::Unary(op, _) | ENode::Binary(op, _, _) => op_cost(*op),
+    }
+}
+
+fn elementwise_op(op: &dyn Operator) -> Option<Op> {
+    let op = op.as_any();
+    [
+        (op.is::<op::Add>(), Op::Add),
+        (op.is::<op::Mul>(), Op::Mul),
+        (op.is::<op::Mod>(), Op::Mod),
+        (op.is::<op::LessThan>(), Op::LessThan),
+        (op.is::<op::Log2>(), Op::Log2),
+        (op.is::<op::Exp2>(), Op::Exp2),
+        (op.is::<op::Sin>(), Op::Sin),
+        (op.is::<op::Sqrt>(), Op::Sqrt),
+        (op.is::<op::Recip>(), Op::Recip),
+    ]
+    .into_iter()
+    .find_map(|(is, o)| is.then_some(o))
+}
+
+/// Does the op index its inputs through their shape trackers, rather than working on the physical buffer?
+fn reads_through_view(op: &dyn Operator) -> bool {
+    elementwise_op(op).is_some_and(|o| !o.is_unary())
+        || op.as_any().is::<op::Contiguous>()
+        || op.as_any().is::<op::SumReduce>()
+        || op.as_any().is::<op::MaxReduce>()
+}
+
+fn operator(op: Op) -> Box<dyn Operator> {
+    match op {
+        Op::Add => Box::new(op::Add),
+        Op::Mul => Box::new(op::Mul),
+        Op::Mod => Box::new(op::Mod),
+        Op::LessThan => Box::new(op::LessThan),
+        Op::Log2 => Box::new(op::Log2),
+        Op::Exp2 => Box::new(op::Exp2),
+        Op::Sin => Box::new(op::Sin),
+        Op::Sqrt => Box::new(op::Sqrt),
+        Op::Recip => Box::new(op::Recip),
+    }
+}
+
+/// Does reading through this tracker give back the buffer as is?
+fn is_identity(shape: &ShapeTracker) -> bool {
+    shape.is_contiguous() && !shape.is_sliced() && !shape.is_padded()
+}
+
+/// An input to a subgraph, read through a shape tracker. Reads through identity trackers are stored without one, so
+/// they compare equal.
+#[derive(Debug, Clone, PartialEq)]
+struct Leaf {
+    node: NodeIndex,
+    output: u8,
+    view: Option<ShapeTracker>,
+    shape: ShapeTracker,
+}
+
+/// An expression that's been added back to the graph
+#[derive(Debug, Clone, Copy)]
+enum Emitted {
+    /// A contiguous buffer of the subgraph's size
+    Node(NodeIndex),
+    Leaf(usize),
+    Const(f32),
+}
+
+impl Compiler for AlgebraicSimplification {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
+        let Ok(order) = petgraph::algo::toposort(&graph.graph, None) else {
+            return;
+        };
+        let candidates = order
+            .iter()
+            .copied()
+            .filter(|n| {
+                let Some(op) = elementwise_op(graph.graph[*n].as_ref()) else {
+                    return false;
+                };
+                let sources = graph.get_sources(*n);
+                sources.len() == if op.is_unary() { 1 } else { 2 }
+                    // Unary ops work on the physical buffer, ignoring the tracker
+                    && (!op.is_unary() || is_identity(&sources[0].2))
+                    && graph.dtype(*n) == DType::F32
+                    && !graph
+                        .graph
+                        .edges_directed(*n, Direction::Incoming)
+                        .chain(graph.graph.edges_directed(*n, Direction::Outgoing))
+                        .any(|e| e.weight().is_schedule())
+            })
+            .collect::<FxHashSet<_>>();
+
+        // Split the candidates into subgraphs connected by identity reads
+        let mut components = UnionFind::new(graph.graph.node_bound());
+        for node in &candidates {
+            for (src, _, shape) in graph.get_sources(*node) {
+                if candidates.contains(&src) && is_identity(&shape) {
+                    components.union(src.index(), node.index());
+                }
+            }
+        }
+        let mut subgraphs = FxHashMap::<usize, Vec<NodeIndex>>::default();
+        for node in order.iter().filter(|n| candidates.contains(n)) {
+            subgraphs
+                .entry(components.find(node.index()))
+                .or_default()
+                .push(*node);
+        }
+        let mut subgraphs = subgraphs.into_values().collect::<Vec<_>>();
+        subgraphs.sort_by_key(|s| s[0]);
+        for nodes in subgraphs {
+            simplify_subgraph(graph, &nodes, &mut remap);
+        }
+    }
+}
+
+/// Rewrite a subgraph of elementwise ops, given in topological order
+fn simplify_subgraph<T: ToIdsMut>(graph: &mut Graph, nodes: &[NodeIndex], remap: &mut T) {
+    let members = nodes.iter().copied().collect::<FxHashSet<_>>();
+    let mut egraph = EGraph::default();
+    let mut leaves = Vec::<Leaf>::new();
+    let mut classes = FxHashMap::<NodeIndex, Id>::default();
+    for node in nodes {
+        let mut inputs = vec![];
+        for (src, output, shape) in graph.get_sources(*node) {
+            let id = if members.contains(&src) {
+                if !is_identity(&shape) {
+                    // Reading another op in the subgraph through a view can't be expressed
+                    return;
+                }
+                classes[&src]
+            } else if let Some(Constant(ConstantValue::Float(c), _)) =
+                graph.graph[src].as_any().downcast_ref::<Constant>()
+            {
+                if shape.is_padded() {
+                    add_leaf(&mut egraph, &mut leaves, src, output, shape)
+                } else {
+                    egraph.add(ENode::Const(c.to_bits()))
+                }
+            } else {
+                add_leaf(&mut egraph, &mut leaves, src, output, shape)
+            };
+            inputs.push(id);
+        }
+        let op = elementwise_op(graph.graph[*node].as_ref()).unwrap();
+        let enode = match inputs[..] {
+            [a] => ENode::Unary(op, a),
+            [a, b] => ENode::Binary(op, a, b),
+            _ => unreachable!(),
+        };
+        classes.insert(*node, egraph.add(enode));
+    }
+    let roots = nodes
+        .iter()
+        .copied()
+        .filter(|n| {
+            graph.no_delete.contains(n)
+                || graph.to_retrieve.contains(n)
+                || graph
+                    .graph
+                    .edges_directed(*n, Direction::Outgoing)
+                    .any(|e| !members.contains(&e.target()))
+        })
+        .collect::<Vec<_>>();
+    let old_cost = nodes
+        .iter()
+        .map(|n| op_cost(elementwise_op(graph.graph[*n].as_ref()).unwrap()))
+        .sum::<usize>();
+
+    egraph.saturate(&rules(), ITERATIONS, NODE_LIMIT);
+    let choice = Choice::new(
+        &egraph,
+        &roots.iter().map(|r| classes[r]).collect::<Vec<_>>(),
+    );
+
+    // Only replace the subgraph if the new one is cheaper
+    let mut new_cost = 0;
+    let mut seen = FxHashSet::default();
+    let mut stack = roots
+        .iter()
+        .map(|r| choice.root(classes[r]))
+        .collect::<Vec<_>>();
+    while let Some(key) = stack.pop() {
+        if seen.insert(key) {
+            let node = choice.node(key);
+            new_cost += cost(&node);
+            stack.extend(node.children().into_iter().map(|c| choice.child(c, key)));
+        }
+    }
+    if new_cost >= old_cost {
+        return;
+    }
+
+    // Every op in the subgraph has the same number of elements, so new ops read each other through the output shape of
+    // the first one
+    let shape = graph.get_sources(nodes[0])[0].2.contiguous();
+    let mut emitter = Emitter {
+        choice: &choice,
+        leaves: &leaves,
+        shape,
+        emitted: FxHashMap::default(),
+        constants: FxHashMap::default(),
+    };
+    for root in roots {
+        // A broadcast constant only works for consumers that index through their view. Unary ops work on the physical
+        // buffer, and padding isn't applied to fake dims
+        let keep = graph.no_delete.contains(&root)
+            || graph.to_retrieve.contains(&root)
+            || graph
+                .graph
+                .edges_directed(root, Direction::Outgoing)
+                .filter(|e| !members.contains(&e.target()))
+                .any(|e| {
+                    !reads_through_view(graph.graph[e.target()].as_ref())
+                        || e.weight()
+                            .as_data()
+                            .is_some_and(|(_, _, sh)| sh.is_padded())
+                });
+        let (target, fake) = match emitter.emit(graph, choice.root(classes[&root])) {
+            Emitted::Leaf(l) if leaves[l].view.is_none() && leaves[l].output == 0 => {
+                (leaves[l].node, false)
+            }
+            Emitted::Const(c) if !keep => (emitter.constant(graph, c), true),
+            e => (emitter.contiguous(graph, e), false),
+        };
+        for (weight, consumer) in graph
+            .graph
+            .edges_directed(root, Direction::Outgoing)
+            .filter(|e| !members.contains(&e.target()))
+            .map(|e| (e.weight().clone(), e.target()))
+            .collect::<Vec<_>>()
+        {
+            let Dependency::Data {
+                input_order,
+                mut shape,
+                ..
+            } = weight
+            else {
+                continue;
+            };
+            if fake {
+                // Broadcast the constant to every element the consumer reads
+                shape.fake = shape.fake.iter().map(|_| true).collect();
+            }
+            let dtype = graph.dtype(target);
+            graph.graph.add_edge(
+                target,
+                consumer,
+                Dependency::Data {
+                    input_order,
+                    output_order: 0,
+                    shape,
+                    dtype,
+                },
+            );
+        }
+        move_references(
+            &mut *remap,
+            &mut graph.no_delete,
+            &mut graph.to_retrieve,
+            root,
+            target,
+        );
+    }
+    for node in nodes {
+        graph.graph.remove_node(*node);
+    }
+}
+
+fn add_leaf(
+    egraph: &mut EGraph,
+    leaves: &mut Vec<Leaf>,
+    node: NodeIndex,
+    output: u8,
+    shape: ShapeTracker,
+) -> Id {
+    let leaf = Leaf {
+        node,
+        output,
+        view: (!is_identity(&shape)).then(|| shape.clone()),
+        shape,
+    };
+    let index = match leaves
+        .iter()
+        .position(|l| (l.node, l.output, &l.view) == (node, output, &leaf.view))
+    {
+        Some(i) => i,
+        None => {
+            leaves.push(leaf);
+            leaves.len() - 1
+        }
+    };
+    egraph.add(ENode::Leaf(index))
+}
+
+/// A class, and if it's a root, its position in the roots
+type Key = (Id, Option<usize>);
+
+/// The expression picked for each class.
+///
+/// Extraction costs each class as a tree, so on its own it would recompute values shared between roots. Each root
+/// gets to pick again, counting the roots before it as free, and reads those roots through their own picks. Roots only
+/// look back, so the picks stay acyclic.
+struct Choice<'a> {
+    egraph: &'a EGraph,
+    best: FxHashMap<Id, (usize, ENode)>,
+    roots: FxHashMap<Id, (usize, ENode)>,
+}
+
+impl<'a> Choice<'a> {
+    fn new(egraph: &'a EGraph, roots: &[Id]) -> Self {
+        let best = egraph.extract(cost);
+        let mut choice = Self {
+            egraph,
+            best,
+            roots: FxHashMap::default(),
+        };
+        for id in roots {
+            let id = egraph.find(*id);
+            if choice.roots.contains_key(&id) {
+                continue;
+            }
+            let position = choice.roots.len();
+            let picked = egraph
+                .nodes(id)
+                .iter()
+                .filter_map(|n| {
+                    let children = n
+                        .children()
+                        .into_iter()
+                        .map(|c| match choice.roots.contains_key(&egraph.find(c)) {
+                            true => Some(0),
+                            false => choice.best.get(&egraph.find(c)).map(|(c, _)| *c),
+                        })
+                        .sum::<Option<usize>>()?;
+                    Some((cost(n) + children, *n))
+                })
+                .min_by_key(|(c, _)| *c)
+                .unwrap()
+                .1;
+            choice.roots.insert(id, (position, picked));
+        }
+        choice
+    }
+
+    fn root(&self, id: Id) -> Key {
+        let id = self.egraph.find(id);
+        (id, self.roots.get(&id).map(|(p, _)| *p))
+    }
+
+    fn node(&self, (id, root): Key) -> ENode {
+        match root {
+            Some(_) => self.roots[&id].1,
+            None => self.best[&id].1,
+        }
+    }
+
+    /// How a child of the expression picked for a class is read
+    fn child(&self, child: Id, (_, root): Key) -> Key {
+        let child = self.egraph.find(child);
+        match (root, self.roots.get(&child)) {
+            (Some(position), Some((p, _))) if *p < position => (child, Some(*p)),
+            _ => (child, None),
+        }
+    }
+}
+
+/// Adds the extracted expressions to the graph, sharing common subexpressions
+struct Emitter<'a> {
+    choice: &'a Choice<'a>,
+    leaves: &'a [Leaf],
+    shape: ShapeTracker,
+    emitted: FxHashMap<Key, Emitted>,
+    constants: FxHashMap<u32, NodeIndex>,
+}
+
+impl Emitter<'_> {
+    fn emit(&mut self, graph: &mut Graph, key: Key) -> Emitted {
+        if let Some(e) = self.emitted.get(&key) {
+            return *e;
+        }
+        let emitted = match self.choice.node(key) {
+            ENode::Leaf(l) => Emitted::Leaf(l),
+            ENode::Const(c) => Emitted::Const(f32::from_bits(c)),
+            ENode::Unary(op, a) => {
+                let a = self.emit(graph, self.choice.child(a, key));
+                let a = match a {
+                    Emitted::Leaf(l) if self.leaves[l].view.is_none() => (
+                        self.leaves[l].node,
+                        self.leaves[l].output,
+                        self.leaves[l].shape.clone(),
+                    ),
+                    // Unary ops ignore the view, so it needs to be applied first
+                    e => (self.contiguous(graph, e), 0, self.shape.clone()),
+                };
+                Emitted::Node(self.add_op(graph, op, &[a]))
+            }
+            ENode::Binary(op, a, b) => {
+                let inputs = [a, b].map(|i| {
+                    let i = self.emit(graph, self.choice.child(i, key));
+                    self.input(graph, i)
+                });
+                Emitted::Node(self.add_op(graph, op, &inputs))
+            }
+        };
+        self.emitted.insert(key, emitted);
+        emitted
+    }
+
+    /// How a binary op reads an expression
+    fn input(&mut self, graph: &mut Graph, e: Emitted) -> (NodeIndex, u8, ShapeTracker) {
+        match e {
+            Emitted::Node(n) => (n, 0, self.shape.clone()),
+            Emitted::Leaf(l) => (
+                self.leaves[l].node,
+                self.leaves[l].output,
+                self.leaves[l].shape.clone(),
+            ),
+            Emitted::Const(c) => {
+                let mut shape = self.shape.clone();
+                shape.fake = shape.fake.iter().map(|_| true).collect();
+                (self.constant(graph, c), 0, shape)
+            }
+        }
+    }
+
+    fn add_op(
+        &mut self,
+        graph: &mut Graph,
+        op: Op,
+        inputs: &[(NodeIndex, u8, ShapeTracker)],
+    ) -> NodeIndex {
+        let node = graph.graph.add_node(operator(op));
+        for (i, (src, output, shape)) in inputs.iter().enumerate() {
+            let dtype = graph.dtype(*src);
+            graph.graph.add_edge(
+                *src,
+                node,
+                Dependency::Data {
+                    input_order: i as u8,
+                    output_order: *output,
+                    shape: shape.clone(),
+                    dtype,
+                },
+            );
+        }
+        node
+    }
+
+    fn constant(&mut self, graph: &mut Graph, c: f32) -> NodeIndex {
+        let dyn_map = &graph.dyn_map as *const _;
+        *self.constants.entry(c.to_bits()).or_insert_with(|| {
+            graph
+                .graph
+                .add_node(Box::new(Constant(ConstantValue::Float(c), dyn_map)))
+        })
+    }
+
+    /// A contiguous buffer holding the expression
+    fn contiguous(&mut self, graph: &mut Graph, e: Emitted) -> NodeIndex {
+        if let Emitted::Node(n) = e {
+            return n;
+        }
+        let input = self.input(graph, e);
+        graph
+            .add_op(op::Contiguous)
+            .input(input.0, input.1, input.2)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        op,
+        prelude::{symbolic::Expression, *},
+        tests::assert_close,
+    };
+
+    fn count<O: 'static>(cx: &Graph) -> usize {
+        cx.graph
+            .node_indices()
+            .filter(|n| cx.graph[*n].as_any().is::<O>())
+            .count()
+    }
+
+    #[test]
+    fn test_identities() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>().set(vec![1., 2., 3.]);
+        let b = cx.tensor::<R1<3>>().set(vec![4., 5., 6.]);
+        // x - x => 0, x / x => 1, x * 0 => 0
+        let mut c = ((a - a) + (b / b) * a + a * 0. + (b - b) / a).retrieve();
+        let mut d = ((a * 2. + 3.) * 4. + a * 2.).retrieve();
+
+        cx.compile(AlgebraicSimplification, (&mut c, &mut d));
+        assert_eq!(count::<op::Recip>(&cx), 0);
+        // 10 * a + 12
+        assert_eq!(count::<op::Mul>(&cx), 1);
+        assert_eq!(count::<op::Add>(&cx), 1);
+        cx.execute();
+
+        assert_close(&c.data(), &[1., 2., 3.]);
+        assert_close(&d.data(), &[22., 32., 42.]);
+    }
+
+    #[test]
+    fn test_exp_log() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>().set(vec![1., 2., 3.]);
+        let b = cx.tensor::<R1<3>>().set(vec![0.5, 1., 1.5]);
+        let mut c = (a.exp2() * b.exp2()).log2().retrieve();
+        let mut d = (a.recip() * b.recip()).recip().retrieve();
+
+        cx.compile(AlgebraicSimplification, (&mut c, &mut d));
+        assert_eq!(count::<op::Exp2>(&cx), 0);
+        assert_eq!(count::<op::Recip>(&cx), 0);
+        cx.execute();
+
+        assert_close(&c.data(), &[1.5, 3., 4.5]);
+        assert_close(&d.data(), &[0.5, 2., 4.5]);
+    }
+
+    #[test]
+    fn test_broadcasts() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>().set(vec![1., 2., 3.]);
+        let b = cx.tensor::<R2<2, 3>>().set(vec![1., 2., 3., 4., 5., 6.]);
+        // The broadcast input and the padded read stay as they are, and the constant chain folds
+        let x = b + a.expand();
+        let mut c = ((x + 1.) + 2.).retrieve();
+        let mut d = (x
+            .slice((.., ..Expression::from(2)))
+            .realize::<R2<2, 2>>()
+            .pad::<R2<2, 3>, _, _>(&[(0, 0), (0, 1)])
+            * 1.)
+            .retrieve();
+
+        cx.compile(AlgebraicSimplification, (&mut c, &mut d));
+        assert_eq!(count::<op::Add>(&cx), 2);
+        assert_eq!(count::<op::Mul>(&cx), 0);
+        cx.execute();
+
+        assert_close(&c.data(), &[5., 7., 9., 8., 10., 12.]);
+        assert_close(&d.data(), &[2., 4., 0., 5., 7., 0.]);
+    }
+
+    #[test]
+    fn test_integers_untouched() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>().set(vec![1i32, 2, 3]);
+        let mut b = ((a + 0.) * 1.).retrieve();
+        let before = cx.graph.node_count();
+
+        cx.compile(AlgebraicSimplification, &mut b);
+        assert_eq!(cx.graph.node_count(), before);
+        cx.execute();
+
+        assert_eq!(b.data_as::<i32>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_broadcast_constant_consumers() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>().set(vec![1., 2., 3.]);
+        let b = a * 0. + 1.;
+        let mut c = b.pad::<R1<5>, _, _>(&[(1, 1)]).contiguous().retrieve();
+        let mut d = b.cast(crate::dtype::DType::F16).retrieve();
+
+        cx.compile(AlgebraicSimplification, (&mut c, &mut d));
+        cx.execute();
+
+        assert_close(&c.data(), &[0., 1., 1., 1., 0.]);
+        assert_eq!(d.data_as::<half::f16>(), vec![half::f16::ONE; 3]);
+    }
+}
@@ -21,6 +21,7 @@ use crate::{
     graph::Graph,
     op::Operator,
     prelude::{Dependency, MainGraph, Shape, ShapeTracker},
+    trace::{short_type_name, trace_pass},
 };
 
 use super::{graph_tensor::GraphTensor, shape::symbolic::Expression};
@@ -64,7 +65,7 @@ impl<T: ToIds> ToIds for &mut [T] {
     }
 }
 
-impl<T: ToIdsMut> ToIdsMut for &mut T {
+impl<T: ToIdsMut + ?Sized> ToIdsMut for &mut T {
     fn to_ids_mut(&mut self) -> Vec<&mut NodeIndex> {
         (*self).to_ids_mut()
     }
@@ -152,21 +153,43 @@ impl Compiler for () {
     fn compile<T: ToIdsMut>(&self, _: &mut Graph, _: T) {}
 }
 
+/// Compilers can be ran by reference, so any state they produce can be read back out afterwards
+impl<C: Compiler> Compiler for &C {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, remap: T) {
+        (*self).compile(graph, remap)
+    }
+}
+
 /// Wrap this around a compiler to rerun the compiler until it doesn't change the graph anymore
 pub struct Looped<C: Compiler + Debug>(C);
 
 impl<C: Compiler + Debug> Compiler for Looped<C> {
     fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
+        run_looped(graph, short_type_name::<C>, |graph| {
+            self.0.compile(graph, &mut remap)
+        });
+    }
+}
+
+/// Rerun a pass until it stops changing the graph, tracing each run as an iteration of `name`
+pub(crate) fn run_looped(
+    graph: &mut Graph,
+    name: impl Fn() -> String,
+    mut pass: impl FnMut(&mut Graph),
+) {
+    graph.toposort();
+    let mut linearized = graph.linearized_graph.clone();
+    for iteration in 1.. {
+        trace_pass(
+            graph,
+            || format!("{} (iteration {iteration})", name()),
+            &mut pass,
+        );
         graph.toposort();
-        let mut linearized = graph.linearized_graph.clone();
-        loop {
-            self.0.compile(graph, &mut remap);
-            graph.toposort();
-            if linearized == graph.linearized_graph {
-                break;
-            }
-            linearized = graph.linearized_graph.clone();
+        if linearized == graph.linearized_graph {
+            break;
         }
+        linearized = graph.linearized_graph.clone();
     }
 }
 
@@ -216,7 +239,7 @@ macro_rules! tuple_impls {
             Compiler, )+
         > Compiler for ($($name,)+) {
             fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
-                $(self.$idx.compile(graph, &mut remap);)+
+                $(trace_pass(graph, short_type_name::<$name>, |graph| self.$idx.compile(graph, &mut remap));)+
             }
         }
     };
@@ -294,7 +317,7 @@ impl Graph {
     ///     .finish();
     /// let b = GraphTensor::<R1<3>>::from_id(b_id, a.shape, a.graph());
     /// ```
-    pub fn add_op<O: Operator + 'static>(&mut self, op: O) -> NewOp {
+    pub fn add_op<O: Operator + 'static>(&mut self, op: O) -> NewOp<'_> {
         NewOp {
             new_op_id: self.graph.add_node(Box::new(op)),
             graph_ref: self,
@@ -313,9 +336,7 @@ impl Graph {
         key: &str,
         input: I,
     ) -> Option<O> {
-        let Some(node_weight) = self.graph.node_weight_mut(node) else {
-            return None;
-        };
+        let node_weight = self.graph.node_weight_mut(node)?;
 
         node_weight
             .custom(key, Box::new(input))
@@ -439,7 +460,7 @@ impl Graph {
     }
 }
 
-/// View a debug graph in the browser
+/// View a debug graph in the browser. This needs network access, see [`Graph::write_dot`] or [`Graph::write_svg`] for offline rendering.
 pub fn display_graph(
     graph: &petgraph::stable_graph::StableGraph<String, u8, petgraph::Directed, u32>,
     schedule_edges: &[EdgeIndex],
@@ -516,14 +537,16 @@ impl<'a> NewOp<'a> {
         self.new_op_id
     }
 
-    pub fn input(mut self, id: NodeIndex, from_output: u8, shape: ShapeTracker) -> Self {
+    pub fn input(mut self, id: NodeIndex, from_output: u8, shape: impl Into<ShapeTracker>) -> Self {
+        let dtype = self.graph_ref.dtype(id);
         self.graph_ref.graph.add_edge(
             id,
             self.new_op_id,
             Dependency::Data {
                 input_order: self.num_srcs,
                 output_order: from_output,
-                shape,
+                shape: shape.into(),
+                dtype,
             },
         );
         self.num_srcs += 1;
@@ -657,6 +680,9 @@ impl GraphSearch {
                 }
             }
             self.returned_anchors.insert(mapping[&self.anchor]);
+            if let Some(trace) = &mut graph.compile_trace {
+                trace.matches += 1;
+            }
             return true;
         }
         false
@@ -709,7 +735,7 @@ fn backtrack_match_new(
             if let Some(new_mapping) =
                 backtrack_match_new(pattern_parent, pattern_graph, *parent, main_graph)
             {
-                mapping.extend(new_mapping.into_iter());
+                mapping.extend(new_mapping);
                 continue 'pattern_loop;
             }
         }
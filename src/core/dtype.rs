@@ -0,0 +1,422 @@
+use std::{any::Any, fmt::Debug, str::FromStr};
+
+use half::{bf16, f16};
+
+use crate::tensor::Tensor;
+
+/// Run an expression with `$T` aliased to the element type of a [`DType`]
+macro_rules! dispatch_dtype {
+    ($dtype:expr, $T:ident => $body:expr) => {
+        match $dtype {
+            $crate::dtype::DType::F32 => {
+                type $T = f32;
+                $body
+            }
+            $crate::dtype::DType::F16 => {
+                type $T = half::f16;
+                $body
+            }
+            $crate::dtype::DType::BF16 => {
+                type $T = half::bf16;
+                $body
+            }
+            $crate::dtype::DType::I32 => {
+                type $T = i32;
+                $body
+            }
+            $crate::dtype::DType::I64 => {
+                type $T = i64;
+                $body
+            }
+            $crate::dtype::DType::Bool => {
+                type $T = bool;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use dispatch_dtype;
+
+/// The element type of a tensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DType {
+    #[default]
+    F32,
+    F16,
+    BF16,
+    I32,
+    I64,
+    Bool,
+}
+
+impl DType {
+    pub const ALL: [DType; 6] = [
+        DType::F32,
+        DType::F16,
+        DType::BF16,
+        DType::I32,
+        DType::I64,
+        DType::Bool,
+    ];
+
+    /// Number of bytes taken by one element
+    pub fn size_of(&self) -> usize {
+        dispatch_dtype!(*self, T => std::mem::size_of::<T>())
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, DType::F32 | DType::F16 | DType::BF16)
+    }
+
+    /// The type of a CPU tensor's data, if it's a `Vec` of one of the supported element types
+    pub fn of(data: &dyn Any) -> Option<DType> {
+        DType::ALL
+            .into_iter()
+            .find(|d| dispatch_dtype!(*d, T => data.is::<Vec<T>>()))
+    }
+}
+
+impl std::fmt::Display for DType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            DType::F32 => "f32",
+            DType::F16 => "f16",
+            DType::BF16 => "bf16",
+            DType::I32 => "i32",
+            DType::I64 => "i64",
+            DType::Bool => "bool",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for DType {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DType::ALL
+            .into_iter()
+            .find(|d| d.to_string() == s)
+            .ok_or(())
+    }
+}
+
+/// A type CPU tensors can hold. Primops are implemented for `Vec`s of every element type.
+///
+/// Math that isn't native to a type (like `sin` on integers) goes through `f64`, and integer arithmetic wraps.
+pub trait Element: Copy + PartialOrd + Debug + Default + Send + Sync + 'static {
+    const DTYPE: DType;
+    const ZERO: Self;
+    const ONE: Self;
+    /// The identity of max, used to start max reductions
+    const MIN: Self;
+
+    fn to_f64(self) -> f64;
+    fn from_f64(v: f64) -> Self;
+    /// Append the little endian bytes of this element
+    fn write_le_bytes(self, bytes: &mut Vec<u8>);
+    /// Read an element from its little endian bytes
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+    /// Read the element as an index into another tensor
+    fn to_index(self) -> usize {
+        self.to_f64() as usize
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        Self::from_f64(self.to_f64() + rhs.to_f64())
+    }
+    fn mul(self, rhs: Self) -> Self {
+        Self::from_f64(self.to_f64() * rhs.to_f64())
+    }
+    fn rem(self, rhs: Self) -> Self {
+        Self::from_f64(self.to_f64() % rhs.to_f64())
+    }
+    fn maximum(self, rhs: Self) -> Self {
+        if rhs > self {
+            rhs
+        } else {
+            self
+        }
+    }
+    fn log2(self) -> Self {
+        Self::from_f64(self.to_f64().log2())
+    }
+    fn exp2(self) -> Self {
+        Self::from_f64(self.to_f64().exp2())
+    }
+    fn sin(self) -> Self {
+        Self::from_f64(self.to_f64().sin())
+    }
+    fn recip(self) -> Self {
+        Self::from_f64(self.to_f64().recip())
+    }
+    fn sqrt(self) -> Self {
+        Self::from_f64(self.to_f64().sqrt())
+    }
+}
+
+/// Convert an element to another type
+pub fn convert<A: Element, B: Element>(a: A) -> B {
+    B::from_f64(a.to_f64())
+}
+
+impl Element for f32 {
+    const DTYPE: DType = DType::F32;
+    const ZERO: Self = 0.;
+    const ONE: Self = 1.;
+    const MIN: Self = f32::NEG_INFINITY;
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+    fn write_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.to_le_bytes());
+    }
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        f32::from_le_bytes(bytes.try_into().unwrap())
+    }
+    fn add(self, rhs: Self) -> Self {
+        self + rhs
+    }
+    fn mul(self, rhs: Self) -> Self {
+        self * rhs
+    }
+    fn rem(self, rhs: Self) -> Self {
+        self % rhs
+    }
+    fn maximum(self, rhs: Self) -> Self {
+        f32::max(self, rhs)
+    }
+    fn log2(self) -> Self {
+        f32::log2(self)
+    }
+    fn exp2(self) -> Self {
+        f32::exp2(self)
+    }
+    fn sin(self) -> Self {
+        f32::sin(self)
+    }
+    fn recip(self) -> Self {
+        f32::recip(self)
+    }
+    fn sqrt(self) -> Self {
+        f32::sqrt(self)
+    }
+}
+
+macro_rules! half_element {
+    ($t:ident, $dtype:ident) => {
+        /// Half precision math is done in f32
+        impl Element for $t {
+            const DTYPE: DType = DType::$dtype;
+            const ZERO: Self = $t::ZERO;
+            const ONE: Self = $t::ONE;
+            const MIN: Self = $t::NEG_INFINITY;
+
+            fn to_f64(self) -> f64 {
+                $t::to_f64(self)
+            }
+            fn from_f64(v: f64) -> Self {
+                $t::from_f64(v)
+            }
+            fn write_le_bytes(self, bytes: &mut Vec<u8>) {
+                bytes.extend(self.to_le_bytes());
+            }
+            fn from_le_bytes(bytes: &[u8]) -> Self {
+                $t::from_le_bytes(bytes.try_into().unwrap())
+            }
+            fn add(self, rhs: Self) -> Self {
+                $t::from_f32(self.to_f32() + rhs.to_f32())
+            }
+            fn mul(self, rhs: Self) -> Self {
+                $t::from_f32(self.to_f32() * rhs.to_f32())
+            }
+            fn rem(self, rhs: Self) -> Self {
+                $t::from_f32(self.to_f32() % rhs.to_f32())
+            }
+            fn maximum(self, rhs: Self) -> Self {
+                $t::from_f32(self.to_f32().max(rhs.to_f32()))
+            }
+            fn log2(self) -> Self {
+                $t::from_f32(self.to_f32().log2())
+            }
+            fn exp2(self) -> Self {
+                $t::from_f32(self.to_f32().exp2())
+            }
+            fn sin(self) -> Self {
+                $t::from_f32(self.to_f32().sin())
+            }
+            fn recip(self) -> Self {
+                $t::from_f32(self.to_f32().recip())
+            }
+            fn sqrt(self) -> Self {
+                $t::from_f32(self.to_f32().sqrt())
+            }
+        }
+    };
+}
+
+half_element!(f16, F16);
+half_element!(bf16, BF16);
+
+macro_rules! int_element {
+    ($t:ident, $dtype:ident) => {
+        impl Element for $t {
+            const DTYPE: DType = DType::$dtype;
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+            const MIN: Self = $t::MIN;
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn from_f64(v: f64) -> Self {
+                v as $t
+            }
+            fn write_le_bytes(self, bytes: &mut Vec<u8>) {
+                bytes.extend(self.to_le_bytes());
+            }
+            fn from_le_bytes(bytes: &[u8]) -> Self {
+                $t::from_le_bytes(bytes.try_into().unwrap())
+            }
+            fn to_index(self) -> usize {
+                self as usize
+            }
+            fn add(self, rhs: Self) -> Self {
+                self.wrapping_add(rhs)
+            }
+            fn mul(self, rhs: Self) -> Self {
+                self.wrapping_mul(rhs)
+            }
+            /// Remainders by zero are zero
+            fn rem(self, rhs: Self) -> Self {
+                self.checked_rem(rhs).unwrap_or_default()
+            }
+        }
+    };
+}
+
+int_element!(i32, I32);
+int_element!(i64, I64);
+
+/// Bools add as `or` and multiply as `and`
+impl Element for bool {
+    const DTYPE: DType = DType::Bool;
+    const ZERO: Self = false;
+    const ONE: Self = true;
+    const MIN: Self = false;
+
+    fn to_f64(self) -> f64 {
+        self as u8 as f64
+    }
+    fn from_f64(v: f64) -> Self {
+        v != 0.
+    }
+    fn write_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.push(self as u8);
+    }
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+    fn add(self, rhs: Self) -> Self {
+        self | rhs
+    }
+    fn mul(self, rhs: Self) -> Self {
+        self & rhs
+    }
+}
+
+impl Tensor {
+    /// The element type of the tensor, if it's a CPU tensor
+    pub fn dtype(&self) -> Option<DType> {
+        DType::of(self.data.as_any())
+    }
+
+    /// The number of elements in a CPU tensor
+    pub fn n_elements(&self) -> Option<usize> {
+        let dtype = self.dtype()?;
+        Some(
+            dispatch_dtype!(dtype, T => self.data.as_any().downcast_ref::<Vec<T>>().unwrap().len()),
+        )
+    }
+
+    /// Get the data of a CPU tensor as `T`, converting it if it's stored as another type
+    pub fn to_vec<T: Element>(&self) -> Vec<T> {
+        let dtype = self.dtype().expect("Expected a CPU tensor");
+        dispatch_dtype!(dtype, S => self
+            .data
+            .as_any()
+            .downcast_ref::<Vec<S>>()
+            .unwrap()
+            .iter()
+            .map(|a| convert::<S, T>(*a))
+            .collect())
+    }
+
+    /// The little endian bytes of a CPU tensor's elements
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        let dtype = self.dtype().expect("Expected a CPU tensor");
+        let mut bytes = Vec::with_capacity(self.n_elements().unwrap() * dtype.size_of());
+        dispatch_dtype!(dtype, T => {
+            for a in self.data.as_any().downcast_ref::<Vec<T>>().unwrap() {
+                a.write_le_bytes(&mut bytes);
+            }
+        });
+        bytes
+    }
+
+    /// Read a CPU tensor from the little endian bytes of its elements
+    pub fn from_le_bytes(dtype: DType, bytes: &[u8]) -> Tensor {
+        dispatch_dtype!(dtype, T => Tensor::new(
+            bytes
+                .chunks_exact(dtype.size_of())
+                .map(<T as Element>::from_le_bytes)
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    /// Convert a CPU tensor to another element type
+    pub fn cast(&self, dtype: DType) -> Tensor {
+        if self.dtype() == Some(dtype) {
+            return self.clone();
+        }
+        dispatch_dtype!(dtype, T => Tensor::new(self.to_vec::<T>()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_element_conversions() {
+        assert_eq!(convert::<f32, i32>(-2.7), -2);
+        assert!(convert::<f32, bool>(0.5));
+        assert_eq!(convert::<i64, f16>(3), f16::from_f32(3.));
+        assert_eq!(convert::<bf16, f32>(bf16::from_f32(1.5)), 1.5);
+        assert_eq!(i32::MAX.add(1), i32::MIN);
+        assert_eq!(7i64.rem(0), 0);
+        assert_eq!(((1i64 << 40) + 1).to_index(), (1 << 40) + 1);
+        assert!(true.add(false) && !true.mul(false));
+
+        let t = Tensor::new(vec![1.5f32, -2., 0.]);
+        assert_eq!(t.dtype(), Some(DType::F32));
+        let h = t.cast(DType::F16);
+        assert_eq!(h.dtype(), Some(DType::F16));
+        assert_eq!(h.n_elements(), Some(3));
+        assert_eq!(h.to_vec::<f32>(), vec![1.5, -2., 0.]);
+        assert_eq!(
+            t.cast(DType::Bool).to_vec::<bool>(),
+            vec![true, true, false]
+        );
+        assert_eq!("bf16".parse::<DType>(), Ok(DType::BF16));
+        let bytes = h.to_le_bytes();
+        assert_eq!(bytes.len(), 6);
+        assert_eq!(
+            Tensor::from_le_bytes(DType::F16, &bytes).to_vec::<f32>(),
+            vec![1.5, -2., 0.]
+        );
+    }
+}
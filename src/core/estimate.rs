@@ -0,0 +1,229 @@
+use std::fmt::Display;
+
+use petgraph::{stable_graph::NodeIndex, Direction};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    graph::{ExecutionError, Graph},
+    op::OpCost,
+    shape::{symbolic::Symbol, ShapeTracker},
+};
+
+/// The estimated cost of running a single node
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeEstimate {
+    pub node: NodeIndex,
+    /// The op's debug name
+    pub op: String,
+    pub flops: usize,
+    /// Bytes allocated for the node's output
+    pub bytes_allocated: usize,
+    /// Bytes resident while the node runs, including its inputs and output
+    pub resident_bytes: usize,
+}
+
+/// Estimated compute and memory usage of a graph execution
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Estimate {
+    /// Per-node estimates, in execution order
+    pub nodes: Vec<NodeEstimate>,
+    pub total_flops: usize,
+    pub total_bytes_allocated: usize,
+    /// The most bytes resident at any point during execution
+    pub peak_bytes: usize,
+}
+
+impl Display for Estimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for n in &self.nodes {
+            writeln!(
+                f,
+                "{:?} {}: {} flops, {} bytes allocated, {} bytes resident",
+                n.node, n.op, n.flops, n.bytes_allocated, n.resident_bytes
+            )?;
+        }
+        write!(
+            f,
+            "Total: {} flops, {} bytes allocated, {} bytes peak",
+            self.total_flops, self.total_bytes_allocated, self.peak_bytes
+        )
+    }
+}
+
+impl Graph {
+    /// Estimate the flops and memory needed to execute the graph, without running it.
+    ///
+    /// Memory is tracked along the current schedule, assuming every op allocates its output and tensors are freed
+    /// after their last consumer (unless they're kept). Ops that don't report a cost are assumed to do no flops, and
+    /// their output size is inferred from the shapes their consumers see. Output bytes use each node's dtype.
+    ///
+    /// Fails with [`ExecutionError::UnboundDim`] if a shape depends on a dyn dim missing from `dyn_map`.
+    pub fn estimate(&self, dyn_map: &FxHashMap<Symbol, usize>) -> Result<Estimate, ExecutionError> {
+        let linearized = match &self.linearized_graph {
+            Some(l) => l.clone(),
+            None => self.linearize(),
+        };
+        let mut remaining_consumers = FxHashMap::<NodeIndex, usize>::default();
+        for (_, src_ids) in &linearized {
+            for ((id, _), _) in src_ids {
+                *remaining_consumers.entry(*id).or_default() += 1;
+            }
+        }
+
+        let mut estimate = Estimate::default();
+        let mut tensor_bytes = FxHashMap::default();
+        let mut resident = 0;
+        let mut dim_stack = vec![];
+        for (node, src_ids) in &linearized {
+            let op = self.graph.node_weight(*node).unwrap();
+            let mut resolve = |st: ShapeTracker| {
+                resolve(st, dyn_map, &mut dim_stack).map_err(|dim| ExecutionError::UnboundDim {
+                    node: *node,
+                    op: format!("{op:?}"),
+                    dim,
+                })
+            };
+            let inputs = src_ids
+                .iter()
+                .map(|(_, st)| resolve(st.clone()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let cost = match op.cost(&inputs, dyn_map) {
+                Some(cost) => cost,
+                None => OpCost {
+                    flops: 0,
+                    output_elements: self
+                        .graph
+                        .edges_directed(*node, Direction::Outgoing)
+                        .filter_map(|e| e.weight().as_data())
+                        .filter(|(_, output, _)| *output == 0)
+                        .map(|(_, _, st)| {
+                            resolve(st).map(|st| st.n_physical_elements().to_usize().unwrap())
+                        })
+                        .collect::<Result<Vec<_>, _>>()?
+                        .into_iter()
+                        .max()
+                        .unwrap_or_default(),
+                },
+            };
+            let bytes = cost.output_elements * self.dtype(*node).size_of();
+            tensor_bytes.insert(*node, bytes);
+            resident += bytes;
+            estimate.peak_bytes = estimate.peak_bytes.max(resident);
+            estimate.total_flops += cost.flops;
+            estimate.total_bytes_allocated += bytes;
+            estimate.nodes.push(NodeEstimate {
+                node: *node,
+                op: format!("{op:?}"),
+                flops: cost.flops,
+                bytes_allocated: bytes,
+                resident_bytes: resident,
+            });
+
+            // Free sources with no consumers left
+            for ((id, _), _) in src_ids {
+                let remaining = remaining_consumers.get_mut(id).unwrap();
+                *remaining -= 1;
+                if *remaining == 0 && !self.no_delete.contains(id) {
+                    resident -= tensor_bytes[id];
+                }
+            }
+        }
+        Ok(estimate)
+    }
+}
+
+/// Resolve a shape's dyn dims, or give back the first one missing from the map
+fn resolve(
+    mut st: ShapeTracker,
+    dyn_map: &FxHashMap<Symbol, usize>,
+    stack: &mut Vec<i32>,
+) -> Result<ShapeTracker, Symbol> {
+    if let Some(dim) = st.symbols().into_iter().find(|d| !dyn_map.contains_key(d)) {
+        return Err(dim);
+    }
+    st.resolve_global_dyn_dims_stack(dyn_map, stack);
+    Ok(st)
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap;
+
+    use crate::prelude::*;
+
+    #[test]
+    fn test_estimate_matmul() {
+        let dyn_map = [('a'.into(), 2)].into_iter().collect::<FxHashMap<_, _>>();
+        let mut cx = Graph::new();
+        let a = cx.tensor::<(Dyn<'a'>, Const<3>)>();
+        let b = cx.tensor::<R2<3, 4>>();
+        a.matmul(b).retrieve();
+        // Mul then SumReduce, both over 2x4x3 elements
+        let unfused = cx.estimate(&dyn_map).unwrap();
+        assert_eq!(unfused.total_flops, 48);
+        assert_eq!(unfused.peak_bytes, (6 + 12 + 24) * 4);
+
+        let mut cx = Graph::new();
+        let a = cx.tensor::<(Dyn<'a'>, Const<3>)>();
+        let b = cx.tensor::<R2<3, 4>>();
+        let c = cx
+            .add_op(MatMul2D)
+            .input(a.id, 0, a.shape)
+            .input(b.id, 0, b.shape)
+            .finish();
+        cx.keep_tensors(c);
+        let fused = cx.estimate(&dyn_map).unwrap();
+        assert_eq!(fused.total_flops, 2 * 2 * 3 * 4);
+        // Inputs and output are all alive while the matmul runs
+        assert_eq!(fused.total_bytes_allocated, (6 + 12 + 8) * 4);
+        assert_eq!(fused.peak_bytes, (6 + 12 + 8) * 4);
+    }
+
+    #[test]
+    fn test_estimate_frees_tensors() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<10>>();
+        let b = a.exp2().sin().log2().retrieve();
+        let estimate = cx.estimate(&FxHashMap::default()).unwrap();
+
+        assert_eq!(estimate.nodes.len(), 4);
+        assert_eq!(estimate.total_flops, 30);
+        assert_eq!(estimate.total_bytes_allocated, 160);
+        // Only an input and output are alive at once
+        assert_eq!(estimate.peak_bytes, 80);
+        assert_eq!(estimate.nodes.last().unwrap().node, b.id);
+    }
+
+    #[test]
+    fn test_estimate_dtype_sizes() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<10>>().set(vec![0i32; 10]);
+        a.cast(DType::F16).retrieve();
+        let estimate = cx.estimate(&FxHashMap::default()).unwrap();
+        assert_eq!(estimate.nodes[0].bytes_allocated, 40);
+        assert_eq!(estimate.nodes[1].bytes_allocated, 20);
+    }
+
+    #[test]
+    fn test_estimate_random_dyn_size() {
+        let mut cx = Graph::new();
+        cx.set_dyn_dim('s', 3);
+        cx.rand::<(Dyn<'s'>, Const<2>)>().retrieve();
+        let dyn_map = [('s'.into(), 5)].into_iter().collect::<FxHashMap<_, _>>();
+        let estimate = cx.estimate(&dyn_map).unwrap();
+        // Costed at the requested size rather than the graph's current one
+        assert_eq!(estimate.nodes[0].flops, 10);
+        assert_eq!(estimate.nodes[0].bytes_allocated, 40);
+    }
+
+    #[test]
+    fn test_estimate_unbound_dim() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<(Dyn<'a'>,)>();
+        a.exp2().retrieve();
+        assert!(matches!(
+            cx.estimate(&FxHashMap::default()),
+            Err(ExecutionError::UnboundDim { dim, .. }) if dim == symbolic::Symbol::from('a')
+        ));
+    }
+}
@@ -2,16 +2,26 @@
 
 use crate::{
     compiler_utils::Compiler,
-    graph_tensor::GraphTensor,
+    dtype::DType,
+    graph_tensor::{GraphTensor, ShapeArena},
+    memory::MemoryPlan,
     op::{self, InputTensor, Operator},
     shape::*,
     tensor::Tensor,
+    trace::CompileTrace,
+    validate::ValidationError,
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex, RwLock,
+    },
 };
-use std::io::Write;
 
-use colored::Colorize;
 use itertools::Itertools;
 use petgraph::{stable_graph::StableGraph, visit::EdgeRef, Direction};
+use rand::{rngs::StdRng, SeedableRng};
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use super::compiler_utils::{ToIds, ToIdsMut};
@@ -24,7 +34,9 @@ pub struct Graph {
     /// The store of tensors in the graph. Indexed by node index and output index.
     pub tensors: rustc_hash::FxHashMap<(NodeIndex, u8), Tensor>,
     /// A map of dynamic dimensions to concrete dimension sizes
-    pub dyn_map: rustc_hash::FxHashMap<char, usize>,
+    pub dyn_map: rustc_hash::FxHashMap<symbolic::Symbol, usize>,
+    /// The declared ranges of dynamic dimensions
+    pub dyn_ranges: rustc_hash::FxHashMap<symbolic::Symbol, symbolic::DimRange>,
     /// Edge weights: (Input index, Output index, Input shape)
     pub graph: MainGraph,
     /// Tensors marked in this set will not get deleted when the graph is ran
@@ -35,11 +47,23 @@ pub struct Graph {
     #[allow(clippy::type_complexity)]
     pub(crate) linearized_graph: Option<Vec<(NodeIndex, Vec<((NodeIndex, u8), ShapeTracker)>)>>,
     /// Cached consumers (for execution only)
-    consumers_map: Option<FxHashMap<(NodeIndex, u8), usize>>,
+    pub(crate) consumers_map: Option<FxHashMap<(NodeIndex, u8), usize>>,
+    /// Buffer assignments and arena used by planned execution
+    pub(crate) memory_plan: Option<MemoryPlan>,
+    /// The trace being recorded by [`Graph::compile_traced`]
+    pub(crate) compile_trace: Option<CompileTrace>,
+    /// Whether each compiler pass should be followed by validation, see [`Graph::compile_validated`]
+    pub(crate) validate_passes: bool,
+    /// The first pass validation failure
+    pub(crate) validation_error: Option<ValidationError>,
+    /// Random number generator used to initialize weights. Seeded from entropy on first use unless [`Graph::seed`] is called.
+    pub(crate) rng: Option<StdRng>,
+    /// The shape trackers of tensors on this graph
+    pub(crate) shapes: ShapeArena,
 }
 
 /// A dependency between two nodes
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
 pub enum Dependency {
     /// A data dependency (transferring a tensor from one node to the next)
@@ -47,6 +71,8 @@ pub enum Dependency {
         input_order: u8,
         output_order: u8,
         shape: ShapeTracker,
+        /// The element type of the tensor, recorded when the edge is made
+        dtype: DType,
     },
     /// Explicit dependency for ordering. No tensors are transferred through this dependency
     Schedule,
@@ -54,14 +80,15 @@ pub enum Dependency {
 
 impl Dependency {
     /// Try to extract dependency data
-    pub fn as_data(self) -> Option<(u8, u8, ShapeTracker)> {
+    pub fn as_data(&self) -> Option<(u8, u8, ShapeTracker)> {
         if let Self::Data {
             input_order,
             output_order,
             shape,
+            ..
         } = self
         {
-            Some((input_order, output_order, shape))
+            Some((*input_order, *output_order, shape.clone()))
         } else {
             None
         }
@@ -79,6 +106,16 @@ impl Graph {
         Graph::default()
     }
 
+    /// Seed the random number generator used to initialize weights, making initialization reproducible
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Some(StdRng::seed_from_u64(seed));
+    }
+
+    /// The random number generator used to initialize weights
+    pub fn rng(&mut self) -> &mut StdRng {
+        self.rng.get_or_insert_with(StdRng::from_entropy)
+    }
+
     /// Try to remove the tensor data from the graph
     pub fn get_tensor(&mut self, id: NodeIndex, ind: u8) -> Option<Tensor> {
         self.tensors.remove(&(id, ind))
@@ -117,10 +154,38 @@ impl Graph {
     }
 
     /// Set a dynamic dimension
-    pub fn set_dyn_dim(&mut self, dimension: char, val: usize) {
+    pub fn set_dyn_dim(&mut self, dimension: impl Into<symbolic::Symbol>, val: usize) {
+        let dimension = dimension.into();
+        if let Some(range) = self.dyn_ranges.get(&dimension) {
+            assert!(
+                range.contains(val),
+                "Dynamic dimension {dimension} set to {val}, which is outside of its declared range {range:?}"
+            );
+        }
         self.dyn_map.insert(dimension, val);
     }
 
+    /// Declare the values a dynamic dimension can take. Shapes are simplified assuming the
+    /// dimension stays in this range, which [`Graph::set_dyn_dim`] checks.
+    pub fn set_dyn_dim_range(
+        &mut self,
+        dimension: impl Into<symbolic::Symbol>,
+        range: impl std::ops::RangeBounds<usize>,
+    ) {
+        let dimension = dimension.into();
+        let multiple_of = self.dyn_ranges.get(&dimension).map_or(1, |r| r.multiple_of);
+        self.dyn_ranges.insert(
+            dimension,
+            symbolic::DimRange::new(range).multiple_of(multiple_of),
+        );
+    }
+
+    /// Declare that a dynamic dimension is always a multiple of `n`
+    pub fn set_dyn_dim_multiple(&mut self, dimension: impl Into<symbolic::Symbol>, n: usize) {
+        let range = self.dyn_ranges.entry(dimension.into()).or_default();
+        *range = range.multiple_of(n);
+    }
+
     /// Create a new tensor with shape S
     pub fn tensor<S: Shape>(&mut self) -> GraphTensor<S> {
         self.named_tensor("Tensor")
@@ -128,14 +193,49 @@ impl Graph {
 
     /// Create a new tensor with shape S and a name. This name will show up on the graph when displayed
     pub fn named_tensor<S: Shape>(&mut self, name: &str) -> GraphTensor<S> {
-        GraphTensor {
-            id: self.graph.add_node(Box::new(op::Function(
-                format!("{name} Load"),
-                Box::new(|_| panic!("You must set a value for this tensor!")),
-            ))),
-            graph_ref: self,
-            shape: S::to_tracker(),
-            _phantom: Default::default(),
+        let id = self
+            .graph
+            .add_node(Box::new(op::Function::unset(format!("{name} Load"))));
+        GraphTensor::from_id(id, S::to_tracker(), self)
+    }
+
+    /// The element type of a node's outputs, from the types recorded on its input edges
+    pub fn dtype(&self, node: NodeIndex) -> DType {
+        let inputs = self
+            .graph
+            .edges_directed(node, Direction::Incoming)
+            .filter_map(|e| match e.weight() {
+                Dependency::Data {
+                    input_order, dtype, ..
+                } => Some((*input_order, *dtype)),
+                Dependency::Schedule => None,
+            })
+            .sorted_by_key(|(i, _)| *i)
+            .map(|(_, d)| d)
+            .collect::<Vec<_>>();
+        self.graph[node].dtype(&inputs)
+    }
+
+    /// Re-record the element type on the edges out of a node, and everything downstream whose type changes with it.
+    /// This needs to be called after changing the type an op outputs, such as when a tensor is set to new data.
+    pub fn update_dtype(&mut self, node: NodeIndex) {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            let new_dtype = self.dtype(node);
+            let stale = self
+                .graph
+                .edges_directed(node, Direction::Outgoing)
+                .filter(
+                    |e| matches!(e.weight(), Dependency::Data { dtype, .. } if *dtype != new_dtype),
+                )
+                .map(|e| (e.id(), e.target()))
+                .collect::<Vec<_>>();
+            for (edge, target) in stale {
+                if let Dependency::Data { dtype, .. } = &mut self.graph[edge] {
+                    *dtype = new_dtype;
+                }
+                stack.push(target);
+            }
         }
     }
 
@@ -147,26 +247,30 @@ impl Graph {
 
     /// Refresh the internally sorted graph
     pub(crate) fn toposort(&mut self) {
-        self.linearized_graph = Some(
-            petgraph::algo::toposort(&self.graph, None)
-                .unwrap()
-                .into_iter()
-                .map(|node| {
-                    (
-                        node,
-                        self.graph
-                            .edges_directed(node, Direction::Incoming)
-                            .filter_map(|e| e.weight().as_data().map(|i| (e.source(), i)))
-                            .sorted_by_key(|(_, (i, _, _))| *i)
-                            .map(|(a, (_, b, c))| ((a, b), c))
-                            .collect::<Vec<_>>(),
-                    )
-                })
-                .collect(),
-        );
+        self.linearized_graph = Some(self.linearize());
         self.create_remaining_consumers_map();
     }
 
+    /// Sort the nodes into execution order, alongside each node's sources
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn linearize(&self) -> Vec<(NodeIndex, Vec<((NodeIndex, u8), ShapeTracker)>)> {
+        petgraph::algo::toposort(&self.graph, None)
+            .unwrap()
+            .into_iter()
+            .map(|node| {
+                (
+                    node,
+                    self.graph
+                        .edges_directed(node, Direction::Incoming)
+                        .filter_map(|e| e.weight().as_data().map(|i| (e.source(), i)))
+                        .sorted_by_key(|(_, (i, _, _))| *i)
+                        .map(|(a, (_, b, c))| ((a, b), c))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect()
+    }
+
     /// Swap the tensors with these ids
     pub fn swap_tensors<A: Shape, B: Shape>(&mut self, a: GraphTensor<A>, b: GraphTensor<B>) {
         // Swap tensors
@@ -245,163 +349,557 @@ impl Graph {
         self.reset();
     }
 
-    /// Execute the graph without deleting intermediate tensors
-    pub fn execute_no_delete(&mut self) {
-        // Track the number of views pointing to each tensor so we know when to clear;
+    /// Execute the graph, returning an error instead of panicking if any op fails.
+    ///
+    /// Ops report failures through [`Operator::try_process`]. Before each op runs, its inputs are checked to be
+    /// present and have their dynamic dimensions set. Intermediate tensors are cleared on failure, so the graph can be
+    /// executed again once the problem is fixed.
+    pub fn try_execute(&mut self) -> Result<(), ExecutionError> {
+        // Track the number of views pointing to each tensor so we know when to clear
         if self.linearized_graph.is_none() {
             self.toposort();
         }
+        let mut remaining_consumers = self.consumers_map.as_ref().unwrap().clone();
         let mut dim_stack = Vec::new();
-        for (node, src_ids) in self.linearized_graph.as_ref().unwrap().iter() {
+        let mut error = None;
+
+        for (node, src_ids) in self.linearized_graph.as_ref().unwrap() {
             if self.tensors.contains_key(&(*node, 0)) {
                 continue;
             }
-            let mut srcs = src_ids
-                .iter()
-                .map(|(id, st)| (InputTensor::Borrowed(self.tensors.get(id).unwrap()), *st))
-                .collect_vec();
+            if let Some(e) =
+                check_sources(&self.graph, &self.tensors, &self.dyn_map, *node, src_ids)
+            {
+                error = Some(e);
+                break;
+            }
+
+            let mut srcs = Vec::new();
+            get_source_tensors(
+                &self.no_delete,
+                &mut self.tensors,
+                src_ids,
+                &remaining_consumers,
+                &mut srcs,
+            );
 
             // Substitute in the dyn dims
             for (_, st) in srcs.iter_mut() {
                 st.resolve_global_dyn_dims_stack(&self.dyn_map, &mut dim_stack);
             }
 
-            // All sources are ready, execute
-            let tensors = self.graph.node_weight_mut(*node).unwrap().process(srcs);
-            for (i, tensor) in tensors.into_iter().enumerate() {
-                self.tensors.insert((*node, i as u8), tensor);
+            // Execute
+            let op = self.graph.node_weight_mut(*node).unwrap();
+            match op.try_process(srcs) {
+                Ok(tensors) => {
+                    for (i, tensor) in tensors.into_iter().enumerate() {
+                        self.tensors.insert((*node, i as u8), tensor);
+                    }
+                }
+                Err(e) => {
+                    error = Some(ExecutionError::from_op_error(
+                        *node,
+                        op.as_ref(),
+                        src_ids,
+                        &self.dyn_map,
+                        e,
+                    ));
+                    break;
+                }
+            }
+
+            // Bookkeep remaining consumers
+            for (source, _) in src_ids {
+                *remaining_consumers.get_mut(source).unwrap() -= 1;
             }
         }
+        self.reset();
+        error.map_or(Ok(()), Err)
     }
 
-    /// Execute the graph with debug prints
-    pub fn execute_debug(&mut self) {
-        // Track the number of views pointing to each tensor so we know when to clear
+    /// Execute the graph, running independent branches in parallel across all available cores
+    pub fn execute_parallel(&mut self) {
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        self.execute_parallel_threads(threads);
+    }
+
+    /// Execute the graph, running independent branches in parallel on the given number of threads.
+    ///
+    /// Ops are handed owned inputs under the same rules as [`Graph::execute`]: a source tensor is moved into
+    /// its last remaining consumer (unless it's marked as no_delete), and borrowed otherwise.
+    pub fn execute_parallel_threads(&mut self, threads: usize) {
         if self.linearized_graph.is_none() {
             self.toposort();
         }
-        let mut dim_stack = Vec::new();
-        let tensors_ptr = &mut self.tensors as *mut _;
+        let linearized = self.linearized_graph.as_ref().unwrap();
+        let task_index = linearized
+            .iter()
+            .enumerate()
+            .map(|(i, (node, _))| (*node, i))
+            .collect::<FxHashMap<_, _>>();
+
+        // Count the incoming edges (data and schedule) each node is waiting on
+        let mut dependents = vec![vec![]; linearized.len()];
+        let waiting_on = linearized
+            .iter()
+            .enumerate()
+            .map(|(i, (node, _))| {
+                let mut n = 0;
+                for edge in self.graph.edges_directed(*node, Direction::Incoming) {
+                    dependents[task_index[&edge.source()]].push(i);
+                    n += 1;
+                }
+                AtomicUsize::new(n)
+            })
+            .collect::<Vec<_>>();
+
+        // Nodes that already have outputs are skipped, same as the sequential executor
+        let skip = linearized
+            .iter()
+            .map(|(node, _)| self.tensors.contains_key(&(*node, 0)))
+            .collect::<Vec<_>>();
+        // Every tensor with consumers gets a slot
+        let slots = self
+            .consumers_map
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|(id, consumers)| {
+                (
+                    *id,
+                    TensorSlot {
+                        tensor: RwLock::new(self.tensors.remove(id)),
+                        remaining_consumers: AtomicUsize::new(*consumers),
+                    },
+                )
+            })
+            .collect::<FxHashMap<_, _>>();
+        // Outputs without consumers
+        let unconsumed = Mutex::new(vec![]);
+        // Each op is only ever processed by one thread. node_weights_mut yields in node index order
+        let node_indices = self.graph.node_indices().collect::<Vec<_>>();
+        let mut ops = node_indices
+            .into_iter()
+            .zip(self.graph.node_weights_mut())
+            .collect::<FxHashMap<_, _>>();
+        let ops = linearized
+            .iter()
+            .map(|(node, _)| Mutex::new(ops.remove(node).unwrap()))
+            .collect::<Vec<_>>();
+
+        let queue = Mutex::new(ReadyQueue {
+            ready: (0..linearized.len())
+                .rev()
+                .filter(|i| waiting_on[*i].load(Ordering::Relaxed) == 0)
+                .collect(),
+            remaining: linearized.len(),
+        });
+        let queue_changed = Condvar::new();
+        let (no_delete, dyn_map) = (&self.no_delete, &self.dyn_map);
+
+        // Run a node, returning the nodes that became ready because of it
+        let run = |task: usize, dim_stack: &mut Vec<i32>| -> Vec<usize> {
+            let (node, src_ids) = &linearized[task];
+            if !skip[task] {
+                // A consumer only takes ownership once every other consumer is finished with the tensor
+                let (mut owned, mut borrowed) = (vec![], vec![]);
+                for (id, _) in src_ids {
+                    let slot = &slots[id];
+                    if slot.remaining_consumers.load(Ordering::Acquire) == 1
+                        && !no_delete.contains(&id.0)
+                    {
+                        owned.push(slot.tensor.write().unwrap().take());
+                        borrowed.push(None);
+                    } else {
+                        owned.push(None);
+                        borrowed.push(Some(slot.tensor.read().unwrap()));
+                    }
+                }
+                let srcs = owned
+                    .into_iter()
+                    .zip(&borrowed)
+                    .zip(src_ids)
+                    .map(|((owned, borrowed), (_, st))| {
+                        let mut st = st.clone();
+                        // Substitute in the dyn dims
+                        st.resolve_global_dyn_dims_stack(dyn_map, dim_stack);
+                        let tensor = match owned {
+                            Some(t) => InputTensor::Owned(t),
+                            None => {
+                                InputTensor::Borrowed(borrowed.as_ref().unwrap().as_ref().unwrap())
+                            }
+                        };
+                        (tensor, st)
+                    })
+                    .collect::<Vec<_>>();
+
+                // Execute
+                let tensors = ops[task].lock().unwrap().process(srcs);
+                drop(borrowed);
+                for (i, tensor) in tensors.into_iter().enumerate() {
+                    if let Some(slot) = slots.get(&(*node, i as u8)) {
+                        *slot.tensor.write().unwrap() = Some(tensor);
+                    } else {
+                        unconsumed.lock().unwrap().push(((*node, i as u8), tensor));
+                    }
+                }
+
+                // Bookkeep remaining consumers, freeing tensors nobody needs anymore
+                for (id, _) in src_ids {
+                    let slot = &slots[id];
+                    if slot.remaining_consumers.fetch_sub(1, Ordering::AcqRel) == 1
+                        && !no_delete.contains(&id.0)
+                    {
+                        slot.tensor.write().unwrap().take();
+                    }
+                }
+            }
+            dependents[task]
+                .iter()
+                .copied()
+                .filter(|d| waiting_on[*d].fetch_sub(1, Ordering::AcqRel) == 1)
+                .collect()
+        };
+
+        std::thread::scope(|scope| {
+            for _ in 0..threads.clamp(1, linearized.len().max(1)) {
+                scope.spawn(|| {
+                    let mut dim_stack = Vec::new();
+                    loop {
+                        let task = {
+                            let mut queue = queue.lock().unwrap();
+                            loop {
+                                if queue.remaining == 0 {
+                                    return;
+                                }
+                                if let Some(task) = queue.ready.pop() {
+                                    break task;
+                                }
+                                queue = queue_changed.wait(queue).unwrap();
+                            }
+                        };
+                        let result =
+                            panic::catch_unwind(AssertUnwindSafe(|| run(task, &mut dim_stack)));
+                        let mut queue = queue.lock().unwrap();
+                        match result {
+                            Ok(ready) => {
+                                queue.ready.extend(ready);
+                                queue.remaining -= 1;
+                                queue_changed.notify_all();
+                            }
+                            Err(e) => {
+                                // Stop the other workers before propagating the panic
+                                queue.remaining = 0;
+                                queue_changed.notify_all();
+                                drop(queue);
+                                panic::resume_unwind(e);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        for (id, slot) in slots {
+            if let Some(tensor) = slot.tensor.into_inner().unwrap() {
+                self.tensors.insert(id, tensor);
+            }
+        }
+        self.tensors.extend(unconsumed.into_inner().unwrap());
+        self.reset();
+    }
+
+    /// The current memory plan, if one has been made
+    pub fn memory_plan(&self) -> Option<&MemoryPlan> {
+        self.memory_plan.as_ref()
+    }
+
+    /// Execute the graph, writing intermediate tensors into the buffers of the memory plan instead of allocating them.
+    ///
+    /// Ops that can't write into existing buffers fall back to allocating. If the graph has no memory plan (see
+    /// [`crate::compilers::MemoryPlanner`]) or the plan is out of date, a new one is made.
+    pub fn execute_planned(&mut self) {
+        if self.linearized_graph.is_none() {
+            self.toposort();
+        }
+        let linearized = self.linearized_graph.as_ref().unwrap();
+        let mut plan = self
+            .memory_plan
+            .take()
+            .filter(|p| p.is_valid_for(linearized))
+            .unwrap_or_else(|| MemoryPlan::new(self));
         let mut remaining_consumers = self.consumers_map.as_ref().unwrap().clone();
-        let mut op_times = FxHashMap::default();
+        let mut dim_stack = Vec::new();
 
-        println!(
-            "{:->2$} Executing {:->2$}",
-            "",
-            "",
-            (term_size::dimensions().unwrap().0 - " Executing ".len()) / 2
-        );
-        let start = std::time::Instant::now();
-        for (node, src_ids) in self.linearized_graph.as_ref().unwrap().iter() {
+        for (node, src_ids) in linearized {
             if self.tensors.contains_key(&(*node, 0)) {
                 continue;
             }
-            let op_name = format!("{:?}", self.graph.node_weight(*node).unwrap());
-            print!("{}", op_name.bold().bright_green());
-
-            let mut srcs = Vec::new();
-            get_source_tensors(
-                &self.no_delete,
-                tensors_ptr,
-                src_ids,
-                &remaining_consumers,
-                &mut srcs,
-            );
+            // Only write into the arena if the output won't be needed after execution
+            let mut output = plan
+                .assignments
+                .get(node)
+                .filter(|_| !self.no_delete.contains(node) && !self.to_retrieve.contains(node))
+                .map(|b| (*b, [plan.buffers[*b].take().unwrap()]));
+
+            // Tensors not in the arena follow the usual ownership rules
+            let owned = src_ids
+                .iter()
+                .map(|(id, _)| {
+                    if remaining_consumers[id] == 1 && !self.no_delete.contains(&id.0) {
+                        self.tensors.remove(id)
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            let mut srcs = owned
+                .into_iter()
+                .zip(src_ids)
+                .map(|(owned, (id, st))| {
+                    let tensor = match owned {
+                        Some(t) => InputTensor::Owned(t),
+                        None => InputTensor::Borrowed(
+                            self.tensors
+                                .get(id)
+                                .or_else(|| {
+                                    plan.assignments
+                                        .get(&id.0)
+                                        .and_then(|b| plan.buffers[*b].as_ref())
+                                })
+                                .unwrap(),
+                        ),
+                    };
+                    (tensor, st.clone())
+                })
+                .collect::<Vec<_>>();
 
             // Substitute in the dyn dims
             for (_, st) in srcs.iter_mut() {
                 st.resolve_global_dyn_dims_stack(&self.dyn_map, &mut dim_stack);
             }
 
-            // All sources are ready
-            let mut shapes_string = srcs
+            // Execute
+            let op = self.graph.node_weight_mut(*node).unwrap();
+            let written = output
+                .as_mut()
+                .map(|(_, out)| op.process_into(&srcs, out))
+                .unwrap_or_default();
+            if !written {
+                for (i, tensor) in op.process(srcs).into_iter().enumerate() {
+                    self.tensors.insert((*node, i as u8), tensor);
+                }
+            }
+            if let Some((buffer, [out])) = output {
+                plan.buffers[buffer] = Some(out);
+            }
+
+            // Bookkeep remaining consumers
+            for (source, _) in src_ids {
+                *remaining_consumers.get_mut(source).unwrap() -= 1;
+            }
+        }
+        self.memory_plan = Some(plan);
+        self.reset();
+    }
+
+    /// Execute the graph without deleting intermediate tensors
+    pub fn execute_no_delete(&mut self) {
+        // Track the number of views pointing to each tensor so we know when to clear;
+        if self.linearized_graph.is_none() {
+            self.toposort();
+        }
+        let mut dim_stack = Vec::new();
+        for (node, src_ids) in self.linearized_graph.as_ref().unwrap().iter() {
+            if self.tensors.contains_key(&(*node, 0)) {
+                continue;
+            }
+            let mut srcs = src_ids
                 .iter()
-                .map(|(_, s)| {
-                    format!(
-                        "{:?}",
-                        s.shape()
-                            .into_iter()
-                            .map(|i| i.to_usize().unwrap())
-                            .collect::<Vec<_>>()
+                .map(|(id, st)| {
+                    (
+                        InputTensor::Borrowed(self.tensors.get(id).unwrap()),
+                        st.clone(),
                     )
                 })
-                .join(", ");
-            if !shapes_string.is_empty() {
-                shapes_string = format!(" ({shapes_string})");
+                .collect_vec();
+
+            // Substitute in the dyn dims
+            for (_, st) in srcs.iter_mut() {
+                st.resolve_global_dyn_dims_stack(&self.dyn_map, &mut dim_stack);
             }
-            print!("{shapes_string}");
-            std::io::stdout().flush().unwrap();
-            // Execute
-            let now = std::time::Instant::now();
+
+            // All sources are ready, execute
             let tensors = self.graph.node_weight_mut(*node).unwrap().process(srcs);
-            let elapsed = now.elapsed();
-            println!(
-                "{:.>1$}",
-                if elapsed.as_secs() > 0 {
-                    format!("{:.2}s", elapsed.as_secs_f32())
-                } else if elapsed.as_millis() > 0 {
-                    format!("{}ms", elapsed.as_millis())
-                } else {
-                    format!("{}µs", elapsed.as_micros())
-                }
-                .bold(),
-                term_size::dimensions().unwrap().0 - op_name.len() - shapes_string.len(),
-            );
             for (i, tensor) in tensors.into_iter().enumerate() {
                 self.tensors.insert((*node, i as u8), tensor);
             }
-            if let Some(t) = op_times.get_mut(&op_name) {
-                *t += elapsed;
-            } else {
-                op_times.insert(op_name, elapsed);
-            }
+        }
+    }
 
-            // Check if we can delete the source tensors now
-            for (source, _) in src_ids {
-                *remaining_consumers.get_mut(source).unwrap() -= 1;
+    /// Execute the graph, printing how long each op took
+    pub fn execute_debug(&mut self) {
+        self.execute_profile().print();
+    }
+}
+
+/// A tensor shared between the threads of the parallel executor
+struct TensorSlot {
+    tensor: RwLock<Option<Tensor>>,
+    remaining_consumers: AtomicUsize,
+}
+
+/// Nodes ready to run in the parallel executor, and how many nodes haven't finished yet
+struct ReadyQueue {
+    ready: Vec<usize>,
+    remaining: usize,
+}
+
+/// Why [`Graph::try_execute`] failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionError {
+    /// A tensor was never given a value
+    UnsetInput { node: NodeIndex, name: String },
+    /// A tensor load, such as a weight loader, failed
+    Load {
+        node: NodeIndex,
+        name: String,
+        error: op::OpError,
+    },
+    /// A dynamic dimension in an op's input shapes isn't in the graph's `dyn_map`
+    UnboundDim {
+        node: NodeIndex,
+        op: String,
+        dim: symbolic::Symbol,
+    },
+    /// An op's input was never produced
+    MissingInput {
+        node: NodeIndex,
+        op: String,
+        source: NodeIndex,
+        output: u8,
+    },
+    /// An op reported an error
+    Op {
+        node: NodeIndex,
+        op: String,
+        shapes: Vec<Vec<usize>>,
+        error: op::OpError,
+    },
+}
+
+impl ExecutionError {
+    fn from_op_error(
+        node: NodeIndex,
+        op: &dyn Operator,
+        src_ids: &[((NodeIndex, u8), ShapeTracker)],
+        dyn_map: &FxHashMap<symbolic::Symbol, usize>,
+        error: op::OpError,
+    ) -> Self {
+        let name = format!("{op:?}");
+        if op.as_any().is::<op::Function>() {
+            return match error {
+                op::OpError::Unset => ExecutionError::UnsetInput { node, name },
+                error => ExecutionError::Load { node, name, error },
+            };
+        }
+        let shapes = src_ids
+            .iter()
+            .map(|(_, st)| {
+                let mut st = st.clone();
+                st.resolve_global_dyn_dims(dyn_map);
+                st.shape()
+                    .into_iter()
+                    .map(|d| d.to_usize().unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+        ExecutionError::Op {
+            node,
+            op: name,
+            shapes,
+            error,
+        }
+    }
+}
+
+impl std::fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionError::UnsetInput { node, name } => {
+                write!(f, "{name} (node {}): {}", node.index(), op::OpError::Unset)
             }
+            ExecutionError::Load { node, name, error } => {
+                write!(f, "{name} (node {}) failed to load: {error}", node.index())
+            }
+            ExecutionError::UnboundDim { node, op, dim } => write!(
+                f,
+                "{op} (node {}) needs dynamic dimension '{dim}', which isn't set",
+                node.index()
+            ),
+            ExecutionError::MissingInput {
+                node,
+                op,
+                source,
+                output,
+            } => write!(
+                f,
+                "{op} (node {}) is missing output {output} of node {}",
+                node.index(),
+                source.index()
+            ),
+            ExecutionError::Op {
+                node,
+                op,
+                shapes,
+                error,
+            } => write!(
+                f,
+                "{op} (node {}) with input shapes {shapes:?} failed: {error}",
+                node.index()
+            ),
         }
+    }
+}
 
-        // Print out total times
-        println!();
-        println!(
-            "{:->2$} Total Times {:->2$}",
-            "",
-            "",
-            (term_size::dimensions().unwrap().0 - " Total Times ".len()) / 2
-        );
-        for (name, elapsed) in op_times.into_iter().sorted_by(|(_, a), (_, b)| b.cmp(a)) {
-            print!("{}", name.bold().bright_green());
-            println!(
-                "{:.>1$}",
-                if elapsed.as_secs() > 0 {
-                    format!("{:.2}s", elapsed.as_secs_f32())
-                } else if elapsed.as_millis() > 0 {
-                    format!("{}ms", elapsed.as_millis())
-                } else {
-                    format!("{}µs", elapsed.as_micros())
-                }
-                .bold(),
-                term_size::dimensions().unwrap().0 - name.len(),
-            );
+impl std::error::Error for ExecutionError {}
+
+/// Make sure every input of a node is there and has its dyn dims set
+fn check_sources(
+    graph: &MainGraph,
+    tensors: &FxHashMap<(NodeIndex, u8), Tensor>,
+    dyn_map: &FxHashMap<symbolic::Symbol, usize>,
+    node: NodeIndex,
+    src_ids: &[((NodeIndex, u8), ShapeTracker)],
+) -> Option<ExecutionError> {
+    for ((source, output), st) in src_ids {
+        if !tensors.contains_key(&(*source, *output)) {
+            return Some(ExecutionError::MissingInput {
+                node,
+                op: format!("{:?}", graph[node]),
+                source: *source,
+                output: *output,
+            });
+        }
+        if let Some(dim) = st
+            .symbols()
+            .into_iter()
+            .find(|d| *d != '-' && !dyn_map.contains_key(d))
+        {
+            return Some(ExecutionError::UnboundDim {
+                node,
+                op: format!("{:?}", graph[node]),
+                dim,
+            });
         }
-        println!(
-            "Total: {}",
-            if start.elapsed().as_secs() > 0 {
-                format!("{:.2}s", start.elapsed().as_secs_f32())
-            } else if start.elapsed().as_millis() > 0 {
-                format!("{}ms", start.elapsed().as_millis())
-            } else {
-                format!("{}µs", start.elapsed().as_micros())
-            }
-            .bold()
-        );
-        self.reset();
     }
+    None
 }
 
 /// Get source tensor array for a node
-fn get_source_tensors(
+pub(crate) fn get_source_tensors(
     no_delete: &FxHashSet<NodeIndex>,
     tensors: *mut FxHashMap<(NodeIndex, u8), Tensor>,
     src_ids: &[((NodeIndex, u8), ShapeTracker)],
@@ -412,12 +910,12 @@ fn get_source_tensors(
         if remaining_consumers[id] == 1 && !no_delete.contains(&id.0) {
             srcs.push((
                 InputTensor::Owned(unsafe { tensors.as_mut().unwrap() }.remove(id).unwrap()),
-                *sh,
+                sh.clone(),
             ));
         } else {
             srcs.push((
                 InputTensor::Borrowed(unsafe { tensors.as_ref().unwrap() }.get(id).unwrap()),
-                *sh,
+                sh.clone(),
             ));
         }
     }
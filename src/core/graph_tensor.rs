@@ -1,12 +1,21 @@
 use crate::{
+    dtype::{DType, Element},
     graph::Graph,
-    op::{self, Function},
+    op::{self, input_as, Function, InputTensor},
     prelude::Data,
-    shape::*,
+    shape::{
+        symbolic::{Expression, Term},
+        *,
+    },
     tensor::Tensor,
 };
 use std::marker::PhantomData;
-use std::{fmt::Debug, path::Path};
+use std::{
+    fmt::Debug,
+    ops::{Deref, DerefMut},
+    path::Path,
+    ptr::NonNull,
+};
 
 use petgraph::graph::NodeIndex;
 
@@ -26,12 +35,91 @@ pub struct GraphTensor<S: Shape> {
     pub id: NodeIndex,
     pub graph_ref: *mut Graph,
     pub(crate) _phantom: PhantomData<S>,
-    pub shape: ShapeTracker,
+    pub shape: ShapeRef,
+}
+
+/// A shape tracker owned by a graph. Trackers aren't `Copy` since high rank ones spill to the heap,
+/// so tensors hold one of these instead, which stays valid as long as the graph does.
+///
+/// Copies of a handle share their tracker, so changing the tracker through one gives it a new tracker
+/// on the graph, the same as if trackers were copied.
+#[derive(Clone, Copy)]
+pub struct ShapeRef {
+    tracker: NonNull<ShapeTracker>,
+    graph_ref: *mut Graph,
+}
+
+impl Deref for ShapeRef {
+    type Target = ShapeTracker;
+    fn deref(&self) -> &ShapeTracker {
+        // SAFETY: trackers are never moved or dropped before their graph
+        unsafe { self.tracker.as_ref() }
+    }
+}
+
+impl DerefMut for ShapeRef {
+    fn deref_mut(&mut self) -> &mut ShapeTracker {
+        let shape = (**self).clone();
+        let graph_ref = self.graph_ref;
+        *self = unsafe { graph_ref.as_mut().unwrap() }
+            .shapes
+            .alloc(shape, graph_ref);
+        // SAFETY: the new tracker is only reachable through this handle so far
+        unsafe { self.tracker.as_mut() }
+    }
+}
+
+impl Debug for ShapeRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
+impl From<ShapeRef> for ShapeTracker {
+    fn from(value: ShapeRef) -> Self {
+        value.deref().clone()
+    }
+}
+
+/// Trackers per chunk of a [`ShapeArena`]
+const SHAPE_CHUNK_SIZE: usize = 256;
+
+/// Storage for the trackers of a graph's tensors. Trackers are stored in chunks that are never
+/// reallocated, so they don't move until the graph is dropped.
+#[derive(Default)]
+pub(crate) struct ShapeArena {
+    chunks: Vec<Vec<ShapeTracker>>,
+}
+
+impl ShapeArena {
+    pub(crate) fn alloc(&mut self, shape: ShapeTracker, graph_ref: *mut Graph) -> ShapeRef {
+        if self.chunks.last().is_none_or(|c| c.len() == c.capacity()) {
+            self.chunks.push(Vec::with_capacity(SHAPE_CHUNK_SIZE));
+        }
+        let chunk = self.chunks.last_mut().unwrap();
+        chunk.push(shape);
+        ShapeRef {
+            tracker: NonNull::from(chunk.last_mut().unwrap()),
+            graph_ref,
+        }
+    }
+}
+
+impl Debug for ShapeArena {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let n = self.chunks.iter().map(|c| c.len()).sum::<usize>();
+        write!(f, "ShapeArena({n} trackers)")
+    }
 }
 
 impl<S: Shape> GraphTensor<S> {
-    /// Create a GraphTensor from a NodeIndex
-    pub fn from_id(id: NodeIndex, shape: ShapeTracker, graph_ref: *mut Graph) -> Self {
+    /// Create a GraphTensor from a NodeIndex. The shape is stored on the graph, which has to be valid like for
+    /// every other use of the tensor.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn from_id(id: NodeIndex, shape: impl Into<ShapeTracker>, graph_ref: *mut Graph) -> Self {
+        let shape = unsafe { graph_ref.as_mut().unwrap() }
+            .shapes
+            .alloc(shape.into(), graph_ref);
         Self {
             id,
             graph_ref,
@@ -40,6 +128,24 @@ impl<S: Shape> GraphTensor<S> {
         }
     }
 
+    /// The element type of the tensor's data, as recorded on the graph
+    pub fn dtype(&self) -> DType {
+        self.graph().dtype(self.id)
+    }
+
+    /// Convert the tensor to another element type
+    pub fn cast(self, dtype: DType) -> Self {
+        if self.dtype() == dtype {
+            return self;
+        }
+        let new_id = self
+            .graph()
+            .add_op(op::Cast(dtype))
+            .input(self.id, 0, self.shape)
+            .finish();
+        GraphTensor::from_id(new_id, self.shape, self.graph_ref)
+    }
+
     /// Mark this tensor to not be deleted
     pub fn keep(self) -> Self {
         self.graph().keep_tensors(self.id);
@@ -64,6 +170,43 @@ impl<S: Shape> GraphTensor<S> {
         unsafe { self.graph_ref.as_mut().unwrap() }
     }
 
+    /// Replace the load function of this tensor. The element type it produces, if known, is recorded on the graph.
+    #[allow(clippy::type_complexity)]
+    fn set_function(
+        &self,
+        dtype: Option<DType>,
+        function: Box<
+            dyn Fn(Vec<(InputTensor, ShapeTracker)>) -> Result<Vec<Tensor>, op::OpError> + Send,
+        >,
+    ) {
+        let node = self
+            .graph()
+            .graph
+            .node_weight_mut(self.id)
+            .unwrap()
+            .as_any_mut()
+            .downcast_mut::<Function>()
+            .unwrap();
+        node.1 = function;
+        if let Some(dtype) = dtype {
+            node.2 = dtype;
+        }
+        self.graph().update_dtype(self.id);
+    }
+
+    /// Set the tensor with a generating closure to be ran at runtime
+    pub fn set_deferred(self, loader: impl Fn() -> Vec<f32> + Send + 'static) -> Self {
+        self.set_function(
+            Some(DType::F32),
+            Box::new(move |_| {
+                Ok(vec![Tensor {
+                    data: Box::new(loader()),
+                }])
+            }),
+        );
+        self
+    }
+
     /// Set the value of the tensor, with dynamic dimensions.
     /// ```rust
     /// use luminal::prelude::*;
@@ -73,33 +216,52 @@ impl<S: Shape> GraphTensor<S> {
     ///     .set_dyn(vec![1., 2., 3., 4.], &[2, 2]);
     /// ```
     ///
+    /// Dims that are a single dynamic dim get set to their size. Other dims have to match their size once the
+    /// dynamic dims they use are set. The shape type gives the dims, except for `Dyn<'-'>` dims and untyped
+    /// tensors, whose dims (like string-named ones) are only on the shape tracker.
+    ///
     /// TODO: shape should be a const sized array. Blocked by https://github.com/rust-lang/rust/issues/60551
     pub fn set_dyn<T: Data + Clone>(self, data: T, shape: &[usize]) -> Self {
         // Report dyn dim values to graph dyn map
-        assert_eq!(
-            S::realized_shape().len(),
-            shape.len(),
-            "Number of dimensions don't match!"
-        );
-        for (d, s) in S::realized_shape().iter().zip(shape.iter()) {
-            if let Some(c) = d.to_symbols().pop() {
-                self.graph().dyn_map.insert(c, *s);
+        let (typed, tracked) = (S::realized_shape(), self.shape.shape());
+        let dims = if typed.len() == tracked.len() {
+            typed
+                .into_iter()
+                .zip(tracked)
+                .map(|(t, d)| if t.is_unknown() { d.into() } else { t })
+                .collect()
+        } else {
+            tracked
+                .into_iter()
+                .map(Expression::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dims.len(), shape.len(), "Number of dimensions don't match!");
+        let mut compound = vec![];
+        for (d, s) in dims.iter().zip(shape.iter()) {
+            match d.terms().as_slice() {
+                [Term::Var(c)] => {
+                    self.graph().set_dyn_dim(*c, *s);
+                }
+                _ => compound.push((*d, *s)),
             }
         }
-        let node = self
-            .graph()
-            .graph
-            .node_weight_mut(self.id)
-            .unwrap()
-            .as_any_mut()
-            .downcast_mut::<Function>()
-            .unwrap();
+        // Check the other dims now their dyn dims are known
+        for (d, s) in compound {
+            let value = d.exec(&self.graph().dyn_map).unwrap_or_else(|| {
+                panic!("Can't infer the dynamic dims of {d:?} from its size. Set them with Graph::set_dyn_dim")
+            });
+            assert_eq!(value, s, "Dimension {d:?} is {value}, but was set to {s}");
+        }
         // We shouldn't do cloning here!
-        node.1 = Box::new(move |_| {
-            vec![Tensor {
-                data: Box::new(data.clone()),
-            }]
-        });
+        self.set_function(
+            DType::of(&data),
+            Box::new(move |_| {
+                Ok(vec![Tensor {
+                    data: Box::new(data.clone()),
+                }])
+            }),
+        );
         self
     }
 
@@ -141,19 +303,23 @@ impl<S: Shape> GraphTensor<S> {
         GraphTensor::from_id(self.id, self.shape, self.graph_ref)
     }
 
-    /// Get the contiguous data of the tensor
+    /// Get the contiguous data of the tensor, converted to f32
     pub fn data(&self) -> Vec<f32> {
-        let mut st = self.shape;
+        self.data_as()
+    }
+
+    /// Get the contiguous data of the tensor as `T`, converting it if it's stored as another type
+    pub fn data_as<T: Element>(&self) -> Vec<T> {
+        let mut st: ShapeTracker = self.shape.into();
         st.resolve_global_dyn_dims(&self.graph().dyn_map);
         let tensor = self.graph().get_tensor_ref(self.id, 0).unwrap();
-        let orig_data = tensor.data.as_any().downcast_ref::<Vec<f32>>().unwrap();
-        let mut data = vec![0.; st.n_elements().to_usize().unwrap()];
-        let ind = st.index_expression();
-        let val = st.valid_expression();
-        #[allow(unused_mut)]
-        for (i, mut r) in data.iter_mut().enumerate() {
-            if val.exec_single_var(i) != 0 {
-                *r = orig_data[ind.exec_single_var(i)];
+        let tensor = InputTensor::Borrowed(tensor);
+        let orig_data = input_as::<T>(&tensor);
+        let mut data = vec![T::ZERO; st.n_elements().to_usize().unwrap()];
+        let n = data.len();
+        for (r, i) in data.iter_mut().zip(Indexer::cached(&st).iter(n)) {
+            if let Some(i) = i {
+                *r = orig_data[i];
             }
         }
         data
@@ -163,39 +329,12 @@ impl<S: Shape> GraphTensor<S> {
 impl<S: ConstShape> GraphTensor<S> {
     /// Set the value of the tensor matching the constant shape
     pub fn set<T: Data + Clone, D: ToData<S, T>>(self, data: D) -> Self {
-        let node = self
-            .graph()
-            .graph
-            .node_weight_mut(self.id)
-            .unwrap()
-            .as_any_mut()
-            .downcast_mut::<Function>()
-            .unwrap();
         let data = data.to_data_vec();
         // We shouldn't do cloning here!
-        node.1 = Box::new(move |_| vec![Tensor::new(data.clone())]);
-        self
-    }
-
-    /// Set the tensor with a generating closure to be ran at runtime
-    pub fn set_deferred(self, loader: impl Fn() -> Vec<f32> + 'static) -> Self {
-        let node = self
-            .graph()
-            .graph
-            .node_weight_mut(self.id)
-            .unwrap()
-            .as_any_mut()
-            .downcast_mut::<Function>()
-            .unwrap();
-
-        // Set the closure here
-        node.1 = Box::new(move |_| {
-            vec![Tensor {
-                data: Box::new(loader()),
-            }]
-        });
-
-        // Return
+        self.set_function(
+            DType::of(&data),
+            Box::new(move |_| Ok(vec![Tensor::new(data.clone())])),
+        );
         self
     }
 }
@@ -421,11 +560,19 @@ pub trait ToData<S: Shape, T> {
     fn to_data_vec(self) -> T;
 }
 
-impl<S: Shape> ToData<S, Vec<f32>> for Vec<f32> {
-    fn to_data_vec(self) -> Vec<f32> {
-        self
-    }
+macro_rules! vec_to_data {
+    ($($t:ty),*) => {
+        $(
+            impl<S: Shape> ToData<S, Vec<$t>> for Vec<$t> {
+                fn to_data_vec(self) -> Vec<$t> {
+                    self
+                }
+            }
+        )*
+    };
 }
+
+vec_to_data!(f32, half::f16, half::bf16, i32, i64, bool);
 impl<const A: usize> ToData<(Const<A>,), Vec<f32>> for [f32; A] {
     fn to_data_vec(self) -> Vec<f32> {
         self.to_vec()
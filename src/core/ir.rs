@@ -0,0 +1,741 @@
+//! A textual IR for saving and loading graphs without rebuilding and recompiling them.
+//!
+//! Each line is one of:
+//! ```text
+//! luminal-ir 1
+//! node <index> <op name> <op args>
+//! edge <source> <dest> data <input order> <output order> <shape tracker>
+//! edge <source> <dest> schedule
+//! keep <index> <index> ...
+//! retrieve <index> <index> ...
+//! ```
+//!
+//! Expressions are written in postfix, with terms joined by `_` (`a_2_mul` is `a * 2`). Variables are written as `v`
+//! followed by the variable, so only alphanumeric variables (and the unknown `-` variable) can be serialized.
+//!
+//! Ops are (de)serialized through an [`OpRegistry`]. The default registry knows about all primops and CPU ops, and
+//! other ops can be added by implementing [`SerializeOp`] and registering them.
+
+use std::fmt::Write;
+
+use petgraph::{
+    stable_graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    dtype::DType,
+    graph::{Dependency, Graph},
+    op::{self, Operator},
+    shape::{
+        symbolic::{BigExpression, Symbol, Term},
+        tracker::ShapeTracker,
+    },
+    tensor::Tensor,
+};
+
+const HEADER: &str = "luminal-ir 1";
+
+/// An error while saving or loading IR
+#[derive(Debug, Clone, PartialEq)]
+pub enum IrError {
+    /// The op isn't registered, so it can't be saved
+    UnregisteredOp(String),
+    /// An op name in the IR isn't registered
+    UnknownOp(String),
+    /// A variable that can't be written in the IR
+    UnsupportedVariable(Symbol),
+    /// IR can only be loaded into an empty graph
+    GraphNotEmpty,
+    /// The IR is malformed
+    Parse { line: usize, message: String },
+}
+
+impl std::fmt::Display for IrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IrError::UnregisteredOp(op) => write!(f, "No serializer registered for op {op}"),
+            IrError::UnknownOp(op) => write!(f, "No deserializer registered for op {op}"),
+            IrError::UnsupportedVariable(c) => write!(f, "Variable '{c}' can't be serialized"),
+            IrError::GraphNotEmpty => write!(f, "IR can only be loaded into an empty graph"),
+            IrError::Parse { line, message } => write!(f, "Line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for IrError {}
+
+/// An op that can be written to and read from the IR
+pub trait SerializeOp: Operator + Sized + 'static {
+    /// The name the op is stored under. Must be unique and can't contain whitespace
+    const NAME: &'static str;
+    /// Write the op's arguments. These can't contain newlines
+    fn serialize_op(&self) -> Result<String, IrError>;
+    /// Read the op back from its arguments
+    fn deserialize_op(args: &str, graph: &mut Graph) -> Option<Self>;
+}
+
+#[allow(clippy::type_complexity)]
+struct RegisteredOp {
+    name: &'static str,
+    serialize: fn(&dyn Operator) -> Option<Result<String, IrError>>,
+    deserialize: fn(&str, &mut Graph) -> Option<Box<dyn Operator>>,
+}
+
+/// The set of ops that can be saved and loaded
+pub struct OpRegistry {
+    ops: Vec<RegisteredOp>,
+}
+
+impl Default for OpRegistry {
+    /// A registry with all primops and CPU ops
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register::<op::Function>()
+            .register::<op::Constant>()
+            .register::<op::ConstantTensor>()
+            .register::<op::Contiguous>()
+            .register::<op::Cast>()
+            .register::<op::Gather>()
+            .register::<op::Random>()
+            .register::<op::Log2>()
+            .register::<op::Exp2>()
+            .register::<op::Sin>()
+            .register::<op::Recip>()
+            .register::<op::Sqrt>()
+            .register::<op::Add>()
+            .register::<op::Mul>()
+            .register::<op::Mod>()
+            .register::<op::LessThan>()
+            .register::<op::SumReduce>()
+            .register::<op::MaxReduce>();
+        crate::compilers::register_cpu_ops(&mut registry);
+        registry
+    }
+}
+
+impl OpRegistry {
+    /// A registry without any ops
+    pub fn empty() -> Self {
+        Self { ops: vec![] }
+    }
+
+    /// Register an op, replacing any op registered under the same name
+    pub fn register<O: SerializeOp>(&mut self) -> &mut Self {
+        assert!(
+            !O::NAME.is_empty() && !O::NAME.contains(char::is_whitespace),
+            "Op names can't be empty or contain whitespace"
+        );
+        self.ops.retain(|o| o.name != O::NAME);
+        self.ops.push(RegisteredOp {
+            name: O::NAME,
+            serialize: |op| op.as_any().downcast_ref::<O>().map(O::serialize_op),
+            deserialize: |args, graph| {
+                O::deserialize_op(args, graph).map(|o| Box::new(o) as Box<dyn Operator>)
+            },
+        });
+        self
+    }
+
+    fn serialize(&self, op: &dyn Operator) -> Result<(&'static str, String), IrError> {
+        for o in &self.ops {
+            if let Some(args) = (o.serialize)(op) {
+                return Ok((o.name, args?));
+            }
+        }
+        Err(IrError::UnregisteredOp(format!("{op:?}")))
+    }
+}
+
+impl Graph {
+    /// Write the graph out as IR. Tensor data isn't included.
+    pub fn to_ir(&self, registry: &OpRegistry) -> Result<String, IrError> {
+        let mut ir = format!("{HEADER}\n");
+        for node in self.graph.node_indices() {
+            let (name, args) =
+                registry.serialize(self.graph.node_weight(node).unwrap().as_ref())?;
+            write!(ir, "node {} {name}", node.index()).unwrap();
+            if !args.is_empty() {
+                write!(ir, " {args}").unwrap();
+            }
+            ir.push('\n');
+        }
+        for edge in self.graph.edge_references() {
+            write!(
+                ir,
+                "edge {} {} ",
+                edge.source().index(),
+                edge.target().index()
+            )
+            .unwrap();
+            match edge.weight() {
+                Dependency::Data {
+                    input_order,
+                    output_order,
+                    shape,
+                    ..
+                } => writeln!(
+                    ir,
+                    "data {input_order} {output_order} {}",
+                    write_tracker(shape)?
+                )
+                .unwrap(),
+                Dependency::Schedule => ir.push_str("schedule\n"),
+            }
+        }
+        for (keyword, set) in [("keep", &self.no_delete), ("retrieve", &self.to_retrieve)] {
+            if !set.is_empty() {
+                let mut nodes = set.iter().map(|n| n.index()).collect::<Vec<_>>();
+                nodes.sort();
+                write!(ir, "{keyword}").unwrap();
+                for n in nodes {
+                    write!(ir, " {n}").unwrap();
+                }
+                ir.push('\n');
+            }
+        }
+        Ok(ir)
+    }
+
+    /// Load IR into this graph, which must be empty. Node indexes are the same as in the graph the IR was made from.
+    pub fn load_ir(&mut self, ir: &str, registry: &OpRegistry) -> Result<(), IrError> {
+        if self.graph.node_count() != 0 {
+            return Err(IrError::GraphNotEmpty);
+        }
+        let mut lines = ir.lines().enumerate().map(|(i, l)| (i + 1, l));
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(parse_error(1, "Missing IR header")),
+        }
+
+        let mut nodes = FxHashMap::default();
+        let mut edges = vec![];
+        for (line, text) in lines {
+            let (keyword, rest) = text.split_once(' ').unwrap_or((text, ""));
+            match keyword {
+                "node" => {
+                    let mut parts = rest.splitn(3, ' ');
+                    let index = parse_index(parts.next(), line)?;
+                    let name = parts
+                        .next()
+                        .ok_or_else(|| parse_error(line, "Missing op name"))?;
+                    let args = parts.next().unwrap_or("");
+                    let registered = registry
+                        .ops
+                        .iter()
+                        .find(|o| o.name == name)
+                        .ok_or_else(|| IrError::UnknownOp(name.to_string()))?;
+                    let op = (registered.deserialize)(args, self).ok_or_else(|| {
+                        parse_error(line, &format!("Invalid arguments for {name}: {args:?}"))
+                    })?;
+                    if nodes.insert(index, op).is_some() {
+                        return Err(parse_error(line, "Duplicate node"));
+                    }
+                }
+                "edge" => {
+                    let mut parts = rest.split(' ');
+                    let src = parse_index(parts.next(), line)?;
+                    let dest = parse_index(parts.next(), line)?;
+                    let dependency = match parts.next() {
+                        Some("schedule") => Dependency::Schedule,
+                        Some("data") => Dependency::Data {
+                            input_order: parse_order(parts.next(), line)?,
+                            output_order: parse_order(parts.next(), line)?,
+                            shape: parse_tracker(&mut parts)
+                                .ok_or_else(|| parse_error(line, "Invalid shape tracker"))?,
+                            // Filled in from the ops once the graph is built
+                            dtype: DType::F32,
+                        },
+                        _ => return Err(parse_error(line, "Invalid dependency")),
+                    };
+                    edges.push((line, src, dest, dependency));
+                }
+                "keep" | "retrieve" => {
+                    for n in rest.split(' ').filter(|s| !s.is_empty()) {
+                        let n = NodeIndex::new(parse_index(Some(n), line)?);
+                        if keyword == "keep" {
+                            self.no_delete.insert(n);
+                        } else {
+                            self.to_retrieve.insert(n);
+                        }
+                    }
+                }
+                "" => {}
+                _ => return Err(parse_error(line, &format!("Unknown keyword {keyword}"))),
+            }
+        }
+
+        // Fill in any gaps with placeholders so each node ends up at its original index
+        let n_nodes = nodes.keys().max().map(|m| m + 1).unwrap_or_default();
+        let mut placeholders = vec![];
+        for i in 0..n_nodes {
+            let id = match nodes.remove(&i) {
+                Some(op) => self.graph.add_node(op),
+                None => {
+                    let id = self.graph.add_node(Box::new(op::Function(
+                        "Placeholder".to_string(),
+                        Box::new(|_| unreachable!()),
+                        DType::F32,
+                    )));
+                    placeholders.push(id);
+                    id
+                }
+            };
+            debug_assert_eq!(id.index(), i);
+        }
+        for (line, src, dest, dependency) in edges {
+            let (src, dest) = (NodeIndex::new(src), NodeIndex::new(dest));
+            if self.graph.node_weight(src).is_none()
+                || self.graph.node_weight(dest).is_none()
+                || placeholders.contains(&src)
+                || placeholders.contains(&dest)
+            {
+                return Err(parse_error(line, "Edge references a missing node"));
+            }
+            self.graph.add_edge(src, dest, dependency);
+        }
+        for p in placeholders {
+            self.graph.remove_node(p);
+        }
+        // Cycles are left for validation to report
+        for node in petgraph::algo::toposort(&self.graph, None).unwrap_or_default() {
+            self.update_dtype(node);
+        }
+        self.linearized_graph = None;
+        Ok(())
+    }
+}
+
+fn parse_error(line: usize, message: &str) -> IrError {
+    IrError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn parse_index(s: Option<&str>, line: usize) -> Result<usize, IrError> {
+    s.and_then(|s| s.parse().ok())
+        .ok_or_else(|| parse_error(line, "Expected an index"))
+}
+
+fn parse_order(s: Option<&str>, line: usize) -> Result<u8, IrError> {
+    u8::try_from(parse_index(s, line)?)
+        .map_err(|_| parse_error(line, "Edge orders must be below 256"))
+}
+
+/// Write an expression in postfix
+pub fn write_expression(expr: &[Term]) -> Result<String, IrError> {
+    let mut terms = vec![];
+    for term in expr {
+        terms.push(match *term {
+            Term::Num(n) => n.to_string(),
+            // Terms are separated by underscores, so they're written as dots in names
+            Term::Var(c)
+                if c.as_str()
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+            {
+                format!("v{}", c.as_str().replace('_', "."))
+            }
+            Term::Var(c) => return Err(IrError::UnsupportedVariable(c)),
+            Term::Add => "add".to_string(),
+            Term::Sub => "sub".to_string(),
+            Term::Mul => "mul".to_string(),
+            Term::Div => "div".to_string(),
+            Term::Mod => "mod".to_string(),
+            Term::Min => "min".to_string(),
+            Term::Max => "max".to_string(),
+            Term::And => "and".to_string(),
+            Term::Or => "or".to_string(),
+            Term::Gte => "gte".to_string(),
+            Term::Lt => "lt".to_string(),
+        });
+    }
+    Ok(terms.join("_"))
+}
+
+/// Read an expression written by [`write_expression`]
+pub fn parse_expression(s: &str) -> Option<BigExpression> {
+    let mut terms = vec![];
+    for term in s.split('_') {
+        terms.push(match term {
+            "add" => Term::Add,
+            "sub" => Term::Sub,
+            "mul" => Term::Mul,
+            "div" => Term::Div,
+            "mod" => Term::Mod,
+            "min" => Term::Min,
+            "max" => Term::Max,
+            "and" => Term::And,
+            "or" => Term::Or,
+            "gte" => Term::Gte,
+            "lt" => Term::Lt,
+            _ => match term.strip_prefix('v') {
+                Some(v) if !v.is_empty() => Term::Var(Symbol::new(&v.replace('.', "_"))),
+                Some(_) => return None,
+                None => Term::Num(term.parse().ok()?),
+            },
+        });
+    }
+    Some(BigExpression { terms })
+}
+
+/// Write a shape tracker as space separated fields: the number of dims, the dim order, then for each dim its size,
+/// whether it's fake, its slice and its padding
+fn write_tracker(tracker: &ShapeTracker) -> Result<String, IrError> {
+    let mut s = tracker.len().to_string();
+    if tracker.is_empty() {
+        return Ok(s);
+    }
+    let indexes = tracker.indexes.iter().map(|i| i.to_string());
+    write!(s, " {}", indexes.collect::<Vec<_>>().join(",")).unwrap();
+    for i in 0..tracker.len() {
+        write!(
+            s,
+            " {} {} {} {} {} {}",
+            write_expression(&tracker.dims[i].terms())?,
+            tracker.fake[i] as u8,
+            write_expression(&tracker.slices[i].0.terms())?,
+            write_expression(&tracker.slices[i].1.terms())?,
+            write_expression(&tracker.padding[i].0.terms())?,
+            write_expression(&tracker.padding[i].1.terms())?,
+        )
+        .unwrap();
+    }
+    Ok(s)
+}
+
+fn parse_tracker<'a>(parts: &mut impl Iterator<Item = &'a str>) -> Option<ShapeTracker> {
+    let n = parts.next()?.parse::<usize>().ok()?;
+    let mut tracker = ShapeTracker::new(&[]);
+    if n == 0 {
+        return Some(tracker);
+    }
+    for i in parts.next()?.split(',') {
+        tracker.indexes.push(i.parse().ok().filter(|i| *i < n)?);
+    }
+    if tracker.indexes.len() != n {
+        return None;
+    }
+    for _ in 0..n {
+        tracker.dims.push(parse_expression(parts.next()?)?.into());
+        tracker.fake.push(match parts.next()? {
+            "0" => false,
+            "1" => true,
+            _ => return None,
+        });
+        let slice = (
+            parse_expression(parts.next()?)?.into(),
+            parse_expression(parts.next()?)?.into(),
+        );
+        tracker.slices.push(slice);
+        let padding = (
+            parse_expression(parts.next()?)?.into(),
+            parse_expression(parts.next()?)?.into(),
+        );
+        tracker.padding.push(padding);
+    }
+    Some(tracker)
+}
+
+/// Implement [`SerializeOp`] for ops without any arguments
+macro_rules! unit_ops {
+    ($($op:ident),*) => {
+        $(
+            impl SerializeOp for op::$op {
+                const NAME: &'static str = stringify!($op);
+                fn serialize_op(&self) -> Result<String, IrError> {
+                    Ok(String::new())
+                }
+                fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+                    args.is_empty().then_some(op::$op)
+                }
+            }
+        )*
+    };
+}
+
+unit_ops!(Contiguous, Log2, Exp2, Sin, Recip, Sqrt, Add, Mul, Mod, LessThan);
+
+impl SerializeOp for op::SumReduce {
+    const NAME: &'static str = "SumReduce";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(self.0.to_string())
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        args.parse().ok().map(Self)
+    }
+}
+
+impl SerializeOp for op::MaxReduce {
+    const NAME: &'static str = "MaxReduce";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(self.0.to_string())
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        args.parse().ok().map(Self)
+    }
+}
+
+impl SerializeOp for op::Cast {
+    const NAME: &'static str = "Cast";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(self.0.to_string())
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        args.parse().ok().map(Self)
+    }
+}
+
+impl SerializeOp for op::Gather {
+    const NAME: &'static str = "Gather";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(self.embed_dim.to_string())
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        Some(Self {
+            embed_dim: args.parse().ok()?,
+        })
+    }
+}
+
+impl SerializeOp for op::Constant {
+    const NAME: &'static str = "Constant";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(match &self.0 {
+            op::ConstantValue::Float(f) => format!("f {f}"),
+            op::ConstantValue::Expression(e) => format!("e {}", write_expression(&e.terms)?),
+        })
+    }
+    fn deserialize_op(args: &str, graph: &mut Graph) -> Option<Self> {
+        let value = match args.split_once(' ')? {
+            ("f", f) => op::ConstantValue::Float(f.parse().ok()?),
+            ("e", e) => op::ConstantValue::Expression(parse_expression(e)?),
+            _ => return None,
+        };
+        Some(Self(value, &graph.dyn_map))
+    }
+}
+
+impl SerializeOp for op::ConstantTensor {
+    const NAME: &'static str = "ConstantTensor";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        let dtype = self.0.dtype().expect("Expected a CPU tensor");
+        // Written as the hex of the little endian bytes, so the data round trips exactly
+        let hex = self
+            .0
+            .to_le_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        Ok(format!("{dtype} {hex}"))
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        let (dtype, hex) = args.split_once(' ')?;
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self(Tensor::from_le_bytes(dtype.parse().ok()?, &bytes)))
+    }
+}
+
+impl SerializeOp for op::Random {
+    const NAME: &'static str = "Random";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        Ok(format!(
+            "{} {} {} {}",
+            self.distribution,
+            self.seed,
+            self.execution,
+            write_expression(&self.size.terms)?
+        ))
+    }
+    fn deserialize_op(args: &str, graph: &mut Graph) -> Option<Self> {
+        let mut args = args.splitn(4, ' ');
+        Some(Self {
+            distribution: args.next()?.parse().ok()?,
+            seed: args.next()?.parse().ok()?,
+            execution: args.next()?.parse().ok()?,
+            size: parse_expression(args.next()?)?,
+            dyn_map: &graph.dyn_map,
+        })
+    }
+}
+
+/// Functions are loaded as tensor loads with the same name and type. Their data needs to be set again after loading
+impl SerializeOp for op::Function {
+    const NAME: &'static str = "Function";
+    fn serialize_op(&self) -> Result<String, IrError> {
+        // The type goes first since names can contain spaces
+        Ok(format!("{} {}", self.2, self.0))
+    }
+    fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+        let (dtype, name) = args.split_once(' ')?;
+        Some(Self(
+            name.to_string(),
+            Box::new(|_| Err(op::OpError::Unset)),
+            dtype.parse().ok()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        op::{get_vec_from_tensor, InputTensor, Operator},
+        prelude::{symbolic::BigExpression, *},
+        tests::{assert_exact, random_vec},
+    };
+
+    #[test]
+    fn test_expression_round_trip() {
+        let e = (BigExpression::from('a') + 3).min('b') * -2 % BigExpression::from('-');
+        let s = write_expression(&e.terms).unwrap();
+        assert_eq!(parse_expression(&s).unwrap(), e);
+        let e = BigExpression::from("batch_size") * 'a';
+        let s = write_expression(&e.terms).unwrap();
+        assert_eq!(parse_expression(&s).unwrap(), e);
+        assert_eq!(
+            write_expression(&BigExpression::from('+').terms),
+            Err(IrError::UnsupportedVariable('+'.into()))
+        );
+        assert!(parse_expression("1_v_add").is_none());
+    }
+
+    #[test]
+    fn test_ir_round_trip() {
+        let mut cx = Graph::new();
+        let model =
+            <(crate::nn::linear::Linear<4, 5>, crate::nn::activation::ReLU)>::initialize(&mut cx);
+        let a = cx.tensor::<(Dyn<'s'>, Const<4>)>();
+        // The folded arange makes a ConstantTensor
+        let scale = (cx.arange::<Const<8>>() + 1.).expand::<(Dyn<'s'>, Const<8>), Axis<0>>();
+        let mut b = ((model.forward(a) * 2.).pad::<(Dyn<'s'>, Const<8>), _, _>(&[(0, 0), (1, 2)])
+            * scale)
+            .retrieve();
+        let ids = cx.tensor::<R1<3>>().set(vec![1i32, 2, 3]);
+        let mut c = (ids * 2.).retrieve();
+        cx.compile(
+            <(GenericCompiler, CPUCompiler)>::default(),
+            (&mut b, &mut c),
+        );
+        let weight = random_vec(20);
+        let input = random_vec(8);
+        model.0.weight.set(weight.clone());
+        a.set_dyn(input.clone(), &[2, 4]);
+        cx.execute();
+        let expected = b.data();
+        let expected_c = c.data_as::<i32>();
+
+        let registry = OpRegistry::default();
+        let ir = cx.to_ir(&registry).unwrap();
+        assert!(ir.contains("ConstantTensor"));
+        let mut loaded = Graph::new();
+        loaded.load_ir(&ir, &registry).unwrap();
+        assert_eq!(loaded.to_ir(&registry).unwrap(), ir);
+        assert_eq!(loaded.graph.node_count(), cx.graph.node_count());
+        // Non-f32 inputs keep their type
+        assert_eq!(loaded.dtype(ids.id), DType::I32);
+        assert_eq!(loaded.dtype(c.id), DType::I32);
+
+        // Node indexes are preserved, so tensors can be set through the original ids
+        GraphTensor::<(Dyn<'s'>, Const<4>)>::from_id(a.id, a.shape, &mut loaded)
+            .set_dyn(input, &[2, 4]);
+        GraphTensor::<R2<4, 5>>::from_id(model.0.weight.id, model.0.weight.shape, &mut loaded)
+            .set(weight);
+        GraphTensor::<R1<3>>::from_id(ids.id, ids.shape, &mut loaded).set(vec![1i32, 2, 3]);
+        loaded.execute();
+        let loaded_b = GraphTensor::<(Dyn<'s'>, Const<8>)>::from_id(b.id, b.shape, &mut loaded);
+        assert_exact(&loaded_b.data(), &expected);
+        let loaded_c = GraphTensor::<R1<3>>::from_id(c.id, c.shape, &mut loaded);
+        assert_eq!(loaded_c.data_as::<i32>(), expected_c);
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct AddScalar(f32);
+
+    impl Operator for AddScalar {
+        fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+            let a = get_vec_from_tensor(&inp[0].0);
+            vec![Tensor::new(
+                a.iter().map(|a| a + self.0).collect::<Vec<_>>(),
+            )]
+        }
+    }
+
+    impl SerializeOp for AddScalar {
+        const NAME: &'static str = "AddScalar";
+        fn serialize_op(&self) -> Result<String, IrError> {
+            Ok(self.0.to_string())
+        }
+        fn deserialize_op(args: &str, _: &mut Graph) -> Option<Self> {
+            args.parse().ok().map(Self)
+        }
+    }
+
+    #[test]
+    fn test_custom_op() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let b = cx.add_op(AddScalar(1.5)).input(a.id, 0, a.shape).finish();
+        cx.keep_tensors(b);
+        cx.to_retrieve.insert(b);
+        assert!(cx.to_ir(&OpRegistry::default()).is_err());
+
+        let mut registry = OpRegistry::default();
+        registry.register::<AddScalar>();
+        let ir = cx.to_ir(&registry).unwrap();
+        assert!(ir.contains("node 1 AddScalar 1.5\n"));
+        let mut loaded = Graph::new();
+        loaded.load_ir(&ir, &registry).unwrap();
+        GraphTensor::<R1<3>>::from_id(a.id, a.shape, &mut loaded).set(vec![1., 2., 3.]);
+        loaded.execute();
+        let b = GraphTensor::<R1<3>>::from_id(b, a.shape, &mut loaded);
+        assert_exact(&b.data(), &[2.5, 3.5, 4.5]);
+    }
+
+    #[test]
+    fn test_ir_errors() {
+        let registry = OpRegistry::default();
+        let mut cx = Graph::new();
+        assert!(matches!(
+            cx.load_ir("node 0 Exp2", &registry),
+            Err(IrError::Parse { line: 1, .. })
+        ));
+        assert_eq!(
+            cx.load_ir("luminal-ir 1\nnode 0 Foo", &registry),
+            Err(IrError::UnknownOp("Foo".to_string()))
+        );
+        let mut cx = Graph::new();
+        assert!(matches!(
+            cx.load_ir("luminal-ir 1\nnode 0 Exp2\nedge 0 1 schedule", &registry),
+            Err(IrError::Parse { line: 3, .. })
+        ));
+        // Orders that don't fit in a u8 aren't wrapped around
+        let mut cx = Graph::new();
+        assert_eq!(
+            cx.load_ir(
+                "luminal-ir 1\nnode 0 Exp2\nnode 1 Exp2\nedge 0 1 data 256 0",
+                &registry
+            ),
+            Err(IrError::Parse {
+                line: 4,
+                message: "Edge orders must be below 256".to_string()
+            })
+        );
+
+        let mut cx = Graph::new();
+        cx.tensor::<R1<2>>().exp2();
+        assert!(matches!(
+            cx.to_ir(&OpRegistry::empty()),
+            Err(IrError::UnregisteredOp(_))
+        ));
+        assert_eq!(
+            cx.load_ir(&cx.to_ir(&registry).unwrap(), &registry),
+            Err(IrError::GraphNotEmpty)
+        );
+    }
+}
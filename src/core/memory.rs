@@ -0,0 +1,145 @@
+use petgraph::stable_graph::NodeIndex;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{dtype::dispatch_dtype, graph::Graph, tensor::Tensor};
+
+/// An assignment of intermediate tensors to reusable buffers, computed from tensor lifetimes in the linearized graph.
+///
+/// Two tensors share a buffer only if their lifetimes don't overlap. Tensors marked to be kept or retrieved, and the
+/// outputs of ops with more than one output, are never planned. Buffers are sized at execution time and keep their
+/// allocations between executions, so repeated runs don't need to allocate.
+#[derive(Debug, Default)]
+pub struct MemoryPlan {
+    /// The execution order this plan was made for
+    order: Vec<NodeIndex>,
+    /// The arena buffer each planned node writes its output into
+    pub assignments: FxHashMap<NodeIndex, usize>,
+    /// The arena
+    pub(crate) buffers: Vec<Option<Tensor>>,
+}
+
+impl MemoryPlan {
+    /// Plan memory for a linearized graph
+    pub fn new(graph: &Graph) -> Self {
+        let linearized = graph
+            .linearized_graph
+            .as_ref()
+            .expect("Graph must be linearized before planning memory");
+
+        // Find the step each tensor is last consumed at
+        let mut last_use = FxHashMap::default();
+        let mut multi_output = FxHashSet::default();
+        for (step, (_, src_ids)) in linearized.iter().enumerate() {
+            for ((id, output), _) in src_ids {
+                if *output != 0 {
+                    multi_output.insert(*id);
+                }
+                last_use.insert(*id, step);
+            }
+        }
+
+        let mut assignments = FxHashMap::default();
+        let (mut free, mut n_buffers) = (vec![], 0);
+        let mut expiring = FxHashMap::<usize, Vec<usize>>::default();
+        for (step, (node, _)) in linearized.iter().enumerate() {
+            if let Some(last) = last_use.get(node) {
+                if !multi_output.contains(node)
+                    && !graph.no_delete.contains(node)
+                    && !graph.to_retrieve.contains(node)
+                {
+                    let buffer = free.pop().unwrap_or_else(|| {
+                        n_buffers += 1;
+                        n_buffers - 1
+                    });
+                    assignments.insert(*node, buffer);
+                    expiring.entry(*last).or_default().push(buffer);
+                }
+            }
+            // Buffers are only freed after their last consumer is done, so an op never writes into its own inputs
+            if let Some(buffers) = expiring.remove(&step) {
+                free.extend(buffers);
+            }
+        }
+
+        Self {
+            order: linearized.iter().map(|(n, _)| *n).collect(),
+            assignments,
+            buffers: vec![Some(Tensor::new(Vec::<f32>::new())); n_buffers],
+        }
+    }
+
+    /// Check if this plan was made for this execution order
+    pub(crate) fn is_valid_for<T>(&self, linearized: &[(NodeIndex, T)]) -> bool {
+        self.order.len() == linearized.len()
+            && self.order.iter().zip(linearized).all(|(a, (b, _))| a == b)
+    }
+
+    /// The number of buffers in the arena
+    pub fn n_buffers(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// The number of bytes currently allocated by the arena
+    pub fn arena_bytes(&self) -> usize {
+        self.buffers
+            .iter()
+            .flatten()
+            .filter_map(|t| {
+                let dtype = t.dtype()?;
+                Some(dispatch_dtype!(dtype, T => t.data.as_any().downcast_ref::<Vec<T>>()?.capacity() * dtype.size_of()))
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        prelude::*,
+        tests::{assert_exact, random_vec},
+    };
+
+    #[test]
+    fn test_buffer_reuse() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<4>>().set(vec![1., 2., 3., 4.]);
+        let mut b = a.exp2().sqrt().log2().recip().exp2().sqrt().retrieve();
+        cx.execute();
+        let unplanned = b.data();
+        b.drop();
+
+        cx.compile(MemoryPlanner, &mut b);
+        // A chain only ever needs two buffers alive at once
+        assert!(cx.memory_plan().unwrap().n_buffers() <= 2);
+        cx.execute_planned();
+        assert_exact(&b.data(), &unplanned);
+    }
+
+    #[test]
+    fn test_execute_planned() {
+        let mut cx = Graph::new();
+        let model = <(
+            crate::nn::linear::Linear<4, 8>,
+            crate::nn::activation::ReLU,
+            crate::nn::linear::Linear<8, 3>,
+        )>::initialize(&mut cx);
+        let a = cx.tensor::<(Dyn<'s'>, Const<4>)>();
+        let mut b = model.forward(a).retrieve();
+        cx.compile(<(GenericCompiler, CPUCompiler)>::default(), &mut b);
+
+        let mut arena_size = None;
+        for seq in [5, 3, 5, 2] {
+            a.set_dyn(random_vec(seq * 4), &[seq, 4]);
+            cx.execute();
+            let unplanned = b.data();
+            b.drop();
+            cx.execute_planned();
+            assert_exact(&b.data(), &unplanned);
+            b.drop();
+            // No more allocations are needed once the arena fits the largest input
+            let bytes = cx.memory_plan().unwrap().arena_bytes();
+            assert!(bytes > 0);
+            assert_eq!(*arena_size.get_or_insert(bytes), bytes);
+        }
+    }
+}
@@ -1,8 +1,18 @@
 pub mod compiler_utils;
+pub mod dtype;
+pub mod estimate;
 pub mod graph;
 pub mod graph_tensor;
+pub mod ir;
+pub mod memory;
 pub mod module;
 pub mod op;
+pub mod pipeline;
+pub mod profile;
+pub mod random;
 pub mod serialization;
 pub mod shape;
 pub mod tensor;
+pub mod trace;
+pub mod validate;
+pub mod visualize;
@@ -1,13 +1,15 @@
 #![allow(clippy::needless_range_loop)]
 
-use std::{any::Any, fmt::Debug, path::PathBuf};
+use std::{any::Any, borrow::Cow, fmt::Debug, path::PathBuf};
 
 use crate::{
-    prelude::{tracker::ShapeTracker, TraitObjEq},
+    dtype::{convert, dispatch_dtype, DType, Element},
+    prelude::{tracker::ShapeTracker, Indexer, TraitObjEq},
+    random::{fill_random, Distribution},
     tensor::Tensor,
 };
 
-use super::shape::symbolic::BigExpression;
+use super::shape::symbolic::{BigExpression, Symbol};
 use colored::Colorize;
 use itertools::Itertools;
 use rustc_hash::FxHashMap;
@@ -38,9 +40,36 @@ impl<'a> InputTensor<'a> {
     }
 }
 
-pub trait Operator: Debug + TraitObjEq {
+/// An op on the graph. Ops are `Send` so [`Graph::execute_parallel`](crate::graph::Graph::execute_parallel) can run
+/// them on worker threads.
+pub trait Operator: Debug + TraitObjEq + Send {
     /// Process the input tensors and produce output tensors
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor>;
+    /// Process the input tensors, reporting failures as an error instead of panicking.
+    /// This is what [`Graph::try_execute`](crate::graph::Graph::try_execute) runs. Ops that can fail should implement this and have [`Operator::process`] panic on the error.
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        Ok(self.process(inp))
+    }
+    /// Process the input tensors, writing the outputs into existing buffers instead of allocating new ones.
+    /// Returns false if this op doesn't support it, in which case [`Operator::process`] should be used instead.
+    #[allow(unused)]
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        false
+    }
+    /// The element type of the op's outputs, given the element types of its inputs.
+    /// Defaults to the type of the first input, or f32 for ops without inputs.
+    fn dtype(&self, inputs: &[DType]) -> DType {
+        inputs.first().copied().unwrap_or(DType::F32)
+    }
+    /// Estimate the cost of running this op, given its input shapes with dyn dims resolved and the dyn dims being
+    /// estimated for. Returns None if the cost isn't known.
+    #[allow(unused)]
+    fn cost(&self, inp: &[ShapeTracker], dyn_map: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        None
+    }
     /// Implement custom functionality
     #[allow(unused)]
     fn custom(&mut self, key: &str, input: Box<dyn Any>) -> Option<Box<dyn Any>> {
@@ -48,13 +77,88 @@ pub trait Operator: Debug + TraitObjEq {
     }
 }
 
-/// An opaque function running on CPU that takes in Vec<f32> tensors and outputs Vec<f32> tensors
+/// An error reported by an op from [`Operator::try_process`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpError {
+    /// An input wasn't the type of tensor the op works on
+    InputType {
+        input: usize,
+        expected: &'static str,
+    },
+    /// A tensor load was never given a value
+    Unset,
+    /// Reading or writing a file failed
+    Io(String),
+    Other(String),
+}
+
+impl std::fmt::Display for OpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpError::InputType { input, expected } => {
+                write!(f, "Expected input {input} to be a {expected}")
+            }
+            OpError::Unset => write!(f, "You must set a value for this tensor!"),
+            OpError::Io(e) => write!(f, "I/O error: {e}"),
+            OpError::Other(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for OpError {}
+
+/// The estimated cost of running an op once
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpCost {
+    /// Floating point operations performed
+    pub flops: usize,
+    /// Number of elements in the (first) output
+    pub output_elements: usize,
+}
+
+/// The cost of an op doing one flop per logical element of its first input
+pub fn elementwise_cost(inp: &[ShapeTracker]) -> OpCost {
+    let n = inp[0].n_elements().to_usize().unwrap();
+    OpCost {
+        flops: n,
+        output_elements: n,
+    }
+}
+
+/// The cost of an op doing one flop per element of its first input's buffer
+fn unary_cost(inp: &[ShapeTracker]) -> OpCost {
+    let n = inp[0].n_physical_elements().to_usize().unwrap();
+    OpCost {
+        flops: n,
+        output_elements: n,
+    }
+}
+
+/// The cost of reducing the first input along a dimension
+fn reduce_cost(inp: &[ShapeTracker], dim: usize) -> OpCost {
+    let n = inp[0].n_elements().to_usize().unwrap();
+    OpCost {
+        flops: n,
+        output_elements: n / inp[0].shape()[dim].to_usize().unwrap().max(1),
+    }
+}
+
+/// An opaque function running on CPU that takes in tensors and outputs tensors of the recorded element type.
+/// Failures, such as a weight file that can't be read, are returned as an error.
 #[allow(clippy::type_complexity)]
 pub struct Function(
     pub String,
-    pub Box<dyn Fn(Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor>>,
+    pub Box<dyn Fn(Vec<(InputTensor, ShapeTracker)>) -> Result<Vec<Tensor>, OpError> + Send>,
+    pub DType,
 );
 
+impl Function {
+    /// An f32 tensor load that fails with [`OpError::Unset`] until it's given a value
+    pub fn unset(name: String) -> Self {
+        Self(name, Box::new(|_| Err(OpError::Unset)), DType::F32)
+    }
+}
+
 impl PartialEq for Function {
     fn eq(&self, other: &Self) -> bool {
         self.0 == other.0
@@ -63,8 +167,19 @@ impl PartialEq for Function {
 
 impl Operator for Function {
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        (self.1)(inp).unwrap_or_else(|e| panic!("{}: {e}", self.0))
+    }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
         (self.1)(inp)
     }
+
+    fn dtype(&self, _: &[DType]) -> DType {
+        self.2
+    }
 }
 
 impl Debug for Function {
@@ -87,17 +202,24 @@ impl Operator for Print {
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
         for (i, (tensor, tracker)) in inp.iter().enumerate() {
             println!("{}", self.0);
-            let d = tensor
-                .borrowed()
-                .data
-                .as_any()
-                .downcast_ref::<Vec<f32>>()
-                .unwrap();
-            println!("{} Data: {:?}", i + 1, &d[..d.len().min(10)]);
+            let tensor = tensor.borrowed();
+            let dtype = tensor.dtype().expect("Expected a CPU tensor");
+            dispatch_dtype!(dtype, T => {
+                let d = tensor.data.as_any().downcast_ref::<Vec<T>>().unwrap();
+                println!("{} Data ({dtype}): {:?}", i + 1, &d[..d.len().min(10)]);
+            });
             println!("{} Shape: {:?}", i + 1, tracker);
         }
         vec![]
     }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        check_cpu_inputs(&inp)?;
+        Ok(self.process(inp))
+    }
 }
 
 /// An op to diff a tensor with a binary file
@@ -111,25 +233,32 @@ impl Debug for Diff {
 }
 
 impl Operator for Diff {
-    fn process(&mut self, mut inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        self.try_process(inp).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    fn try_process(
+        &mut self,
+        mut inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
         // Get tensor data and file data
         let (tensor, shape) = inp.pop().unwrap();
-        let d = tensor
-            .borrowed()
-            .data
-            .as_any()
-            .downcast_ref::<Vec<f32>>()
-            .unwrap();
+        if tensor.borrowed().dtype().is_none() {
+            return Err(OpError::InputType {
+                input: 0,
+                expected: "CPU tensor",
+            });
+        }
+        let d = input_as::<f32>(&tensor);
         let mut data = vec![0.; d.len()];
-        let (ind, val) = (shape.index_expression(), shape.valid_expression());
-        #[allow(unused_mut)]
-        for (i, mut r) in data.iter_mut().enumerate() {
-            if val.exec_single_var(i) != 0 {
-                *r = d[ind.exec_single_var(i)];
+        let n = data.len();
+        for (r, i) in data.iter_mut().zip(Indexer::cached(&shape).iter(n)) {
+            if let Some(i) = i {
+                *r = d[i];
             }
         }
         let bin_data = std::fs::read(&self.0)
-            .unwrap()
+            .map_err(|e| OpError::Io(format!("{}: {e}", self.0.display())))?
             .chunks(4)
             .map(|i| f32::from_ne_bytes([i[0], i[1], i[2], i[3]]))
             .collect::<Vec<_>>();
@@ -146,7 +275,7 @@ impl Operator for Diff {
                 .red()
             );
             println!("Data Shape: {shape:?}");
-            return vec![];
+            return Ok(vec![]);
         }
         let data_nan = data.iter().any(|i| i.is_nan());
         let file_nan = bin_data.iter().any(|i| i.is_nan());
@@ -167,7 +296,7 @@ impl Operator for Diff {
             );
         }
         if data_nan || file_nan {
-            return vec![];
+            return Ok(vec![]);
         }
         let mut matched = true;
         for (i, (a, b)) in data.iter().zip(bin_data.iter()).enumerate() {
@@ -257,7 +386,7 @@ impl Operator for Diff {
                     .bright_green()
             );
         }
-        vec![]
+        Ok(vec![])
     }
 }
 
@@ -270,7 +399,7 @@ pub enum ConstantValue {
 
 /// Produces a single number constant from an expression or a float
 #[derive(Clone, PartialEq)]
-pub struct Constant(pub ConstantValue, pub *const FxHashMap<char, usize>);
+pub struct Constant(pub ConstantValue, pub *const FxHashMap<Symbol, usize>);
 impl Debug for Constant {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Constant(",)?;
@@ -282,16 +411,147 @@ impl Debug for Constant {
     }
 }
 
+// SAFETY: The pointer is to the owning graph's `dyn_map`, and it's only dereferenced in `process_into`. Moving the op
+// to another thread doesn't touch the map. The only executor that runs ops on other threads is
+// `Graph::execute_parallel_threads`, which holds `&mut Graph` and joins every worker before returning, so the map
+// can't be mutated or moved while a worker reads it. `FxHashMap<Symbol, usize>` is `Sync`, so reading it from
+// several workers at once is fine. A dangling pointer (op outliving its graph) is no more possible than without Send.
+unsafe impl Send for Constant {}
+
 impl Operator for Constant {
+    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &inp)
+    }
+
+    fn process_into(&mut self, _: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        let value = match &self.0 {
+            ConstantValue::Expression(e) => {
+                e.exec(unsafe { self.1.as_ref().unwrap() }).unwrap() as f32
+            }
+            ConstantValue::Float(f) => *f,
+        };
+        prepare_output(out, 1, value);
+        true
+    }
+
+    fn cost(&self, _: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(OpCost {
+            flops: 0,
+            output_elements: 1,
+        })
+    }
+}
+
+/// A tensor computed ahead of time, like the subgraphs [`ConstantFolding`](crate::compilers::ConstantFolding) evaluates
+#[derive(Clone)]
+pub struct ConstantTensor(pub Tensor);
+
+impl Debug for ConstantTensor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.0.dtype(), self.0.n_elements()) {
+            (Some(dtype), Some(n)) => write!(f, "ConstantTensor({dtype} x {n})"),
+            _ => write!(f, "ConstantTensor"),
+        }
+    }
+}
+
+impl PartialEq for ConstantTensor {
+    fn eq(&self, other: &Self) -> bool {
+        let (Some(dtype), Some(n)) = (self.0.dtype(), self.0.n_elements()) else {
+            return false;
+        };
+        if other.0.dtype() != Some(dtype) || other.0.n_elements() != Some(n) {
+            return false;
+        }
+        dispatch_dtype!(dtype, T => {
+            let (a, b) = (
+                self.0.data.as_any().downcast_ref::<Vec<T>>().unwrap(),
+                other.0.data.as_any().downcast_ref::<Vec<T>>().unwrap(),
+            );
+            if !dtype.is_float() {
+                return a == b;
+            }
+            // Floats compare by bits, so NaN matches itself and 0 doesn't match -0
+            a.iter()
+                .zip(b)
+                .all(|(a, b)| a.to_f64().to_bits() == b.to_f64().to_bits())
+        })
+    }
+}
+
+impl Operator for ConstantTensor {
     fn process(&mut self, _: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        vec![Tensor {
-            data: Box::new(vec![match &self.0 {
-                ConstantValue::Expression(e) => {
-                    e.exec(unsafe { self.1.as_ref().unwrap() }).unwrap() as f32
-                }
-                ConstantValue::Float(f) => *f,
-            }]),
-        }]
+        vec![self.0.clone()]
+    }
+
+    fn dtype(&self, _: &[DType]) -> DType {
+        self.0.dtype().expect("Expected a CPU tensor")
+    }
+
+    fn process_into(&mut self, _: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        let dtype = self.0.dtype().expect("Expected a CPU tensor");
+        dispatch_dtype!(dtype, T => {
+            let src = self.0.data.as_any().downcast_ref::<Vec<T>>().unwrap();
+            prepare_output(out, src.len(), T::ZERO).copy_from_slice(src);
+        });
+        true
+    }
+
+    fn cost(&self, _: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(OpCost {
+            flops: 0,
+            output_elements: self.0.n_elements()?,
+        })
+    }
+}
+
+/// Produces `size` random f32s. See [`crate::random`] for exactly how each element is generated.
+///
+/// The execution counter goes up by one every time the op runs, so each execution draws new values.
+#[derive(Clone, PartialEq)]
+pub struct Random {
+    pub distribution: Distribution,
+    pub seed: u64,
+    pub execution: u64,
+    pub size: BigExpression,
+    pub dyn_map: *const FxHashMap<Symbol, usize>,
+}
+impl Debug for Random {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Random({}, seed {})", self.distribution, self.seed)
+    }
+}
+
+// SAFETY: `dyn_map` is only dereferenced in `process_into`, while the owning graph is executing. See the impl for
+// `Constant`, which holds the same pointer.
+unsafe impl Send for Random {}
+
+impl Operator for Random {
+    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &inp)
+    }
+
+    fn process_into(&mut self, _: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        let n = self
+            .size
+            .exec(unsafe { self.dyn_map.as_ref().unwrap() })
+            .unwrap();
+        fill_random(
+            prepare_output(out, n, 0.),
+            self.distribution,
+            self.seed,
+            self.execution,
+        );
+        self.execution += 1;
+        true
+    }
+
+    fn cost(&self, _: &[ShapeTracker], dyn_map: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        let n = self.size.exec(dyn_map)?;
+        Some(OpCost {
+            flops: n,
+            output_elements: n,
+        })
     }
 }
 
@@ -300,331 +560,540 @@ impl Operator for Constant {
 pub struct Contiguous;
 impl Operator for Contiguous {
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &inp)
+    }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        try_process_with_new_output(self, &inp)
+    }
+
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
         // Copy data over to new tensor
-        let src = get_vec_from_tensor(&inp[0].0);
-        let mut res = vec![0.; inp[0].1.n_elements().to_usize().unwrap()];
-        let ind = inp[0].1.index_expression();
-        let val = inp[0].1.valid_expression();
-        for i in 0..res.len() {
-            if val.exec_single_var(i) != 0 {
-                res[i] = src[ind.exec_single_var(i)];
+        let indexer = Indexer::cached(&inp[0].1);
+        let n = inp[0].1.n_elements().to_usize().unwrap();
+        dispatch_dtype!(input_dtype(inp), T => {
+            let src = input_as::<T>(&inp[0].0);
+            let res = prepare_output(out, n, T::ZERO);
+            for (r, i) in res.iter_mut().zip(indexer.iter(n)) {
+                if let Some(i) = i {
+                    *r = src[i];
+                }
             }
-        }
-        vec![Tensor {
-            data: Box::new(res),
-        }]
+        });
+        true
     }
-}
 
-// Below are all the primitive operators
-
-// Unary Op (A -> A)
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(OpCost {
+            flops: 0,
+            ..elementwise_cost(inp)
+        })
+    }
+}
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct Log2;
-impl Operator for Log2 {
+/// Convert a tensor to another element type. This works on the physical buffer, so the shape is unchanged
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cast(pub DType);
+impl Operator for Cast {
     fn process(&mut self, mut inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let mut tensor = inp.pop().unwrap().0.cloned();
-        for a in get_vec_from_tensor_owned(&mut tensor).iter_mut() {
-            *a = a.log2();
+        let tensor = inp.pop().unwrap().0;
+        if tensor.borrowed().dtype() == Some(self.0) {
+            return vec![tensor.cloned()];
         }
+        vec![tensor.borrowed().cast(self.0)]
+    }
 
-        vec![tensor]
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        check_cpu_inputs(&inp)?;
+        Ok(self.process(inp))
     }
-}
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct Exp2;
-impl Operator for Exp2 {
-    fn process(&mut self, mut inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let mut tensor = inp.pop().unwrap().0.cloned();
-        for a in get_vec_from_tensor_owned(&mut tensor).iter_mut() {
-            *a = a.exp2();
-        }
+    fn dtype(&self, _: &[DType]) -> DType {
+        self.0
+    }
 
-        vec![tensor]
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        dispatch_dtype!(input_dtype(inp), A => dispatch_dtype!(self.0, B => {
+            let src = input_as::<A>(&inp[0].0);
+            for (o, a) in prepare_output(out, src.len(), B::ZERO).iter_mut().zip(src.iter()) {
+                *o = convert(*a);
+            }
+        }));
+        true
     }
-}
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct Sin;
-impl Operator for Sin {
-    fn process(&mut self, mut inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let mut tensor = inp.pop().unwrap().0.cloned();
-        for a in get_vec_from_tensor_owned(&mut tensor).iter_mut() {
-            *a = a.sin();
-        }
-        vec![tensor]
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(OpCost {
+            flops: 0,
+            ..unary_cost(inp)
+        })
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct Recip;
-impl Operator for Recip {
-    fn process(&mut self, mut inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let mut tensor = inp.pop().unwrap().0.cloned();
-        for a in get_vec_from_tensor_owned(&mut tensor).iter_mut() {
-            *a = a.recip();
-        }
-        vec![tensor]
-    }
+/// Gather rows of a matrix. Input 0 holds the row indexes and input 1 the matrix, which has rows of `embed_dim` elements.
+/// Both are read through their shape trackers, so they can be views.
+///
+/// Integer indexes are read exactly. Float indexes are truncated. Negative or out of range indexes are an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gather {
+    pub embed_dim: usize,
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct Sqrt;
-impl Operator for Sqrt {
-    fn process(&mut self, mut inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let mut tensor = inp.pop().unwrap().0.cloned();
-        for a in get_vec_from_tensor_owned(&mut tensor).iter_mut() {
-            *a = a.sqrt();
-        }
-        vec![tensor]
+impl Gather {
+    /// Copy the indexed rows into the output, checking the indexes are in range of the matrix
+    fn gather_into(
+        &self,
+        inp: &[(InputTensor, ShapeTracker)],
+        out: &mut [Tensor],
+    ) -> Result<(), OpError> {
+        let rows = inp[1].1.n_elements().to_usize().unwrap() / self.embed_dim;
+        let n = inp[0].1.n_elements().to_usize().unwrap();
+        let indexer = Indexer::cached(&inp[0].1);
+        let indexes = dispatch_dtype!(input_dtype(inp), I => {
+            let src = input_as::<I>(&inp[0].0);
+            indexer
+                .iter(n)
+                .map(|i| {
+                    let index = i.map(|i| src[i]).unwrap_or(I::ZERO);
+                    if index.to_f64() < 0. || index.to_index() >= rows {
+                        return Err(OpError::Other(format!(
+                            "Index {} is out of range for {rows} rows",
+                            index.to_f64()
+                        )));
+                    }
+                    Ok(index.to_index())
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        });
+        let indexer = Indexer::cached(&inp[1].1);
+        let weight_dtype = inp[1].0.borrowed().dtype().expect("Expected a CPU tensor");
+        dispatch_dtype!(weight_dtype, T => {
+            let weights = input_as::<T>(&inp[1].0);
+            let data = prepare_output(out, indexes.len() * self.embed_dim, T::ZERO);
+            for (row, index) in data.chunks_exact_mut(self.embed_dim).zip(indexes) {
+                let start = index * self.embed_dim;
+                if let Indexer::Contiguous = *indexer {
+                    row.copy_from_slice(&weights[start..start + self.embed_dim]);
+                    continue;
+                }
+                for (j, o) in row.iter_mut().enumerate() {
+                    *o = indexer.index(start + j).map(|i| weights[i]).unwrap_or(T::ZERO);
+                }
+            }
+        });
+        Ok(())
     }
 }
 
-// Binary Ops (A x A -> A)
-
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct Add;
-impl Operator for Add {
+impl Operator for Gather {
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let (a_data, b_data) = (
-            get_vec_from_tensor(&inp[0].0),
-            get_vec_from_tensor(&inp[1].0),
-        );
-        let (a_ind, a_val, b_ind, b_val) = (
-            inp[0].1.index_expression(),
-            inp[0].1.valid_expression(),
-            inp[1].1.index_expression(),
-            inp[1].1.valid_expression(),
-        );
-        let mut data = vec![0.; inp[0].1.n_elements().to_usize().unwrap()];
-        for i in 0..data.len() {
-            let lhs = if a_val.exec_single_var(i) != 0 {
-                a_data[a_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
-            let rhs = if b_val.exec_single_var(i) != 0 {
-                b_data[b_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
-            data[i] = lhs + rhs;
+        process_with_new_output(self, &inp)
+    }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        check_cpu_inputs(&inp)?;
+        let mut out = vec![Tensor::new(Vec::<f32>::new())];
+        self.gather_into(&inp, &mut out)?;
+        Ok(out)
+    }
+
+    fn dtype(&self, inputs: &[DType]) -> DType {
+        // Rows are copied from the weights
+        inputs.get(1).copied().unwrap_or(DType::F32)
+    }
+
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        if let Err(e) = self.gather_into(inp, out) {
+            panic!("{e}");
         }
-        vec![Tensor {
-            data: Box::new(data),
-        }]
+        true
+    }
+
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(OpCost {
+            flops: 0,
+            output_elements: inp[0].n_elements().to_usize().unwrap() * self.embed_dim,
+        })
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct Mul;
-impl Operator for Mul {
-    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let (a_data, b_data) = (
-            get_vec_from_tensor(&inp[0].0),
-            get_vec_from_tensor(&inp[1].0),
-        );
-        let mut data = vec![0.; inp[0].1.n_elements().to_usize().unwrap()];
-        let (a_ind, a_val, b_ind, b_val) = (
-            inp[0].1.index_expression(),
-            inp[0].1.valid_expression(),
-            inp[1].1.index_expression(),
-            inp[1].1.valid_expression(),
-        );
-        for i in 0..data.len() {
-            data[i] = if a_val.exec_single_var(i) != 0 {
-                a_data[a_ind.exec_single_var(i)]
-            } else {
-                0.0
-            } * if b_val.exec_single_var(i) != 0 {
-                b_data[b_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
+// Below are all the primitive operators
+
+// Unary Op (A -> A)
+
+/// An elementwise function of one input
+trait UnaryOp {
+    fn apply<T: Element>(a: T) -> T;
+}
+
+fn process_unary<O: UnaryOp>(mut inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+    let mut tensor = inp.pop().unwrap().0.cloned();
+    let dtype = tensor.dtype().expect("Expected a CPU tensor");
+    dispatch_dtype!(dtype, T => {
+        for a in tensor.data.as_any_mut().downcast_mut::<Vec<T>>().unwrap() {
+            *a = O::apply(*a);
         }
-        vec![Tensor {
-            data: Box::new(data),
-        }]
-    }
+    });
+    vec![tensor]
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct Mod;
-impl Operator for Mod {
-    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let (a_data, b_data) = (
-            get_vec_from_tensor(&inp[0].0),
-            get_vec_from_tensor(&inp[1].0),
-        );
-        let mut data = vec![0.; inp[0].1.n_elements().to_usize().unwrap()];
-        let (a_ind, a_val, b_ind, b_val) = (
-            inp[0].1.index_expression(),
-            inp[0].1.valid_expression(),
-            inp[1].1.index_expression(),
-            inp[1].1.valid_expression(),
-        );
-        for i in 0..data.len() {
-            data[i] = if a_val.exec_single_var(i) != 0 {
-                a_data[a_ind.exec_single_var(i)]
-            } else {
-                0.0
-            } % if b_val.exec_single_var(i) != 0 {
-                b_data[b_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
+fn process_unary_into<O: UnaryOp>(inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+    dispatch_dtype!(input_dtype(inp), T => {
+        let src = input_as::<T>(&inp[0].0);
+        for (o, a) in prepare_output(out, src.len(), T::ZERO).iter_mut().zip(src.iter()) {
+            *o = O::apply(*a);
         }
-        vec![Tensor {
-            data: Box::new(data),
-        }]
-    }
+    });
+    true
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct LessThan;
-impl Operator for LessThan {
-    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let (a_data, b_data) = (
-            get_vec_from_tensor(&inp[0].0),
-            get_vec_from_tensor(&inp[1].0),
-        );
-        let mut data = vec![0.; inp[0].1.n_elements().to_usize().unwrap()];
-        let (a_ind, a_val, b_ind, b_val) = (
-            inp[0].1.index_expression(),
-            inp[0].1.valid_expression(),
-            inp[1].1.index_expression(),
-            inp[1].1.valid_expression(),
-        );
-        for i in 0..data.len() {
-            let a = if a_val.exec_single_var(i) != 0 {
-                a_data[a_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
-            let b = if b_val.exec_single_var(i) != 0 {
-                b_data[b_ind.exec_single_var(i)]
-            } else {
-                0.0
-            };
-            data[i] = if a < b { 1. } else { 0. };
+macro_rules! unary_ops {
+    ($($op:ident => $f:ident),*) => {
+        $(
+            #[derive(Debug, Clone, Default, PartialEq)]
+            pub struct $op;
+
+            impl UnaryOp for $op {
+                fn apply<T: Element>(a: T) -> T {
+                    a.$f()
+                }
+            }
+
+            impl Operator for $op {
+                fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+                    process_unary::<Self>(inp)
+                }
+
+                fn try_process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Result<Vec<Tensor>, OpError> {
+                    check_cpu_inputs(&inp)?;
+                    Ok(process_unary::<Self>(inp))
+                }
+
+                fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+                    process_unary_into::<Self>(inp, out)
+                }
+
+                fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+                    Some(unary_cost(inp))
+                }
+            }
+        )*
+    };
+}
+
+unary_ops!(Log2 => log2, Exp2 => exp2, Sin => sin, Recip => recip, Sqrt => sqrt);
+
+// Binary Ops (A x A -> A)
+
+/// An elementwise function of two inputs. Padded out elements of either input are zero
+trait BinaryOp {
+    fn apply<T: Element>(a: T, b: T) -> T;
+}
+
+/// Inputs stored as a different type than the first are converted to it
+fn process_binary_into<O: BinaryOp>(
+    inp: &[(InputTensor, ShapeTracker)],
+    out: &mut [Tensor],
+) -> bool {
+    dispatch_dtype!(input_dtype(inp), T => {
+        let (a_data, b_data) = (input_as::<T>(&inp[0].0), input_as::<T>(&inp[1].0));
+        let (a_ind, b_ind) = (Indexer::cached(&inp[0].1), Indexer::cached(&inp[1].1));
+        let n = inp[0].1.n_elements().to_usize().unwrap();
+        let data = prepare_output(out, n, T::ZERO);
+        for (r, (a, b)) in data.iter_mut().zip(a_ind.iter(n).zip(b_ind.iter(n))) {
+            let lhs = a.map(|i| a_data[i]).unwrap_or(T::ZERO);
+            let rhs = b.map(|i| b_data[i]).unwrap_or(T::ZERO);
+            *r = O::apply(lhs, rhs);
         }
-        vec![Tensor {
-            data: Box::new(data),
-        }]
-    }
+    });
+    true
 }
 
+macro_rules! binary_ops {
+    ($($op:ident => |$a:ident, $b:ident| $f:expr),*) => {
+        $(
+            #[derive(Debug, Clone, Default, PartialEq)]
+            pub struct $op;
+
+            impl BinaryOp for $op {
+                fn apply<T: Element>($a: T, $b: T) -> T {
+                    $f
+                }
+            }
+
+            impl Operator for $op {
+                fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+                    process_with_new_output(self, &inp)
+                }
+
+                fn try_process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Result<Vec<Tensor>, OpError> {
+                    try_process_with_new_output(self, &inp)
+                }
+
+                fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+                    process_binary_into::<Self>(inp, out)
+                }
+
+                fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+                    Some(elementwise_cost(inp))
+                }
+            }
+        )*
+    };
+}
+
+binary_ops!(
+    Add => |a, b| a.add(b),
+    Mul => |a, b| a.mul(b),
+    Mod => |a, b| a.rem(b),
+    LessThan => |a, b| if a < b { T::ONE } else { T::ZERO }
+);
+
 // Reduce Ops (A -> B (different shape))
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct SumReduce(pub usize);
-impl Operator for SumReduce {
-    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let front_size: usize = inp[0]
-            .1
-            .shape()
-            .iter()
-            .take(self.0)
-            .filter_map(BigExpression::to_usize)
-            .product();
-        let back_size: usize = inp[0]
-            .1
-            .shape()
-            .iter()
-            .skip(self.0 + 1)
-            .filter_map(BigExpression::to_usize)
-            .product();
-        let dim_size = match inp[0].1.shape()[self.0].to_usize() {
-            Some(n) => n,
-            None => panic!("Can't reduce over an unknown dimension"),
-        };
-        let mut result: Vec<f32> = vec![0.0; front_size * back_size];
-        let a_data = get_vec_from_tensor(&inp[0].0);
-        let ind = inp[0].1.index_expression();
-        let val = inp[0].1.valid_expression();
+/// Reduce the first input along a dimension
+trait ReduceOp {
+    fn dim(&self) -> usize;
+    fn init<T: Element>() -> T;
+    fn apply<T: Element>(acc: T, a: T) -> T;
+}
 
+fn process_reduce_into<O: ReduceOp>(
+    op: &O,
+    inp: &[(InputTensor, ShapeTracker)],
+    out: &mut [Tensor],
+) -> bool {
+    let dim = op.dim();
+    let front_size: usize = inp[0]
+        .1
+        .shape()
+        .iter()
+        .take(dim)
+        .filter_map(BigExpression::to_usize)
+        .product();
+    let back_size: usize = inp[0]
+        .1
+        .shape()
+        .iter()
+        .skip(dim + 1)
+        .filter_map(BigExpression::to_usize)
+        .product();
+    let dim_size = match inp[0].1.shape()[dim].to_usize() {
+        Some(n) => n,
+        None => panic!("Can't reduce over an unknown dimension"),
+    };
+    let indexer = Indexer::cached(&inp[0].1);
+    dispatch_dtype!(input_dtype(inp), T => {
+        let result = prepare_output(out, front_size * back_size, O::init::<T>());
+        let a_data = input_as::<T>(&inp[0].0);
         for i in 0..front_size {
             for j in 0..back_size {
                 for k in 0..dim_size {
                     let original_index = i * dim_size * back_size + k * back_size + j;
                     let new_index = i * back_size + j;
-                    if val.exec_single_var(original_index) != 0 {
-                        result[new_index] += a_data[ind.exec_single_var(original_index)];
+                    if let Some(i) = indexer.index(original_index) {
+                        result[new_index] = O::apply(result[new_index], a_data[i]);
                     }
                 }
             }
         }
-        vec![Tensor {
-            data: Box::new(result),
-        }]
+    });
+    true
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SumReduce(pub usize);
+
+impl ReduceOp for SumReduce {
+    fn dim(&self) -> usize {
+        self.0
+    }
+    fn init<T: Element>() -> T {
+        T::ZERO
+    }
+    fn apply<T: Element>(acc: T, a: T) -> T {
+        acc.add(a)
+    }
+}
+
+impl Operator for SumReduce {
+    fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        process_with_new_output(self, &inp)
+    }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        try_process_with_new_output(self, &inp)
+    }
+
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        process_reduce_into(self, inp, out)
+    }
+
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(reduce_cost(inp, self.0))
     }
 }
 
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct MaxReduce(pub usize);
+
+impl ReduceOp for MaxReduce {
+    fn dim(&self) -> usize {
+        self.0
+    }
+    fn init<T: Element>() -> T {
+        T::MIN
+    }
+    fn apply<T: Element>(acc: T, a: T) -> T {
+        acc.maximum(a)
+    }
+}
+
 impl Operator for MaxReduce {
     fn process(&mut self, inp: Vec<(InputTensor, ShapeTracker)>) -> Vec<Tensor> {
-        let front_size: usize = inp[0]
-            .1
-            .shape()
-            .iter()
-            .take(self.0)
-            .filter_map(BigExpression::to_usize)
-            .product();
-        let back_size: usize = inp[0]
-            .1
-            .shape()
-            .iter()
-            .skip(self.0 + 1)
-            .filter_map(BigExpression::to_usize)
-            .product();
-        let dim_size = match inp[0].1.shape()[self.0].to_usize() {
-            Some(n) => n,
-            None => panic!("Can't reduce over an unknown dimension"),
-        };
-        let mut result: Vec<f32> = vec![-f32::INFINITY; front_size * back_size];
-        let a_data = get_vec_from_tensor(&inp[0].0);
-        let ind = inp[0].1.index_expression();
-        let val = inp[0].1.valid_expression();
+        process_with_new_output(self, &inp)
+    }
 
-        for i in 0..front_size {
-            for j in 0..back_size {
-                for k in 0..dim_size {
-                    let original_index = i * dim_size * back_size + k * back_size + j;
-                    let new_index = i * back_size + j;
-                    if val.exec_single_var(original_index) != 0 {
-                        result[new_index] =
-                            result[new_index].max(a_data[ind.exec_single_var(original_index)]);
-                    }
-                }
-            }
-        }
-        vec![Tensor {
-            data: Box::new(result),
-        }]
+    fn try_process(
+        &mut self,
+        inp: Vec<(InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, OpError> {
+        try_process_with_new_output(self, &inp)
+    }
+
+    fn process_into(&mut self, inp: &[(InputTensor, ShapeTracker)], out: &mut [Tensor]) -> bool {
+        process_reduce_into(self, inp, out)
+    }
+
+    fn cost(&self, inp: &[ShapeTracker], _: &FxHashMap<Symbol, usize>) -> Option<OpCost> {
+        Some(reduce_cost(inp, self.0))
     }
 }
 
 pub fn get_vec_from_tensor<'a>(tensor: &'a InputTensor<'a>) -> &'a Vec<f32> {
+    try_get_vec_from_tensor(tensor, 0).unwrap_or_else(|_| panic!("Expected a Vec<f32> tensor"))
+}
+
+/// Get the data of an op's input, or an error if it isn't a `Vec<f32>`
+pub fn try_get_vec_from_tensor<'a>(
+    tensor: &'a InputTensor<'a>,
+    input: usize,
+) -> Result<&'a Vec<f32>, OpError> {
     tensor
         .borrowed()
         .data
         .as_any()
         .downcast_ref::<Vec<f32>>()
-        .unwrap()
+        .ok_or(OpError::InputType {
+            input,
+            expected: "Vec<f32>",
+        })
 }
 
 pub fn get_vec_from_tensor_owned(tensor: &mut Tensor) -> &mut Vec<f32> {
     tensor.data.as_any_mut().downcast_mut::<Vec<f32>>().unwrap()
 }
 
+/// Get an input's data as `T`, converting it if it's stored as another type
+pub fn input_as<'a, T: Element>(tensor: &'a InputTensor<'a>) -> Cow<'a, [T]> {
+    let tensor = tensor.borrowed();
+    match tensor.data.as_any().downcast_ref::<Vec<T>>() {
+        Some(data) => Cow::Borrowed(data),
+        None => Cow::Owned(tensor.to_vec()),
+    }
+}
+
+/// The element type of an op's first input
+fn input_dtype(inp: &[(InputTensor, ShapeTracker)]) -> DType {
+    inp[0].0.borrowed().dtype().expect("Expected a CPU tensor")
+}
+
+/// Make sure every input is a CPU tensor, reporting the first one that isn't
+pub fn check_cpu_inputs(inp: &[(InputTensor, ShapeTracker)]) -> Result<(), OpError> {
+    match inp.iter().position(|(t, _)| t.borrowed().dtype().is_none()) {
+        Some(input) => Err(OpError::InputType {
+            input,
+            expected: "CPU tensor",
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Are all of an op's inputs `Vec<f32>`s? Ops that only work on f32 should return false from [`Operator::process_into`] otherwise
+pub fn inputs_are_f32(inp: &[(InputTensor, ShapeTracker)]) -> bool {
+    inp.iter()
+        .all(|(t, _)| t.borrowed().data.as_any().is::<Vec<f32>>())
+}
+
+/// Resize the first output buffer to `n` elements, all set to `fill`. The buffer's allocation is reused where possible
+pub fn prepare_output<T: Element>(out: &mut [Tensor], n: usize, fill: T) -> &mut Vec<T> {
+    if !out[0].data.as_any().is::<Vec<T>>() {
+        out[0] = Tensor::new(Vec::<T>::new());
+    }
+    let data = out[0].data.as_any_mut().downcast_mut::<Vec<T>>().unwrap();
+    data.clear();
+    data.resize(n, fill);
+    data
+}
+
+/// Run an op's [`Operator::process_into`] with a freshly allocated output.
+///
+/// If the op only works on f32, other inputs are converted to f32 and its outputs are converted back to the type of the first input.
+pub fn process_with_new_output<O: Operator>(
+    op: &mut O,
+    inp: &[(InputTensor, ShapeTracker)],
+) -> Vec<Tensor> {
+    let mut out = vec![Tensor::new(Vec::<f32>::new())];
+    if op.process_into(inp, &mut out) {
+        return out;
+    }
+    assert!(
+        !inputs_are_f32(inp),
+        "{op:?} doesn't support processing into an output buffer"
+    );
+    let converted = inp
+        .iter()
+        .map(|(t, st)| {
+            (
+                InputTensor::Owned(t.borrowed().cast(DType::F32)),
+                st.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        op.process_into(&converted, &mut out),
+        "{op:?} doesn't support processing into an output buffer"
+    );
+    let dtype = inp[0].0.borrowed().dtype().unwrap();
+    out.iter().map(|t| t.cast(dtype)).collect()
+}
+
+/// [`process_with_new_output`] for [`Operator::try_process`], reporting inputs that aren't CPU tensors as an error
+pub fn try_process_with_new_output<O: Operator>(
+    op: &mut O,
+    inp: &[(InputTensor, ShapeTracker)],
+) -> Result<Vec<Tensor>, OpError> {
+    check_cpu_inputs(inp)?;
+    Ok(process_with_new_output(op, inp))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         prelude::{symbolic::Expression, *},
-        tests::assert_close,
+        tests::{assert_close, assert_close_precision, assert_exact, random_vec},
     };
     use dfdx::prelude::*;
     use itertools::Itertools;
@@ -951,4 +1420,180 @@ mod tests {
         assert_close(&c.data(), &d_c.as_vec());
         assert_close(&d.data(), &d_d.as_vec());
     }
+
+    // Data type tests
+
+    #[test]
+    fn test_half_precision() {
+        let data = [[1., -2., 0.5], [4., 3., -1.5]];
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R2<2, 3>>().set(
+            data.iter()
+                .flatten()
+                .map(|i| half::f16::from_f32(*i))
+                .collect_vec(),
+        );
+        assert_eq!(a.dtype(), DType::F16);
+        let b = (a.exp2() * 2.0 + a).retrieve();
+        let c = a.sum_reduce::<_, crate::prelude::Axis<1>>().retrieve();
+        let d = a.max_reduce::<_, crate::prelude::Axis<0>>().retrieve();
+        let e = a
+            .permute::<_, crate::prelude::Axes2<1, 0>>()
+            .contiguous()
+            .retrieve();
+        assert_eq!(b.dtype(), DType::F16);
+        cx.execute();
+
+        assert!(b.graph().get_tensor_ref(b.id, 0).unwrap().dtype() == Some(DType::F16));
+        let mut ref_cx = Graph::new();
+        let ref_a = ref_cx.tensor::<R2<2, 3>>().set(data);
+        let ref_b = (ref_a.exp2() * 2.0 + ref_a).retrieve();
+        let ref_c = ref_a.sum_reduce::<_, crate::prelude::Axis<1>>().retrieve();
+        let ref_d = ref_a.max_reduce::<_, crate::prelude::Axis<0>>().retrieve();
+        let ref_e = ref_a
+            .permute::<_, crate::prelude::Axes2<1, 0>>()
+            .contiguous()
+            .retrieve();
+        ref_cx.execute();
+
+        assert_close_precision(&b.data(), &ref_b.data(), 2);
+        assert_close_precision(&c.data(), &ref_c.data(), 2);
+        assert_exact(&d.data(), &ref_d.data());
+        assert_exact(&e.data(), &ref_e.data());
+    }
+
+    #[test]
+    fn test_integer_ops() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<4>>().set(vec![7i32, -3, 10, 4]);
+        let b = cx.tensor::<R1<4>>().set(vec![2i32, 5, 10, 0]);
+        let sum = (a + b).retrieve();
+        let prod = (a * b).retrieve();
+        let rem = (a % b).retrieve();
+        let lt = a.less_than(b).retrieve();
+        let total = a.sum_reduce::<_, crate::prelude::Axis<0>>().retrieve();
+        let max = a.max_reduce::<_, crate::prelude::Axis<0>>().retrieve();
+        cx.execute();
+
+        assert_exact(&sum.data_as::<i32>(), &[9, 2, 20, 4]);
+        assert_exact(&prod.data_as::<i32>(), &[14, -15, 100, 0]);
+        // Remainders by zero are zero instead of panicking
+        assert_exact(&rem.data_as::<i32>(), &[1, -3, 0, 0]);
+        assert_exact(&lt.data_as::<i32>(), &[0, 1, 0, 0]);
+        assert_exact(&total.data_as::<i32>(), &[18]);
+        assert_exact(&max.data_as::<i32>(), &[10]);
+        // Integers convert exactly to f32 for data()
+        assert_exact(&sum.data(), &[9., 2., 20., 4.]);
+    }
+
+    #[test]
+    fn test_cast() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<4>>().set([1.7, -2.2, 0., 3.5]);
+        let ints = a.cast(DType::I64).retrieve();
+        let bools = a.cast(DType::Bool).retrieve();
+        let back = bools.cast(DType::F32).retrieve();
+        // Casting to the same type is free
+        assert_eq!(a.cast(DType::F32).id, a.id);
+        cx.execute();
+
+        assert_exact(&ints.data_as::<i64>(), &[1, -2, 0, 3]);
+        assert_exact(&bools.data_as::<bool>(), &[true, true, false, true]);
+        assert_exact(&back.data(), &[1., 1., 0., 1.]);
+    }
+
+    #[test]
+    fn test_dtype_recorded_on_graph() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let b = (a.exp2() + a).sum_reduce::<_, crate::prelude::Axis<0>>();
+        let c = a.cast(DType::I32) * 2.0;
+        assert_eq!(b.dtype(), DType::F32);
+
+        // Setting new data updates everything built on the tensor, except through casts
+        a.set(vec![half::f16::ONE; 3]);
+        assert_eq!((a.dtype(), b.dtype()), (DType::F16, DType::F16));
+        assert_eq!(c.dtype(), DType::I32);
+        assert!(cx
+            .graph
+            .edges_directed(b.id, petgraph::Direction::Incoming)
+            .all(|e| matches!(e.weight(), Dependency::Data { dtype, .. } if *dtype == DType::F16)));
+        // Tensors made from ids read the type off the graph
+        let from_id = GraphTensor::<R0>::from_id(b.id, b.shape, &mut cx);
+        assert_eq!(cx.dtype(from_id.id), DType::F16);
+        assert_eq!(from_id.dtype(), DType::F16);
+    }
+
+    #[test]
+    fn test_mixed_dtypes() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>().set(vec![1i32, 2, 3]);
+        let b = cx.tensor::<R1<3>>().set([0.5, 1.5, 2.5]);
+        // The rhs is cast to the type of the lhs
+        let c = (a + b).retrieve();
+        let d = (b + a).retrieve();
+        let e = (a * 2.0).retrieve();
+        assert_eq!(
+            (c.dtype(), d.dtype(), e.dtype()),
+            (DType::I32, DType::F32, DType::I32)
+        );
+        assert_eq!(
+            cx.graph
+                .node_indices()
+                .filter(|n| cx.graph[*n].as_any().is::<super::Cast>())
+                .count(),
+            3
+        );
+        cx.execute();
+
+        assert_exact(&c.data_as::<i32>(), &[1, 3, 5]);
+        assert_exact(&d.data(), &[1.5, 3.5, 5.5]);
+        assert_exact(&e.data_as::<i32>(), &[2, 4, 6]);
+    }
+
+    #[test]
+    fn test_half_precision_compiled() {
+        let mut cx = Graph::new();
+        let model = <(
+            crate::nn::linear::Linear<4, 8>,
+            crate::nn::activation::ReLU,
+            crate::nn::linear::Linear<8, 3>,
+        )>::initialize(&mut cx);
+        let a = cx.tensor::<R2<2, 4>>().set(random_vec(2 * 4));
+        let mut b = crate::prelude::Module::forward(&model, a).retrieve();
+        model.0.weight.keep();
+        model.2.weight.keep();
+        cx.execute();
+        let expected = b.data();
+
+        // Store the weights as f16. The fused CPU ops convert them as they're used
+        for weight in [model.0.weight.id, model.2.weight.id] {
+            let t = cx.get_tensor_ref(weight, 0).unwrap().cast(DType::F16);
+            cx.set_tensor(weight, 0, t);
+        }
+        b.drop();
+        cx.compile(<(GenericCompiler, CPUCompiler)>::default(), &mut b);
+        cx.execute();
+        assert_close_precision(&b.data(), &expected, 2);
+        b.drop();
+        cx.execute_planned();
+        assert_close_precision(&b.data(), &expected, 2);
+        assert_eq!(
+            cx.get_tensor_ref(model.0.weight.id, 0).unwrap().dtype(),
+            Some(DType::F16)
+        );
+    }
+
+    #[test]
+    fn test_constant_tensor_eq() {
+        fn constant<T: crate::dtype::Element>(data: Vec<T>) -> crate::op::ConstantTensor {
+            crate::op::ConstantTensor(crate::prelude::Tensor::new(data))
+        }
+        let a = constant(vec![1f32, f32::NAN]);
+        assert_eq!(a, a.clone());
+        assert_ne!(a, constant(vec![1f32]));
+        assert_ne!(constant(vec![0f32]), constant(vec![-0f32]));
+        assert_ne!(constant(vec![1i32]), constant(vec![1i64]));
+        assert_eq!(constant(vec![1i64, 2]), constant(vec![1i64, 2]));
+    }
 }
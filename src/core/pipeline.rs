@@ -0,0 +1,450 @@
+//! Compiler pipelines assembled at runtime.
+//!
+//! [`Compiler::compile`] is generic over the ids it remaps, so compilers can't be boxed directly. [`DynCompiler`] is
+//! the object-safe version, implemented for every compiler, and a [`CompilerPipeline`] is a list of boxed passes that
+//! can be added, reordered, switched off and looped at runtime. Pipelines can also be read from a spec, like a config
+//! file, using the compilers in a [`CompilerRegistry`]:
+//! ```text
+//! # One pass per name, separated by commas or newlines
+//! GenericCompiler
+//! loop(ArithmeticElimination, CSE)  # rerun until the graph stops changing
+//! !ConstantFolding                  # kept in the pipeline, but disabled
+//! CPUCompiler
+//! ```
+
+use std::fmt::Debug;
+
+use crate::{
+    compiler_utils::{run_looped, Compiler, ToIdsMut},
+    graph::Graph,
+    trace::{short_type_name, trace_pass},
+};
+
+/// An object-safe [`Compiler`], so passes can be stored as `Box<dyn DynCompiler>`
+pub trait DynCompiler {
+    /// Run a compilation pass
+    fn compile_dyn(&self, graph: &mut Graph, remap: &mut dyn ToIdsMut);
+}
+
+impl<C: Compiler> DynCompiler for C {
+    fn compile_dyn(&self, graph: &mut Graph, remap: &mut dyn ToIdsMut) {
+        self.compile(graph, remap)
+    }
+}
+
+impl Compiler for Box<dyn DynCompiler> {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
+        // The box is a compiler itself, so go through the inner one to avoid recursing
+        self.as_ref().compile_dyn(graph, &mut remap)
+    }
+}
+
+/// An error while building a pipeline from a spec
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineError {
+    /// No compiler is registered under this name
+    UnknownCompiler(String),
+    /// The spec is malformed
+    Parse(String),
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineError::UnknownCompiler(name) => write!(f, "No compiler registered as {name}"),
+            PipelineError::Parse(message) => write!(f, "Invalid pipeline spec: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// The compilers a [`CompilerPipeline`] spec can refer to by name
+#[allow(clippy::type_complexity)]
+pub struct CompilerRegistry {
+    compilers: Vec<(String, fn() -> Box<dyn DynCompiler>)>,
+}
+
+impl Default for CompilerRegistry {
+    /// A registry with the generic and CPU compilers, both as individual passes and as `GenericCompiler` and
+    /// `CPUCompiler`
+    fn default() -> Self {
+        use crate::compilers::*;
+        let mut registry = Self::empty();
+        registry
+            .register_as::<GenericCompiler>("GenericCompiler")
+            .register::<RemoveUnusedSlices>()
+            .register::<RemoveSingleReductions>()
+            .register::<ArithmeticElimination>()
+            .register::<UnarySequentialElimination>()
+            .register::<AlgebraicSimplification>()
+            .register::<ConstantFolding>()
+            .register::<CSE>()
+            .register::<RemoveUnusedNodes>()
+            .register::<DepthFirst>()
+            .register::<GatherToOneHot>();
+        register_cpu_compilers(&mut registry);
+        registry
+    }
+}
+
+impl CompilerRegistry {
+    /// A registry without any compilers
+    pub fn empty() -> Self {
+        Self { compilers: vec![] }
+    }
+
+    /// Register a compiler under its type name
+    pub fn register<C: Compiler + Default + 'static>(&mut self) -> &mut Self {
+        self.register_as::<C>(&short_type_name::<C>())
+    }
+
+    /// Register a compiler under a name, replacing any compiler registered under the same name. Useful for type aliases
+    pub fn register_as<C: Compiler + Default + 'static>(&mut self, name: &str) -> &mut Self {
+        assert!(
+            !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'),
+            "Compiler names can only contain alphanumerics and underscores"
+        );
+        self.compilers.retain(|(n, _)| n != name);
+        self.compilers
+            .push((name.to_string(), || Box::new(C::default())));
+        self
+    }
+
+    /// Make a new instance of a registered compiler
+    pub fn create(&self, name: &str) -> Option<Box<dyn DynCompiler>> {
+        self.compilers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, create)| create())
+    }
+}
+
+enum Pass {
+    Compiler(Box<dyn DynCompiler>),
+    Looped(CompilerPipeline),
+}
+
+struct Stage {
+    name: String,
+    pass: Pass,
+    enabled: bool,
+}
+
+/// A sequence of compilers built at runtime. Runs like a tuple of compilers, and is traced the same way.
+///
+/// ```rust
+/// use luminal::prelude::*;
+/// let mut pipeline = CompilerPipeline::new();
+/// pipeline
+///     .add_named("GenericCompiler", GenericCompiler::default())
+///     .add_looped(CompilerPipeline::from_spec("ArithmeticElimination, CSE", &CompilerRegistry::default()).unwrap())
+///     .add_named("CPUCompiler", CPUCompiler::default());
+/// pipeline.set_enabled("CSE", false);
+/// pipeline.move_to("CPUCompiler", 0);
+/// assert_eq!(pipeline.names(), ["CPUCompiler", "GenericCompiler", "Looped(ArithmeticElimination, CSE)"]);
+///
+/// let mut cx = Graph::new();
+/// let mut b = cx.tensor::<R1<3>>().exp2().log2().retrieve();
+/// cx.compile(&pipeline, &mut b);
+/// ```
+#[derive(Default)]
+pub struct CompilerPipeline {
+    stages: Vec<Stage>,
+}
+
+impl Debug for CompilerPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.stages.iter().map(|s| {
+                if s.enabled {
+                    s.name.clone()
+                } else {
+                    format!("!{}", s.name)
+                }
+            }))
+            .finish()
+    }
+}
+
+impl CompilerPipeline {
+    /// An empty pipeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a pipeline from a spec (see the [module docs](self)), creating compilers from the registry
+    pub fn from_spec(spec: &str, registry: &CompilerRegistry) -> Result<Self, PipelineError> {
+        let mut tokens = tokenize(spec)?.into_iter().peekable();
+        let pipeline = parse_stages(&mut tokens, registry)?;
+        match tokens.next() {
+            Some(t) => Err(PipelineError::Parse(format!("Unexpected {t:?}"))),
+            None => Ok(pipeline),
+        }
+    }
+
+    /// Add a compiler to the end of the pipeline, named after its type
+    pub fn add<C: Compiler + 'static>(&mut self, compiler: C) -> &mut Self {
+        self.add_named(&short_type_name::<C>(), compiler)
+    }
+
+    /// Add a compiler to the end of the pipeline under a name
+    pub fn add_named<C: Compiler + 'static>(&mut self, name: &str, compiler: C) -> &mut Self {
+        self.push(name.to_string(), Pass::Compiler(Box::new(compiler)))
+    }
+
+    /// Add a pipeline to the end of this one, rerunning it until it stops changing the graph.
+    /// It's named `Looped(..)` after the stages inside it.
+    pub fn add_looped(&mut self, pipeline: CompilerPipeline) -> &mut Self {
+        let name = format!("Looped({})", pipeline.names().join(", "));
+        self.push(name, Pass::Looped(pipeline))
+    }
+
+    fn push(&mut self, name: String, pass: Pass) -> &mut Self {
+        self.stages.push(Stage {
+            name,
+            pass,
+            enabled: true,
+        });
+        self
+    }
+
+    /// The names of the top level stages, in order
+    pub fn names(&self) -> Vec<&str> {
+        self.stages.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    /// Enable or disable every stage with this name, including ones inside loops. Returns false if there are none
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for stage in &mut self.stages {
+            if stage.name == name {
+                stage.enabled = enabled;
+                found = true;
+            }
+            if let Pass::Looped(inner) = &mut stage.pass {
+                found |= inner.set_enabled(name, enabled);
+            }
+        }
+        found
+    }
+
+    /// Remove the first top level stage with this name. Returns false if there isn't one
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.position(name).map(|i| self.stages.remove(i)).is_some()
+    }
+
+    /// Move the first top level stage with this name to `index`. Returns false if there isn't one
+    pub fn move_to(&mut self, name: &str, index: usize) -> bool {
+        let Some(i) = self.position(name) else {
+            return false;
+        };
+        let stage = self.stages.remove(i);
+        self.stages.insert(index.min(self.stages.len()), stage);
+        true
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|s| s.name == name)
+    }
+
+    fn run(&self, graph: &mut Graph, remap: &mut dyn ToIdsMut) {
+        for stage in self.stages.iter().filter(|s| s.enabled) {
+            match &stage.pass {
+                Pass::Compiler(compiler) => trace_pass(
+                    graph,
+                    || stage.name.clone(),
+                    |graph| compiler.as_ref().compile_dyn(graph, remap),
+                ),
+                Pass::Looped(inner) => trace_pass(
+                    graph,
+                    || stage.name.clone(),
+                    |graph| {
+                        run_looped(
+                            graph,
+                            || stage.name.clone(),
+                            |graph| inner.run(graph, remap),
+                        )
+                    },
+                ),
+            }
+        }
+    }
+}
+
+impl Compiler for CompilerPipeline {
+    fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
+        self.run(graph, &mut remap)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Open,
+    Close,
+    Not,
+}
+
+fn tokenize(spec: &str) -> Result<Vec<Token>, PipelineError> {
+    let mut tokens = vec![];
+    for line in spec.lines() {
+        let line = line.split('#').next().unwrap();
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '(' => tokens.push(Token::Open),
+                ')' => tokens.push(Token::Close),
+                '!' => tokens.push(Token::Not),
+                ',' => {}
+                c if c.is_whitespace() => {}
+                c if c.is_alphanumeric() || c == '_' => {
+                    let mut name = c.to_string();
+                    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                        name.push(c);
+                    }
+                    tokens.push(Token::Name(name));
+                }
+                c => return Err(PipelineError::Parse(format!("Unexpected character {c:?}"))),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse stages until the end of the spec or a closing bracket, which is left for the caller
+fn parse_stages(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = Token>>,
+    registry: &CompilerRegistry,
+) -> Result<CompilerPipeline, PipelineError> {
+    let mut pipeline = CompilerPipeline::new();
+    while tokens.peek().is_some_and(|t| *t != Token::Close) {
+        let enabled = tokens.next_if_eq(&Token::Not).is_none();
+        let Some(Token::Name(name)) = tokens.next() else {
+            return Err(PipelineError::Parse("Expected a compiler name".to_string()));
+        };
+        if name == "loop" {
+            if tokens.next() != Some(Token::Open) {
+                return Err(PipelineError::Parse("Expected ( after loop".to_string()));
+            }
+            let inner = parse_stages(tokens, registry)?;
+            if tokens.next() != Some(Token::Close) {
+                return Err(PipelineError::Parse("Unclosed loop".to_string()));
+            }
+            pipeline.add_looped(inner);
+        } else {
+            let compiler = registry
+                .create(&name)
+                .ok_or(PipelineError::UnknownCompiler(name.clone()))?;
+            pipeline.push(name, Pass::Compiler(compiler));
+        }
+        pipeline.stages.last_mut().unwrap().enabled = enabled;
+    }
+    Ok(pipeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{prelude::*, tests::assert_exact};
+
+    #[test]
+    fn test_pipeline_matches_tuple() {
+        let build = |cx: &mut Graph| {
+            let a = cx.tensor::<R1<3>>().set(vec![1., 2., 3.]);
+            ((a.log2().exp2() * 1.) + (a + 0.).sin()).retrieve()
+        };
+        let mut cx = Graph::new();
+        let mut a = build(&mut cx);
+        cx.compile(<(GenericCompiler, CPUCompiler)>::default(), &mut a);
+
+        let pipeline = CompilerPipeline::from_spec(
+            "GenericCompiler # generic passes\nCPUCompiler",
+            &CompilerRegistry::default(),
+        )
+        .unwrap();
+        let mut cx2 = Graph::new();
+        let mut b = build(&mut cx2);
+        cx2.compile(&pipeline, &mut b);
+
+        assert_eq!(cx.graph.node_count(), cx2.graph.node_count());
+        cx.execute();
+        cx2.execute();
+        assert_exact(&a.data(), &b.data());
+    }
+
+    #[test]
+    fn test_pipeline_stages() {
+        let registry = CompilerRegistry::default();
+        let mut pipeline = CompilerPipeline::from_spec(
+            "RemoveSingleReductions, !CSE, loop(ArithmeticElimination UnarySequentialElimination)",
+            &registry,
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{pipeline:?}"),
+            "[\"RemoveSingleReductions\", \"!CSE\", \"Looped(ArithmeticElimination, UnarySequentialElimination)\"]"
+        );
+
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let mut b = (a.log2().exp2() * 1.).retrieve();
+        let trace = cx.compile_traced(&pipeline, &mut b);
+        // CSE is disabled, and the loop stops once an iteration doesn't change anything
+        assert!(trace.pass("CSE").is_none());
+        assert!(trace
+            .pass("Looped(ArithmeticElimination, UnarySequentialElimination) (iteration 2)")
+            .is_some());
+        assert_eq!(cx.graph.node_count(), 1);
+
+        // Disabling passes inside loops and reordering
+        assert!(pipeline.set_enabled("UnarySequentialElimination", false));
+        assert!(pipeline.move_to("CSE", 0));
+        assert!(pipeline.remove("RemoveSingleReductions"));
+        assert!(!pipeline.remove("RemoveSingleReductions"));
+        assert_eq!(
+            pipeline.names(),
+            [
+                "CSE",
+                "Looped(ArithmeticElimination, UnarySequentialElimination)"
+            ]
+        );
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let mut b = (a.log2().exp2() * 1.).retrieve();
+        cx.compile(&pipeline, &mut b);
+        assert_eq!(cx.graph.node_count(), 3);
+
+        // Boxed compilers run like any other
+        let boxed: Vec<Box<dyn DynCompiler>> = vec![
+            Box::new(GenericCompiler::default()),
+            Box::new(Looped::<ArithmeticElimination>::default()),
+            registry.create("CPUCompiler").unwrap(),
+        ];
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let mut b = (a.log2().exp2() * 1.).retrieve();
+        for compiler in &boxed {
+            cx.compile(compiler, &mut b);
+        }
+        assert_eq!(cx.graph.node_count(), 1);
+    }
+
+    #[test]
+    fn test_pipeline_errors() {
+        let registry = CompilerRegistry::default();
+        assert_eq!(
+            CompilerPipeline::from_spec("CSE, Unknown", &registry).unwrap_err(),
+            PipelineError::UnknownCompiler("Unknown".to_string())
+        );
+        for spec in ["loop(CSE", "CSE)", "loop CSE", "!", "CSE; CSE"] {
+            assert!(matches!(
+                CompilerPipeline::from_spec(spec, &registry),
+                Err(PipelineError::Parse(_))
+            ));
+        }
+        assert!(CompilerPipeline::from_spec("", &registry)
+            .unwrap()
+            .names()
+            .is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    fmt::Write as _,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+use itertools::Itertools;
+use petgraph::stable_graph::NodeIndex;
+
+use crate::{
+    graph::{get_source_tensors, Graph},
+    tensor::Tensor,
+};
+
+/// Timing and memory information for a single node execution
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeProfile {
+    pub node: NodeIndex,
+    /// The op's debug name
+    pub op: String,
+    /// The logical shapes of each input
+    pub input_shapes: Vec<Vec<usize>>,
+    /// When the node started, relative to the start of execution
+    pub start: Duration,
+    pub duration: Duration,
+    /// Bytes in all outputs of the node
+    pub output_bytes: usize,
+}
+
+/// A profile of a graph execution, gathered by [`Graph::execute_profile`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    /// Every node that ran, in execution order
+    pub nodes: Vec<NodeProfile>,
+    /// The total execution time
+    pub total: Duration,
+}
+
+impl Profile {
+    /// Total time spent in each op type, longest first
+    pub fn op_totals(&self) -> Vec<(String, Duration)> {
+        let mut totals: Vec<(String, Duration)> = vec![];
+        for node in &self.nodes {
+            match totals.iter_mut().find(|(op, _)| *op == node.op) {
+                Some((_, d)) => *d += node.duration,
+                None => totals.push((node.op.clone(), node.duration)),
+            }
+        }
+        totals.sort_by(|(_, a), (_, b)| b.cmp(a));
+        totals
+    }
+
+    /// Render the profile in the Chrome trace event format, which can be opened in chrome://tracing or Perfetto
+    pub fn to_chrome_trace(&self) -> String {
+        let mut s = "{\"traceEvents\":[".to_string();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i > 0 {
+                s.push(',');
+            }
+            write!(
+                s,
+                "{{\"name\":\"{}\",\"cat\":\"op\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":0,\"tid\":0,\
+                \"args\":{{\"node\":{},\"input_shapes\":\"{:?}\",\"output_bytes\":{}}}}}",
+                escape_json(&node.op),
+                node.start.as_secs_f64() * 1e6,
+                node.duration.as_secs_f64() * 1e6,
+                node.node.index(),
+                node.input_shapes,
+                node.output_bytes
+            )
+            .unwrap();
+        }
+        s.push_str("],\"displayTimeUnit\":\"ms\"}");
+        s
+    }
+
+    /// Write the profile to a Chrome trace file
+    pub fn write_chrome_trace<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_chrome_trace())
+    }
+
+    /// Pretty print the profile to the terminal
+    pub fn print(&self) {
+        let width = term_size::dimensions().map(|(w, _)| w).unwrap_or(80);
+        println!(
+            "{:->2$} Executing {:->2$}",
+            "",
+            "",
+            width.saturating_sub(" Executing ".len()) / 2
+        );
+        for node in &self.nodes {
+            let mut shapes_string = node
+                .input_shapes
+                .iter()
+                .map(|s| format!("{s:?}"))
+                .join(", ");
+            if !shapes_string.is_empty() {
+                shapes_string = format!(" ({shapes_string})");
+            }
+            println!(
+                "{}{shapes_string}{:.>2$}",
+                node.op.bold().bright_green(),
+                format_duration(node.duration).bold(),
+                width.saturating_sub(node.op.len() + shapes_string.len()),
+            );
+        }
+
+        // Print out total times
+        println!();
+        println!(
+            "{:->2$} Total Times {:->2$}",
+            "",
+            "",
+            width.saturating_sub(" Total Times ".len()) / 2
+        );
+        for (name, elapsed) in self.op_totals() {
+            println!(
+                "{}{:.>2$}",
+                name.bold().bright_green(),
+                format_duration(elapsed).bold(),
+                width.saturating_sub(name.len()),
+            );
+        }
+        println!("Total: {}", format_duration(self.total).bold());
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    if d.as_secs() > 0 {
+        format!("{:.2}s", d.as_secs_f32())
+    } else if d.as_millis() > 0 {
+        format!("{}ms", d.as_millis())
+    } else {
+        format!("{}µs", d.as_micros())
+    }
+}
+
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn tensor_bytes(tensor: &Tensor) -> usize {
+    tensor
+        .dtype()
+        .zip(tensor.n_elements())
+        .map(|(dtype, n)| n * dtype.size_of())
+        .unwrap_or_default()
+}
+
+impl Graph {
+    /// Execute the graph, recording how long each node takes
+    pub fn execute_profile(&mut self) -> Profile {
+        if self.linearized_graph.is_none() {
+            self.toposort();
+        }
+        let mut remaining_consumers = self.consumers_map.as_ref().unwrap().clone();
+        let mut dim_stack = Vec::new();
+        let mut profile = Profile::default();
+
+        let start = Instant::now();
+        for (node, src_ids) in self.linearized_graph.as_ref().unwrap() {
+            if self.tensors.contains_key(&(*node, 0)) {
+                continue;
+            }
+
+            let mut srcs = Vec::new();
+            get_source_tensors(
+                &self.no_delete,
+                &mut self.tensors,
+                src_ids,
+                &remaining_consumers,
+                &mut srcs,
+            );
+
+            // Substitute in the dyn dims
+            for (_, st) in srcs.iter_mut() {
+                st.resolve_global_dyn_dims_stack(&self.dyn_map, &mut dim_stack);
+            }
+            let input_shapes = srcs
+                .iter()
+                .map(|(_, st)| {
+                    st.shape()
+                        .into_iter()
+                        .map(|i| i.to_usize().unwrap())
+                        .collect()
+                })
+                .collect();
+
+            // Execute
+            let op = self.graph.node_weight_mut(*node).unwrap();
+            let node_start = Instant::now();
+            let tensors = op.process(srcs);
+            let duration = node_start.elapsed();
+            profile.nodes.push(NodeProfile {
+                node: *node,
+                op: format!("{op:?}"),
+                input_shapes,
+                start: node_start - start,
+                duration,
+                output_bytes: tensors.iter().map(tensor_bytes).sum(),
+            });
+            for (i, tensor) in tensors.into_iter().enumerate() {
+                self.tensors.insert((*node, i as u8), tensor);
+            }
+
+            // Bookkeep remaining consumers
+            for (source, _) in src_ids {
+                *remaining_consumers.get_mut(source).unwrap() -= 1;
+            }
+        }
+        profile.total = start.elapsed();
+        self.reset();
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{prelude::*, tests::assert_exact};
+
+    #[test]
+    fn test_profile() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R2<2, 3>>().set(vec![1., 2., 3., 4., 5., 6.]);
+        let b = (a.exp2() + a).sum_reduce::<_, Axis<1>>().retrieve();
+        cx.execute();
+        let expected = b.data();
+        b.drop();
+
+        let profile = cx.execute_profile();
+        assert_exact(&b.data(), &expected);
+        let ops = profile
+            .nodes
+            .iter()
+            .map(|n| n.op.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ops, ["Tensor Load", "Exp2", "Add", "SumReduce(1)"]);
+        assert_eq!(profile.nodes[2].input_shapes, vec![vec![2, 3], vec![2, 3]]);
+        assert_eq!(profile.nodes[3].output_bytes, 2 * 4);
+        assert!(profile.nodes.iter().all(|n| n.start <= profile.total));
+        assert_eq!(profile.op_totals().len(), 4);
+
+        let trace = profile.to_chrome_trace();
+        assert!(trace.starts_with("{\"traceEvents\":[{\"name\":\"Tensor Load\""));
+        assert_eq!(trace.matches("\"ph\":\"X\"").count(), 4);
+    }
+}
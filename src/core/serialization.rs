@@ -1,6 +1,6 @@
-use crate::op::Function;
+use crate::dtype::DType;
+use crate::op::{Function, OpError};
 use crate::prelude::{Graph, GraphTensor, Shape, Tensor};
-use half::{bf16, f16};
 use memmap2::MmapOptions;
 use petgraph::stable_graph::NodeIndex;
 use rustc_hash::FxHashMap;
@@ -93,62 +93,104 @@ impl Loader for StateDictLoader {
 pub struct SafeTensorLoader {
     /// The paths to the safetensors file
     paths: Vec<String>,
+    native_dtypes: bool,
 }
 
 impl SafeTensorLoader {
+    /// Load tensors from these files, converting them to f32
     pub fn new<S: ToString>(paths: &[S]) -> Self {
         Self {
             paths: paths.iter().map(|s| s.to_string()).collect(),
+            native_dtypes: false,
         }
     }
+
+    /// Keep tensors in the type they're stored as in the file. Half precision weights then take half the memory,
+    /// and ops convert them as they're used.
+    pub fn native_dtypes(mut self) -> Self {
+        self.native_dtypes = true;
+        self
+    }
 }
 
 impl Loader for SafeTensorLoader {
     type Output = ();
     fn load<M: SerializeModule>(self, model: &M, graph: &mut Graph) {
+        // Record the type each tensor is stored as, so the graph knows it before the weights are loaded.
+        // Files that can't be read are reported when the graph runs.
+        let stored_dtypes = if self.native_dtypes {
+            stored_dtypes(&self.paths)
+        } else {
+            FxHashMap::default()
+        };
         for (weight_name, node_index) in state_dict(model) {
             if let Some(loading_node) = graph
                 .graph
                 .node_weight_mut(node_index)
                 .and_then(|op| op.as_any_mut().downcast_mut::<Function>())
             {
+                loading_node.2 = stored_dtypes
+                    .get(&weight_name.replace('/', "."))
+                    .copied()
+                    .unwrap_or(DType::F32);
                 let file_paths = self.paths.clone();
+                let native_dtypes = self.native_dtypes;
                 loading_node.1 = Box::new(move |_| {
                     for file_path in file_paths.iter() {
-                        let file = File::open(file_path).unwrap();
-                        let buffer = unsafe { MmapOptions::new().map(&file).unwrap() };
-                        let safetensors = SafeTensors::deserialize(&buffer).unwrap();
+                        let file = File::open(file_path)
+                            .map_err(|e| OpError::Io(format!("Couldn't open {file_path}: {e}")))?;
+                        let buffer = unsafe { MmapOptions::new().map(&file) }
+                            .map_err(|e| OpError::Io(format!("Couldn't map {file_path}: {e}")))?;
+                        let safetensors = SafeTensors::deserialize(&buffer).map_err(|e| {
+                            OpError::Other(format!("Couldn't read {file_path}: {e:?}"))
+                        })?;
 
                         if let Ok(tensor_view) = safetensors.tensor(&weight_name.replace('/', "."))
                         {
-                            // Convert to fp32
-                            let bytes = tensor_view.data().to_vec();
-                            let data: Vec<f32> = match tensor_view.dtype() {
-                                Dtype::F32 => bytes
-                                    .chunks_exact(4)
-                                    .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
-                                    .collect(),
-                                Dtype::F16 => bytes
-                                    .chunks_exact(2)
-                                    .map(|c| f16::from_ne_bytes([c[0], c[1]]).to_f32())
-                                    .collect(),
-                                Dtype::BF16 => bytes
-                                    .chunks_exact(2)
-                                    .map(|c| bf16::from_ne_bytes([c[0], c[1]]).to_f32())
-                                    .collect(),
-                                _ => panic!("{:?} is not a supported dtype", tensor_view.dtype()),
-                            };
-                            return vec![Tensor {
-                                data: Box::new(data),
-                            }];
+                            if from_safetensors_dtype(tensor_view.dtype()).is_none() {
+                                return Err(OpError::Other(format!(
+                                    "Tensor \"{weight_name}\" is a {:?}, which isn't a supported dtype",
+                                    tensor_view.dtype()
+                                )));
+                            }
+                            let tensor = Tensor::from(tensor_view);
+                            if native_dtypes {
+                                return Ok(vec![tensor]);
+                            }
+                            return Ok(vec![tensor.cast(DType::F32)]);
                         }
                     }
 
-                    panic!("Tensor \"{weight_name}\" not found in files");
+                    Err(OpError::Other(format!(
+                        "Tensor \"{weight_name}\" not found in files"
+                    )))
                 });
+                graph.update_dtype(node_index);
+            }
+        }
+    }
+}
+
+/// The supported types tensors are stored as in safetensors files, by tensor name. The first file with a tensor wins.
+fn stored_dtypes(paths: &[String]) -> FxHashMap<String, DType> {
+    let mut dtypes = FxHashMap::default();
+    for path in paths {
+        let Ok(file) = File::open(path) else {
+            continue;
+        };
+        let Ok(buffer) = (unsafe { MmapOptions::new().map(&file) }) else {
+            continue;
+        };
+        let Ok((_, metadata)) = SafeTensors::read_metadata(&buffer) else {
+            continue;
+        };
+        for (name, info) in metadata.tensors() {
+            if let Some(dtype) = from_safetensors_dtype(info.dtype) {
+                dtypes.entry(name).or_insert(dtype);
             }
         }
     }
+    dtypes
 }
 
 /// Serializer keeps track of the tensors and modules that make up a model
@@ -185,33 +227,49 @@ impl Serializer {
     }
 }
 
-impl<'data> View for &'data Tensor {
+fn to_safetensors_dtype(dtype: DType) -> Dtype {
+    match dtype {
+        DType::F32 => Dtype::F32,
+        DType::F16 => Dtype::F16,
+        DType::BF16 => Dtype::BF16,
+        DType::I32 => Dtype::I32,
+        DType::I64 => Dtype::I64,
+        DType::Bool => Dtype::BOOL,
+    }
+}
+
+fn from_safetensors_dtype(dtype: Dtype) -> Option<DType> {
+    Some(match dtype {
+        Dtype::F32 => DType::F32,
+        Dtype::F16 => DType::F16,
+        Dtype::BF16 => DType::BF16,
+        Dtype::I32 => DType::I32,
+        Dtype::I64 => DType::I64,
+        Dtype::BOOL => DType::Bool,
+        _ => return None,
+    })
+}
+
+impl View for &Tensor {
     fn dtype(&self) -> Dtype {
-        Dtype::F32 // For now just assume float, this should change in the future
+        to_safetensors_dtype(Tensor::dtype(self).expect("Expected a CPU tensor"))
     }
     fn shape(&self) -> &[usize] {
         &[]
     }
-    fn data(&self) -> Cow<[u8]> {
-        self.data
-            .as_any()
-            .downcast_ref::<Vec<f32>>()
-            .unwrap()
-            .iter()
-            .flat_map(|f| f.to_le_bytes().into_iter())
-            .collect::<Vec<_>>()
-            .into()
+    fn data(&self) -> Cow<'_, [u8]> {
+        self.to_le_bytes().into()
     }
     fn data_len(&self) -> usize {
-        self.data.as_any().downcast_ref::<Vec<f32>>().unwrap().len()
+        self.n_elements().unwrap() * Tensor::dtype(self).unwrap().size_of()
     }
 }
 
 impl<'a> std::convert::From<safetensors::tensor::TensorView<'a>> for Tensor {
     fn from(value: safetensors::tensor::TensorView<'a>) -> Self {
-        Tensor {
-            data: Box::new(unsafe { std::mem::transmute::<_, &'a [f32]>(value.data()) }.to_vec()),
-        }
+        let dtype = from_safetensors_dtype(value.dtype())
+            .unwrap_or_else(|| panic!("{:?} is not a supported dtype", value.dtype()));
+        Tensor::from_le_bytes(dtype, value.data())
     }
 }
 
@@ -78,6 +78,21 @@ impl<const I: usize, const J: usize, const K: usize, const L: usize, const M: us
     }
 }
 
+/// A set of 7 axes
+#[rustfmt::skip]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Axes7<const I: usize, const J: usize, const K: usize, const L: usize, const M: usize, const N: usize, const O: usize>;
+#[rustfmt::skip]
+impl<const I: usize, const J: usize, const K: usize, const L: usize, const M: usize, const N: usize, const O: usize> Axes
+    for Axes7<I, J, K, L, M, N, O>
+{
+    type Array = [usize; 7];
+    #[inline(always)]
+    fn as_array() -> Self::Array {
+        [I, J, K, L, M, N, O]
+    }
+}
+
 /// Represents something that has the axes `Ax`
 pub trait HasAxes<Ax> {}
 
@@ -114,6 +129,13 @@ impl_has_axis!((D1, D2, D3, D4, D5, D6), 6, 2);
 impl_has_axis!((D1, D2, D3, D4, D5, D6), 6, 3);
 impl_has_axis!((D1, D2, D3, D4, D5, D6), 6, 4);
 impl_has_axis!((D1, D2, D3, D4, D5, D6), 6, 5);
+impl_has_axis!((D1, D2, D3, D4, D5, D6, D7), 7, 0);
+impl_has_axis!((D1, D2, D3, D4, D5, D6, D7), 7, 1);
+impl_has_axis!((D1, D2, D3, D4, D5, D6, D7), 7, 2);
+impl_has_axis!((D1, D2, D3, D4, D5, D6, D7), 7, 3);
+impl_has_axis!((D1, D2, D3, D4, D5, D6, D7), 7, 4);
+impl_has_axis!((D1, D2, D3, D4, D5, D6, D7), 7, 5);
+impl_has_axis!((D1, D2, D3, D4, D5, D6, D7), 7, 6);
 
 impl<const I: usize, const J: usize, S> HasAxes<Axes2<I, J>> for S where
     Self: HasAxes<Axis<I>> + HasAxes<Axis<J>>
@@ -161,3 +183,24 @@ where
         + HasAxes<Axis<N>>,
 {
 }
+
+impl<
+        const I: usize,
+        const J: usize,
+        const K: usize,
+        const L: usize,
+        const M: usize,
+        const N: usize,
+        const O: usize,
+        S,
+    > HasAxes<Axes7<I, J, K, L, M, N, O>> for S
+where
+    Self: HasAxes<Axis<I>>
+        + HasAxes<Axis<J>>
+        + HasAxes<Axis<K>>
+        + HasAxes<Axis<L>>
+        + HasAxes<Axis<M>>
+        + HasAxes<Axis<N>>
+        + HasAxes<Axis<O>>,
+{
+}
@@ -50,8 +50,6 @@ macro_rules! length {
     ($x:tt $($xs:tt)*) => {1 + length!($($xs)*)};
 }
 
-pub(crate) use length;
-
 // Defines all reduce/broadcast rules recursively
 macro_rules! broadcast_to_all {
     ([$($s1:ident)*] [$($s2:ident)*] [$($ax:tt)*] [] [$axis:tt $($axes:tt)*]) => {
@@ -74,7 +72,7 @@ macro_rules! broadcast_to_all {
     }
 }
 
-broadcast_to_all!([] [] [] [A B C D E F] [() Axis Axes2 Axes3 Axes4 Axes5 Axes6]);
+broadcast_to_all!([] [] [] [A B C D E F G] [() Axis Axes2 Axes3 Axes4 Axes5 Axes6 Axes7]);
 
 /// Internal implementation for broadcasting strides
 pub trait BroadcastStridesTo<S: Shape, Ax>: Shape + BroadcastShapeTo<S, Ax> {
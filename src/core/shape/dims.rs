@@ -0,0 +1,237 @@
+use std::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+};
+
+use tinyvec::{ArrayVec, ArrayVecIterator};
+
+use super::symbolic::Expression;
+
+/// The most dimensions a [`ShapeTracker`](super::ShapeTracker) stores inline. Higher ranks spill to
+/// the heap.
+pub const MAX_DIMS: usize = 10;
+
+/// Data stored per dimension in a [`ShapeTracker`](super::ShapeTracker)
+pub trait DimData: Copy + Default + Eq + Hash + Debug + Send + Sync + 'static {}
+
+impl DimData for usize {}
+impl DimData for bool {}
+impl DimData for Expression {}
+impl DimData for (Expression, Expression) {}
+
+/// A vector of per-dimension data. Up to [`MAX_DIMS`] entries are stored inline, so common shapes
+/// don't allocate, and anything longer moves to the heap.
+#[derive(Clone)]
+pub struct DimVec<T: DimData>(Repr<T>);
+
+#[derive(Clone)]
+enum Repr<T: DimData> {
+    Inline(ArrayVec<[T; MAX_DIMS]>),
+    Heap(Vec<T>),
+}
+
+impl<T: DimData> Default for DimVec<T> {
+    fn default() -> Self {
+        Self(Repr::Inline(ArrayVec::new()))
+    }
+}
+
+impl<T: DimData> DimVec<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the entries to the heap if there's no room left inline
+    fn reserve_one(&mut self) {
+        if let Repr::Inline(v) = &mut self.0 {
+            if v.len() == MAX_DIMS {
+                let mut heap = Vec::with_capacity(MAX_DIMS + 1);
+                heap.extend(v.drain(..));
+                self.0 = Repr::Heap(heap);
+            }
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.reserve_one();
+        match &mut self.0 {
+            Repr::Inline(v) => v.push(value),
+            Repr::Heap(v) => v.push(value),
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.reserve_one();
+        match &mut self.0 {
+            Repr::Inline(v) => v.insert(index, value),
+            Repr::Heap(v) => v.insert(index, value),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        match &mut self.0 {
+            Repr::Inline(v) => v.remove(index),
+            Repr::Heap(v) => v.remove(index),
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: T) {
+        self[index] = value;
+    }
+
+    /// Modify the entries in place
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut [T]) -> R) -> R {
+        f(self)
+    }
+
+    /// Whether the entries have spilled to the heap
+    pub fn spilled(&self) -> bool {
+        matches!(self.0, Repr::Heap(_))
+    }
+}
+
+impl<T: DimData> From<&[T]> for DimVec<T> {
+    fn from(data: &[T]) -> Self {
+        data.iter().copied().collect()
+    }
+}
+
+impl<T: DimData> FromIterator<T> for DimVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut v = Self::new();
+        for value in iter {
+            v.push(value);
+        }
+        v
+    }
+}
+
+impl<T: DimData> Deref for DimVec<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        match &self.0 {
+            Repr::Inline(v) => v,
+            Repr::Heap(v) => v,
+        }
+    }
+}
+
+impl<T: DimData> DerefMut for DimVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match &mut self.0 {
+            Repr::Inline(v) => v,
+            Repr::Heap(v) => v,
+        }
+    }
+}
+
+impl<T: DimData> Debug for DimVec<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
+impl<T: DimData> PartialEq for DimVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.deref() == other.deref()
+    }
+}
+
+impl<T: DimData> Eq for DimVec<T> {}
+
+impl<T: DimData> Hash for DimVec<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.deref().hash(state)
+    }
+}
+
+/// An owning iterator over a [`DimVec`]
+pub enum DimVecIter<T: DimData> {
+    Inline(ArrayVecIterator<[T; MAX_DIMS]>),
+    Heap(std::vec::IntoIter<T>),
+}
+
+impl<T: DimData> Iterator for DimVecIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        match self {
+            Self::Inline(i) => i.next(),
+            Self::Heap(i) => i.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Inline(i) => i.size_hint(),
+            Self::Heap(i) => i.size_hint(),
+        }
+    }
+}
+
+impl<T: DimData> DoubleEndedIterator for DimVecIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        match self {
+            Self::Inline(i) => i.next_back(),
+            Self::Heap(i) => i.next_back(),
+        }
+    }
+}
+
+impl<T: DimData> ExactSizeIterator for DimVecIter<T> {}
+
+impl<T: DimData> IntoIterator for DimVec<T> {
+    type Item = T;
+    type IntoIter = DimVecIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        match self.0 {
+            Repr::Inline(v) => DimVecIter::Inline(v.into_iter()),
+            Repr::Heap(v) => DimVecIter::Heap(v.into_iter()),
+        }
+    }
+}
+
+impl<'a, T: DimData> IntoIterator for &'a DimVec<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dim_vec() {
+        let mut v = (0..MAX_DIMS - 1).collect::<DimVec<usize>>();
+        v.insert(0, 10);
+        v.set(1, 20);
+        assert_eq!(v.len(), MAX_DIMS);
+        assert!(!v.spilled());
+        assert_eq!(&v[..3], &[10, 20, 1]);
+        assert_eq!(v.clone().into_iter().next_back(), Some(MAX_DIMS - 2));
+        assert_eq!(v.remove(0), 10);
+        assert_eq!(v.len(), MAX_DIMS - 1);
+    }
+
+    #[test]
+    fn test_spilled_dims() {
+        let inline = (0..MAX_DIMS).collect::<DimVec<usize>>();
+        let mut v = inline.clone();
+        v.push(MAX_DIMS);
+        v.insert(0, 100);
+        assert!(v.spilled());
+        assert_eq!(v.len(), MAX_DIMS + 2);
+        assert_eq!(v[0], 100);
+        assert_eq!(v.remove(0), 100);
+        assert_eq!(v.remove(MAX_DIMS), MAX_DIMS);
+        // Spilled vectors compare by their entries
+        assert_eq!(v, inline);
+        assert_eq!(
+            v.into_iter().rev().collect::<Vec<_>>(),
+            (0..MAX_DIMS).rev().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::{cell::RefCell, rc::Rc};
+
+use rustc_hash::FxHashMap;
+
+use super::{symbolic::BigExpression, ShapeTracker};
+
+/// Trackers cached per thread before the cache is cleared
+const MAX_CACHED: usize = 4096;
+
+thread_local! {
+    static INDEXERS: RefCell<FxHashMap<ShapeTracker, Rc<Indexer>>> = RefCell::default();
+}
+
+/// Maps logical indexes of a view to indexes into its physical buffer. This evaluates the same as
+/// [`ShapeTracker::index_expression`] and [`ShapeTracker::valid_expression`], without
+/// interpreting the expressions for every element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Indexer {
+    /// Logical indexes are physical indexes
+    Contiguous,
+    /// A view with known dims, stored from the outermost logical dim in
+    Strided(Vec<StridedDim>),
+    /// A view with unknown dims, which still needs the expressions
+    Expression {
+        index: BigExpression,
+        valid: BigExpression,
+    },
+}
+
+/// A single logical dimension of a strided view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StridedDim {
+    /// Logical size, including padding and slicing
+    pub size: usize,
+    /// Physical stride, or 0 for fake dims
+    pub stride: usize,
+    /// Added to the logical position to get the physical position
+    pub offset: i64,
+    /// Logical positions in `valid.0..valid.1` are in bounds. Always in bounds for fake dims
+    pub valid: (i64, i64),
+}
+
+impl Indexer {
+    pub fn new(tracker: &ShapeTracker) -> Self {
+        if tracker.is_contiguous() && !tracker.is_sliced() && !tracker.is_padded() {
+            return Self::Contiguous;
+        }
+        Self::strided(tracker).unwrap_or_else(|| Self::Expression {
+            index: tracker.index_expression(),
+            valid: tracker.valid_expression(),
+        })
+    }
+
+    fn strided(tracker: &ShapeTracker) -> Option<Self> {
+        let n = |e: super::symbolic::Expression| e.to_usize().map(|n| n as i64);
+        // Physical strides, in the original dim order
+        let mut strides = vec![0; tracker.len()];
+        let mut stride = 1;
+        for i in (0..tracker.len()).rev() {
+            if !tracker.fake[i] {
+                strides[i] = stride;
+                stride *= n(tracker.dims[i])? as usize;
+            }
+        }
+        let mut dims = vec![];
+        for &i in &tracker.indexes {
+            let (dim, (pad_start, pad_end), (slice_start, slice_end)) =
+                (n(tracker.dims[i])?, tracker.padding[i], tracker.slices[i]);
+            let (pad_start, pad_end) = (n(pad_start)?, n(pad_end)?);
+            let (slice_start, slice_end) = (n(slice_start)?, n(slice_end)?);
+            let size = (dim + pad_start + pad_end).min(slice_end) - slice_start;
+            dims.push(if tracker.fake[i] {
+                StridedDim {
+                    size: size as usize,
+                    stride: 0,
+                    offset: 0,
+                    valid: (i64::MIN, i64::MAX),
+                }
+            } else {
+                StridedDim {
+                    size: size as usize,
+                    stride: strides[i],
+                    offset: slice_start - pad_start.min(slice_start) - pad_start,
+                    valid: (
+                        pad_start - slice_start.min(pad_start),
+                        (dim + pad_start).min(slice_end),
+                    ),
+                }
+            });
+        }
+        Some(Self::Strided(dims))
+    }
+
+    /// Get the indexer for a tracker, reusing it if it's been seen before. Dyn dims should already
+    /// be resolved, since the cache is keyed by the tracker alone.
+    pub fn cached(tracker: &ShapeTracker) -> Rc<Self> {
+        INDEXERS.with_borrow_mut(|indexers| {
+            if let Some(indexer) = indexers.get(tracker) {
+                return indexer.clone();
+            }
+            if indexers.len() >= MAX_CACHED {
+                indexers.clear();
+            }
+            let indexer = Rc::new(Self::new(tracker));
+            indexers.insert(tracker.clone(), indexer.clone());
+            indexer
+        })
+    }
+
+    /// The physical index of a logical index, or None if it lands in padding
+    pub fn index(&self, logical: usize) -> Option<usize> {
+        match self {
+            Self::Contiguous => Some(logical),
+            Self::Strided(dims) => {
+                let (mut rem, mut index) = (logical, 0);
+                for dim in dims.iter().rev() {
+                    let pos = (rem % dim.size) as i64;
+                    rem /= dim.size;
+                    if pos < dim.valid.0 || pos >= dim.valid.1 {
+                        return None;
+                    }
+                    index += (pos + dim.offset) * dim.stride as i64;
+                }
+                Some(index as usize)
+            }
+            Self::Expression { index, valid } => {
+                (valid.exec_single_var(logical) != 0).then(|| index.exec_single_var(logical))
+            }
+        }
+    }
+
+    /// Physical indexes of the first `n` logical indexes, in order
+    pub fn iter(&self, n: usize) -> IndexIter<'_> {
+        IndexIter {
+            indexer: self,
+            logical: 0,
+            n,
+            positions: match self {
+                Self::Strided(dims) => vec![0; dims.len()],
+                _ => vec![],
+            },
+        }
+    }
+}
+
+/// Steps through logical indexes, only touching the dims that change each step
+pub struct IndexIter<'a> {
+    indexer: &'a Indexer,
+    logical: usize,
+    n: usize,
+    positions: Vec<usize>,
+}
+
+impl Iterator for IndexIter<'_> {
+    type Item = Option<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.logical == self.n {
+            return None;
+        }
+        let Indexer::Strided(dims) = self.indexer else {
+            self.logical += 1;
+            return Some(self.indexer.index(self.logical - 1));
+        };
+        let mut index = 0;
+        let mut valid = true;
+        for (dim, &pos) in dims.iter().zip(&self.positions) {
+            let pos = pos as i64;
+            valid &= pos >= dim.valid.0 && pos < dim.valid.1;
+            index += (pos + dim.offset) * dim.stride as i64;
+        }
+        // Advance the positions like an odometer
+        for (dim, pos) in dims.iter().zip(&mut self.positions).rev() {
+            *pos += 1;
+            if *pos < dim.size {
+                break;
+            }
+            *pos = 0;
+        }
+        self.logical += 1;
+        Some(valid.then_some(index as usize))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.n - self.logical, Some(self.n - self.logical))
+    }
+}
+
+impl ExactSizeIterator for IndexIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::symbolic::Expression;
+
+    fn check(tracker: &ShapeTracker) {
+        let (ind, val) = (tracker.index_expression(), tracker.valid_expression());
+        let n = tracker.n_elements().to_usize().unwrap();
+        let indexer = Indexer::new(tracker);
+        let expected = (0..n)
+            .map(|i| (val.exec_single_var(i) != 0).then(|| ind.exec_single_var(i)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            (0..n).map(|i| indexer.index(i)).collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(indexer.iter(n).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_indexers_match_expressions() {
+        let dims = |d: &[usize]| d.iter().map(|&n| Expression::from(n)).collect::<Vec<_>>();
+        let tracker = ShapeTracker::new(&dims(&[2, 3, 4]));
+        assert_eq!(Indexer::new(&tracker), Indexer::Contiguous);
+        check(&tracker);
+
+        let mut permuted = tracker.clone();
+        permuted.permute(&[2, 0, 1]);
+        check(&permuted);
+
+        let mut expanded = permuted.clone();
+        expanded.expand(1, 5.into());
+        check(&expanded);
+
+        let mut sliced = tracker.clone();
+        sliced.slice(&[
+            (0.into(), 2.into()),
+            (1.into(), 3.into()),
+            (2.into(), 3.into()),
+        ]);
+        check(&sliced);
+        let mut sliced = sliced.contiguous();
+        sliced.slice(&[(1.into(), 2.into())]);
+        check(&sliced);
+
+        let mut padded = permuted;
+        padded.pad(&[
+            (1.into(), 0.into()),
+            (0.into(), 2.into()),
+            (3.into(), 1.into()),
+        ]);
+        check(&padded);
+
+        let mut high_rank = ShapeTracker::new(&dims(&[2, 1, 3, 1, 2, 1, 2, 2]));
+        high_rank.permute(&[7, 6, 5, 4, 3, 2, 1, 0]);
+        high_rank.expand(3, 2.into());
+        check(&high_rank);
+
+        // Unknown dims fall back to the expressions
+        let mut symbolic = ShapeTracker::new(&['a'.into(), 2.into()]);
+        symbolic.permute(&[1, 0]);
+        assert!(matches!(
+            Indexer::new(&symbolic),
+            Indexer::Expression { .. }
+        ));
+    }
+}
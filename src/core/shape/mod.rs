@@ -1,5 +1,7 @@
 mod axes;
 mod broadcast;
+mod dims;
+mod indexer;
 mod permute;
 mod realize;
 mod slice;
@@ -11,6 +13,8 @@ pub use slice::*;
 
 pub use axes::*;
 pub use broadcast::*;
+pub use dims::*;
+pub use indexer::*;
 pub use permute::*;
 pub use tracker::*;
 
@@ -35,6 +39,8 @@ pub trait ConstDim: Default + Dimension {
     const SIZE: usize;
 }
 
+/// A dimension only known at runtime, named by a single character. Dimensions with longer names
+/// can be set at runtime with [`GraphTensor::dyn_reshape`](crate::prelude::GraphTensor::dyn_reshape).
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct Dyn<const C: char>;
 
@@ -218,6 +224,10 @@ pub type R5<const M: usize, const N: usize, const O: usize, const P: usize, cons
 /// Compile time known shape with 6 dimensions
 pub type R6<const M: usize, const N: usize, const O: usize, const P: usize, const Q: usize, const R: usize> =
     (Const<M>, Const<N>, Const<O>, Const<P>, Const<Q>, Const<R>);
+#[rustfmt::skip]
+/// Compile time known shape with 7 dimensions
+pub type R7<const M: usize, const N: usize, const O: usize, const P: usize, const Q: usize, const R: usize, const S: usize> =
+    (Const<M>, Const<N>, Const<O>, Const<P>, Const<Q>, Const<R>, Const<S>);
 
 macro_rules! shape {
     (($($D:tt $Idx:tt),*), rank=$Num:expr, all=$All:tt) => {
@@ -286,6 +296,7 @@ shape!((D1 0, D2 1, D3 2), rank=3, all=Axes3);
 shape!((D1 0, D2 1, D3 2, D4 3), rank=4, all=Axes4);
 shape!((D1 0, D2 1, D3 2, D4 3, D5 4), rank=5, all=Axes5);
 shape!((D1 0, D2 1, D3 2, D4 3, D5 4, D6 5), rank=6, all=Axes6);
+shape!((D1 0, D2 1, D3 2, D4 3, D5 4, D6 5, D7 6), rank=7, all=Axes7);
 
 /// Marker for shapes that have the same number of elements as `Dst`
 pub trait AssertSameNumel<Dst: ConstShape>: ConstShape {
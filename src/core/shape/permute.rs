@@ -31,9 +31,19 @@ macro_rules! impl_permute {
         {
         }
     };
+    ($Ax0:tt, $Ax1:tt, $Ax2:tt, $Ax3:tt, $Ax4:tt, $Ax5:tt) => {
+        impl<D1, D2, D3, D4, D5, D6>
+            PermuteShapeTo<
+                (d!($Ax0), d!($Ax1), d!($Ax2), d!($Ax3), d!($Ax4), d!($Ax5)),
+                Axes6<$Ax0, $Ax1, $Ax2, $Ax3, $Ax4, $Ax5>,
+            > for (D1, D2, D3, D4, D5, D6)
+        {
+        }
+    };
 }
 
-/// Expand out all the possible permutations for 2-4d
+/// Expand out all the possible permutations for 2-6d. 7d shapes would need 5040 impls, so they get
+/// a single impl below instead.
 macro_rules! permutations {
     ([$Ax0:tt, $Ax1:tt]) => {
         impl_permute!($Ax1, $Ax0);
@@ -125,3 +135,54 @@ permutations!([0, 1]);
 permutations!([0, 1, 2]);
 permutations!([0, 1, 2, 3]);
 permutations!([0, 1, 2, 3, 4]);
+permutations!([0, 1, 2, 3, 4, 5]);
+
+/// The type of the dim at index `I` of a shape
+pub trait DimAt<const I: usize> {
+    type Dim;
+}
+
+macro_rules! impl_dim_at {
+    ($($I:tt $D:ident),*) => {
+        $(impl<D1, D2, D3, D4, D5, D6, D7> DimAt<$I> for (D1, D2, D3, D4, D5, D6, D7) {
+            type Dim = $D;
+        })*
+    };
+}
+
+impl_dim_at!(0 D1, 1 D2, 2 D3, 3 D4, 4 D5, 5 D6, 6 D7);
+
+/// 7d permutes look up each dim by its axis. This doesn't check the axes are a permutation, so
+/// [`GraphTensor::permute`](crate::prelude::GraphTensor::permute) checks that when the graph is built.
+impl<
+        D1,
+        D2,
+        D3,
+        D4,
+        D5,
+        D6,
+        D7,
+        const A: usize,
+        const B: usize,
+        const C: usize,
+        const D: usize,
+        const E: usize,
+        const F: usize,
+        const G: usize,
+    >
+    PermuteShapeTo<
+        (
+            <Self as DimAt<A>>::Dim,
+            <Self as DimAt<B>>::Dim,
+            <Self as DimAt<C>>::Dim,
+            <Self as DimAt<D>>::Dim,
+            <Self as DimAt<E>>::Dim,
+            <Self as DimAt<F>>::Dim,
+            <Self as DimAt<G>>::Dim,
+        ),
+        Axes7<A, B, C, D, E, F, G>,
+    > for (D1, D2, D3, D4, D5, D6, D7)
+where
+    Self: DimAt<A> + DimAt<B> + DimAt<C> + DimAt<D> + DimAt<E> + DimAt<F> + DimAt<G>,
+{
+}
@@ -2,20 +2,270 @@
 // Super minimal symbolic algebra library
 
 use std::{
+    cell::RefCell,
     cmp::Ordering,
     fmt::Debug,
-    ops::{Add, BitAnd, BitOr, Div, IndexMut, Mul, Rem, Sub},
+    hash::{Hash, Hasher},
+    ops::{Add, BitAnd, BitOr, Bound, Div, IndexMut, Mul, RangeBounds, Rem, Sub},
+    sync::{LazyLock, Mutex},
 };
 
 use itertools::Itertools;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
 use tinyvec::ArrayVec;
 
-/// A symbolic expression stored on the stack
-pub type Expression = GenericExpression<ArrayVec<[Term; 20]>>; // We need to figure out how to reduce this, can't be fixed at 20. ShapeTracker would take up 6 dims * 12 pads * 12 slices * 20 terms * 8 bytes = 138kb
 /// A symbolic expression stored on the heap
 pub type BigExpression = GenericExpression<Vec<Term>>;
 
+/// A hash-consed symbolic expression. Each distinct node of an expression DAG is stored once and
+/// shared by every expression containing it, so this is a pointer sized `Copy` handle, and equality
+/// and hashing don't look at the terms.
+///
+/// Interned nodes are never freed. Numbers are stored in the handle itself instead of being
+/// interned, so evaluating expressions and resolving dyn dims doesn't take up any memory. Only
+/// expressions with variables are interned, which are bounded by the shapes graphs are built with.
+#[derive(Clone, Copy)]
+pub struct Expression {
+    /// Either a number shifted up with the low bit set, or a pointer to an interned node
+    ptr: *const Node,
+}
+
+// SAFETY: expressions only point to interned nodes, which are immutable and never freed
+unsafe impl Send for Expression {}
+unsafe impl Sync for Expression {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Node {
+    Leaf(Term),
+    /// An op applied to the top of the stack and the value below it, like in the postfix terms
+    Op(Term, Expression, Expression),
+}
+
+// The low bit of node pointers is free to tag numbers with
+const _: () = assert!(std::mem::align_of::<Node>() > 1);
+
+/// Nodes are spread over shards by hash so threads building expressions rarely wait on each other
+const SHARDS: usize = 16;
+
+/// Nodes are allocated in chunks of this many
+const CHUNK_SIZE: usize = 1024;
+
+#[derive(Default)]
+struct Shard {
+    nodes: FxHashSet<&'static Node>,
+    chunks: Vec<Vec<Node>>,
+}
+
+impl Shard {
+    fn alloc(&mut self, node: Node) -> &'static Node {
+        if self.chunks.last().is_none_or(|c| c.len() == c.capacity()) {
+            self.chunks.push(Vec::with_capacity(CHUNK_SIZE));
+        }
+        let chunk = self.chunks.last_mut().unwrap();
+        chunk.push(node);
+        // SAFETY: chunks are never pushed past their capacity or dropped, so nodes never move and
+        // live as long as the static shards
+        unsafe { &*chunk.as_ptr().add(chunk.len() - 1) }
+    }
+}
+
+static NODES: LazyLock<[Mutex<Shard>; SHARDS]> = LazyLock::new(Default::default);
+
+/// Op results remembered per thread before the memo is cleared
+const MEMO_CAPACITY: usize = 4096;
+
+thread_local! {
+    /// Results of ops already applied to expressions
+    static MEMO: RefCell<FxHashMap<(Expression, Term, Expression), Expression>> =
+        RefCell::new(FxHashMap::default());
+}
+
+impl Expression {
+    fn intern(node: Node) -> Self {
+        if let Node::Leaf(Term::Num(n)) = node {
+            // Only numbers that fit beside the tag bit, which is all of them on 64 bit targets
+            if let Some(bits) = usize::try_from(n as u32)
+                .ok()
+                .and_then(|n| n.checked_mul(2))
+            {
+                return Self {
+                    ptr: std::ptr::without_provenance(bits | 1),
+                };
+            }
+        }
+        let mut hasher = FxHasher::default();
+        node.hash(&mut hasher);
+        let mut shard = NODES[hasher.finish() as usize % SHARDS].lock().unwrap();
+        let node = match shard.nodes.get(&node) {
+            Some(node) => *node,
+            None => {
+                let node = shard.alloc(node);
+                shard.nodes.insert(node);
+                node
+            }
+        };
+        Self { ptr: node }
+    }
+
+    /// The number stored in the handle, if this is one
+    fn inline_number(self) -> Option<i32> {
+        (self.ptr.addr() & 1 == 1).then(|| (self.ptr.addr() >> 1) as u32 as i32)
+    }
+
+    fn node(self) -> Node {
+        match self.inline_number() {
+            Some(n) => Node::Leaf(Term::Num(n)),
+            // SAFETY: untagged pointers always come from a node interned for the whole program
+            None => unsafe { *self.ptr },
+        }
+    }
+
+    /// The terms of this expression in postfix order
+    pub fn terms(&self) -> Vec<Term> {
+        let mut terms = vec![];
+        self.push_terms(&mut terms);
+        terms
+    }
+
+    fn push_terms(self, terms: &mut Vec<Term>) {
+        match self.node() {
+            Node::Leaf(term) => terms.push(term),
+            Node::Op(op, a, b) => {
+                b.push_terms(terms);
+                a.push_terms(terms);
+                terms.push(op);
+            }
+        }
+    }
+
+    fn apply(self, rhs: Self, op: Term) -> Self {
+        if let (Node::Leaf(Term::Num(a)), Node::Leaf(Term::Num(b))) = (self.node(), rhs.node()) {
+            return op.as_op().unwrap()(a, b).into();
+        }
+        let key = (self, op, rhs);
+        if let Some(e) = MEMO.with_borrow(|memo| memo.get(&key).copied()) {
+            return e;
+        }
+        let mut terms = rhs.terms();
+        self.push_terms(&mut terms);
+        terms.push(op);
+        let e = Self::from(BigExpression { terms }.minimize());
+        MEMO.with_borrow_mut(|memo| {
+            if memo.len() >= MEMO_CAPACITY {
+                memo.clear();
+            }
+            memo.insert(key, e);
+        });
+        e
+    }
+
+    pub fn minimize(self) -> Self {
+        BigExpression::from(self).minimize().into()
+    }
+
+    /// Minimize using the known ranges of variables, see [`GenericExpression::minimize_bounded`]
+    pub fn minimize_bounded(self, ranges: &FxHashMap<Symbol, DimRange>) -> Self {
+        BigExpression::from(self).minimize_bounded(ranges).into()
+    }
+
+    /// The largest value this expression can take given the ranges of its variables, if bounded
+    pub fn upper_bound(&self, ranges: &FxHashMap<Symbol, DimRange>) -> Option<usize> {
+        upper_bound(&self.terms(), ranges)
+    }
+
+    pub fn min<E: Into<Self>>(self, rhs: E) -> Self {
+        self.apply(rhs.into(), Term::Min)
+    }
+
+    pub fn max<E: Into<Self>>(self, rhs: E) -> Self {
+        self.apply(rhs.into(), Term::Max)
+    }
+
+    pub fn gte<E: Into<Self>>(self, rhs: E) -> Self {
+        self.apply(rhs.into(), Term::Gte)
+    }
+
+    pub fn lt<E: Into<Self>>(self, rhs: E) -> Self {
+        self.apply(rhs.into(), Term::Lt)
+    }
+
+    /// Evaluate the expression with no variables. Returns Some(value) if no variables are required, otherwise returns None.
+    pub fn to_usize(&self) -> Option<usize> {
+        self.exec(&FxHashMap::default())
+    }
+    /// Evaluate the expression with one value for all variables.
+    pub fn exec_single_var(&self, value: usize) -> usize {
+        let variables = self.to_symbols().into_iter().map(|s| (s, value)).collect();
+        self.exec(&variables).unwrap()
+    }
+    /// Evaluate the expression given variables.
+    pub fn exec(&self, variables: &FxHashMap<Symbol, usize>) -> Option<usize> {
+        self.exec_stack(variables, &mut Vec::new())
+    }
+    /// Evaluate the expression given variables. This function requires a stack to be given for use as storage
+    pub fn exec_stack(
+        &self,
+        variables: &FxHashMap<Symbol, usize>,
+        stack: &mut Vec<i32>,
+    ) -> Option<usize> {
+        self.push_values(variables, stack)?;
+        stack.pop().map(|i| i as usize)
+    }
+
+    fn push_values(self, variables: &FxHashMap<Symbol, usize>, stack: &mut Vec<i32>) -> Option<()> {
+        match self.node() {
+            Node::Leaf(Term::Num(n)) => stack.push(n),
+            Node::Leaf(Term::Var(c)) => stack.push(*variables.get(&c)? as i32),
+            Node::Leaf(_) => unreachable!("Leaves are numbers or variables"),
+            Node::Op(op, a, b) => {
+                b.push_values(variables, stack)?;
+                a.push_values(variables, stack)?;
+                let a = stack.pop().unwrap();
+                let b = stack.pop().unwrap();
+                stack.push(op.as_op().unwrap()(a, b));
+            }
+        }
+        Some(())
+    }
+    /// Retrieve all symbols in the expression.
+    pub fn to_symbols(&self) -> Vec<Symbol> {
+        to_symbols(&self.terms())
+    }
+    /// Check if the '-' variable exists in the expression.
+    pub fn is_unknown(&self) -> bool {
+        match self.node() {
+            Node::Leaf(term) => term == Term::Var('-'.into()),
+            Node::Op(_, a, b) => a.is_unknown() || b.is_unknown(),
+        }
+    }
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        0.into()
+    }
+}
+
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.ptr, other.ptr)
+    }
+}
+
+impl Eq for Expression {}
+
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.ptr, state)
+    }
+}
+
+impl Debug for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_terms(self.terms(), f)
+    }
+}
+
 /// Trait implemented on the 2 main symbolic expression storage types, Vec<Term> and ArrayVec<Term>
 #[allow(clippy::len_without_is_empty)]
 pub trait ExpressionStorage:
@@ -87,33 +337,48 @@ impl<S: PartialEq + ExpressionStorage> PartialEq for GenericExpression<S> {
     }
 }
 
+impl<S: Eq + ExpressionStorage> Eq for GenericExpression<S> {}
+
+impl<S: std::hash::Hash + ExpressionStorage> std::hash::Hash for GenericExpression<S> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.terms.hash(state)
+    }
+}
+
 impl<S: ExpressionStorage + Clone> Debug for GenericExpression<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut symbols = vec![];
-        for term in self.terms.clone() {
-            let new_symbol = match term {
-                Term::Num(n) => n.to_string(),
-                Term::Var(c) => c.to_string(),
-                Term::Max => format!(
-                    "max({}, {})",
-                    symbols.pop().unwrap(),
-                    symbols.pop().unwrap()
-                ),
-                Term::Min => format!(
-                    "min({}, {})",
-                    symbols.pop().unwrap(),
-                    symbols.pop().unwrap()
-                ),
-                _ => format!(
-                    "({}{term:?}{})",
-                    symbols.pop().unwrap(),
-                    symbols.pop().unwrap()
-                ),
-            };
-            symbols.push(new_symbol);
-        }
-        write!(f, "{}", symbols.pop().unwrap())
+        fmt_terms(self.terms.clone(), f)
+    }
+}
+
+fn fmt_terms(
+    terms: impl IntoIterator<Item = Term>,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    let mut symbols = vec![];
+    for term in terms {
+        let new_symbol = match term {
+            Term::Num(n) => n.to_string(),
+            Term::Var(c) => c.to_string(),
+            Term::Max => format!(
+                "max({}, {})",
+                symbols.pop().unwrap(),
+                symbols.pop().unwrap()
+            ),
+            Term::Min => format!(
+                "min({}, {})",
+                symbols.pop().unwrap(),
+                symbols.pop().unwrap()
+            ),
+            _ => format!(
+                "({}{term:?}{})",
+                symbols.pop().unwrap(),
+                symbols.pop().unwrap()
+            ),
+        };
+        symbols.push(new_symbol);
     }
+    write!(f, "{}", symbols.pop().unwrap())
 }
 
 impl<S: ExpressionStorage> GenericExpression<S> {
@@ -133,6 +398,16 @@ impl<S: ExpressionStorage> GenericExpression<S> {
         reduce_triples(self)
     }
 
+    /// Minimize, also simplifying terms which are decided by the known ranges of variables. This
+    /// removes things like `min(s, 4096)` when `s` is at most 4096, or `s % 8` when `s` is a
+    /// multiple of 8. Variables without a range are assumed to be any non-negative value.
+    pub fn minimize_bounded(self, ranges: &FxHashMap<Symbol, DimRange>) -> Self {
+        let terms = simplify_bounded(&self.minimize().terms, ranges);
+        let mut s = S::default();
+        s.extend(terms);
+        GenericExpression { terms: s }.minimize()
+    }
+
     pub fn min<E: Into<Self>>(self, rhs: E) -> Self {
         let mut rhs = rhs.into();
         rhs.terms.extend(self.terms);
@@ -162,6 +437,202 @@ impl<S: ExpressionStorage> GenericExpression<S> {
     }
 }
 
+/// The values a variable can take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DimRange {
+    pub min: usize,
+    pub max: usize,
+    /// The variable is always a multiple of this
+    pub multiple_of: usize,
+}
+
+impl Default for DimRange {
+    fn default() -> Self {
+        Self {
+            min: 0,
+            max: usize::MAX,
+            multiple_of: 1,
+        }
+    }
+}
+
+impl DimRange {
+    pub fn new(range: impl RangeBounds<usize>) -> Self {
+        Self {
+            min: match range.start_bound() {
+                Bound::Included(n) => *n,
+                Bound::Excluded(n) => n + 1,
+                Bound::Unbounded => 0,
+            },
+            max: match range.end_bound() {
+                Bound::Included(n) => *n,
+                Bound::Excluded(n) => n.saturating_sub(1),
+                Bound::Unbounded => usize::MAX,
+            },
+            multiple_of: 1,
+        }
+    }
+
+    pub fn multiple_of(self, n: usize) -> Self {
+        assert!(n > 0, "Dimensions can't be a multiple of 0");
+        Self {
+            multiple_of: n,
+            ..self
+        }
+    }
+
+    /// Check if a value is allowed by this range
+    pub fn contains(&self, value: usize) -> bool {
+        value >= self.min && value <= self.max && value.is_multiple_of(self.multiple_of)
+    }
+}
+
+/// A subexpression with what's known about its value
+struct Bounded {
+    terms: Vec<Term>,
+    /// Inclusive bounds
+    min: i64,
+    max: i64,
+    /// Always a multiple of this, with 0 meaning the value is always 0
+    multiple: i64,
+    /// If this is `a % b` with a non-negative `a` and positive `b`, the terms of `b`
+    divisor: Option<Vec<Term>>,
+}
+
+impl Bounded {
+    fn num(n: i64) -> Self {
+        Self {
+            terms: vec![Term::Num(n as i32)],
+            min: n,
+            max: n,
+            multiple: n.abs(),
+            divisor: None,
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        self.min > 0 || self.max < 0
+    }
+
+    fn falsy(&self) -> bool {
+        self.min == 0 && self.max == 0
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn bound_terms(terms: &[Term], ranges: &FxHashMap<Symbol, DimRange>) -> Bounded {
+    let mut stack: Vec<Bounded> = vec![];
+    for term in terms {
+        let bounded = match *term {
+            Term::Num(n) => Bounded::num(n as i64),
+            Term::Var(c) => {
+                let range = ranges.get(&c).copied().unwrap_or_default();
+                let m = range.multiple_of;
+                let clamp = |n: usize| n.min(i64::MAX as usize) as i64;
+                Bounded {
+                    terms: vec![*term],
+                    min: clamp(range.min.div_ceil(m).saturating_mul(m)),
+                    max: clamp(range.max / m * m),
+                    multiple: clamp(m),
+                    divisor: None,
+                }
+            }
+            op => {
+                let a = stack.pop().unwrap();
+                let b = stack.pop().unwrap();
+                match op {
+                    Term::Min if a.max <= b.min => a,
+                    Term::Min if b.max <= a.min => b,
+                    Term::Max if a.min >= b.max => a,
+                    Term::Max if b.min >= a.max => b,
+                    // a % b < b
+                    Term::Lt if a.divisor.as_ref() == Some(&b.terms) => Bounded::num(1),
+                    Term::Mod if a.min >= 0 && a.max < b.min => a,
+                    Term::Mod if b.min == b.max && b.min > 0 && a.multiple % b.min == 0 => {
+                        Bounded::num(0)
+                    }
+                    Term::Div if a.min >= 0 && a.max < b.min => Bounded::num(0),
+                    _ => combine_bounded(a, op, b),
+                }
+            }
+        };
+        stack.push(bounded);
+    }
+    stack.pop().unwrap()
+}
+
+fn combine_bounded(a: Bounded, op: Term, b: Bounded) -> Bounded {
+    let corners = |f: fn(i64, i64) -> i64| {
+        let c = [
+            f(a.min, b.min),
+            f(a.min, b.max),
+            f(a.max, b.min),
+            f(a.max, b.max),
+        ];
+        (*c.iter().min().unwrap(), *c.iter().max().unwrap())
+    };
+    let boolean = |t: bool, f: bool| match (t, f) {
+        (true, _) => (1, 1),
+        (_, true) => (0, 0),
+        _ => (0, 1),
+    };
+    let (min, max) = match op {
+        Term::Add => (a.min.saturating_add(b.min), a.max.saturating_add(b.max)),
+        Term::Sub => (a.min.saturating_sub(b.max), a.max.saturating_sub(b.min)),
+        Term::Mul => corners(i64::saturating_mul),
+        Term::Div if b.min > 0 => corners(|a, b| a / b),
+        Term::Mod if a.min >= 0 && b.min > 0 => (0, a.max.min(b.max - 1)),
+        Term::Min => (a.min.min(b.min), a.max.min(b.max)),
+        Term::Max => (a.min.max(b.min), a.max.max(b.max)),
+        Term::And => boolean(a.truthy() && b.truthy(), a.falsy() || b.falsy()),
+        Term::Or => boolean(a.truthy() || b.truthy(), a.falsy() && b.falsy()),
+        Term::Gte => boolean(a.min >= b.max, a.max < b.min),
+        Term::Lt => boolean(a.max < b.min, a.min >= b.max),
+        _ => (i64::MIN, i64::MAX),
+    };
+    if min == max && i32::try_from(min).is_ok() {
+        return Bounded::num(min);
+    }
+    let multiple = match op {
+        Term::Add | Term::Sub | Term::Min | Term::Max | Term::Mod => gcd(a.multiple, b.multiple),
+        Term::Mul => a.multiple.checked_mul(b.multiple).unwrap_or(1),
+        _ => 1,
+    };
+    let divisor = (op == Term::Mod && a.min >= 0 && b.min > 0).then(|| b.terms.clone());
+    let mut terms = b.terms;
+    terms.extend(a.terms);
+    terms.push(op);
+    Bounded {
+        terms,
+        min,
+        max,
+        multiple,
+        divisor,
+    }
+}
+
+fn simplify_bounded<S: ExpressionStorage>(
+    terms: &S,
+    ranges: &FxHashMap<Symbol, DimRange>,
+) -> Vec<Term> {
+    bound_terms(&terms.clone().into_iter().collect::<Vec<_>>(), ranges).terms
+}
+
+fn upper_bound<'a>(
+    terms: impl IntoIterator<Item = &'a Term>,
+    ranges: &FxHashMap<Symbol, DimRange>,
+) -> Option<usize> {
+    let max = bound_terms(&terms.into_iter().copied().collect::<Vec<_>>(), ranges).max;
+    (max < i64::MAX).then(|| max.max(0) as usize)
+}
+
 fn reduce_triples<S: ExpressionStorage>(mut expr: GenericExpression<S>) -> GenericExpression<S> {
     fn get_triples<S: ExpressionStorage>(
         exp: &GenericExpression<S>,
@@ -200,7 +671,6 @@ fn reduce_triples<S: ExpressionStorage>(mut expr: GenericExpression<S>) -> Gener
         }
     }
 
-    #[macro_export]
     macro_rules! unwrap_cont {
         ($i: expr) => {
             if let Some(s) = $i {
@@ -444,61 +914,27 @@ where
     }
     /// Evaluate the expression with one value for all variables.
     pub fn exec_single_var(&self, value: usize) -> usize {
-        let mut stack = Vec::new();
-        for term in &self.terms {
-            match term {
-                Term::Num(n) => stack.push(*n),
-                Term::Var(_) => stack.push(value as i32),
-                _ => {
-                    let a = stack.pop().unwrap();
-                    let b = stack.pop().unwrap();
-                    stack.push(term.as_op().unwrap()(a, b));
-                }
-            }
-        }
-        stack.pop().unwrap() as usize
+        exec_single_var(&self.terms, value)
     }
     /// Evaluate the expression given variables.
-    pub fn exec(&self, variables: &FxHashMap<char, usize>) -> Option<usize> {
+    pub fn exec(&self, variables: &FxHashMap<Symbol, usize>) -> Option<usize> {
         self.exec_stack(variables, &mut Vec::new())
     }
     /// Evaluate the expression given variables. This function requires a stack to be given for use as storage
     pub fn exec_stack(
         &self,
-        variables: &FxHashMap<char, usize>,
+        variables: &FxHashMap<Symbol, usize>,
         stack: &mut Vec<i32>,
     ) -> Option<usize> {
-        for term in &self.terms {
-            match term {
-                Term::Num(n) => stack.push(*n),
-                Term::Var(c) =>
-                {
-                    #[allow(clippy::needless_borrow)]
-                    if let Some(n) = variables.get(&c) {
-                        stack.push(*n as i32)
-                    } else {
-                        return None;
-                    }
-                }
-                _ => {
-                    let a = stack.pop().unwrap();
-                    let b = stack.pop().unwrap();
-                    stack.push(term.as_op().unwrap()(a, b));
-                }
-            }
-        }
-        stack.pop().map(|i| i as usize)
+        exec_stack(&self.terms, variables, stack)
     }
     /// Retrieve all symbols in the expression.
-    pub fn to_symbols(&self) -> Vec<char> {
-        self.terms
-            .clone()
-            .into_iter()
-            .filter_map(|t| match t {
-                Term::Var(c) => Some(c),
-                _ => None,
-            })
-            .collect()
+    pub fn to_symbols(&self) -> Vec<Symbol> {
+        to_symbols(&self.terms)
+    }
+    /// The largest value this expression can take given the ranges of its variables, if bounded
+    pub fn upper_bound(&self, ranges: &FxHashMap<Symbol, DimRange>) -> Option<usize> {
+        upper_bound(&self.terms, ranges)
     }
 
     /// Check if the '-' variable exists in the expression.
@@ -506,7 +942,148 @@ where
         self.terms
             .clone()
             .into_iter()
-            .any(|t| matches!(t, Term::Var('-')))
+            .any(|t| t == Term::Var('-'.into()))
+    }
+}
+
+fn exec_single_var<'a>(terms: impl IntoIterator<Item = &'a Term>, value: usize) -> usize {
+    let mut stack = Vec::new();
+    for term in terms {
+        match term {
+            Term::Num(n) => stack.push(*n),
+            Term::Var(_) => stack.push(value as i32),
+            _ => {
+                let a = stack.pop().unwrap();
+                let b = stack.pop().unwrap();
+                stack.push(term.as_op().unwrap()(a, b));
+            }
+        }
+    }
+    stack.pop().unwrap() as usize
+}
+
+fn exec_stack<'a>(
+    terms: impl IntoIterator<Item = &'a Term>,
+    variables: &FxHashMap<Symbol, usize>,
+    stack: &mut Vec<i32>,
+) -> Option<usize> {
+    for term in terms {
+        match term {
+            Term::Num(n) => stack.push(*n),
+            Term::Var(c) => {
+                if let Some(n) = variables.get(c) {
+                    stack.push(*n as i32)
+                } else {
+                    return None;
+                }
+            }
+            _ => {
+                let a = stack.pop().unwrap();
+                let b = stack.pop().unwrap();
+                stack.push(term.as_op().unwrap()(a, b));
+            }
+        }
+    }
+    stack.pop().map(|i| i as usize)
+}
+
+fn to_symbols<'a>(terms: impl IntoIterator<Item = &'a Term>) -> Vec<Symbol> {
+    terms
+        .into_iter()
+        .filter_map(|t| match t {
+            Term::Var(c) => Some(*c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The interned name of a variable. Any string can be a name, and a `char` is the name made of
+/// just that character, so `Dyn<'s'>` and `"s"` are the same variable.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+static SYMBOLS: LazyLock<Mutex<FxHashSet<&'static str>>> = LazyLock::new(Default::default);
+
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        let mut symbols = SYMBOLS.lock().unwrap();
+        if let Some(name) = symbols.get(name) {
+            return Self(name);
+        }
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        symbols.insert(name);
+        Self(name)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<char> for Symbol {
+    fn eq(&self, other: &char) -> bool {
+        *self == Symbol::from(*other)
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hash the name rather than the address so hash maps iterate in the same order every run
+        self.0.hash(state)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(value: &String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<char> for Symbol {
+    fn from(value: char) -> Self {
+        // Single characters are the most common names, so skip the lock for them
+        static ASCII: LazyLock<Vec<Symbol>> = LazyLock::new(|| {
+            (0..128u8)
+                .map(|c| Symbol::new((c as char).encode_utf8(&mut [0; 4])))
+                .collect()
+        });
+        if value.is_ascii() {
+            ASCII[value as usize]
+        } else {
+            Self::new(value.encode_utf8(&mut [0; 4]))
+        }
+    }
+}
+
+impl From<&char> for Symbol {
+    fn from(value: &char) -> Self {
+        Self::from(*value)
     }
 }
 
@@ -514,7 +1091,7 @@ where
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Term {
     Num(i32),
-    Var(char),
+    Var(Symbol),
     Add,
     Sub,
     Mul,
@@ -581,15 +1158,27 @@ impl<S: ExpressionStorage> From<Term> for GenericExpression<S> {
     }
 }
 
+impl<S: ExpressionStorage> From<Symbol> for GenericExpression<S> {
+    fn from(value: Symbol) -> Self {
+        GenericExpression::from(Term::Var(value))
+    }
+}
+
 impl<S: ExpressionStorage> From<char> for GenericExpression<S> {
     fn from(value: char) -> Self {
-        GenericExpression::from(Term::Var(value))
+        GenericExpression::from(Term::Var(value.into()))
     }
 }
 
 impl<S: ExpressionStorage> From<&char> for GenericExpression<S> {
     fn from(value: &char) -> Self {
-        GenericExpression::from(Term::Var(*value))
+        GenericExpression::from(Term::Var(value.into()))
+    }
+}
+
+impl<S: ExpressionStorage> From<&str> for GenericExpression<S> {
+    fn from(value: &str) -> Self {
+        GenericExpression::from(Term::Var(value.into()))
     }
 }
 
@@ -617,10 +1206,64 @@ impl<S: ExpressionStorage> From<&i32> for GenericExpression<S> {
     }
 }
 
+impl From<Term> for Expression {
+    fn from(value: Term) -> Self {
+        Self::intern(Node::Leaf(value))
+    }
+}
+
+impl From<Symbol> for Expression {
+    fn from(value: Symbol) -> Self {
+        Term::Var(value).into()
+    }
+}
+
+impl From<char> for Expression {
+    fn from(value: char) -> Self {
+        Term::Var(value.into()).into()
+    }
+}
+
+impl From<&char> for Expression {
+    fn from(value: &char) -> Self {
+        Term::Var(value.into()).into()
+    }
+}
+
+impl From<&str> for Expression {
+    fn from(value: &str) -> Self {
+        Term::Var(value.into()).into()
+    }
+}
+
+impl From<usize> for Expression {
+    fn from(value: usize) -> Self {
+        Term::Num(value as i32).into()
+    }
+}
+
+impl From<&usize> for Expression {
+    fn from(value: &usize) -> Self {
+        Term::Num(*value as i32).into()
+    }
+}
+
+impl From<i32> for Expression {
+    fn from(value: i32) -> Self {
+        Term::Num(value).into()
+    }
+}
+
+impl From<&i32> for Expression {
+    fn from(value: &i32) -> Self {
+        Term::Num(*value).into()
+    }
+}
+
 impl From<Expression> for BigExpression {
     fn from(value: Expression) -> Self {
         Self {
-            terms: value.terms.to_vec(),
+            terms: value.terms(),
         }
     }
 }
@@ -633,9 +1276,50 @@ impl From<&Expression> for Expression {
 
 impl From<BigExpression> for Expression {
     fn from(value: BigExpression) -> Self {
-        let mut terms = ArrayVec::new();
-        terms.extend(value.terms);
-        Self { terms }
+        let mut stack: Vec<Expression> = vec![];
+        for term in value.terms {
+            let node = match term {
+                Term::Num(_) | Term::Var(_) => Node::Leaf(term),
+                _ => Node::Op(term, stack.pop().unwrap(), stack.pop().unwrap()),
+            };
+            stack.push(Self::intern(node));
+        }
+        stack.pop().unwrap()
+    }
+}
+
+macro_rules! expression_ops {
+    ($($trait:ident $fn:ident $term:ident),*) => {
+        $(
+            impl<E: Into<Self>> $trait<E> for Expression {
+                type Output = Self;
+                fn $fn(self, rhs: E) -> Self::Output {
+                    self.apply(rhs.into(), Term::$term)
+                }
+            }
+        )*
+    };
+}
+
+expression_ops!(
+    Add add Add,
+    Sub sub Sub,
+    Mul mul Mul,
+    Div div Div,
+    Rem rem Mod,
+    BitAnd bitand And,
+    BitOr bitor Or
+);
+
+impl std::iter::Product for Expression {
+    fn product<I: Iterator<Item = Expression>>(mut iter: I) -> Self {
+        let Some(mut p) = iter.next() else {
+            return 0.into();
+        };
+        for n in iter {
+            p = p * n;
+        }
+        p
     }
 }
 
@@ -727,22 +1411,65 @@ mod tests {
     #[test]
     fn test_expressions() {
         let n = (Expression::from('x') + Term::Num(255)) / Term::Num(256) * Term::Num(256);
-        assert_eq!(n.exec(&[('x', 767)].into_iter().collect()).unwrap(), 768);
+        assert_eq!(
+            n.exec(&[('x'.into(), 767)].into_iter().collect()).unwrap(),
+            768
+        );
 
         let n = (Expression::from('x') + Term::Num(255)) / Term::Num(256) * Term::Num(256);
-        assert_eq!(n.exec(&[('x', 767)].into_iter().collect()).unwrap(), 768);
+        assert_eq!(
+            n.exec(&[('x'.into(), 767)].into_iter().collect()).unwrap(),
+            768
+        );
+    }
+
+    #[test]
+    fn test_bounded_minimization() {
+        let ranges = [
+            ('s'.into(), DimRange::new(1..=4096).multiple_of(8)),
+            ('t'.into(), DimRange::new(..8)),
+        ]
+        .into_iter()
+        .collect();
+        let s = Expression::from('s');
+        assert_eq!(s.min(4096).minimize_bounded(&ranges), s);
+        assert_eq!(s.max(4096).minimize_bounded(&ranges), 4096.into());
+        assert_eq!((s % 8).minimize_bounded(&ranges), 0.into());
+        assert_eq!(((s * 2 + 16) % 8).minimize_bounded(&ranges), 0.into());
+        assert_eq!((s % 3).minimize_bounded(&ranges), s % 3);
+        assert_eq!(s.gte(1).minimize_bounded(&ranges), 1.into());
+        assert_eq!(
+            (Expression::from('t') % s).lt(s).minimize_bounded(&ranges),
+            1.into()
+        );
+        // s is a positive multiple of 8, so it's at least 8 and always above t
+        assert_eq!(
+            (Expression::from('t') / 8).minimize_bounded(&ranges),
+            0.into()
+        );
+        assert_eq!(
+            Expression::from('t').lt(s).minimize_bounded(&ranges),
+            1.into()
+        );
+        assert_eq!(
+            Expression::from('u').lt(s).minimize_bounded(&ranges),
+            Expression::from('u').lt(s)
+        );
+        assert_eq!(s.min(4096).upper_bound(&ranges), Some(4096));
+        assert_eq!((s * 't').upper_bound(&ranges), Some(4096 * 7));
+        assert_eq!(Expression::from('u').upper_bound(&ranges), None);
     }
 
     #[test]
     fn test_minimizations() {
         let expr = BigExpression {
             terms: vec![
-                Term::Var('a'),
-                Term::Var('a'),
+                Term::Var('a'.into()),
+                Term::Var('a'.into()),
                 Term::Num(1),
                 Term::Sub,
                 Term::Add,
-                Term::Var('a'),
+                Term::Var('a'.into()),
                 Term::Sub,
                 Term::Num(1),
                 Term::Add,
@@ -752,4 +1479,65 @@ mod tests {
         let reduced_expr = expr.minimize();
         assert_eq!(reduced_expr, 'a'.into());
     }
+
+    #[test]
+    fn test_interning() {
+        let a = (Expression::from('x') + 1) * 'y';
+        let b = (Expression::from('x') + 1) * 'y';
+        assert_eq!(a, b);
+        assert!(std::ptr::eq(a.ptr, b.ptr));
+        // Expressions built on other threads are the same nodes
+        let c = std::thread::spawn(|| (Expression::from('x') + 1) * 'y')
+            .join()
+            .unwrap();
+        assert_eq!(a, c);
+        // Numbers are stored inline instead of being interned, so resolved dims don't pile up
+        for n in [0, 3, 300, i32::MAX, -5] {
+            let e = Expression::from(n);
+            assert_eq!(e.inline_number(), Some(n));
+            assert_eq!(e.to_usize(), Some(n as usize));
+        }
+        assert_eq!(Expression::from(200) + 100, Expression::from(300));
+        assert_eq!(a.inline_number(), None);
+        assert_ne!(a, Expression::from('x') * 'y');
+        assert_eq!(format!("{a:?}"), format!("{:?}", BigExpression::from(a)));
+
+        // Expressions aren't limited in length
+        let long = (2..20).fold(Expression::from('a'), |e, i| {
+            e.max(Expression::from('b') % i)
+        });
+        assert!(long.terms().len() > 20);
+        let vars = [('a'.into(), 1), ('b'.into(), 100)].into_iter().collect();
+        assert_eq!(long.exec(&vars), (2..20).map(|i| 100 % i).max());
+
+        // The memo is cleared instead of growing without bound
+        for i in 0..MEMO_CAPACITY + 10 {
+            let _ = Expression::from('a') + i;
+        }
+        assert!(MEMO.with_borrow(|memo| memo.len()) <= MEMO_CAPACITY);
+
+        assert!(std::mem::size_of::<crate::prelude::ShapeTracker>() < 1024);
+    }
+
+    #[test]
+    fn test_named_symbols() {
+        assert_eq!(Symbol::from('s'), Symbol::from("s"));
+        assert_eq!(
+            Symbol::from("seq_len"),
+            Symbol::from(&String::from("seq_len"))
+        );
+        assert_ne!(Symbol::from("seq_len"), Symbol::from("seq"));
+        assert_eq!(Symbol::from("s"), 's');
+
+        let e = Expression::from("seq_len").max("batch");
+        assert_eq!(format!("{e:?}"), "max(seq_len, batch)");
+        assert_eq!(
+            e.to_symbols(),
+            vec![Symbol::from("batch"), "seq_len".into()]
+        );
+        let vars = [("seq_len".into(), 7), ("batch".into(), 2)]
+            .into_iter()
+            .collect();
+        assert_eq!(e.exec(&vars), Some(7));
+    }
 }
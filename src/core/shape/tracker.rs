@@ -1,42 +1,36 @@
+use itertools::Itertools;
 use rustc_hash::FxHashMap;
-use tinyvec::ArrayVec;
 
-use super::symbolic::{BigExpression, Expression};
+use super::{
+    symbolic::{BigExpression, DimRange, Expression, Symbol},
+    DimVec,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ShapeTracker {
-    pub dims: ArrayVec<[Expression; 6]>,
-    pub indexes: ArrayVec<[usize; 6]>,
-    pub fake: ArrayVec<[bool; 6]>,
-    pub slices: ArrayVec<[(Expression, Expression); 6]>,
-    pub padding: ArrayVec<[(Expression, Expression); 6]>,
+    pub dims: DimVec<Expression>,
+    pub indexes: DimVec<usize>,
+    pub fake: DimVec<bool>,
+    pub slices: DimVec<(Expression, Expression)>,
+    pub padding: DimVec<(Expression, Expression)>,
 }
 
 impl ShapeTracker {
     pub fn new(dims: &[Expression]) -> Self {
-        let mut s = Self {
-            dims: Default::default(),
-            indexes: Default::default(),
-            fake: Default::default(),
-            slices: Default::default(),
-            padding: Default::default(),
-        };
-        for (i, d) in dims.iter().enumerate() {
-            s.dims.push(*d);
-            s.indexes.push(i);
-            s.fake.push(false);
-            s.slices.push((0.into(), i32::MAX.into())); // Unset upper bound slices are i32::MAX
-            s.padding.push((0.into(), 0.into()));
+        Self {
+            dims: dims.into(),
+            indexes: (0..dims.len()).collect(),
+            fake: dims.iter().map(|_| false).collect(),
+            // Unset upper bound slices are i32::MAX
+            slices: dims.iter().map(|_| (0.into(), i32::MAX.into())).collect(),
+            padding: dims.iter().map(|_| (0.into(), 0.into())).collect(),
         }
-        s
     }
 
     /// Create a shape tracker where all dims are fake
     pub fn fake(dims: &[Expression]) -> Self {
         let mut s = Self::new(dims);
-        for i in 0..dims.len() {
-            s.fake[i] = true;
-        }
+        s.fake = dims.iter().map(|_| true).collect();
         s
     }
 
@@ -52,18 +46,20 @@ impl ShapeTracker {
     /// Add fake dim along a certian axis
     pub fn expand(&mut self, axis: usize, dim: Expression) {
         self.add_dim(axis, dim);
-        self.fake[self.indexes[axis]] = true;
+        self.fake.set(self.indexes[axis], true);
     }
 
     /// Remove a dimension
     pub fn remove_dim(&mut self, axis: usize) -> Expression {
         let index = self.indexes.remove(axis);
         self.fake.remove(index);
-        for i in self.indexes.iter_mut() {
-            if *i > index {
-                *i -= 1;
+        self.indexes.update(|indexes| {
+            for i in indexes {
+                if *i > index {
+                    *i -= 1;
+                }
             }
-        }
+        });
         self.slices.remove(index);
         self.padding.remove(index);
         self.dims.remove(index)
@@ -71,8 +67,7 @@ impl ShapeTracker {
 
     /// Permute the dimensions
     pub fn permute(&mut self, axes: &[usize]) {
-        let new_indexes = axes.iter().map(|i| self.indexes[*i]).collect::<Vec<_>>();
-        self.indexes.copy_from_slice(&new_indexes);
+        self.indexes = axes.iter().map(|i| self.indexes[*i]).collect();
     }
 
     /// Strides without permute applied
@@ -97,7 +92,7 @@ impl ShapeTracker {
     /// Compute strides
     pub fn strides(&self) -> Vec<Expression> {
         let strides = self.unordered_strides();
-        self.indexes.into_iter().map(|i| strides[i]).collect()
+        self.indexes.iter().copied().map(|i| strides[i]).collect()
     }
 
     pub fn index_expression(&self) -> BigExpression {
@@ -123,7 +118,7 @@ impl ShapeTracker {
         let mut acc = BigExpression::from(1);
         let logical = BigExpression::from('z');
         // Loop through all dims in current order
-        for (sh, stride, padding, slice, fake) in self.indexes.into_iter().rev().map(|i| {
+        for (sh, stride, padding, slice, fake) in self.indexes.iter().copied().rev().map(|i| {
             (
                 self.dims[i],
                 strides[i].clone(),
@@ -157,7 +152,8 @@ impl ShapeTracker {
         let logical = BigExpression::from('z');
         for (sh, padding, slice, fake) in self
             .indexes
-            .into_iter()
+            .iter()
+            .copied()
             .rev()
             .map(|i| (self.dims[i], self.padding[i], self.slices[i], self.fake[i]))
         {
@@ -177,11 +173,52 @@ impl ShapeTracker {
         ret.minimize()
     }
 
+    /// The index expression, simplified using the known ranges of dynamic dimensions
+    pub fn index_expression_bounded(&self, ranges: &FxHashMap<Symbol, DimRange>) -> BigExpression {
+        self.index_expression()
+            .minimize_bounded(&self.logical_ranges(ranges))
+    }
+
+    /// The valid expression, leaving out checks that always pass given the known ranges of dynamic
+    /// dimensions
+    pub fn valid_expression_bounded(&self, ranges: &FxHashMap<Symbol, DimRange>) -> BigExpression {
+        self.valid_expression()
+            .minimize_bounded(&self.logical_ranges(ranges))
+    }
+
+    /// Drop slice ends that can't cut into their dimension given the known ranges of dynamic dimensions, like
+    /// `..4096` on a dimension that's at most 4096
+    pub fn remove_unused_slices(&mut self, ranges: &FxHashMap<Symbol, DimRange>) {
+        for i in 0..self.dims.len() {
+            let (start, end) = self.slices[i];
+            if end.to_usize().is_some_and(|n| n as i32 == i32::MAX) {
+                continue;
+            }
+            let (pad_start, pad_end) = self.padding[i];
+            let full = BigExpression::from(self.dims[i]) + pad_start + pad_end;
+            if full.clone().min(end).minimize_bounded(ranges) == full.minimize_bounded(ranges) {
+                self.slices.set(i, (start, i32::MAX.into()));
+            }
+        }
+    }
+
+    /// Add the range of the logical index to the ranges of the dynamic dimensions
+    fn logical_ranges(&self, ranges: &FxHashMap<Symbol, DimRange>) -> FxHashMap<Symbol, DimRange> {
+        let mut ranges = ranges.clone();
+        let max = self
+            .n_elements()
+            .upper_bound(&ranges)
+            .map_or(usize::MAX, |n| n.saturating_sub(1));
+        ranges.insert('z'.into(), DimRange::new(..=max));
+        ranges
+    }
+
     /// The number of elements in this tensor, including pads and slices
     pub fn n_elements(&self) -> BigExpression {
         let r = self
             .indexes
-            .into_iter()
+            .iter()
+            .copied()
             .map(|i| (i, BigExpression::from(self.dims[i])))
             // Add pads
             .map(|(i, dim)| (i, dim + self.padding[i].0 + self.padding[i].1))
@@ -199,7 +236,8 @@ impl ShapeTracker {
     pub fn n_physical_elements(&self) -> BigExpression {
         let r = self
             .dims
-            .into_iter()
+            .iter()
+            .copied()
             // Filter out fake dimensions
             .enumerate()
             .filter(|(i, _)| !self.fake[*i])
@@ -212,6 +250,18 @@ impl ShapeTracker {
         }
     }
 
+    /// The dynamic dimensions used anywhere in the tracker
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.dims
+            .iter()
+            .copied()
+            .chain(self.slices.iter().copied().flat_map(|(a, b)| [a, b]))
+            .chain(self.padding.iter().copied().flat_map(|(a, b)| [a, b]))
+            .flat_map(|e| e.to_symbols())
+            .unique()
+            .collect()
+    }
+
     /// The number of dimensions
     pub fn len(&self) -> usize {
         self.dims.len()
@@ -222,21 +272,21 @@ impl ShapeTracker {
     }
 
     pub fn realize(mut self, dims: &[Expression]) -> Self {
-        for (i, ind) in self.indexes.iter().enumerate() {
-            self.dims[*ind] = dims[i];
+        for (i, dim) in dims.iter().enumerate() {
+            self.dims.set(self.indexes[i], *dim);
         }
         self
     }
 
     /// Create a contiguous version
-    pub fn contiguous(self) -> Self {
+    pub fn contiguous(&self) -> Self {
         let new_dims = self
             .indexes
-            .into_iter()
+            .iter()
+            .copied()
             .map(|i| {
-                self.dims[i].min(self.slices[i].1 - self.slices[i].0)
-                    + self.padding[i].0
-                    + self.padding[i].1
+                (self.dims[i] + self.padding[i].0 + self.padding[i].1).min(self.slices[i].1)
+                    - self.slices[i].0
             })
             .collect::<Vec<_>>();
         Self::new(&new_dims)
@@ -250,11 +300,12 @@ impl ShapeTracker {
     /// Realize the true shape
     pub fn shape(&self) -> Vec<BigExpression> {
         self.indexes
-            .into_iter()
+            .iter()
+            .copied()
             .map(|i| {
-                (BigExpression::from(self.dims[i]) + self.padding[i].0 - self.slices[i].0
-                    + self.padding[i].1)
+                (BigExpression::from(self.dims[i]) + self.padding[i].0 + self.padding[i].1)
                     .min(self.slices[i].1)
+                    - self.slices[i].0
             })
             .collect()
     }
@@ -262,8 +313,9 @@ impl ShapeTracker {
     /// Take a slice
     pub fn slice(&mut self, slices: &[(Expression, Expression)]) {
         for (i, (s, e)) in slices.iter().enumerate() {
-            self.slices[self.indexes[i]].0 = self.slices[self.indexes[i]].0.max(s.max(0));
-            self.slices[self.indexes[i]].1 = self.slices[self.indexes[i]].1.min(e.max(0));
+            let (start, end) = self.slices[self.indexes[i]];
+            self.slices
+                .set(self.indexes[i], (start.max(s.max(0)), end.min(e.max(0))));
         }
     }
 
@@ -285,32 +337,35 @@ impl ShapeTracker {
             {
                 panic!("Adding padding to a slice isn't supported")
             }
-            self.padding[self.indexes[i]].0 = self.padding[self.indexes[i]].0 + s.max(0);
-            self.padding[self.indexes[i]].1 = self.padding[self.indexes[i]].1 + e.max(0);
+            let (before, after) = self.padding[self.indexes[i]];
+            self.padding
+                .set(self.indexes[i], (before + s.max(0), after + e.max(0)));
         }
     }
 
     /// Given a dyn dim map, resolve global dyn dims into known dims
-    pub fn resolve_global_dyn_dims(&mut self, dyn_dim_map: &FxHashMap<char, usize>) {
+    pub fn resolve_global_dyn_dims(&mut self, dyn_dim_map: &FxHashMap<Symbol, usize>) {
         self.resolve_global_dyn_dims_stack(dyn_dim_map, &mut Vec::new());
     }
 
     /// Given a dyn dim map, resolve global dyn dims into known dims. This function requires a stack to work with
     pub fn resolve_global_dyn_dims_stack(
         &mut self,
-        dyn_dim_map: &FxHashMap<char, usize>,
+        dyn_dim_map: &FxHashMap<Symbol, usize>,
         stack: &mut Vec<i32>,
     ) {
-        for d in self.dims.iter_mut() {
-            *d = d.exec_stack(dyn_dim_map, stack).unwrap().into();
-        }
-        for (a, b) in self.padding.iter_mut() {
-            *a = a.exec_stack(dyn_dim_map, stack).unwrap().into();
-            *b = b.exec_stack(dyn_dim_map, stack).unwrap().into();
-        }
-        for (a, b) in self.slices.iter_mut() {
-            *a = a.exec_stack(dyn_dim_map, stack).unwrap().into();
-            *b = b.exec_stack(dyn_dim_map, stack).unwrap().into();
+        self.dims.update(|dims| {
+            for d in dims {
+                *d = d.exec_stack(dyn_dim_map, stack).unwrap().into();
+            }
+        });
+        for pairs in [&mut self.padding, &mut self.slices] {
+            pairs.update(|pairs| {
+                for (a, b) in pairs {
+                    *a = a.exec_stack(dyn_dim_map, stack).unwrap().into();
+                    *b = b.exec_stack(dyn_dim_map, stack).unwrap().into();
+                }
+            });
         }
     }
 
@@ -332,22 +387,46 @@ impl ShapeTracker {
 /// Resolve shapes between the two trackers to the best of our ability
 pub fn resolve_local_dyn_dims(a: &mut ShapeTracker, b: &mut ShapeTracker, default_to_one: bool) {
     // B to A
+    resolve_unknown_dims(a, b, default_to_one);
+    // A to B
+    resolve_unknown_dims(b, a, default_to_one);
+}
+
+/// Fill in the unknown dims of `a` from `b`
+fn resolve_unknown_dims(a: &mut ShapeTracker, b: &ShapeTracker, default_to_one: bool) {
     for i in 0..a.dims.len() {
         if a.dims[a.indexes[i]].is_unknown() {
-            a.dims[a.indexes[i]] = b.dims[b.indexes[i]];
-            if a.dims[a.indexes[i]].is_unknown() && default_to_one {
-                a.dims[a.indexes[i]] = 1.into();
+            let mut dim = b.dims[b.indexes[i]];
+            if dim.is_unknown() && default_to_one {
+                dim = 1.into();
             }
+            a.dims.set(a.indexes[i], dim);
         }
     }
+}
 
-    // A to B
-    for i in 0..a.dims.len() {
-        if b.dims[b.indexes[i]].is_unknown() {
-            b.dims[b.indexes[i]] = a.dims[a.indexes[i]];
-            if b.dims[b.indexes[i]].is_unknown() && default_to_one {
-                b.dims[b.indexes[i]] = 1.into();
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shape() {
+        let mut tracker = ShapeTracker::new(&[5.into(), 3.into()]);
+        tracker.slice(&[(1.into(), 4.into()), (0.into(), i32::MAX.into())]);
+        tracker.pad(&[(0.into(), 0.into()), (1.into(), 2.into())]);
+        let shape = |t: &ShapeTracker| {
+            t.shape()
+                .into_iter()
+                .map(|d| d.to_usize().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(shape(&tracker), vec![3, 6]);
+        assert_eq!(tracker.n_elements().to_usize(), Some(18));
+
+        // Slices apply to the padded dimension
+        let mut tracker = ShapeTracker::new(&[5.into()]);
+        tracker.pad(&[(2.into(), 0.into())]);
+        tracker.slice(&[(1.into(), 4.into())]);
+        assert_eq!(shape(&tracker), vec![3]);
     }
 }
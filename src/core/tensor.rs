@@ -2,6 +2,8 @@ use std::{any::Any, fmt::Debug};
 
 use dyn_clone::{clone_trait_object, DynClone};
 
+use crate::dtype::Element;
+
 /// A tensor with data. The data can be anything that implements the Data trait
 #[derive(Debug, Clone)]
 pub struct Tensor {
@@ -17,14 +19,14 @@ impl Tensor {
 }
 
 /// Some sort of data, for instance a Vec<f32> on CPU, CudaSlice<f32> on Nvidia GPUs, or metal::Buffer for Apple GPUs
-pub trait Data: Any + Debug + DynClone {
+pub trait Data: Any + Debug + DynClone + Send + Sync {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
 clone_trait_object!(Data);
 
-impl Data for Vec<f32> {
+impl<T: Element> Data for Vec<T> {
     fn as_any(&self) -> &dyn Any {
         self
     }
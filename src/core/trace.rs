@@ -0,0 +1,425 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Write as _},
+    time::{Duration, Instant},
+};
+
+use petgraph::{
+    stable_graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+};
+use regex::Regex;
+
+use crate::{
+    compiler_utils::{Compiler, ToIdsMut},
+    graph::Graph,
+    validate::ValidationError,
+};
+
+/// The structure of a graph at one point during compilation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GraphSnapshot {
+    /// The debug name of each node's op
+    pub nodes: BTreeMap<NodeIndex, String>,
+    /// (source, target, input index) of each edge, with no input index for schedule dependencies
+    pub edges: BTreeSet<(NodeIndex, NodeIndex, Option<u8>)>,
+}
+
+impl GraphSnapshot {
+    pub fn new(graph: &Graph) -> Self {
+        Self {
+            nodes: graph
+                .graph
+                .node_indices()
+                .map(|n| (n, format!("{:?}", graph.graph[n])))
+                .collect(),
+            edges: graph
+                .graph
+                .edge_references()
+                .map(|e| (e.source(), e.target(), e.weight().as_data().map(|d| d.0)))
+                .collect(),
+        }
+    }
+}
+
+/// What a single compiler pass changed in the graph
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PassTrace {
+    pub name: String,
+    /// How deeply the pass is nested in tuples and loops, the compiler passed to [`Graph::compile_traced`] being 0
+    pub depth: usize,
+    pub added: Vec<NodeIndex>,
+    /// Removed nodes and their ops
+    pub removed: Vec<(NodeIndex, String)>,
+    /// Removed nodes paired with the added node that took over their consumers
+    pub replaced: Vec<(NodeIndex, NodeIndex)>,
+    pub added_edges: Vec<(NodeIndex, NodeIndex, Option<u8>)>,
+    pub removed_edges: Vec<(NodeIndex, NodeIndex, Option<u8>)>,
+    /// Number of patterns matched by graph searches during the pass
+    pub matches: usize,
+    pub duration: Duration,
+    /// The graph once the pass finished
+    pub after: GraphSnapshot,
+}
+
+impl PassTrace {
+    fn diff(before: &GraphSnapshot, after: GraphSnapshot) -> Self {
+        // A node whose index got reused for a different op counts as removed and added
+        let removed = before
+            .nodes
+            .iter()
+            .filter(|(n, op)| after.nodes.get(n) != Some(op))
+            .map(|(n, op)| (*n, op.clone()))
+            .collect::<Vec<_>>();
+        let added = after
+            .nodes
+            .iter()
+            .filter(|(n, op)| before.nodes.get(n) != Some(op))
+            .map(|(n, _)| *n)
+            .collect::<Vec<_>>();
+        let replaced = removed
+            .iter()
+            .filter_map(|(old, _)| {
+                let consumers = before
+                    .edges
+                    .iter()
+                    .filter(|(src, _, _)| src == old)
+                    .map(|(_, dest, _)| *dest)
+                    .collect::<BTreeSet<_>>();
+                added
+                    .iter()
+                    .find(|new| {
+                        after
+                            .edges
+                            .iter()
+                            .any(|(src, dest, _)| src == *new && consumers.contains(dest))
+                    })
+                    .map(|new| (*old, *new))
+            })
+            .collect();
+        Self {
+            added_edges: after.edges.difference(&before.edges).copied().collect(),
+            removed_edges: before.edges.difference(&after.edges).copied().collect(),
+            added,
+            removed,
+            replaced,
+            after,
+            ..Default::default()
+        }
+    }
+
+    /// Did the pass change the graph at all
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+    }
+
+    /// Render the graph after this pass, with added nodes in green, replacing nodes in orange and removed nodes and edges dashed red
+    pub fn to_dot(&self) -> String {
+        let removed = self
+            .removed
+            .iter()
+            .map(|(n, _)| *n)
+            .collect::<BTreeSet<_>>();
+        let mut s = "digraph {\n".to_string();
+        for (node, op) in &self.after.nodes {
+            write!(
+                s,
+                "    n{} [label=\"{}\"",
+                node.index(),
+                node_label(*node, op)
+            )
+            .unwrap();
+            if self.replaced.iter().any(|(_, new)| new == node) {
+                s.push_str(" style=filled fillcolor=orange");
+            } else if self.added.contains(node) {
+                s.push_str(" style=filled fillcolor=palegreen");
+            }
+            s.push_str("];\n");
+        }
+        for (node, op) in &self.removed {
+            writeln!(
+                s,
+                "    removed{} [label=\"{}\" style=dashed color=red fontcolor=red];",
+                node.index(),
+                node_label(*node, op)
+            )
+            .unwrap();
+        }
+        for (src, dest, input) in &self.after.edges {
+            write!(s, "    n{} -> n{}", src.index(), dest.index()).unwrap();
+            let mut attrs = vec![];
+            if input.is_none() {
+                attrs.push("style=dashed");
+            }
+            if self.added_edges.contains(&(*src, *dest, *input)) {
+                attrs.push("color=green");
+            }
+            if !attrs.is_empty() {
+                write!(s, " [{}]", attrs.join(" ")).unwrap();
+            }
+            s.push_str(";\n");
+        }
+        let endpoint = |n: &NodeIndex| {
+            if removed.contains(n) {
+                format!("removed{}", n.index())
+            } else {
+                format!("n{}", n.index())
+            }
+        };
+        for (src, dest, _) in &self.removed_edges {
+            writeln!(
+                s,
+                "    {} -> {} [style=dashed color=red];",
+                endpoint(src),
+                endpoint(dest)
+            )
+            .unwrap();
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+fn node_label(node: NodeIndex, op: &str) -> String {
+    format!("{op} ({})", node.index())
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+}
+
+/// A record of every pass ran by [`Graph::compile_traced`], in the order they started
+#[derive(Debug, Clone, Default)]
+pub struct CompileTrace {
+    pub passes: Vec<PassTrace>,
+    depth: usize,
+    pub(crate) matches: usize,
+}
+
+impl CompileTrace {
+    /// Passes that don't contain other passes
+    pub fn leaves(&self) -> impl Iterator<Item = &PassTrace> {
+        self.passes.iter().enumerate().filter_map(|(i, pass)| {
+            (self.passes.get(i + 1).map(|p| p.depth) <= Some(pass.depth)).then_some(pass)
+        })
+    }
+
+    /// Find the first pass with a given name
+    pub fn pass(&self, name: &str) -> Option<&PassTrace> {
+        self.passes.iter().find(|p| p.name == name)
+    }
+}
+
+impl Display for CompileTrace {
+    /// Every pass with its changes. Node level changes are only listed on passes that don't contain other passes.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, pass) in self.passes.iter().enumerate() {
+            let indent = "  ".repeat(pass.depth);
+            write!(f, "{indent}{}: ", pass.name)?;
+            if pass.is_empty() {
+                write!(f, "no changes")?;
+            } else {
+                write!(
+                    f,
+                    "+{} -{} nodes, +{} -{} edges",
+                    pass.added.len(),
+                    pass.removed.len(),
+                    pass.added_edges.len(),
+                    pass.removed_edges.len()
+                )?;
+            }
+            writeln!(f, ", {} matches in {:?}", pass.matches, pass.duration)?;
+            if self.passes.get(i + 1).map(|p| p.depth) > Some(pass.depth) {
+                continue;
+            }
+            for (old, new) in &pass.replaced {
+                let old_op = &pass.removed.iter().find(|(n, _)| n == old).unwrap().1;
+                writeln!(
+                    f,
+                    "{indent}  ~ {old_op} ({}) -> {} ({})",
+                    old.index(),
+                    pass.after.nodes[new],
+                    new.index()
+                )?;
+            }
+            for (node, op) in &pass.removed {
+                if !pass.replaced.iter().any(|(old, _)| old == node) {
+                    writeln!(f, "{indent}  - {op} ({})", node.index())?;
+                }
+            }
+            for node in &pass.added {
+                if !pass.replaced.iter().any(|(_, new)| new == node) {
+                    writeln!(
+                        f,
+                        "{indent}  + {} ({})",
+                        pass.after.nodes[node],
+                        node.index()
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A type's name without module paths
+pub(crate) fn short_type_name<T>() -> String {
+    Regex::new(r"(\w+::)+")
+        .unwrap()
+        .replace_all(std::any::type_name::<T>(), "")
+        .to_string()
+}
+
+/// Run a compiler pass, recording what it changed if the graph is being traced, and checking the graph afterwards if passes are being validated
+pub(crate) fn trace_pass(
+    graph: &mut Graph,
+    name: impl FnOnce() -> String,
+    pass: impl FnOnce(&mut Graph),
+) {
+    if graph.compile_trace.is_none() && !graph.validate_passes {
+        pass(graph);
+        return;
+    }
+    let name = name();
+    let before = graph
+        .compile_trace
+        .is_some()
+        .then(|| GraphSnapshot::new(graph));
+    let (mut index, mut matches) = (0, 0);
+    if let Some(trace) = &mut graph.compile_trace {
+        index = trace.passes.len();
+        trace.passes.push(PassTrace {
+            name: name.clone(),
+            depth: trace.depth,
+            ..Default::default()
+        });
+        trace.depth += 1;
+        matches = trace.matches;
+    }
+    let start = Instant::now();
+    pass(graph);
+    let duration = start.elapsed();
+
+    // Only the first invalid pass is reported, since later passes will usually be tripped up by it
+    if graph.validate_passes && graph.validation_error.is_none() {
+        let diagnostics = graph.diagnostics(false);
+        if !diagnostics.is_empty() {
+            graph.validation_error = Some(ValidationError {
+                pass: name.clone(),
+                diagnostics,
+            });
+        }
+    }
+    if let Some(before) = before {
+        let after = GraphSnapshot::new(graph);
+        let trace = graph.compile_trace.as_mut().unwrap();
+        trace.depth -= 1;
+        let depth = trace.passes[index].depth;
+        trace.passes[index] = PassTrace {
+            name,
+            depth,
+            matches: trace.matches - matches,
+            duration,
+            ..PassTrace::diff(&before, after)
+        };
+    }
+}
+
+impl Graph {
+    /// Compile the graph, recording what each pass in the compiler's tuples and loops changed
+    pub fn compile_traced<T: ToIdsMut, C: Compiler>(
+        &mut self,
+        compiler: C,
+        remap: T,
+    ) -> CompileTrace {
+        self.compile_trace = Some(CompileTrace::default());
+        trace_pass(self, short_type_name::<C>, |graph| {
+            compiler.compile(graph, remap)
+        });
+        self.toposort();
+        self.compile_trace.take().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn test_compile_trace() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let mut b = a.log2().exp2().sin().retrieve();
+
+        let trace = cx.compile_traced(GenericCompiler::default(), &mut b);
+        assert_eq!(trace.passes.len(), 8);
+        assert_eq!(trace.passes[0].depth, 0);
+        assert!(trace.passes[1..].iter().all(|p| p.depth == 1));
+        assert_eq!(trace.leaves().count(), 7);
+
+        let pass = trace.pass("UnarySequentialElimination").unwrap();
+        assert_eq!(pass.removed.len(), 2);
+        assert!(pass.added.is_empty());
+        assert_eq!(pass.matches, 1);
+        assert!(trace.pass("CSE").unwrap().is_empty());
+        // The outer pass sees everything its children did
+        assert_eq!(trace.passes[0].removed, pass.removed);
+        assert_eq!(
+            trace.passes[0].matches,
+            trace.leaves().map(|p| p.matches).sum::<usize>()
+        );
+
+        let text = trace.to_string();
+        assert!(text.contains("  UnarySequentialElimination: +0 -2 nodes"));
+        assert!(text.contains("    - Log2"));
+        assert!(text.contains("  CSE: no changes"));
+
+        let dot = pass.to_dot();
+        assert!(dot.contains("removed1 [label=\"Log2 (1)\" style=dashed color=red"));
+        assert!(dot.contains("removed1 -> removed2 [style=dashed color=red];"));
+        assert!(dot.contains(&format!(
+            "n{} -> n{} [color=green];",
+            a.id.index(),
+            b.id.index()
+        )));
+    }
+
+    #[test]
+    fn test_trace_replacements() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let b = cx.tensor::<R1<3>>();
+        let c = a - b;
+        let mut d = c.sin().retrieve();
+        let old = cx.get_sources(d.id)[0].0;
+
+        let trace = cx.compile_traced(CPUCompiler::default(), &mut d);
+        let pass = trace.pass("SubtractionCompiler").unwrap();
+        assert_eq!(pass.added.len(), 1);
+        assert_eq!(pass.replaced, vec![(old, pass.added[0])]);
+        assert!(trace.to_string().contains(" ~ Add "));
+        assert!(pass.to_dot().contains("fillcolor=orange"));
+        // Tracing leaves the graph compiled as usual
+        assert_eq!(cx.get_sources(d.id)[0].0, pass.added[0]);
+    }
+
+    #[test]
+    fn test_trace_looped() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let mut b = a.log2().exp2().log2().exp2().retrieve();
+
+        let trace = cx.compile_traced(Looped::<(UnarySequentialElimination,)>::default(), &mut b);
+        assert!(trace.passes[1]
+            .name
+            .starts_with("(UnarySequentialElimination,) (iteration 1)"));
+        let iterations = trace
+            .passes
+            .iter()
+            .filter(|p| p.name.contains("iteration"))
+            .count();
+        assert!(iterations >= 2);
+        assert!(trace.passes.last().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt::Display;
+
+use itertools::Itertools;
+use petgraph::{
+    stable_graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+    Direction,
+};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    compiler_utils::{Compiler, ToIdsMut},
+    graph::Graph,
+    shape::symbolic::Symbol,
+    trace::{short_type_name, trace_pass},
+};
+
+/// A problem found by [`Graph::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// These nodes depend on each other
+    Cycle(Vec<NodeIndex>),
+    /// The node has inputs after this index, but nothing feeding this index
+    MissingInput { node: NodeIndex, input: u8 },
+    /// More than one edge feeds this input of the node
+    DuplicateInput { node: NodeIndex, input: u8 },
+    /// A consumer expects a different number of physical elements from an output than another consumer, or than the tensor stored for it
+    ElementCountMismatch {
+        node: NodeIndex,
+        output: u8,
+        consumer: NodeIndex,
+        expected: usize,
+        found: usize,
+    },
+    /// A dynamic dimension used by the node's inputs isn't in the graph's `dyn_map`
+    UnboundDim { node: NodeIndex, dim: Symbol },
+    /// A node marked to not be deleted no longer exists
+    RemovedNoDelete(NodeIndex),
+    /// A node marked to be retrieved no longer exists
+    RemovedToRetrieve(NodeIndex),
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::Cycle(nodes) => write!(
+                f,
+                "cycle between nodes {}",
+                nodes.iter().map(|n| n.index()).join(", ")
+            ),
+            Diagnostic::MissingInput { node, input } => {
+                write!(f, "node {} has no input {input}", node.index())
+            }
+            Diagnostic::DuplicateInput { node, input } => {
+                write!(f, "node {} has more than one input {input}", node.index())
+            }
+            Diagnostic::ElementCountMismatch {
+                node,
+                output,
+                consumer,
+                expected,
+                found,
+            } => write!(
+                f,
+                "node {} reads {found} elements from output {output} of node {}, expected {expected}",
+                consumer.index(),
+                node.index()
+            ),
+            Diagnostic::UnboundDim { node, dim } => write!(
+                f,
+                "node {} uses dynamic dimension '{dim}', which isn't set",
+                node.index()
+            ),
+            Diagnostic::RemovedNoDelete(node) => {
+                write!(f, "node {} is marked no_delete but was removed", node.index())
+            }
+            Diagnostic::RemovedToRetrieve(node) => {
+                write!(f, "node {} is marked to_retrieve but was removed", node.index())
+            }
+        }
+    }
+}
+
+/// The first compiler pass that left the graph invalid
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub pass: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} left the graph invalid:", self.pass)?;
+        for d in &self.diagnostics {
+            write!(f, "\n  {d}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Graph {
+    /// Check the graph for structural problems that would otherwise show up as panics during execution
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        self.diagnostics(true)
+    }
+
+    /// Compile the graph, validating it after every pass in the compiler's tuples and loops.
+    ///
+    /// Dynamic dimensions usually aren't set until after compilation, so unbound dimensions aren't reported here.
+    pub fn compile_validated<T: ToIdsMut, C: Compiler>(
+        &mut self,
+        compiler: C,
+        remap: T,
+    ) -> Result<(), ValidationError> {
+        self.validate_passes = true;
+        trace_pass(self, short_type_name::<C>, |graph| {
+            compiler.compile(graph, remap)
+        });
+        self.validate_passes = false;
+        if let Some(error) = self.validation_error.take() {
+            return Err(error);
+        }
+        self.toposort();
+        Ok(())
+    }
+
+    pub(crate) fn diagnostics(&self, check_dyn_dims: bool) -> Vec<Diagnostic> {
+        let mut diagnostics = petgraph::algo::tarjan_scc(&self.graph)
+            .into_iter()
+            .filter(|c| c.len() > 1 || self.graph.contains_edge(c[0], c[0]))
+            .map(|c| Diagnostic::Cycle(c.into_iter().sorted().collect()))
+            .collect::<Vec<_>>();
+
+        // Inputs should be numbered 0..n with no gaps or repeats
+        for node in self.graph.node_indices() {
+            let inputs = self
+                .graph
+                .edges_directed(node, Direction::Incoming)
+                .filter_map(|e| e.weight().as_data().map(|d| d.0))
+                .sorted()
+                .collect::<Vec<_>>();
+            for (input, n) in &inputs.iter().group_by(|i| **i) {
+                if n.count() > 1 {
+                    diagnostics.push(Diagnostic::DuplicateInput { node, input });
+                }
+            }
+            if let Some(last) = inputs.last() {
+                for input in 0..*last {
+                    if !inputs.contains(&input) {
+                        diagnostics.push(Diagnostic::MissingInput { node, input });
+                    }
+                }
+            }
+        }
+
+        // Every consumer of an output should agree on how many elements it has
+        let mut counts = FxHashMap::<(NodeIndex, u8), usize>::default();
+        for ((node, output), tensor) in &self.tensors {
+            if let Some(n) = tensor.n_elements() {
+                counts.insert((*node, *output), n);
+            }
+        }
+        for edge in self
+            .graph
+            .edge_references()
+            .sorted_by_key(|e| (e.source(), e.target()))
+        {
+            let Some((_, output, shape)) = edge.weight().as_data() else {
+                continue;
+            };
+            let Some(found) = shape.n_physical_elements().exec(&self.dyn_map) else {
+                continue;
+            };
+            let expected = *counts.entry((edge.source(), output)).or_insert(found);
+            if expected != found {
+                diagnostics.push(Diagnostic::ElementCountMismatch {
+                    node: edge.source(),
+                    output,
+                    consumer: edge.target(),
+                    expected,
+                    found,
+                });
+            }
+        }
+
+        if check_dyn_dims {
+            let mut unbound = vec![];
+            for edge in self.graph.edge_references() {
+                let Some((_, _, shape)) = edge.weight().as_data() else {
+                    continue;
+                };
+                for dim in shape.symbols() {
+                    if dim != '-' && !self.dyn_map.contains_key(&dim) && !unbound.contains(&dim) {
+                        unbound.push(dim);
+                        diagnostics.push(Diagnostic::UnboundDim {
+                            node: edge.target(),
+                            dim,
+                        });
+                    }
+                }
+            }
+        }
+
+        for node in self.no_delete.iter().sorted() {
+            if !self.graph.contains_node(*node) {
+                diagnostics.push(Diagnostic::RemovedNoDelete(*node));
+            }
+        }
+        for node in self.to_retrieve.iter().sorted() {
+            if !self.graph.contains_node(*node) {
+                diagnostics.push(Diagnostic::RemovedToRetrieve(*node));
+            }
+        }
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{op, prelude::*};
+
+    use super::*;
+
+    #[test]
+    fn test_valid_graph() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<(Dyn<'a'>, Const<3>)>();
+        let b = cx.tensor::<R1<3>>();
+        let mut c = (a + b.expand()).sum_reduce::<_, Axis<1>>().retrieve();
+
+        assert!(matches!(
+            cx.validate()[..],
+            [Diagnostic::UnboundDim { dim, .. }] if dim == 'a'
+        ));
+        cx.set_dyn_dim('a', 2);
+        assert!(cx.validate().is_empty());
+
+        assert!(cx
+            .compile_validated(<(GenericCompiler, CPUCompiler)>::default(), &mut c)
+            .is_ok());
+        assert!(cx.validate().is_empty());
+    }
+
+    #[test]
+    fn test_structural_errors() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let b = cx.tensor::<R1<3>>();
+        let c = (a + b).retrieve();
+        let d = c.sin();
+        cx.keep_tensors(d.id);
+
+        // Feed input 1 twice and leave input 0 empty
+        let edge = cx.graph.find_edge(a.id, c.id).unwrap();
+        cx.graph[edge] = Dependency::Data {
+            input_order: 1,
+            output_order: 0,
+            shape: a.shape.into(),
+            dtype: a.dtype(),
+        };
+        cx.graph.add_edge(c.id, a.id, Dependency::Schedule);
+        // Read 4 elements from a 3 element tensor
+        let e = cx
+            .add_op(op::Exp2)
+            .input(b.id, 0, R1::<4>::to_tracker())
+            .finish();
+        cx.graph.remove_node(d.id);
+
+        let diagnostics = cx.validate();
+        assert!(diagnostics.contains(&Diagnostic::Cycle(vec![a.id, c.id])));
+        assert!(diagnostics.contains(&Diagnostic::DuplicateInput {
+            node: c.id,
+            input: 1
+        }));
+        assert!(diagnostics.contains(&Diagnostic::MissingInput {
+            node: c.id,
+            input: 0
+        }));
+        assert!(diagnostics.contains(&Diagnostic::ElementCountMismatch {
+            node: b.id,
+            output: 0,
+            consumer: e,
+            expected: 3,
+            found: 4,
+        }));
+        assert!(diagnostics.contains(&Diagnostic::RemovedNoDelete(d.id)));
+        assert_eq!(
+            Diagnostic::MissingInput {
+                node: c.id,
+                input: 0
+            }
+            .to_string(),
+            format!("node {} has no input 0", c.id.index())
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct DropRetrieved;
+
+    impl Compiler for DropRetrieved {
+        fn compile<T: ToIdsMut>(&self, graph: &mut Graph, _: T) {
+            let node = *graph.to_retrieve.iter().next().unwrap();
+            graph.graph.remove_node(node);
+        }
+    }
+
+    #[test]
+    fn test_compile_validated() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let b = a.sin().retrieve();
+
+        let error = cx
+            .compile_validated(<(GenericCompiler, DropRetrieved)>::default(), ())
+            .unwrap_err();
+        assert_eq!(error.pass, "DropRetrieved");
+        assert_eq!(
+            error.diagnostics,
+            vec![
+                Diagnostic::RemovedNoDelete(b.id),
+                Diagnostic::RemovedToRetrieve(b.id)
+            ]
+        );
+    }
+}
@@ -0,0 +1,384 @@
+use std::{fmt::Write as _, path::Path, process::Command};
+
+use itertools::Itertools;
+use petgraph::{
+    stable_graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    graph::{Dependency, Graph},
+    module::state_dict,
+    profile::escape_json,
+    serialization::SerializeModule,
+};
+
+/// What to include when rendering a graph to DOT or JSON
+#[derive(Debug, Clone, Default)]
+pub struct GraphVisOptions {
+    /// Label data edges with the logical shape they carry
+    pub show_shapes: bool,
+    /// Include schedule dependencies (drawn dashed and green in DOT)
+    pub show_schedule_edges: bool,
+    /// Nodes to highlight
+    pub marked: FxHashSet<NodeIndex>,
+    /// Module path of each node, with components separated by `/`. Nodes sharing a path prefix get nested clusters.
+    pub clusters: FxHashMap<NodeIndex, String>,
+}
+
+impl GraphVisOptions {
+    pub fn shapes(mut self) -> Self {
+        self.show_shapes = true;
+        self
+    }
+
+    pub fn schedule_edges(mut self) -> Self {
+        self.show_schedule_edges = true;
+        self
+    }
+
+    pub fn mark(mut self, nodes: &[NodeIndex]) -> Self {
+        self.marked.extend(nodes.iter().copied());
+        self
+    }
+
+    /// Cluster nodes by the module paths of a model.
+    ///
+    /// Weights go in the module that owns them, and any op directly consuming weights of exactly one module joins that module.
+    pub fn cluster_modules<M: SerializeModule>(mut self, model: &M, graph: &Graph) -> Self {
+        for (name, node) in state_dict(model) {
+            let path = name.rsplit_once('/').map(|(p, _)| p).unwrap_or_default();
+            if !path.is_empty() {
+                self.clusters.insert(node, path.to_string());
+            }
+        }
+        let weights = self.clusters.clone();
+        for node in graph.graph.node_indices() {
+            if weights.contains_key(&node) {
+                continue;
+            }
+            if let Ok(path) = graph
+                .get_sources(node)
+                .into_iter()
+                .filter_map(|(src, _, _)| weights.get(&src))
+                .unique()
+                .exactly_one()
+            {
+                self.clusters.insert(node, path.clone());
+            }
+        }
+        self
+    }
+
+    fn includes(&self, dependency: &Dependency) -> bool {
+        self.show_schedule_edges || !dependency.is_schedule()
+    }
+}
+
+/// A tree of clusters, keyed by path component
+#[derive(Default)]
+struct ClusterTree {
+    nodes: Vec<NodeIndex>,
+    children: Vec<(String, ClusterTree)>,
+}
+
+impl ClusterTree {
+    fn insert(&mut self, path: &[&str], node: NodeIndex) {
+        let Some((first, rest)) = path.split_first() else {
+            self.nodes.push(node);
+            return;
+        };
+        let child = match self.children.iter().position(|(c, _)| c == first) {
+            Some(i) => &mut self.children[i].1,
+            None => {
+                self.children
+                    .push((first.to_string(), ClusterTree::default()));
+                &mut self.children.last_mut().unwrap().1
+            }
+        };
+        child.insert(rest, node);
+    }
+
+    fn write(
+        &self,
+        graph: &Graph,
+        options: &GraphVisOptions,
+        path: &str,
+        depth: usize,
+        n_clusters: &mut usize,
+        s: &mut String,
+    ) {
+        let indent = "    ".repeat(depth);
+        for node in &self.nodes {
+            let label = format!("{:?} ({})", graph.graph[*node], node.index());
+            write!(
+                s,
+                "{indent}n{} [label=\"{}\"",
+                node.index(),
+                escape_dot(&label)
+            )
+            .unwrap();
+            if options.marked.contains(node) {
+                s.push_str(" style=filled fillcolor=yellow");
+            }
+            s.push_str("];\n");
+        }
+        for (name, child) in &self.children {
+            let path = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{path}/{name}")
+            };
+            writeln!(
+                s,
+                "{indent}subgraph cluster_{n_clusters} {{\n{indent}    label=\"{}\";",
+                escape_dot(&path)
+            )
+            .unwrap();
+            *n_clusters += 1;
+            child.write(graph, options, &path, depth + 1, n_clusters, s);
+            writeln!(s, "{indent}}}").unwrap();
+        }
+    }
+}
+
+impl Graph {
+    /// Render the graph in the Graphviz DOT language
+    pub fn to_dot(&self, options: &GraphVisOptions) -> String {
+        let mut tree = ClusterTree::default();
+        for node in self.graph.node_indices() {
+            match options.clusters.get(&node) {
+                Some(path) => tree.insert(&path.split('/').collect::<Vec<_>>(), node),
+                None => tree.nodes.push(node),
+            }
+        }
+        let mut s = "digraph {\n".to_string();
+        tree.write(self, options, "", 1, &mut 0, &mut s);
+        for edge in self
+            .graph
+            .edge_references()
+            .filter(|e| options.includes(e.weight()))
+            .sorted_by_key(|e| (e.target(), e.weight().as_data().map(|d| d.0)))
+        {
+            write!(
+                s,
+                "    n{} -> n{}",
+                edge.source().index(),
+                edge.target().index()
+            )
+            .unwrap();
+            match edge.weight() {
+                Dependency::Schedule => s.push_str(" [style=dashed color=green]"),
+                Dependency::Data { shape, .. } if options.show_shapes => write!(
+                    s,
+                    " [label=\"{}\"]",
+                    escape_dot(&format!("{:?}", shape.shape()))
+                )
+                .unwrap(),
+                _ => {}
+            }
+            s.push_str(";\n");
+        }
+        s.push_str("}\n");
+        s
+    }
+
+    /// Dump the nodes and edges of the graph as JSON
+    pub fn to_json(&self, options: &GraphVisOptions) -> String {
+        let nodes = self
+            .graph
+            .node_indices()
+            .map(|node| {
+                let mut n = format!(
+                    "{{\"id\":{},\"op\":\"{}\",\"marked\":{}",
+                    node.index(),
+                    escape_json(&format!("{:?}", self.graph[node])),
+                    options.marked.contains(&node)
+                );
+                if let Some(cluster) = options.clusters.get(&node) {
+                    write!(n, ",\"cluster\":\"{}\"", escape_json(cluster)).unwrap();
+                }
+                n.push('}');
+                n
+            })
+            .join(",");
+        let edges = self
+            .graph
+            .edge_references()
+            .filter(|e| options.includes(e.weight()))
+            .map(|edge| {
+                let mut e = format!(
+                    "{{\"source\":{},\"target\":{}",
+                    edge.source().index(),
+                    edge.target().index()
+                );
+                match edge.weight() {
+                    Dependency::Schedule => e.push_str(",\"kind\":\"schedule\""),
+                    Dependency::Data {
+                        input_order,
+                        output_order,
+                        shape,
+                        ..
+                    } => {
+                        write!(
+                            e,
+                            ",\"kind\":\"data\",\"input\":{input_order},\"output\":{output_order}"
+                        )
+                        .unwrap();
+                        if options.show_shapes {
+                            write!(
+                                e,
+                                ",\"shape\":[{}]",
+                                shape
+                                    .shape()
+                                    .iter()
+                                    .map(|d| format!("\"{}\"", escape_json(&format!("{d:?}"))))
+                                    .join(",")
+                            )
+                            .unwrap();
+                        }
+                    }
+                }
+                e.push('}');
+                e
+            })
+            .join(",");
+        format!("{{\"nodes\":[{nodes}],\"edges\":[{edges}]}}")
+    }
+
+    /// Write the graph to a DOT file
+    pub fn write_dot<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &GraphVisOptions,
+    ) -> std::io::Result<()> {
+        std::fs::write(path, self.to_dot(options))
+    }
+
+    /// Write the graph to a JSON file
+    pub fn write_json<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &GraphVisOptions,
+    ) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json(options))
+    }
+
+    /// Render the graph to an SVG file. This needs Graphviz's `dot` on the path.
+    pub fn write_svg<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &GraphVisOptions,
+    ) -> std::io::Result<()> {
+        let dot_path = path.as_ref().with_extension("dot");
+        self.write_dot(&dot_path, options)?;
+        let output = Command::new("dot")
+            .arg("-Tsvg")
+            .arg(&dot_path)
+            .arg("-o")
+            .arg(path.as_ref())
+            .output();
+        std::fs::remove_file(&dot_path)?;
+        let output = output?;
+        if !output.status.success() {
+            return Err(std::io::Error::other(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::Direction;
+
+    use crate::{nn::linear::Linear, prelude::*};
+
+    use super::*;
+
+    #[test]
+    fn test_to_dot() {
+        let mut cx = Graph::new();
+        let a = cx.named_tensor::<R1<3>>("\"A\"");
+        let b = cx.tensor::<R1<3>>();
+        let c = (a + b).retrieve();
+        cx.add_schedule_dependency(a.id, b.id);
+
+        let dot = cx.to_dot(&GraphVisOptions::default());
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains(&format!("n{} -> n{};", a.id.index(), c.id.index())));
+        assert!(!dot.contains("dashed"));
+        assert!(!dot.contains("fillcolor"));
+        // Debug names with quotes stay valid DOT
+        assert!(dot.contains("label=\"\\\"A\\\" Load (0)\""));
+
+        let dot = cx.to_dot(
+            &GraphVisOptions::default()
+                .schedule_edges()
+                .shapes()
+                .mark(&[c.id]),
+        );
+        assert!(dot.contains(&format!(
+            "n{} -> n{} [style=dashed color=green];",
+            a.id.index(),
+            b.id.index()
+        )));
+        assert!(dot.contains("[label=\"[3]\"]"));
+        assert!(dot.contains(&format!(
+            "n{0} [label=\"Add ({0})\" style=filled fillcolor=yellow];",
+            c.id.index()
+        )));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R1<3>>();
+        let b = (a * a).retrieve();
+
+        let json = cx.to_json(&GraphVisOptions::default().shapes().mark(&[b.id]));
+        assert!(json.starts_with("{\"nodes\":["));
+        assert_eq!(json.matches("\"id\":").count(), cx.graph.node_count());
+        assert_eq!(
+            json.matches("\"kind\":\"data\"").count(),
+            cx.graph.edge_count()
+        );
+        assert!(json.contains(&format!(
+            "{{\"id\":{},\"op\":\"Mul\",\"marked\":true}}",
+            b.id.index()
+        )));
+        assert!(json.contains("\"shape\":[\"3\"]"));
+    }
+
+    #[test]
+    fn test_module_clusters() {
+        let mut cx = Graph::new();
+        let model = <(Linear<3, 4>, Linear<4, 2>)>::initialize(&mut cx);
+        let a = cx.tensor::<R1<3>>();
+        model.forward(a).retrieve();
+
+        let options = GraphVisOptions::default().cluster_modules(&model, &cx);
+        assert_eq!(options.clusters[&model.0.weight.id], "layer0");
+        assert_eq!(options.clusters[&model.1.weight.id], "layer1");
+        assert!(!options.clusters.contains_key(&a.id));
+        // Ops consuming a layer's weight are grouped with it
+        for (layer, weight) in [("layer0", model.0.weight.id), ("layer1", model.1.weight.id)] {
+            for consumer in cx.graph.neighbors_directed(weight, Direction::Outgoing) {
+                assert_eq!(options.clusters[&consumer], layer);
+            }
+        }
+
+        let dot = cx.to_dot(&options);
+        assert_eq!(dot.matches("subgraph cluster_").count(), 2);
+        assert!(dot.contains("label=\"layer0\";"));
+        assert!(dot.contains("label=\"layer1\";"));
+    }
+}
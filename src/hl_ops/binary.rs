@@ -7,6 +7,7 @@ use std::ops::RemAssign;
 use std::ops::SubAssign;
 use std::ops::{Add, Div, Mul, Rem, Sub};
 
+use self::symbolic::Expression;
 use self::symbolic::ExpressionStorage;
 use self::symbolic::GenericExpression;
 use self::symbolic::Term;
@@ -16,6 +17,7 @@ impl<S: Shape> Add for GraphTensor<S> {
 
     fn add(mut self, mut rhs: GraphTensor<S>) -> Self::Output {
         resolve_local_dyn_dims(&mut self.shape, &mut rhs.shape, false);
+        rhs = rhs.cast(self.dtype());
         let mut new_shape = ShapeTracker::new(&S::realized_shape());
         resolve_local_dyn_dims(&mut new_shape, &mut rhs.shape, false);
         let new_id = self
@@ -53,6 +55,7 @@ impl<S: Shape> Mul for GraphTensor<S> {
 
     fn mul(mut self, mut rhs: GraphTensor<S>) -> Self::Output {
         resolve_local_dyn_dims(&mut self.shape, &mut rhs.shape, false);
+        rhs = rhs.cast(self.dtype());
         let new_id = self
             .graph()
             .add_op(op::Mul)
@@ -89,6 +92,7 @@ impl<S: Shape> Rem<GraphTensor<S>> for GraphTensor<S> {
 
     fn rem(mut self, mut rhs: GraphTensor<S>) -> Self::Output {
         resolve_local_dyn_dims(&mut self.shape, &mut rhs.shape, false);
+        rhs = rhs.cast(self.dtype());
         let mut new_shape = ShapeTracker::new(&S::realized_shape());
         resolve_local_dyn_dims(&mut new_shape, &mut rhs.shape, false);
         let new_id = self
@@ -203,10 +207,27 @@ where
     }
 }
 
+macro_rules! expression_ops {
+    ($($trait:ident $fn:ident $op:tt),*) => {
+        $(
+            impl<S: Shape> $trait<Expression> for GraphTensor<S> {
+                type Output = GraphTensor<S>;
+
+                fn $fn(self, rhs: Expression) -> Self::Output {
+                    self $op self.graph().constant_expr(rhs).expand()
+                }
+            }
+        )*
+    };
+}
+
+expression_ops!(Add add +, Sub sub -, Mul mul *, Div div /, Rem rem %);
+
 // Comparisons (based on https://github.com/tinygrad/tinygrad/blob/3e0c2d256fe9f4f5f85cd3e4d8733a51d7b4a984/tinygrad/tensor.py#L653)
 impl<S: Shape> GraphTensor<S> {
     pub fn less_than(mut self, mut rhs: GraphTensor<S>) -> GraphTensor<S> {
         resolve_local_dyn_dims(&mut self.shape, &mut rhs.shape, false);
+        rhs = rhs.cast(self.dtype());
         let mut new_shape = ShapeTracker::new(&S::realized_shape());
         resolve_local_dyn_dims(&mut new_shape, &mut rhs.shape, false);
         let new_id = self
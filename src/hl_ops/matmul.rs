@@ -100,6 +100,31 @@ impl<A: Dimension, B: Dimension, C: Dimension, D: Dimension, E: Dimension, F: Di
     }
 }
 
+// ABCDEFxABCDFG -> ABCDEG
+impl<
+        A: Dimension,
+        B: Dimension,
+        C: Dimension,
+        D: Dimension,
+        E: Dimension,
+        F: Dimension,
+        G: Dimension,
+    > Matmul<(A, B, C, D, F, G)> for GraphTensor<(A, B, C, D, E, F)>
+{
+    type Output = GraphTensor<(A, B, C, D, E, G)>;
+    fn matmul(self, rhs: GraphTensor<(A, B, C, D, F, G)>) -> Self::Output {
+        // Reshape
+        let w: GraphTensor<(A, B, C, D, G, F)> = rhs.permute::<_, Axes6<0, 1, 2, 3, 5, 4>>();
+
+        // Broadcasted Multiply
+        let mul =
+            self.expand::<(A, B, C, D, E, G, F), _>() * w.expand::<(A, B, C, D, E, G, F), _>();
+
+        // Sum Reduce
+        mul.sum_reduce::<_, Axis<6>>()
+    }
+}
+
 impl<A: Dimension> GraphTensor<(A,)> {
     /// Simple dot product of two vectors
     pub fn dot(self, rhs: GraphTensor<(A,)>) -> GraphTensor<R0> {
@@ -193,6 +218,24 @@ mod tests {
         assert_close(&c.data(), &d_c.as_vec());
     }
 
+    #[test]
+    fn test_6d_matmul() {
+        let mut cx = Graph::new();
+        let (a_data, b_data) = (random_vec(24), random_vec(24));
+        let a = cx.tensor::<R6<1, 2, 1, 2, 2, 3>>().set(a_data.clone());
+        let b = cx.tensor::<R6<1, 2, 1, 2, 3, 2>>().set(b_data.clone());
+        let c = a.matmul(b).retrieve();
+
+        cx.execute();
+
+        let d_dev = Cpu::default();
+        let d_a = d_dev.tensor_from_vec(a_data, (DConst::<4>, DConst::<2>, DConst::<3>));
+        let d_b = d_dev.tensor_from_vec(b_data, (DConst::<4>, DConst::<3>, DConst::<2>));
+        let d_c = d_a.matmul(d_b);
+
+        assert_close(&c.data(), &d_c.as_vec());
+    }
+
     #[test]
     fn test_batch_batch_matmul2() {
         let mut cx = Graph::new();
@@ -7,12 +7,18 @@ use crate::{
 };
 
 impl<S: Shape> GraphTensor<S> {
+    /// Permute the dimensions. Higher rank tensors can be permuted with
+    /// [`ShapeTracker::permute`] on the shape of a `GraphTensor<()>`.
     pub fn permute<Dst: Shape, Ax: Axes>(mut self) -> GraphTensor<Dst>
     where
         S: PermuteShapeTo<Dst, Ax>,
     {
-        self.shape
-            .permute(&Ax::as_array().into_iter().collect::<Vec<_>>());
+        let axes = Ax::as_array().into_iter().collect::<Vec<_>>();
+        assert!(
+            (0..axes.len()).all(|i| axes.contains(&i)),
+            "Permute axes {axes:?} aren't a permutation"
+        );
+        self.shape.permute(&axes);
         GraphTensor::from_id(self.id, self.shape, self.graph_ref)
     }
 
@@ -81,7 +87,7 @@ impl<S: Shape> GraphTensor<S> {
     ) -> GraphTensor<Slice::OutputShape> {
         let ranges = slice.to_range_vec();
         // This exists because currently padding and slicing on the same dimension (even on opposite sides) is unsupported
-        if ranges.iter().zip(self.shape.indexes).any(|(range, ind)| {
+        if ranges.iter().zip(&self.shape.indexes).any(|(range, &ind)| {
             (range.0 != 0.into() || range.1 != i32::MAX.into())
                 && (self.shape.padding[self.shape.indexes[ind]].0 != 0.into()
                     || self.shape.padding[self.shape.indexes[ind]].1 != 0.into())
@@ -100,18 +106,20 @@ impl<S: Shape> GraphTensor<S> {
             / (spacing + size)
             * (spacing + size);
         let padding = total_size - self.shape.dims[self.shape.indexes[n_dims - 1]];
-        self.shape.padding[self.shape.indexes[n_dims - 1]].1 = padding;
+        let last = self.shape.indexes[n_dims - 1];
+        self.shape.padding.update(|p| p[last].1 = padding);
 
         self = self.contiguous();
         // Expand a new dimension to do the slicing on
         let n_rows = total_size / (spacing + size);
         self.shape.expand(n_dims, (spacing + size).into());
         // self = self.contiguous();
-        self.shape.dims[self.shape.indexes[n_dims - 1]] = n_rows;
-        self.shape.fake[self.shape.indexes[n_dims]] = false;
+        let (last, new) = (self.shape.indexes[n_dims - 1], self.shape.indexes[n_dims]);
+        self.shape.dims.set(last, n_rows);
+        self.shape.fake.set(new, false);
 
         // Slice
-        self.shape.slices[self.shape.indexes[n_dims]].1 = spacing.into();
+        self.shape.slices.update(|p| p[new].1 = spacing.into());
 
         self = self.contiguous();
 
@@ -142,18 +150,25 @@ impl<S: Shape> GraphTensor<S> {
             let actual_size = orig_width.clone() * self.shape.dims[self.shape.indexes[n_dims - 1]];
             // Reshape into single dimension to pad
             self.shape.remove_dim(n_dims);
-            self.shape.dims[self.shape.indexes[n_dims - 1]] = actual_size.clone().into();
-            self.shape.padding[self.shape.indexes[n_dims - 1]].1 = (mat_size - actual_size).into();
+            let last = self.shape.indexes[n_dims - 1];
+            self.shape.dims.set(last, actual_size.clone().into());
+            self.shape
+                .padding
+                .update(|p| p[last].1 = (mat_size - actual_size).into());
             self = self.contiguous();
             // Reshape back (mats should be full now)
             self.shape.add_dim(n_dims, (orig_width + stride).into());
         } else {
-            self.shape.dims[self.shape.indexes[n_dims]] = (orig_width + stride).into();
+            let last = self.shape.indexes[n_dims];
+            self.shape.dims.set(last, (orig_width + stride).into());
         }
-        self.shape.dims[self.shape.indexes[n_dims - 1]] = number_of_windows;
+        let (windows, kernels) = (self.shape.indexes[n_dims - 1], self.shape.indexes[n_dims]);
+        self.shape.dims.set(windows, number_of_windows);
         // Slice down to kernel size
-        self.shape.slices[self.shape.indexes[n_dims]].1 = full_kernel;
-        self.shape.slices[self.shape.indexes[n_dims - 1]].1 = number_of_windows;
+        self.shape.slices.update(|p| p[kernels].1 = full_kernel);
+        self.shape
+            .slices
+            .update(|p| p[windows].1 = number_of_windows);
         self = self.contiguous();
 
         if dilation > 0 {
@@ -174,7 +189,7 @@ impl<S: Shape> GraphTensor<S> {
             .map(|i| (i.0.into(), i.1.into()))
             .collect::<Vec<_>>();
         // This exists because currently padding and slicing on the same dimension (even on opposite sides) is unsupported
-        if ranges.iter().zip(self.shape.indexes).any(|(range, ind)| {
+        if ranges.iter().zip(&self.shape.indexes).any(|(range, &ind)| {
             (range.0 != 0.into() || range.1 != 0.into())
                 && (self.shape.slices[self.shape.indexes[ind]].0 != 0.into()
                     || self.shape.slices[self.shape.indexes[ind]].1 != i32::MAX.into())
@@ -351,6 +366,73 @@ mod tests {
         assert_exact(&c.data(), &[1., 1., 1., 2., 2., 2., 3., 3., 3.]);
     }
 
+    #[test]
+    fn test_high_rank() {
+        let mut cx = Graph::new();
+        let a = cx.tensor::<R2<2, 3>>().set(vec![1., 2., 3., 4., 5., 6.]);
+        let b = a
+            .pad::<R2<2, 4>, _, _>(&[(0, 0), (1, 0)])
+            .expand::<R7<2, 2, 1, 1, 1, 1, 4>, LAxes5<1, 2, 3, 4, 5>>();
+        let d = b.contiguous().retrieve();
+        let c = d.sum_reduce::<R2<2, 4>, LAxes5<1, 2, 3, 4, 5>>().retrieve();
+        cx.execute();
+
+        assert_exact(&c.data(), &[0., 2., 4., 6., 0., 8., 10., 12.]);
+        assert_exact(
+            &d.data(),
+            &[
+                0., 1., 2., 3., 0., 1., 2., 3., 0., 4., 5., 6., 0., 4., 5., 6.,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_permute_7d() {
+        let mut cx = Graph::new();
+        let data = (0..12).map(|i| i as f32).collect::<Vec<_>>();
+        let a = cx.tensor::<R7<2, 1, 3, 1, 1, 1, 2>>().set(data.clone());
+        let b: GraphTensor<R7<3, 2, 2, 1, 1, 1, 1>> = a.permute::<_, Axes7<2, 0, 6, 1, 3, 4, 5>>();
+        let b = b.contiguous().retrieve();
+        cx.execute();
+
+        let mut expected = vec![];
+        for c in 0..3 {
+            for a in 0..2 {
+                for g in 0..2 {
+                    expected.push(data[a * 6 + c * 2 + g]);
+                }
+            }
+        }
+        assert_exact(&b.data(), &expected);
+    }
+
+    #[test]
+    fn test_permute_past_max_dims() {
+        let mut cx = Graph::new();
+        let data = (0..12).map(|i| i as f32).collect::<Vec<_>>();
+        let mut dims = vec![Expression::from(1); MAX_DIMS + 2];
+        dims[0] = 2.into();
+        dims[MAX_DIMS] = 3.into();
+        dims[MAX_DIMS + 1] = 2.into();
+        let mut a = cx.named_tensor::<()>("Input").set(data.clone());
+        *a.shape = ShapeTracker::new(&dims);
+        assert!(a.shape.dims.spilled());
+        a.shape.permute(&(0..dims.len()).rev().collect::<Vec<_>>());
+        let b = a.contiguous().retrieve();
+        cx.execute();
+
+        let mut expected = vec![];
+        for k in 0..2 {
+            for j in 0..3 {
+                for i in 0..2 {
+                    expected.push(data[i * 6 + j * 2 + k]);
+                }
+            }
+        }
+        assert_eq!(b.shape.len(), dims.len());
+        assert_exact(&b.data(), &expected);
+    }
+
     #[test]
     fn test_pool_1d() {
         let mut cx = Graph::new();
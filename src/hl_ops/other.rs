@@ -1,6 +1,12 @@
+use rand::Rng;
+
 use crate::{
     op::{self, Constant, ConstantValue},
-    prelude::{symbolic::BigExpression, *},
+    prelude::{
+        symbolic::{BigExpression, Expression},
+        *,
+    },
+    random::Distribution,
 };
 
 impl<S: Shape> GraphTensor<S> {
@@ -12,7 +18,8 @@ impl<S: Shape> GraphTensor<S> {
         }
         // Pad out length
         let orig_length = self.shape.dims[self.shape.indexes[axis]];
-        self.shape.padding[self.shape.indexes[axis]].0 = orig_length - 1;
+        let index = self.shape.indexes[axis];
+        self.shape.padding.update(|p| p[index].0 = orig_length - 1);
         self = self.contiguous();
 
         // Pool
@@ -71,6 +78,35 @@ impl Graph {
         }
     }
 
+    /// Values drawn uniformly from `[0, 1)`. New values are drawn every execution
+    pub fn rand<S: Shape>(&mut self) -> GraphTensor<S> {
+        self.random(Distribution::Uniform)
+    }
+
+    /// Values drawn from a standard normal distribution. New values are drawn every execution
+    pub fn randn<S: Shape>(&mut self) -> GraphTensor<S> {
+        self.random(Distribution::Normal)
+    }
+
+    /// 1 with probability `p`, otherwise 0. New values are drawn every execution
+    pub fn bernoulli<S: Shape>(&mut self, p: f32) -> GraphTensor<S> {
+        let p = self.constant(p).expand();
+        self.rand::<S>().less_than(p)
+    }
+
+    /// A random op, seeded from the graph's random number generator so seeded graphs draw the same values
+    fn random<S: Shape>(&mut self, distribution: Distribution) -> GraphTensor<S> {
+        let shape = ShapeTracker::new(&S::realized_shape());
+        let op = op::Random {
+            distribution,
+            seed: self.rng().gen(),
+            execution: 0,
+            size: shape.n_elements(),
+            dyn_map: &self.dyn_map,
+        };
+        GraphTensor::from_id(self.add_op(op).finish(), shape, self)
+    }
+
     /// Lower left-hand triangle of 1s. Currently required to be square
     ///
     /// Same API as https://pytorch.org/docs/stable/generated/torch.tril
@@ -93,17 +129,60 @@ impl Graph {
 }
 
 impl<S: Dimension, const DIM: usize> GraphTensor<(S, Const<DIM>)> {
-    /// Gather a batch of vectors from a matrix
+    /// Gather a batch of vectors from a matrix.
+    ///
+    /// Integer indexes gather rows directly and exactly. Float indexes are gathered with [`GraphTensor::gather_one_hot`],
+    /// which the CPU compiler turns back into a direct gather.
     pub fn gather<B: Dimension>(self, indexes: GraphTensor<(B,)>) -> GraphTensor<(B, Const<DIM>)> {
-        let one_hot = indexes
+        if indexes.dtype().is_float() {
+            return self.gather_one_hot(indexes);
+        }
+        let new_id = self
             .graph()
-            .arange::<S>()
-            .expand::<(B, S), _>()
-            .equals(indexes.expand());
-        (one_hot.expand::<(B, S, Const<DIM>), _>() * self.expand()).sum_reduce::<_, Axis<1>>()
+            .add_op(op::Gather { embed_dim: DIM })
+            .input(indexes.id, 0, indexes.shape)
+            .input(self.id, 0, self.shape)
+            .finish();
+        GraphTensor::from_id(
+            new_id,
+            ShapeTracker::new(&[indexes.shape.shape()[0].clone().into(), DIM.into()]),
+            self.graph_ref,
+        )
+    }
+}
+
+impl<S: Dimension, D: Dimension> GraphTensor<(S, D)> {
+    /// Gather a batch of vectors from a matrix by multiplying it with a one-hot mask of the indexes.
+    ///
+    /// This takes O(rows * indexes) work, so it's meant for backends without a [`op::Gather`] kernel. The mask is
+    /// compared in the type of the indexes, so integer indexes stay exact.
+    pub fn gather_one_hot<B: Dimension>(self, indexes: GraphTensor<(B,)>) -> GraphTensor<(B, D)> {
+        let dims = self.shape.shape();
+        let (n, d): (Expression, Expression) = (dims[0].clone().into(), dims[1].clone().into());
+        let b: Expression = indexes.shape.shape()[0].clone().into();
+        let cx = indexes.graph();
+        let ones = with_dim(
+            cx.constant(1.).cast(indexes.dtype()).expand::<(S,), _>(),
+            0,
+            n,
+        );
+        let arange = ones.cumsum_last_dim() - with_dim(cx.constant(1.).expand::<(S,), _>(), 0, n);
+        let one_hot = with_dim(arange.expand::<(B, S), Axis<0>>(), 0, b)
+            .equals(with_dim(indexes.expand::<(B, S), Axis<1>>(), 1, n))
+            .cast(self.dtype());
+        (with_dim(one_hot.expand::<(B, S, D), Axis<2>>(), 2, d)
+            * with_dim(self.expand::<(B, S, D), Axis<0>>(), 0, b))
+        .sum_reduce::<_, Axis<1>>()
     }
 }
 
+/// Set the size of a dimension, so shapes stay concrete when the shape type is only known at runtime (`Dyn<'-'>`)
+fn with_dim<S: Shape>(mut tensor: GraphTensor<S>, axis: usize, dim: Expression) -> GraphTensor<S> {
+    let index = tensor.shape.indexes[axis];
+    tensor.shape.dims.set(index, dim);
+    tensor
+}
+
 #[cfg(test)]
 mod tests {
     crate::test_imports!();
@@ -117,6 +196,33 @@ mod tests {
         assert_exact(&arange.data(), &[0., 1., 2., 3., 4., 5., 6., 7., 8., 9.]);
     }
 
+    #[test]
+    fn test_random() {
+        let draw = |seed| {
+            let mut cx = Graph::new();
+            cx.seed(seed);
+            let a = cx.rand::<R1<1000>>().retrieve();
+            let b = cx.randn::<(Dyn<'a'>,)>().retrieve();
+            let c = cx.bernoulli::<R1<1000>>(0.25).retrieve();
+            cx.set_dyn_dim('a', 1000);
+            cx.execute();
+            let first = (a.data(), b.data(), c.data());
+            cx.drop_tensors((a, b, c));
+            cx.execute();
+            assert_ne!(a.data(), first.0);
+            first
+        };
+        let (a, b, c) = draw(0);
+        assert_eq!((a.clone(), b.clone(), c.clone()), draw(0));
+        assert_ne!(a, draw(1).0);
+
+        assert!(a.iter().all(|v| (0. ..1.).contains(v)));
+        assert!((a.iter().sum::<f32>() / 1000. - 0.5).abs() < 0.05);
+        assert!((b.iter().sum::<f32>() / 1000.).abs() < 0.1);
+        assert!(c.iter().all(|v| *v == 0. || *v == 1.));
+        assert!((c.iter().sum::<f32>() / 1000. - 0.25).abs() < 0.05);
+    }
+
     #[test]
     fn test_cumprod() {
         let mut cx = Graph::new();
@@ -195,4 +301,53 @@ mod tests {
                 .collect::<Vec<_>>(),
         );
     }
+
+    #[test]
+    fn test_gather_views() {
+        let mut cx = Graph::new();
+        let weights = cx
+            .tensor::<R2<2, 3>>()
+            .set([[1., 2., 3.], [4., 5., 6.]])
+            .permute::<R2<3, 2>, _>();
+        let int_ids = cx
+            .tensor::<R1<4>>()
+            .set(vec![0i32, 2, 1, 0])
+            .slice((symbolic::Expression::from(1)..,))
+            .realize::<R1<3>>();
+        let float_ids = cx.tensor::<R1<3>>().set(vec![2., 1., 0.]);
+        let mut a = weights.gather(int_ids).retrieve();
+        let mut b = weights.gather(float_ids).retrieve();
+
+        cx.compile(
+            <(GenericCompiler, CPUCompiler)>::default(),
+            (&mut a, &mut b),
+        );
+        // Both gathers read the permuted weights and sliced ids in place
+        assert_eq!(
+            cx.graph
+                .node_indices()
+                .filter(|n| cx.graph[*n].as_any().is::<crate::op::Gather>())
+                .count(),
+            2
+        );
+        cx.execute();
+
+        assert_exact(&a.data(), &[3., 6., 2., 5., 1., 4.]);
+        assert_exact(&b.data(), &[3., 6., 2., 5., 1., 4.]);
+    }
+
+    #[test]
+    fn test_gather_out_of_range() {
+        for ids in [vec![0i32, 3], vec![-1, 0]] {
+            let mut cx = Graph::new();
+            let weights = cx.tensor::<R2<3, 2>>().set(vec![0.; 6]);
+            let ids = cx.tensor::<R1<2>>().set(ids);
+            let out = weights.gather(ids).retrieve();
+            assert!(matches!(
+                cx.try_execute(),
+                Err(ExecutionError::Op { node, error: crate::op::OpError::Other(_), .. })
+                    if node == out.id
+            ));
+        }
+    }
 }
@@ -10,14 +10,14 @@ impl<S: Shape> GraphTensor<S> {
     where
         S: HasAxes<Ax> + ReduceShapeTo<Dst, Ax>,
     {
-        let mut shape = self.shape;
+        let mut shape: ShapeTracker = self.shape.into();
 
         let mut new_id = self.id;
         for dim in Ax::as_array().into_iter().collect_vec().into_iter().rev() {
             new_id = self
                 .graph()
                 .add_op(op::SumReduce(dim))
-                .input(new_id, 0, shape)
+                .input(new_id, 0, shape.clone())
                 .finish();
             // Reduce shape
             shape.remove_dim(dim);
@@ -29,14 +29,14 @@ impl<S: Shape> GraphTensor<S> {
     where
         S: HasAxes<Ax> + ReduceShapeTo<Dst, Ax>,
     {
-        let mut shape = self.shape;
+        let mut shape: ShapeTracker = self.shape.into();
 
         let mut new_id = self.id;
         for dim in Ax::as_array().into_iter().collect_vec().into_iter().rev() {
             new_id = self
                 .graph()
                 .add_op(op::MaxReduce(dim))
-                .input(new_id, 0, shape)
+                .input(new_id, 0, shape.clone())
                 .finish();
             // Reduce shape
             shape.remove_dim(dim);
@@ -48,14 +48,14 @@ impl<S: Shape> GraphTensor<S> {
     where
         S: HasAxes<Ax> + ReduceShapeTo<Dst, Ax>,
     {
-        let mut shape = self.shape;
+        let mut shape: ShapeTracker = self.shape.into();
         let mut node_id = self.id;
         for dim in Ax::as_array().into_iter().collect_vec().into_iter().rev() {
             // Sum reduce
             node_id = self
                 .graph()
                 .add_op(op::SumReduce(dim))
-                .input(node_id, 0, shape)
+                .input(node_id, 0, shape.clone())
                 .finish();
 
             // Divide by size of dimension
@@ -68,7 +68,7 @@ impl<S: Shape> GraphTensor<S> {
             node_id = self
                 .graph()
                 .add_op(op::Mul)
-                .input(node_id, 0, shape)
+                .input(node_id, 0, shape.clone())
                 .input(
                     mul_tensor,
                     0,
@@ -2,6 +2,7 @@ mod core;
 pub use crate::core::*;
 pub mod compilers;
 pub mod nn;
+pub mod onnx;
 
 mod hl_ops;
 
@@ -10,13 +11,22 @@ pub mod tests;
 pub mod prelude {
     pub use crate::compiler_utils::*;
     pub use crate::compilers::*;
+    pub use crate::dtype::DType;
+    pub use crate::estimate::*;
     pub use crate::graph::*;
     pub use crate::graph_tensor::*;
     pub use crate::hl_ops::*;
+    pub use crate::ir::*;
+    pub use crate::memory::*;
     pub use crate::module::*;
+    pub use crate::pipeline::*;
+    pub use crate::profile::*;
     pub use crate::serialization::*;
     pub use crate::shape::*;
     pub use crate::tensor::*;
+    pub use crate::trace::*;
+    pub use crate::validate::*;
+    pub use crate::visualize::*;
     pub use half::{bf16, f16};
     pub use luminal_macro::*;
     pub use petgraph;
@@ -1,5 +1,4 @@
-use crate::prelude::*;
-use rand::{thread_rng, Rng};
+use crate::{nn::init::Init, prelude::*};
 
 pub struct Conv1D<
     const CHANNELS_IN: usize,
@@ -27,13 +26,7 @@ impl<
             weight: cx.named_tensor("Weight"),
         };
 
-        // Init weight as uniform(-1, 1)
-        let mut rng = thread_rng();
-        conv.weight.set(
-            (0..(CHANNELS_IN * CHANNELS_OUT * KERNEL))
-                .map(|_| rng.gen_range(-1_f32..1_f32))
-                .collect::<Vec<_>>(),
-        );
+        conv.init(Init::Uniform { low: -1., high: 1. });
         conv
     }
 }
@@ -63,6 +56,11 @@ impl<
         const CHANNELS_IN_TIMES_KERNEL: usize,
     > Conv1D<CHANNELS_IN, CHANNELS_OUT, KERNEL, STRIDE, DILATION, CHANNELS_IN_TIMES_KERNEL>
 {
+    /// Reinitialize the weight with a scheme
+    pub fn init(&self, init: Init) {
+        init.set(self.weight, CHANNELS_IN * KERNEL, CHANNELS_OUT * KERNEL);
+    }
+
     pub fn forward<const DIM_IN: usize, const DIM_OUT: usize>(
         &self,
         input: GraphTensor<R2<CHANNELS_IN, DIM_IN>>,
@@ -122,13 +120,7 @@ impl<
             weight: cx.named_tensor("Weight"),
         };
 
-        // Init weight as uniform(-1, 1)
-        let mut rng = thread_rng();
-        conv.weight.set(
-            (0..(CHANNELS_IN * CHANNELS_OUT * KERNELX * KERNELY))
-                .map(|_| rng.gen_range(-1_f32..1_f32))
-                .collect::<Vec<_>>(),
-        );
+        conv.init(Init::Uniform { low: -1., high: 1. });
         conv
     }
 }
@@ -185,6 +177,15 @@ impl<
         CHANNELS_IN_TIMES_KERNELX_KERNELY,
     >
 {
+    /// Reinitialize the weight with a scheme
+    pub fn init(&self, init: Init) {
+        init.set(
+            self.weight,
+            CHANNELS_IN * KERNELX * KERNELY,
+            CHANNELS_OUT * KERNELX * KERNELY,
+        );
+    }
+
     pub fn forward<
         const DIMX_IN: usize,
         const DIMY_IN: usize,
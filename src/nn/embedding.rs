@@ -1,4 +1,4 @@
-use crate::prelude::*;
+use crate::{nn::init::Init, prelude::*};
 
 pub struct Embedding<const N: usize, const DIM: usize> {
     pub weight: GraphTensor<R2<N, DIM>>,
@@ -12,6 +12,13 @@ impl<const A: usize, const B: usize> InitModule for Embedding<A, B> {
     }
 }
 
+impl<const A: usize, const B: usize> Embedding<A, B> {
+    /// Initialize the weight with a scheme. The weight is left unset by [`InitModule::initialize`].
+    pub fn init(&self, init: Init) {
+        init.set(self.weight, A, B);
+    }
+}
+
 impl<const A: usize, const B: usize> SerializeModule for Embedding<A, B> {
     fn serialize(&self, s: &mut crate::serialization::Serializer) {
         s.tensor("weight", self.weight);
@@ -89,4 +96,41 @@ mod tests {
         assert_close(&b.data(), &d_b.as_vec());
         assert_close(&batch_out.data(), &d_batch_out.as_vec());
     }
+
+    #[test]
+    fn test_embedding_int_ids() {
+        let mut cx = Graph::new();
+        let weights = vec![1.1, 2., 3., 1., 2., 3., 14., 2., 33., 1., 2., 3.];
+        let float_ids = cx.tensor::<R1<4>>().set(vec![2., 0., 1., 2.]);
+        let int_ids = cx.tensor::<R1<4>>().set(vec![2i64, 0, 1, 2]);
+        let batch = cx.tensor::<R2<2, 2>>().set(vec![2i32, 0, 1, 2]);
+
+        let model: Embedding<3, 4> = InitModule::initialize(&mut cx);
+        model.weight.set(weights);
+        let mut a = model.forward(float_ids).retrieve();
+        let mut b = model.forward(int_ids).retrieve();
+        let mut c = model.forward(batch).retrieve();
+        // Integer ids gather directly, float ids go through a one-hot mask
+        let n_gathers = |cx: &Graph| {
+            cx.graph
+                .node_indices()
+                .filter(|n| cx.graph[*n].as_any().is::<crate::op::Gather>())
+                .count()
+        };
+        assert_eq!(n_gathers(&cx), 2);
+
+        cx.compile(
+            <(GenericCompiler, CPUCompiler)>::default(),
+            (&mut a, &mut b, &mut c),
+        );
+        assert_eq!(n_gathers(&cx), 3);
+        cx.execute();
+
+        let expected = [
+            33., 1., 2., 3., 1.1, 2., 3., 1., 2., 3., 14., 2., 33., 1., 2., 3.,
+        ];
+        assert_exact(&a.data(), &expected);
+        assert_exact(&b.data(), &expected);
+        assert_exact(&c.data(), &expected);
+    }
 }
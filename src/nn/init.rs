@@ -0,0 +1,125 @@
+use rand::Rng;
+use rand_distr::{Distribution, Normal, Uniform};
+
+use crate::prelude::*;
+
+/// A scheme for initializing weights. Samples are drawn from the graph's random number generator, so seeding the
+/// graph with [`Graph::seed`] makes initialization reproducible.
+///
+/// Xavier and Kaiming schemes scale with the number of inputs (`fan_in`) and outputs (`fan_out`) of each unit. Kaiming
+/// uses the gain for ReLU.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Init {
+    Zeros,
+    Ones,
+    Constant(f32),
+    Uniform { low: f32, high: f32 },
+    Normal { mean: f32, std: f32 },
+    XavierUniform,
+    XavierNormal,
+    KaimingUniform,
+    KaimingNormal,
+}
+
+impl Init {
+    /// Draw `n` values
+    pub fn sample<R: Rng>(self, n: usize, fan_in: usize, fan_out: usize, rng: &mut R) -> Vec<f32> {
+        let uniform = |bound: f32, rng: &mut R| {
+            Uniform::new(-bound, bound)
+                .sample_iter(rng)
+                .take(n)
+                .collect()
+        };
+        let normal = |mean: f32, std: f32, rng: &mut R| {
+            Normal::new(mean, std)
+                .expect("Standard deviation must be finite")
+                .sample_iter(rng)
+                .take(n)
+                .collect()
+        };
+        let (fan_in, fan_avg) = (fan_in as f32, (fan_in + fan_out) as f32 / 2.);
+        match self {
+            Init::Zeros => vec![0.; n],
+            Init::Ones => vec![1.; n],
+            Init::Constant(c) => vec![c; n],
+            Init::Uniform { low, high } => {
+                Uniform::new(low, high).sample_iter(rng).take(n).collect()
+            }
+            Init::Normal { mean, std } => normal(mean, std, rng),
+            Init::XavierUniform => uniform((3. / fan_avg).sqrt(), rng),
+            Init::XavierNormal => normal(0., (1. / fan_avg).sqrt(), rng),
+            Init::KaimingUniform => uniform((6. / fan_in).sqrt(), rng),
+            Init::KaimingNormal => normal(0., (2. / fan_in).sqrt(), rng),
+        }
+    }
+
+    /// Set a tensor's data to values drawn from its graph's random number generator
+    pub fn set<S: ConstShape>(
+        self,
+        tensor: GraphTensor<S>,
+        fan_in: usize,
+        fan_out: usize,
+    ) -> GraphTensor<S> {
+        let n = tensor
+            .shape
+            .n_elements()
+            .to_usize()
+            .expect("Only tensors with known shapes can be initialized");
+        let data = self.sample(n, fan_in, fan_out, tensor.graph().rng());
+        tensor.set(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::nn::{linear::Linear, transformer::encoder::TransformerEncoderBlock};
+
+    #[test]
+    fn test_init_schemes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(Init::Zeros.sample(3, 1, 1, &mut rng), vec![0.; 3]);
+        assert_eq!(Init::Constant(0.5).sample(2, 1, 1, &mut rng), vec![0.5; 2]);
+
+        let bound = (6. / 100_f32).sqrt();
+        let samples = Init::KaimingUniform.sample(10_000, 100, 10, &mut rng);
+        assert!(samples.iter().all(|s| s.abs() <= bound));
+        let samples = Init::XavierNormal.sample(10_000, 300, 100, &mut rng);
+        let var = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        assert!((var - 1. / 200.).abs() < 5e-4);
+
+        let a = Init::Normal { mean: 1., std: 2. }.sample(8, 1, 1, &mut StdRng::seed_from_u64(7));
+        let b = Init::Normal { mean: 1., std: 2. }.sample(8, 1, 1, &mut StdRng::seed_from_u64(7));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_seeded_initialization() {
+        let weights = |seed| {
+            let mut cx = Graph::new();
+            cx.seed(seed);
+            let model = <(Linear<3, 4>, TransformerEncoderBlock<4, 8, 1>)>::initialize(&mut cx);
+            let weights = state_set(&model);
+            cx.keep_tensors(&weights);
+            cx.execute();
+            weights
+                .into_iter()
+                .map(|w| cx.get_tensor_ref(w, 0).unwrap().to_vec::<f32>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(weights(1), weights(1));
+        assert_ne!(weights(1), weights(2));
+
+        let mut cx = Graph::new();
+        let model = <TransformerEncoderBlock<4, 8, 1>>::initialize(&mut cx);
+        model.init(Init::Zeros);
+        model.ff.2.init(Init::Ones);
+        cx.keep_tensors(state_set(&model));
+        cx.execute();
+        let data = |w: NodeIndex| cx.get_tensor_ref(w, 0).unwrap().to_vec::<f32>();
+        assert_eq!(data(model.attention.w_q.weight.id), vec![0.; 16]);
+        assert_eq!(data(model.ff.2.weight.id), vec![1.; 32]);
+    }
+}
@@ -1,6 +1,4 @@
-use rand::{thread_rng, Rng};
-
-use crate::prelude::*;
+use crate::{nn::init::Init, prelude::*};
 
 /// A simple linear layer
 pub struct Linear<const A: usize, const B: usize> {
@@ -12,17 +10,18 @@ impl<const A: usize, const B: usize> InitModule for Linear<A, B> {
         let s = Self {
             weight: cx.named_tensor("Weight"),
         };
-        // Init weight as uniform(-1, 1)
-        let mut rng = thread_rng();
-        s.weight.set(
-            (0..(A * B))
-                .map(|_| rng.gen_range(-1_f32..1_f32))
-                .collect::<Vec<_>>(),
-        );
+        s.init(Init::Uniform { low: -1., high: 1. });
         s
     }
 }
 
+impl<const A: usize, const B: usize> Linear<A, B> {
+    /// Reinitialize the weight with a scheme
+    pub fn init(&self, init: Init) {
+        init.set(self.weight, A, B);
+    }
+}
+
 impl<const A: usize, const B: usize> SerializeModule for Linear<A, B> {
     fn serialize(&self, s: &mut crate::serialization::Serializer) {
         s.tensor("weight", self.weight);
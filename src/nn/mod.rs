@@ -3,8 +3,10 @@ use crate::prelude::*;
 pub mod activation;
 pub mod convolution;
 pub mod embedding;
+pub mod init;
 pub mod linear;
 pub mod norm;
+pub mod optimizer;
 pub mod transformer;
 
 pub struct Repeated<T, const N: usize> {
@@ -0,0 +1,334 @@
+use crate::prelude::{symbolic::Expression, *};
+
+/// A flat view of a parameter-sized tensor, so updates can be written elementwise regardless of the parameter's shape
+type Flat = GraphTensor<(Dyn<'-'>,)>;
+
+/// How weight decay is applied to the parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WeightDecay {
+    /// Add `decay * param` to the gradient (classic L2 regularization)
+    L2(f32),
+    /// Shrink the parameters by `lr * decay * param` directly, decoupled from the gradient (as in AdamW)
+    Decoupled(f32),
+}
+
+/// The in-graph update built by an optimizer.
+///
+/// After each execution, call [`OptimizerUpdate::step`] to move the new parameters and optimizer state into place
+/// for the next execution.
+#[derive(Debug, Default, Clone)]
+pub struct OptimizerUpdate {
+    /// The parameters being optimized
+    pub params: Vec<GraphTensor<()>>,
+    /// The updated parameters
+    pub new_params: Vec<GraphTensor<()>>,
+    /// Optimizer state (moments, step counts) read during the update
+    pub state: Vec<GraphTensor<()>>,
+    /// The updated optimizer state
+    pub new_state: Vec<GraphTensor<()>>,
+}
+
+impl OptimizerUpdate {
+    /// Swap the updated parameters and optimizer state into place
+    pub fn step(&self, graph: &mut Graph) {
+        transfer_data_same_graph(&self.new_params, &self.params, graph);
+        transfer_data_same_graph(&self.new_state, &self.state, graph);
+    }
+
+    /// Create a kept state tensor of `n` elements, initialized to `value`
+    fn add_state(&mut self, graph: &mut Graph, name: &str, n: Expression, value: f32) -> Flat {
+        let size = n
+            .to_usize()
+            .expect("Optimizer state requires parameters with known sizes");
+        let state = graph
+            .named_tensor::<(Dyn<'-'>,)>(name)
+            .keep()
+            .set_deferred(move || vec![value; size]);
+        let state = GraphTensor::from_id(state.id, ShapeTracker::new(&[n]), graph);
+        self.state.push(state.no_shape());
+        state
+    }
+
+    /// Register the updated value of the last state tensor added
+    fn set_state(&mut self, new_state: Flat) {
+        self.new_state.push(new_state.keep().no_shape());
+    }
+}
+
+impl ToIdsMut for OptimizerUpdate {
+    fn to_ids_mut(&mut self) -> Vec<&mut NodeIndex> {
+        self.params
+            .iter_mut()
+            .chain(self.new_params.iter_mut())
+            .chain(self.state.iter_mut())
+            .chain(self.new_state.iter_mut())
+            .map(|t| &mut t.id)
+            .collect()
+    }
+}
+
+impl ToIds for OptimizerUpdate {
+    fn to_ids(&self) -> Vec<NodeIndex> {
+        self.params
+            .iter()
+            .chain(&self.new_params)
+            .chain(&self.state)
+            .chain(&self.new_state)
+            .map(|t| t.id)
+            .collect()
+    }
+}
+
+/// An optimizer that builds parameter updates on the graph
+pub trait Optimizer {
+    /// Build the update for a single parameter given its gradient, both viewed flat
+    fn update_param(&self, update: &mut OptimizerUpdate, param: Flat, grad: Flat) -> Flat;
+
+    /// Build updates for each parameter, given gradients in the same order.
+    /// Gradients can be any tensors on the graph with the same number of elements as their parameter.
+    fn build<P: ToIds>(&self, params: P, grads: &[GraphTensor<()>]) -> OptimizerUpdate {
+        let params = params.to_ids();
+        assert_eq!(
+            params.len(),
+            grads.len(),
+            "Number of parameters and gradients don't match"
+        );
+        let mut update = OptimizerUpdate::default();
+        for (param, grad) in params.into_iter().zip(grads) {
+            let grad = grad.contiguous();
+            let shape = ShapeTracker::new(&[grad.shape.n_elements().into()]);
+            let new_param = self.update_param(
+                &mut update,
+                GraphTensor::from_id(param, shape.clone(), grad.graph_ref),
+                GraphTensor::from_id(grad.id, shape, grad.graph_ref),
+            );
+            update
+                .params
+                .push(GraphTensor::from_id(param, grad.shape, grad.graph_ref).keep());
+            update.new_params.push(GraphTensor::from_id(
+                new_param.keep().id,
+                grad.shape,
+                grad.graph_ref,
+            ));
+        }
+        update
+    }
+
+    /// Build updates for every weight in a module, given gradients in the order of [`state_set`]
+    fn build_module<M: SerializeModule>(
+        &self,
+        model: &M,
+        grads: &[GraphTensor<()>],
+    ) -> OptimizerUpdate {
+        self.build(state_set(model), grads)
+    }
+}
+
+fn apply_l2(weight_decay: Option<WeightDecay>, param: Flat, grad: Flat) -> Flat {
+    match weight_decay {
+        Some(WeightDecay::L2(decay)) => grad + param * decay,
+        _ => grad,
+    }
+}
+
+fn apply_decoupled(weight_decay: Option<WeightDecay>, lr: f32, param: Flat) -> Flat {
+    match weight_decay {
+        Some(WeightDecay::Decoupled(decay)) => param * (1. - lr * decay),
+        _ => param,
+    }
+}
+
+/// Stochastic gradient descent: `param -= lr * grad`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SGD {
+    pub lr: f32,
+    pub weight_decay: Option<WeightDecay>,
+}
+
+impl SGD {
+    pub fn new(lr: f32) -> Self {
+        Self {
+            lr,
+            weight_decay: None,
+        }
+    }
+}
+
+impl Optimizer for SGD {
+    fn update_param(&self, _: &mut OptimizerUpdate, param: Flat, grad: Flat) -> Flat {
+        let grad = apply_l2(self.weight_decay, param, grad);
+        apply_decoupled(self.weight_decay, self.lr, param) - grad * self.lr
+    }
+}
+
+/// SGD with momentum: `velocity = momentum * velocity + grad`, `param -= lr * velocity`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Momentum {
+    pub lr: f32,
+    pub momentum: f32,
+    pub weight_decay: Option<WeightDecay>,
+}
+
+impl Momentum {
+    pub fn new(lr: f32, momentum: f32) -> Self {
+        Self {
+            lr,
+            momentum,
+            weight_decay: None,
+        }
+    }
+}
+
+impl Optimizer for Momentum {
+    fn update_param(&self, update: &mut OptimizerUpdate, param: Flat, grad: Flat) -> Flat {
+        let grad = apply_l2(self.weight_decay, param, grad);
+        let velocity = update.add_state(param.graph(), "Velocity", param.shape.dims[0], 0.);
+        let velocity = velocity * self.momentum + grad;
+        update.set_state(velocity);
+        apply_decoupled(self.weight_decay, self.lr, param) - velocity * self.lr
+    }
+}
+
+/// Adam, with bias-corrected first and second moments. Use [`WeightDecay::Decoupled`] for AdamW.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adam {
+    pub lr: f32,
+    pub betas: (f32, f32),
+    pub epsilon: f32,
+    pub weight_decay: Option<WeightDecay>,
+}
+
+impl Default for Adam {
+    fn default() -> Self {
+        Self {
+            lr: 1e-3,
+            betas: (0.9, 0.999),
+            epsilon: 1e-8,
+            weight_decay: None,
+        }
+    }
+}
+
+impl Adam {
+    pub fn new(lr: f32) -> Self {
+        Self {
+            lr,
+            ..Default::default()
+        }
+    }
+
+    /// AdamW: Adam with decoupled weight decay
+    pub fn adamw(lr: f32, weight_decay: f32) -> Self {
+        Self {
+            lr,
+            weight_decay: Some(WeightDecay::Decoupled(weight_decay)),
+            ..Default::default()
+        }
+    }
+}
+
+impl Optimizer for Adam {
+    fn update_param(&self, update: &mut OptimizerUpdate, param: Flat, grad: Flat) -> Flat {
+        let (beta1, beta2) = self.betas;
+        let grad = apply_l2(self.weight_decay, param, grad);
+        let n = param.shape.dims[0];
+        let cx = param.graph();
+
+        let m = update.add_state(cx, "Adam M", n, 0.);
+        let m = m * beta1 + grad * (1. - beta1);
+        update.set_state(m);
+        let v = update.add_state(cx, "Adam V", n, 0.);
+        let v = v * beta2 + grad * grad * (1. - beta2);
+        update.set_state(v);
+        let t = update.add_state(cx, "Adam Step", 1.into(), 0.) + 1.;
+        update.set_state(t);
+        // View the step count as parameter-sized
+        let mut t_shape = ShapeTracker::new(&[]);
+        t_shape.expand(0, n);
+        let t = Flat::from_id(t.id, t_shape, t.graph_ref);
+
+        // Bias correction: 1 - beta^t
+        let m_hat = m / ((t * beta1.log2()).exp2() * -1. + 1.);
+        let v_hat = v / ((t * beta2.log2()).exp2() * -1. + 1.);
+        apply_decoupled(self.weight_decay, self.lr, param)
+            - m_hat / (v_hat.sqrt() + self.epsilon) * self.lr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{nn::linear::Linear, tests::assert_close};
+
+    #[test]
+    fn test_sgd_linear_regression() {
+        let mut cx = Graph::new();
+        let model = Linear::<2, 1>::initialize(&mut cx);
+        model.weight.set(vec![0., 0.]);
+        let input = cx
+            .tensor::<R2<4, 2>>()
+            .set(vec![1., 0., 0., 1., 1., 1., 2., 1.]);
+        let target = cx.tensor::<R2<4, 1>>().set(vec![2., -1., 1., 3.]);
+        let diff = model.forward(input) - target;
+        let mut loss = (diff * diff).mean_reduce::<_, Axes2<0, 1>>().retrieve();
+
+        let autograd = Autograd::new(state_set(&model), loss);
+        cx.compile(&autograd, ());
+        let mut update = SGD::new(0.1).build_module(&model, &autograd.grads());
+        cx.compile(CPUCompiler::default(), (&mut loss, &mut update));
+
+        let mut losses = vec![];
+        for _ in 0..300 {
+            cx.execute();
+            losses.push(loss.data()[0]);
+            loss.drop();
+            update.step(&mut cx);
+        }
+        assert!(losses.windows(2).all(|w| w[1] <= w[0] + 1e-6));
+        assert!(losses[losses.len() - 1] < 1e-4);
+        // y = 2 * x0 - x1 fits the data exactly
+        assert_close(&update.params[0].data(), &[2., -1.]);
+    }
+
+    #[test]
+    fn test_adam_step() {
+        let mut cx = Graph::new();
+        let param_data = vec![0.5, -1.0, 2.0];
+        let grad_data = vec![0.1, -0.2, 0.3];
+        let param = cx.tensor::<R1<3>>().set(param_data.clone());
+        let grad = cx.tensor::<R1<3>>().set(grad_data.clone());
+        let adam = Adam::adamw(0.01, 0.1);
+        let update = adam.build(param, &[grad.no_shape()]);
+
+        let mut m = [0.; 3];
+        let mut v = [0.; 3];
+        let mut p = param_data.clone();
+        for t in 1..=3 {
+            cx.execute();
+            update.step(&mut cx);
+            for i in 0..3 {
+                m[i] = 0.9 * m[i] + 0.1 * grad_data[i];
+                v[i] = 0.999 * v[i] + 0.001 * grad_data[i] * grad_data[i];
+                let m_hat = m[i] / (1. - 0.9_f32.powi(t));
+                let v_hat = v[i] / (1. - 0.999_f32.powi(t));
+                p[i] = p[i] * (1. - 0.01 * 0.1) - 0.01 * m_hat / (v_hat.sqrt() + 1e-8);
+            }
+            assert_close(&update.params[0].data(), &p);
+        }
+    }
+
+    #[test]
+    fn test_momentum_step() {
+        let mut cx = Graph::new();
+        let param = cx.tensor::<R1<2>>().set(vec![1., 2.]);
+        let grad = cx.tensor::<R1<2>>().set(vec![1., -1.]);
+        let update = Momentum::new(0.5, 0.9).build(param, &[grad.no_shape()]);
+        cx.execute();
+        update.step(&mut cx);
+        assert_close(&update.params[0].data(), &[0.5, 2.5]);
+        cx.execute();
+        update.step(&mut cx);
+        // Velocity is now 1.9
+        assert_close(&update.params[0].data(), &[-0.45, 3.45]);
+    }
+}
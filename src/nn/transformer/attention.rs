@@ -1,6 +1,9 @@
 use std::ops::Mul;
 
-use crate::{nn::linear::Linear, prelude::*};
+use crate::{
+    nn::{init::Init, linear::Linear},
+    prelude::*,
+};
 
 // This is still single head attention because I need a runtime reshape, like the try_reshape in dfdx
 pub struct MultiHeadSelfAttention<
@@ -28,6 +31,18 @@ impl<const DIM: usize, const K_DIM: usize, const V_DIM: usize, const HEADS: usiz
     }
 }
 
+impl<const DIM: usize, const K_DIM: usize, const V_DIM: usize, const HEADS: usize>
+    MultiHeadSelfAttention<DIM, K_DIM, V_DIM, HEADS>
+{
+    /// Reinitialize all projections with a scheme
+    pub fn init(&self, init: Init) {
+        self.w_q.init(init);
+        self.w_k.init(init);
+        self.w_v.init(init);
+        self.w_o.init(init);
+    }
+}
+
 impl<const DIM: usize, const K_DIM: usize, const V_DIM: usize, const HEADS: usize> SerializeModule
     for MultiHeadSelfAttention<DIM, K_DIM, V_DIM, HEADS>
 {
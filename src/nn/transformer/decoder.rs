@@ -1,5 +1,5 @@
 use crate::{
-    nn::{activation::ReLU, linear::Linear},
+    nn::{activation::ReLU, init::Init, linear::Linear},
     prelude::*,
 };
 
@@ -25,6 +25,17 @@ impl<const DIM: usize, const FF: usize, const HEADS: usize, const LAYERS: usize>
     }
 }
 
+impl<const DIM: usize, const FF: usize, const HEADS: usize, const LAYERS: usize>
+    TransformerDecoder<DIM, FF, HEADS, LAYERS>
+{
+    /// Reinitialize every layer with a scheme
+    pub fn init(&self, init: Init) {
+        for layer in &self.layers {
+            layer.init(init);
+        }
+    }
+}
+
 impl<const DIM: usize, const FF: usize, const HEADS: usize, const LAYERS: usize> SerializeModule
     for TransformerDecoder<DIM, FF, HEADS, LAYERS>
 {
@@ -110,6 +121,18 @@ impl<const DIM: usize, const FF: usize, const HEADS: usize> InitModule
     }
 }
 
+impl<const DIM: usize, const FF: usize, const HEADS: usize>
+    TransformerDecoderBlock<DIM, FF, HEADS>
+{
+    /// Reinitialize the attention and feed forward layers with a scheme
+    pub fn init(&self, init: Init) {
+        self.self_attention.init(init);
+        self.cross_attention.init(init);
+        self.ff.0.init(init);
+        self.ff.2.init(init);
+    }
+}
+
 impl<const DIM: usize, const FF: usize, const HEADS: usize> SerializeModule
     for TransformerDecoderBlock<DIM, FF, HEADS>
 {
@@ -1,5 +1,5 @@
 use crate::{
-    nn::{activation::ReLU, linear::Linear, Repeated},
+    nn::{activation::ReLU, init::Init, linear::Linear, Repeated},
     prelude::*,
 };
 
@@ -30,6 +30,17 @@ impl<const DIM: usize, const FF: usize, const HEADS: usize> InitModule
     }
 }
 
+impl<const DIM: usize, const FF: usize, const HEADS: usize>
+    TransformerEncoderBlock<DIM, FF, HEADS>
+{
+    /// Reinitialize the attention and feed forward layers with a scheme
+    pub fn init(&self, init: Init) {
+        self.attention.init(init);
+        self.ff.0.init(init);
+        self.ff.2.init(init);
+    }
+}
+
 impl<const DIM: usize, const FF: usize, const HEADS: usize> SerializeModule
     for TransformerEncoderBlock<DIM, FF, HEADS>
 {
@@ -1,4 +1,4 @@
-use crate::prelude::*;
+use crate::{nn::init::Init, prelude::*};
 
 pub mod attention;
 pub mod decoder;
@@ -33,6 +33,24 @@ impl<
     }
 }
 
+impl<
+        const DIM: usize,
+        const FF: usize,
+        const ENC_HEADS: usize,
+        const DEC_HEADS: usize,
+        const ENC_LAYERS: usize,
+        const DEC_LAYERS: usize,
+    > Transformer<DIM, FF, ENC_HEADS, DEC_HEADS, ENC_LAYERS, DEC_LAYERS>
+{
+    /// Reinitialize every encoder and decoder layer with a scheme
+    pub fn init(&self, init: Init) {
+        for layer in &self.encoder.modules {
+            layer.init(init);
+        }
+        self.decoder.init(init);
+    }
+}
+
 impl<
         const DIM: usize,
         const FF: usize,
@@ -0,0 +1,1557 @@
+//! Import ONNX models into luminal graphs.
+//!
+//! ONNX ops are lowered onto primops while the graph is built, so the imported graph can be compiled like any other.
+//! Initializers are loaded as named weight tensors, and symbolic ONNX dims (like a `batch` dim) become dynamic dims.
+//!
+//! Integer tensors that are known at import time (shapes, axes, slice bounds and constant indexes) are folded while
+//! importing. Everything computed at runtime is kept in f32 tensors, so a `Cast` rounds its input through the target
+//! type (truncating for integers) and converts back to f32.
+//!
+//! ```no_run
+//! use luminal::prelude::*;
+//! let mut cx = Graph::new();
+//! let model = luminal::onnx::import_onnx_file("model.onnx", &mut cx).unwrap();
+//! model.set_input("input", vec![0.; 2 * 4], &[2, 4]);
+//! cx.execute();
+//! let output = model.output("output").unwrap().data();
+//! ```
+
+mod ops;
+mod proto;
+
+use std::path::Path;
+
+use rustc_hash::FxHashMap;
+
+use crate::prelude::{
+    symbolic::{BigExpression, Expression, Symbol},
+    *,
+};
+use ops::dims;
+pub use ops::Value;
+use proto::{Attribute, Dim, ModelProto, NodeProto, TensorProto};
+
+/// Every ONNX op the importer can lower
+pub const SUPPORTED_OPS: &[&str] = &[
+    "Abs",
+    "Add",
+    "Cast",
+    "Clip",
+    "Concat",
+    "Constant",
+    "Conv",
+    "Cos",
+    "Div",
+    "Dropout",
+    "Equal",
+    "Exp",
+    "Expand",
+    "Flatten",
+    "Gather",
+    "Gemm",
+    "GlobalAveragePool",
+    "Greater",
+    "Identity",
+    "LayerNormalization",
+    "LeakyRelu",
+    "Less",
+    "Log",
+    "LogSoftmax",
+    "MatMul",
+    "Max",
+    "Min",
+    "Mul",
+    "Neg",
+    "Pow",
+    "Reciprocal",
+    "ReduceMax",
+    "ReduceMean",
+    "ReduceSum",
+    "Relu",
+    "Reshape",
+    "Shape",
+    "Sigmoid",
+    "Sin",
+    "Slice",
+    "Softmax",
+    "Sqrt",
+    "Squeeze",
+    "Sub",
+    "Tanh",
+    "Transpose",
+    "Unsqueeze",
+    "Where",
+];
+
+/// An error while importing an ONNX model
+#[derive(Debug)]
+pub enum OnnxError {
+    Io(std::io::Error),
+    /// The file isn't a valid ONNX protobuf
+    Decode(String),
+    /// The model uses ops the importer doesn't support
+    UnsupportedOps(Vec<String>),
+    /// A supported op is used in a way the importer doesn't support
+    Unsupported {
+        node: String,
+        message: String,
+    },
+    /// A node uses a value that was never defined
+    MissingValue(String),
+}
+
+impl std::fmt::Display for OnnxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OnnxError::Io(e) => write!(f, "Failed to read model: {e}"),
+            OnnxError::Decode(e) => write!(f, "Failed to decode model: {e}"),
+            OnnxError::UnsupportedOps(ops) => write!(f, "Unsupported ops: {}", ops.join(", ")),
+            OnnxError::Unsupported { node, message } => write!(f, "{node}: {message}"),
+            OnnxError::MissingValue(name) => write!(f, "Value {name} is never defined"),
+        }
+    }
+}
+
+impl std::error::Error for OnnxError {}
+
+impl From<std::io::Error> for OnnxError {
+    fn from(value: std::io::Error) -> Self {
+        OnnxError::Io(value)
+    }
+}
+
+/// An imported model
+#[derive(Debug)]
+pub struct OnnxModel {
+    /// Graph inputs that need to be set before running
+    pub inputs: Vec<(String, Value)>,
+    /// Graph outputs, which are marked to be retrieved
+    pub outputs: Vec<(String, Value)>,
+    /// The dynamic dim each symbolic ONNX dim was mapped to
+    pub dyn_dims: Vec<(String, Symbol)>,
+    /// The model's opset version
+    pub opset: i64,
+}
+
+impl OnnxModel {
+    pub fn input(&self, name: &str) -> Option<Value> {
+        self.inputs.iter().find(|(n, _)| n == name).map(|(_, v)| *v)
+    }
+
+    pub fn output(&self, name: &str) -> Option<Value> {
+        self.outputs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| *v)
+    }
+
+    /// Set an input, and the values of any dynamic dims in its shape
+    pub fn set_input(&self, name: &str, data: Vec<f32>, shape: &[usize]) {
+        let input = self
+            .input(name)
+            .unwrap_or_else(|| panic!("Model has no input {name}"));
+        input.set_dyn(data, shape);
+    }
+}
+
+/// Import an ONNX model from a file
+pub fn import_onnx_file<P: AsRef<Path>>(path: P, cx: &mut Graph) -> Result<OnnxModel, OnnxError> {
+    import_onnx(&std::fs::read(path)?, cx)
+}
+
+/// Import an ONNX model into a graph
+pub fn import_onnx(bytes: &[u8], cx: &mut Graph) -> Result<OnnxModel, OnnxError> {
+    let model = ModelProto::decode(bytes)?;
+
+    // Report every unsupported op at once
+    let mut unsupported = vec![];
+    for node in &model.graph.nodes {
+        let op = if node.domain.is_empty() || node.domain == "ai.onnx" {
+            node.op_type.clone()
+        } else {
+            format!("{}.{}", node.domain, node.op_type)
+        };
+        if (op != node.op_type || !SUPPORTED_OPS.contains(&op.as_str()))
+            && !unsupported.contains(&op)
+        {
+            unsupported.push(op);
+        }
+    }
+    if !unsupported.is_empty() {
+        return Err(OnnxError::UnsupportedOps(unsupported));
+    }
+
+    let mut importer = Importer {
+        cx,
+        opset: model.opset,
+        values: FxHashMap::default(),
+        known: FxHashMap::default(),
+        floats: FxHashMap::default(),
+        dyn_dims: vec![],
+    };
+    for init in &model.graph.initializers {
+        importer.initializer(init)?;
+    }
+    let mut inputs = vec![];
+    for input in &model.graph.inputs {
+        if importer.values.contains_key(&input.name) || importer.known.contains_key(&input.name) {
+            // Older models list initializers as inputs too
+            continue;
+        }
+        let shape = input.dims.as_ref().ok_or_else(|| OnnxError::Unsupported {
+            node: input.name.clone(),
+            message: "Inputs need a known rank".to_string(),
+        })?;
+        let mut dims = vec![];
+        for (i, d) in shape.iter().enumerate() {
+            dims.push(match d {
+                Dim::Value(n) if *n > 0 => Expression::from(*n as usize),
+                Dim::Param(p) => importer.dyn_dim(p).into(),
+                _ => importer.dyn_dim(&format!("{}_{i}", input.name)).into(),
+            });
+        }
+        let mut tensor = importer.cx.named_tensor::<()>(&input.name);
+        *tensor.shape = ShapeTracker::new(&dims);
+        importer.values.insert(input.name.clone(), tensor);
+        inputs.push((input.name.clone(), tensor));
+    }
+    for node in &model.graph.nodes {
+        importer.node(node)?;
+    }
+    let mut outputs = vec![];
+    for output in &model.graph.outputs {
+        let value = importer.value(&output.name)?.retrieve();
+        outputs.push((output.name.clone(), value));
+    }
+    Ok(OnnxModel {
+        inputs,
+        outputs,
+        dyn_dims: importer.dyn_dims,
+        opset: model.opset,
+    })
+}
+
+/// An integer tensor known at import time. Elements can be symbolic, like in the shape of a tensor with dynamic dims
+#[derive(Debug, Clone)]
+struct Known {
+    dims: Vec<usize>,
+    values: Vec<BigExpression>,
+}
+
+impl Known {
+    fn from_ints(dims: Vec<usize>, ints: &[i64]) -> Self {
+        Self {
+            dims,
+            values: ints.iter().map(|i| int_expr(*i)).collect(),
+        }
+    }
+
+    fn ints(&self) -> Option<Vec<i64>> {
+        self.values
+            .iter()
+            .map(|v| v.to_usize().map(|v| v as i32 as i64))
+            .collect()
+    }
+}
+
+/// Integers are clamped to the range of expressions, which also maps "to the end" slice bounds onto i32::MAX
+fn int_expr(i: i64) -> BigExpression {
+    (i.clamp(i32::MIN as i64, i32::MAX as i64) as i32).into()
+}
+
+struct Importer<'a> {
+    cx: &'a mut Graph,
+    opset: i64,
+    values: FxHashMap<String, Value>,
+    known: FxHashMap<String, Known>,
+    /// Data of small float constants, for ops that take scalar arguments as inputs
+    floats: FxHashMap<String, Vec<f32>>,
+    dyn_dims: Vec<(String, Symbol)>,
+}
+
+/// The name to report errors under
+fn node_name(node: &NodeProto) -> String {
+    if node.name.is_empty() {
+        node.op_type.clone()
+    } else {
+        format!("{} ({})", node.name, node.op_type)
+    }
+}
+
+fn unsupported(node: &NodeProto, message: &str) -> OnnxError {
+    OnnxError::Unsupported {
+        node: node_name(node),
+        message: message.to_string(),
+    }
+}
+
+fn attr_int(node: &NodeProto, name: &str, default: i64) -> i64 {
+    match node.attribute(name) {
+        Some(Attribute::Int(i)) => *i,
+        _ => default,
+    }
+}
+
+fn attr_float(node: &NodeProto, name: &str, default: f32) -> f32 {
+    match node.attribute(name) {
+        Some(Attribute::Float(f)) => *f,
+        _ => default,
+    }
+}
+
+fn attr_ints(node: &NodeProto, name: &str) -> Option<Vec<i64>> {
+    match node.attribute(name) {
+        Some(Attribute::Ints(i)) => Some(i.clone()),
+        _ => None,
+    }
+}
+
+/// Resolve a possibly negative axis
+fn axis(node: &NodeProto, axis: i64, rank: usize) -> Result<usize, OnnxError> {
+    let a = if axis < 0 { axis + rank as i64 } else { axis };
+    if a < 0 || a >= rank as i64 {
+        return Err(unsupported(
+            node,
+            &format!("Axis {axis} is out of range for rank {rank}"),
+        ));
+    }
+    Ok(a as usize)
+}
+
+/// The type a `Cast` node rounds values through, or None if f32 already holds every value of the target type
+fn cast_dtype(node: &NodeProto) -> Result<Option<DType>, OnnxError> {
+    use proto::data_type;
+    match attr_int(node, "to", 0) as i32 {
+        data_type::FLOAT | data_type::DOUBLE => Ok(None),
+        data_type::FLOAT16 => Ok(Some(DType::F16)),
+        data_type::BFLOAT16 => Ok(Some(DType::BF16)),
+        data_type::INT32 => Ok(Some(DType::I32)),
+        data_type::INT64 => Ok(Some(DType::I64)),
+        data_type::BOOL => Ok(Some(DType::Bool)),
+        to => Err(unsupported(node, &format!("Can't cast to data type {to}"))),
+    }
+}
+
+/// The product of dims, where no dims multiply to 1
+fn product(dims: &[Expression]) -> Expression {
+    dims.iter()
+        .fold(BigExpression::from(1), |p, d| p * *d)
+        .into()
+}
+
+impl Importer<'_> {
+    /// The dynamic dim for a symbolic ONNX dim, which shares its name
+    fn dyn_dim(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol::new(name);
+        if !self.dyn_dims.iter().any(|(n, _)| n == name) {
+            self.dyn_dims.push((name.to_string(), symbol));
+        }
+        symbol
+    }
+
+    fn initializer(&mut self, init: &TensorProto) -> Result<(), OnnxError> {
+        let dims = init.dims.iter().map(|d| *d as usize).collect::<Vec<_>>();
+        if init.is_float() {
+            let data = init.to_f32()?;
+            if data.len() <= 16 {
+                self.floats.insert(init.name.clone(), data.clone());
+            }
+            let mut tensor = self.cx.named_tensor::<()>(&init.name).set(data);
+            *tensor.shape =
+                ShapeTracker::new(&dims.iter().map(|d| (*d).into()).collect::<Vec<_>>());
+            self.values.insert(init.name.clone(), tensor);
+        } else {
+            self.known
+                .insert(init.name.clone(), Known::from_ints(dims, &init.to_i64()?));
+        }
+        Ok(())
+    }
+
+    /// Get a value on the graph, loading known integer tensors as float tensors if needed
+    fn value(&mut self, name: &str) -> Result<Value, OnnxError> {
+        if let Some(v) = self.values.get(name) {
+            return Ok(*v);
+        }
+        let known = self
+            .known
+            .get(name)
+            .cloned()
+            .ok_or_else(|| OnnxError::MissingValue(name.to_string()))?;
+        let dims = known
+            .dims
+            .iter()
+            .map(|d| (*d).into())
+            .collect::<Vec<Expression>>();
+        let v = match known.ints() {
+            Some(ints) => {
+                let mut t = self
+                    .cx
+                    .named_tensor::<()>(name)
+                    .set(ints.iter().map(|i| *i as f32).collect::<Vec<_>>());
+                *t.shape = ShapeTracker::new(&dims);
+                t
+            }
+            None => {
+                // Symbolic values are evaluated at runtime
+                let elements = known
+                    .values
+                    .into_iter()
+                    .map(|e| {
+                        let id = self.cx.constant_expr(e).id;
+                        ops::unsqueeze(GraphTensor::from_id(id, ShapeTracker::new(&[]), self.cx), 0)
+                    })
+                    .collect::<Vec<_>>();
+                ops::reshape(ops::concat(&elements, 0), &dims)
+            }
+        };
+        self.values.insert(name.to_string(), v);
+        Ok(v)
+    }
+
+    /// A required input
+    fn input(&mut self, node: &NodeProto, i: usize) -> Result<Value, OnnxError> {
+        let name = node
+            .input(i)
+            .ok_or_else(|| unsupported(node, &format!("Missing input {i}")))?;
+        self.value(name)
+    }
+
+    fn known_input(&self, node: &NodeProto, i: usize) -> Option<&Known> {
+        node.input(i).and_then(|n| self.known.get(n))
+    }
+
+    /// An input that must be a constant list of integers
+    fn ints_input(&self, node: &NodeProto, i: usize) -> Result<Option<Vec<i64>>, OnnxError> {
+        if node.input(i).is_none() {
+            return Ok(None);
+        }
+        self.known_input(node, i)
+            .and_then(Known::ints)
+            .map(Some)
+            .ok_or_else(|| unsupported(node, &format!("Input {i} must be a constant")))
+    }
+
+    /// An input that must be a constant float scalar
+    fn float_input(&self, node: &NodeProto, i: usize) -> Result<Option<f32>, OnnxError> {
+        let Some(name) = node.input(i) else {
+            return Ok(None);
+        };
+        let scalar = match (
+            self.floats.get(name),
+            self.known.get(name).and_then(Known::ints),
+        ) {
+            (Some(f), _) if f.len() == 1 => Some(f[0]),
+            (_, Some(i)) if i.len() == 1 => Some(i[0] as f32),
+            _ => None,
+        };
+        scalar
+            .map(Some)
+            .ok_or_else(|| unsupported(node, &format!("Input {i} must be a constant scalar")))
+    }
+
+    fn output(&mut self, node: &NodeProto, v: Value) {
+        self.values.insert(node.outputs[0].clone(), v);
+    }
+
+    fn node(&mut self, node: &NodeProto) -> Result<(), OnnxError> {
+        if let Some(known) = self.fold(node)? {
+            self.known.insert(node.outputs[0].clone(), known);
+            return Ok(());
+        }
+        let out = match node.op_type.as_str() {
+            "Identity" | "Dropout" => self.input(node, 0)?,
+            "Cast" => {
+                let a = self.input(node, 0)?;
+                match cast_dtype(node)? {
+                    Some(dtype) => a.cast(dtype).cast(DType::F32),
+                    None => a,
+                }
+            }
+            "Constant" => {
+                let Some(Attribute::Tensor(t)) = node.attribute("value") else {
+                    return Err(unsupported(node, "Only tensor constants are supported"));
+                };
+                let mut t = t.clone();
+                t.name = node.outputs[0].clone();
+                return self.initializer(&t);
+            }
+            "Add" | "Sub" | "Mul" | "Div" | "Max" | "Min" | "Less" | "Greater" | "Equal" => {
+                let mut r = self.input(node, 0)?;
+                for i in 1..node.inputs.len() {
+                    let b = self.input(node, i)?;
+                    r = match node.op_type.as_str() {
+                        "Add" => ops::add(r, b),
+                        "Sub" => ops::sub(r, b),
+                        "Mul" => ops::mul(r, b),
+                        "Div" => ops::div(r, b),
+                        "Max" => ops::max(r, b),
+                        "Min" => ops::min(r, b),
+                        "Less" => ops::less_than(r, b),
+                        "Greater" => ops::less_than(b, r),
+                        _ => ops::equals(r, b),
+                    };
+                }
+                r
+            }
+            "Pow" => {
+                let a = self.input(node, 0)?;
+                match self.float_input(node, 1) {
+                    Ok(Some(1.)) => a,
+                    Ok(Some(2.)) => ops::mul(a, a),
+                    Ok(Some(3.)) => ops::mul(ops::mul(a, a), a),
+                    Ok(Some(0.5)) => ops::sqrt(a),
+                    Ok(Some(-1.)) => ops::recip(a),
+                    // Approximate for negative bases, same as the hl pow
+                    Ok(Some(e)) => ops::exp(ops::mul_f32(ops::ln(ops::abs(a)), e)),
+                    _ => ops::exp(ops::mul(ops::ln(a), self.input(node, 1)?)),
+                }
+            }
+            "Sqrt" => ops::sqrt(self.input(node, 0)?),
+            "Exp" => ops::exp(self.input(node, 0)?),
+            "Log" => ops::ln(self.input(node, 0)?),
+            "Neg" => ops::neg(self.input(node, 0)?),
+            "Reciprocal" => ops::recip(self.input(node, 0)?),
+            "Abs" => ops::abs(self.input(node, 0)?),
+            "Relu" => ops::relu(self.input(node, 0)?),
+            "Sigmoid" => ops::sigmoid(self.input(node, 0)?),
+            "Tanh" => ops::tanh(self.input(node, 0)?),
+            "Sin" => ops::sin(self.input(node, 0)?),
+            "Cos" => ops::cos(self.input(node, 0)?),
+            "LeakyRelu" => {
+                let a = self.input(node, 0)?;
+                let alpha = attr_float(node, "alpha", 0.01);
+                ops::sub(ops::relu(a), ops::relu(ops::mul_f32(a, -alpha)))
+            }
+            "Clip" => {
+                let mut a = self.input(node, 0)?;
+                let (min, max) = if self.opset < 11 {
+                    (
+                        Some(attr_float(node, "min", f32::MIN)),
+                        Some(attr_float(node, "max", f32::MAX)),
+                    )
+                } else {
+                    (self.float_input(node, 1)?, self.float_input(node, 2)?)
+                };
+                if let Some(min) = min {
+                    a = ops::max(a, ops::constant(self.cx, min, &[]));
+                }
+                if let Some(max) = max {
+                    a = ops::min(a, ops::constant(self.cx, max, &[]));
+                }
+                a
+            }
+            "Where" => {
+                let cond = self.input(node, 0)?;
+                let (a, b) = (self.input(node, 1)?, self.input(node, 2)?);
+                ops::add(
+                    ops::mul(cond, a),
+                    ops::mul(ops::add_f32(ops::neg(cond), 1.), b),
+                )
+            }
+            "MatMul" => ops::matmul(self.input(node, 0)?, self.input(node, 1)?),
+            "Gemm" => {
+                let (mut a, mut b) = (self.input(node, 0)?, self.input(node, 1)?);
+                if attr_int(node, "transA", 0) != 0 {
+                    a = ops::permute(a, &[1, 0]);
+                }
+                if attr_int(node, "transB", 0) != 0 {
+                    b = ops::permute(b, &[1, 0]);
+                }
+                let mut r = ops::matmul(a, b);
+                let alpha = attr_float(node, "alpha", 1.);
+                if alpha != 1. {
+                    r = ops::mul_f32(r, alpha);
+                }
+                if node.input(2).is_some() {
+                    let mut c = self.input(node, 2)?;
+                    let beta = attr_float(node, "beta", 1.);
+                    if beta != 1. {
+                        c = ops::mul_f32(c, beta);
+                    }
+                    r = ops::add(r, c);
+                }
+                r
+            }
+            "Softmax" | "LogSoftmax" => {
+                let a = self.input(node, 0)?;
+                let rank = a.shape.len();
+                let default_axis = if self.opset < 13 { 1 } else { -1 };
+                let ax = axis(node, attr_int(node, "axis", default_axis), rank)?;
+                let r = if self.opset < 13 && ax != rank - 1 {
+                    // Older opsets flatten everything after the axis into one dim
+                    let d = dims(&a);
+                    let flat = ops::reshape(a, &[product(&d[..ax]), product(&d[ax..])]);
+                    ops::reshape(ops::softmax(flat, 1), &d)
+                } else {
+                    ops::softmax(a, ax)
+                };
+                if node.op_type == "LogSoftmax" {
+                    ops::ln(r)
+                } else {
+                    r
+                }
+            }
+            "LayerNormalization" => {
+                let a = self.input(node, 0)?;
+                let ax = axis(node, attr_int(node, "axis", -1), a.shape.len())?;
+                let axes = (ax..a.shape.len()).collect::<Vec<_>>();
+                let epsilon = attr_float(node, "epsilon", 1e-5);
+                let centered = ops::sub(a, ops::mean_reduce(a, &axes, true));
+                let var = ops::mean_reduce(ops::mul(centered, centered), &axes, true);
+                let mut r = ops::mul(centered, ops::recip(ops::sqrt(ops::add_f32(var, epsilon))));
+                r = ops::mul(r, self.input(node, 1)?);
+                if node.input(2).is_some() {
+                    r = ops::add(r, self.input(node, 2)?);
+                }
+                r
+            }
+            "ReduceMean" | "ReduceSum" | "ReduceMax" => {
+                let a = self.input(node, 0)?;
+                let axes_in_input =
+                    (node.op_type == "ReduceSum" && self.opset >= 13) || self.opset >= 18;
+                let axes = if axes_in_input {
+                    self.ints_input(node, 1)?
+                } else {
+                    attr_ints(node, "axes")
+                };
+                let axes = match axes {
+                    Some(axes) if !axes.is_empty() => axes
+                        .iter()
+                        .map(|i| axis(node, *i, a.shape.len()))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    _ if attr_int(node, "noop_with_empty_axes", 0) != 0 => {
+                        self.output(node, a);
+                        return Ok(());
+                    }
+                    _ => (0..a.shape.len()).collect(),
+                };
+                let keep_dims = attr_int(node, "keepdims", 1) != 0;
+                match node.op_type.as_str() {
+                    "ReduceMean" => ops::mean_reduce(a, &axes, keep_dims),
+                    "ReduceSum" => ops::sum_reduce(a, &axes, keep_dims),
+                    _ => ops::max_reduce(a, &axes, keep_dims),
+                }
+            }
+            "GlobalAveragePool" => {
+                let a = self.input(node, 0)?;
+                ops::mean_reduce(a, &(2..a.shape.len()).collect::<Vec<_>>(), true)
+            }
+            "Gather" => {
+                let data = self.input(node, 0)?;
+                let ax = axis(node, attr_int(node, "axis", 0), data.shape.len())?;
+                let dim = dims(&data)[ax];
+                match self.known_input(node, 1).cloned() {
+                    // A single known index is just a slice
+                    Some(Known { dims: d, values }) if d.is_empty() => {
+                        let mut index = values[0].clone();
+                        if index.to_usize().map(|i| (i as i32) < 0).unwrap_or_default() {
+                            index = index + dim;
+                        }
+                        let index = Expression::from(index);
+                        let mut ranges = vec![(0.into(), i32::MAX.into()); data.shape.len()];
+                        ranges[ax] = (index, index + 1);
+                        ops::squeeze(ops::slice(data, &ranges), ax)
+                    }
+                    _ => ops::gather(data, self.input(node, 1)?, ax),
+                }
+            }
+            "Conv" => self.conv(node)?,
+            "Reshape" => {
+                let a = self.input(node, 0)?;
+                let shape = self
+                    .known_input(node, 1)
+                    .cloned()
+                    .ok_or_else(|| unsupported(node, "The shape must be a constant"))?;
+                let allow_zero = attr_int(node, "allowzero", 0) != 0;
+                let in_dims = dims(&a);
+                let mut out = vec![];
+                for (i, d) in shape.values.into_iter().enumerate() {
+                    out.push(match d.to_usize().map(|d| d as i32) {
+                        Some(0) if !allow_zero => Some(*in_dims.get(i).ok_or_else(|| {
+                            unsupported(
+                                node,
+                                &format!("Dim {i} copies a dim the input doesn't have"),
+                            )
+                        })?),
+                        Some(-1) => None,
+                        Some(d) if d < 0 => {
+                            return Err(unsupported(node, &format!("Invalid dim {d}")))
+                        }
+                        _ => Some(d.into()),
+                    });
+                }
+                if out.iter().filter(|d| d.is_none()).count() > 1 {
+                    return Err(unsupported(node, "Only one dim can be inferred"));
+                }
+                if let Some(infer) = out.iter().position(Option::is_none) {
+                    // Cancel out dims kept as they are, so symbolic dims don't build up long expressions
+                    let mut remaining = in_dims.clone();
+                    let mut known = vec![];
+                    for d in out.iter().flatten() {
+                        match remaining.iter().position(|r| r == d) {
+                            Some(i) => {
+                                remaining.remove(i);
+                            }
+                            None => known.push(*d),
+                        }
+                    }
+                    out[infer] = Some(
+                        (BigExpression::from(product(&remaining))
+                            / BigExpression::from(product(&known)))
+                        .into(),
+                    );
+                }
+                ops::reshape(a, &out.into_iter().flatten().collect::<Vec<_>>())
+            }
+            "Flatten" => {
+                let a = self.input(node, 0)?;
+                let d = dims(&a);
+                let ax = match attr_int(node, "axis", 1) {
+                    a if a == d.len() as i64 => d.len(),
+                    a => axis(node, a, d.len())?,
+                };
+                ops::reshape(a, &[product(&d[..ax]), product(&d[ax..])])
+            }
+            "Transpose" => {
+                let a = self.input(node, 0)?;
+                let rank = a.shape.len();
+                let perm = match attr_ints(node, "perm") {
+                    Some(p) => p
+                        .iter()
+                        .map(|i| axis(node, *i, rank))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    None => (0..rank).rev().collect(),
+                };
+                ops::permute(a, &perm)
+            }
+            "Squeeze" => {
+                let mut a = self.input(node, 0)?;
+                let d = dims(&a);
+                let axes = match self.axes(node)? {
+                    Some(axes) => axes
+                        .iter()
+                        .map(|i| axis(node, *i, d.len()))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    None => (0..d.len()).filter(|i| d[*i] == 1.into()).collect(),
+                };
+                let mut axes = axes;
+                axes.sort();
+                for ax in axes.into_iter().rev() {
+                    a = ops::squeeze(a, ax);
+                }
+                a
+            }
+            "Unsqueeze" => {
+                let mut a = self.input(node, 0)?;
+                let axes = self
+                    .axes(node)?
+                    .ok_or_else(|| unsupported(node, "Missing axes"))?;
+                let rank = a.shape.len() + axes.len();
+                let mut axes = axes
+                    .iter()
+                    .map(|i| axis(node, *i, rank))
+                    .collect::<Result<Vec<_>, _>>()?;
+                axes.sort();
+                for ax in axes {
+                    a = ops::unsqueeze(a, ax);
+                }
+                a
+            }
+            "Slice" => {
+                let a = self.input(node, 0)?;
+                let (starts, ends, axes, steps) = if self.opset < 10 {
+                    (
+                        attr_ints(node, "starts"),
+                        attr_ints(node, "ends"),
+                        attr_ints(node, "axes"),
+                        None,
+                    )
+                } else {
+                    (
+                        self.ints_input(node, 1)?,
+                        self.ints_input(node, 2)?,
+                        self.ints_input(node, 3)?,
+                        self.ints_input(node, 4)?,
+                    )
+                };
+                let (starts, ends) = starts
+                    .zip(ends)
+                    .ok_or_else(|| unsupported(node, "Missing starts or ends"))?;
+                if steps.unwrap_or_default().iter().any(|s| *s != 1) {
+                    return Err(unsupported(node, "Only steps of 1 are supported"));
+                }
+                let d = dims(&a);
+                let axes = axes.unwrap_or_else(|| (0..starts.len() as i64).collect());
+                let mut ranges = vec![(0.into(), i32::MAX.into()); d.len()];
+                for ((ax, start), end) in axes.iter().zip(starts).zip(ends) {
+                    let ax = axis(node, *ax, d.len())?;
+                    // Negative bounds count from the end
+                    let bound = |b: i64| -> Expression {
+                        if b < 0 {
+                            (d[ax] + Expression::from(int_expr(b))).max(0)
+                        } else {
+                            int_expr(b).into()
+                        }
+                    };
+                    ranges[ax] = (bound(start), bound(end));
+                }
+                ops::slice(a, &ranges)
+            }
+            "Concat" => {
+                let values = (0..node.inputs.len())
+                    .map(|i| self.input(node, i))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let ax = axis(node, attr_int(node, "axis", 0), values[0].shape.len())?;
+                ops::concat(&values, ax)
+            }
+            "Expand" => {
+                let a = self.input(node, 0)?;
+                let shape = self
+                    .known_input(node, 1)
+                    .cloned()
+                    .ok_or_else(|| unsupported(node, "The shape must be a constant"))?;
+                let shape = shape
+                    .values
+                    .into_iter()
+                    .map(Expression::from)
+                    .collect::<Vec<_>>();
+                ops::expand(a, &shape)
+            }
+            _ => return Err(OnnxError::UnsupportedOps(vec![node.op_type.clone()])),
+        };
+        self.output(node, out);
+        Ok(())
+    }
+
+    /// Axes given as an attribute in older opsets and as an input in newer ones
+    fn axes(&self, node: &NodeProto) -> Result<Option<Vec<i64>>, OnnxError> {
+        if self.opset < 13 {
+            Ok(attr_ints(node, "axes"))
+        } else {
+            self.ints_input(node, 1)
+        }
+    }
+
+    /// Lower a convolution over any number of spatial dims to pooling and a matmul
+    fn conv(&mut self, node: &NodeProto) -> Result<Value, OnnxError> {
+        let (mut x, w) = (self.input(node, 0)?, self.input(node, 1)?);
+        let w_dims = dims(&w);
+        if w_dims.len() < 3 || w_dims.len() != x.shape.len() {
+            return Err(unsupported(
+                node,
+                "Weights need a spatial dim and the same rank as the input",
+            ));
+        }
+        let spatial = w_dims.len() - 2;
+        let kernel = w_dims[2..]
+            .iter()
+            .map(|d| d.to_usize())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| unsupported(node, "Kernel size must be known"))?;
+        if attr_int(node, "group", 1) != 1 {
+            return Err(unsupported(node, "Grouped convolutions aren't supported"));
+        }
+        if attr_ints(node, "dilations").is_some_and(|d| d.iter().any(|d| *d != 1)) {
+            return Err(unsupported(node, "Dilated convolutions aren't supported"));
+        }
+        if matches!(node.attribute("auto_pad"), Some(Attribute::String(p)) if p != "NOTSET") {
+            return Err(unsupported(node, "Only explicit padding is supported"));
+        }
+        let strides = attr_ints(node, "strides").unwrap_or_else(|| vec![1; spatial]);
+        if strides.len() != spatial || strides.iter().any(|s| *s < 1) {
+            return Err(unsupported(
+                node,
+                "Expected a positive stride per spatial dim",
+            ));
+        }
+        let pads = attr_ints(node, "pads");
+        if pads.as_ref().is_some_and(|p| p.len() != 2 * spatial) {
+            return Err(unsupported(
+                node,
+                "Expected a start and end pad per spatial dim",
+            ));
+        }
+        if let Some(pads) = pads.filter(|p| p.iter().any(|p| *p != 0)) {
+            let mut ranges = vec![(0.into(), 0.into()); 2];
+            for i in 0..spatial {
+                ranges.push((int_expr(pads[i]).into(), int_expr(pads[i + spatial]).into()));
+            }
+            x = ops::pad(x, &ranges);
+        }
+
+        // Pool each spatial dim: [N, C, S...] -> [N, C, W1, K1, W2, K2, ...]
+        for (i, k) in kernel.iter().enumerate() {
+            let rank = x.shape.len();
+            let mut perm = (0..rank).collect::<Vec<_>>();
+            let pooled = perm.remove(2);
+            perm.push(pooled);
+            x = ops::permute(x, &perm).contiguous();
+            x = x.pool_last_dim::<()>((*k).into(), (strides[i] as usize).into(), 0);
+        }
+        // [N, W..., C, K...] -> [N, prod(W), C * prod(K)]
+        let d = dims(&x);
+        let windows = (0..spatial).map(|i| d[2 + 2 * i]).collect::<Vec<_>>();
+        let mut perm = vec![0];
+        perm.extend((0..spatial).map(|i| 2 + 2 * i));
+        perm.push(1);
+        perm.extend((0..spatial).map(|i| 3 + 2 * i));
+        x = ops::permute(x, &perm);
+        let patch = product(&w_dims[1..]);
+        x = ops::reshape(x, &[d[0], product(&windows), patch]);
+
+        // [N, prod(W), C * prod(K)] x [C * prod(K), O] -> [N, O, W...]
+        let w = ops::permute(ops::reshape(w, &[w_dims[0], patch]), &[1, 0]);
+        let mut out_dims = vec![d[0], w_dims[0]];
+        out_dims.extend(windows);
+        let mut r = ops::reshape(ops::permute(ops::matmul(x, w), &[0, 2, 1]), &out_dims);
+        if node.input(2).is_some() {
+            let mut bias = self.input(node, 2)?;
+            for _ in 0..spatial {
+                bias = ops::unsqueeze(bias, bias.shape.len());
+            }
+            r = ops::add(r, bias);
+        }
+        Ok(r)
+    }
+
+    /// Evaluate integer ops on known tensors at import time
+    fn fold(&mut self, node: &NodeProto) -> Result<Option<Known>, OnnxError> {
+        let op = node.op_type.as_str();
+        if op == "Constant" {
+            return Ok(match node.attribute("value") {
+                Some(Attribute::Tensor(t)) if !t.is_float() => Some(Known::from_ints(
+                    t.dims.iter().map(|d| *d as usize).collect(),
+                    &t.to_i64()?,
+                )),
+                _ => match (node.attribute("value_int"), node.attribute("value_ints")) {
+                    (Some(Attribute::Int(i)), _) => Some(Known::from_ints(vec![], &[*i])),
+                    (_, Some(Attribute::Ints(i))) => Some(Known::from_ints(vec![i.len()], i)),
+                    _ => None,
+                },
+            });
+        }
+        if op == "Shape" {
+            let Some(name) = node.input(0) else {
+                return Ok(None);
+            };
+            let mut shape = match self.known.get(name) {
+                Some(k) => k.dims.iter().map(|d| BigExpression::from(*d)).collect(),
+                None => self.value(name)?.shape.shape(),
+            };
+            let rank = shape.len() as i64;
+            let bound = |b: i64| (if b < 0 { b + rank } else { b }).clamp(0, rank) as usize;
+            let start = bound(attr_int(node, "start", 0));
+            let end = bound(attr_int(node, "end", rank)).max(start);
+            shape = shape[start..end].to_vec();
+            return Ok(Some(Known {
+                dims: vec![shape.len()],
+                values: shape,
+            }));
+        }
+        // Everything else folds only if all inputs are known
+        let inputs = node
+            .inputs
+            .iter()
+            .filter(|i| !i.is_empty())
+            .map(|i| self.known.get(i).cloned())
+            .collect::<Option<Vec<_>>>();
+        let Some(inputs) = inputs else {
+            return Ok(None);
+        };
+        let folded = match op {
+            "Identity" => Some(inputs[0].clone()),
+            // Known integers are already whole, so only a cast to bool changes them
+            "Cast" if cast_dtype(node)? != Some(DType::Bool) => Some(inputs[0].clone()),
+            "Add" | "Sub" | "Mul" | "Div" if inputs.len() == 2 => {
+                let (a, b) = (&inputs[0], &inputs[1]);
+                let n = a.values.len().max(b.values.len());
+                if a.values.len() != n && a.values.len() != 1
+                    || b.values.len() != n && b.values.len() != 1
+                {
+                    return Ok(None);
+                }
+                let values = (0..n)
+                    .map(|i| {
+                        let x = a.values[i % a.values.len()].clone();
+                        let y = b.values[i % b.values.len()].clone();
+                        match op {
+                            "Add" => x + y,
+                            "Sub" => x - y,
+                            "Mul" => x * y,
+                            _ => x / y,
+                        }
+                    })
+                    .collect();
+                let dims = if a.values.len() == n {
+                    a.dims.clone()
+                } else {
+                    b.dims.clone()
+                };
+                Some(Known { dims, values })
+            }
+            "Concat" if inputs.iter().all(|i| i.dims.len() == 1) => {
+                let values = inputs
+                    .iter()
+                    .flat_map(|i| i.values.clone())
+                    .collect::<Vec<_>>();
+                Some(Known {
+                    dims: vec![values.len()],
+                    values,
+                })
+            }
+            "Gather" if inputs[0].dims.len() == 1 => {
+                let n = inputs[0].values.len() as i64;
+                let Some(indexes) = inputs[1].ints() else {
+                    return Ok(None);
+                };
+                let values = indexes
+                    .iter()
+                    .map(|i| {
+                        let i = if *i < 0 { i + n } else { *i };
+                        inputs[0].values.get(i as usize).cloned()
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| unsupported(node, "Index out of range"))?;
+                Some(Known {
+                    dims: inputs[1].dims.clone(),
+                    values,
+                })
+            }
+            // Only scalars and vectors are used for shapes
+            "Unsqueeze" if inputs[0].dims.is_empty() => Some(Known {
+                dims: vec![1],
+                ..inputs[0].clone()
+            }),
+            "Squeeze" if inputs[0].values.len() == 1 => Some(Known {
+                dims: vec![],
+                ..inputs[0].clone()
+            }),
+            "Reshape" if inputs[1].values.len() == 1 => Some(Known {
+                dims: vec![inputs[0].values.len()],
+                ..inputs[0].clone()
+            }),
+            _ => None,
+        };
+        Ok(folded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{assert_close, random_vec};
+
+    // A small protobuf encoder to build test models with
+
+    fn varint(mut v: u64, out: &mut Vec<u8>) {
+        while v >= 0x80 {
+            out.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn int_field(field: u64, v: i64, out: &mut Vec<u8>) {
+        varint(field << 3, out);
+        varint(v as u64, out);
+    }
+
+    fn bytes_field(field: u64, data: &[u8], out: &mut Vec<u8>) {
+        varint(field << 3 | 2, out);
+        varint(data.len() as u64, out);
+        out.extend_from_slice(data);
+    }
+
+    fn float_tensor(name: &str, dims: &[i64], data: &[f32]) -> Vec<u8> {
+        let mut t = vec![];
+        for d in dims {
+            int_field(1, *d, &mut t);
+        }
+        int_field(2, 1, &mut t);
+        let packed = data
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        bytes_field(4, &packed, &mut t);
+        bytes_field(8, name.as_bytes(), &mut t);
+        t
+    }
+
+    fn int_tensor(name: &str, dims: &[i64], data: &[i64]) -> Vec<u8> {
+        let mut t = vec![];
+        for d in dims {
+            int_field(1, *d, &mut t);
+        }
+        int_field(2, 7, &mut t);
+        bytes_field(8, name.as_bytes(), &mut t);
+        let raw = data
+            .iter()
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        bytes_field(9, &raw, &mut t);
+        t
+    }
+
+    /// Dims are either sizes or symbolic names
+    fn value_info(name: &str, dims: &[Result<i64, &str>]) -> Vec<u8> {
+        let mut shape = vec![];
+        for d in dims {
+            let mut dim = vec![];
+            match d {
+                Ok(n) => int_field(1, *n, &mut dim),
+                Err(p) => bytes_field(2, p.as_bytes(), &mut dim),
+            }
+            bytes_field(1, &dim, &mut shape);
+        }
+        let mut tensor_type = vec![];
+        int_field(1, 1, &mut tensor_type);
+        bytes_field(2, &shape, &mut tensor_type);
+        let mut type_proto = vec![];
+        bytes_field(1, &tensor_type, &mut type_proto);
+        let mut info = vec![];
+        bytes_field(1, name.as_bytes(), &mut info);
+        bytes_field(2, &type_proto, &mut info);
+        info
+    }
+
+    fn attr_int(name: &str, v: i64) -> Vec<u8> {
+        let mut a = vec![];
+        bytes_field(1, name.as_bytes(), &mut a);
+        int_field(3, v, &mut a);
+        int_field(20, 2, &mut a);
+        a
+    }
+
+    fn attr_float(name: &str, v: f32) -> Vec<u8> {
+        let mut a = vec![];
+        bytes_field(1, name.as_bytes(), &mut a);
+        varint(2 << 3 | 5, &mut a);
+        a.extend_from_slice(&v.to_le_bytes());
+        int_field(20, 1, &mut a);
+        a
+    }
+
+    fn attr_ints(name: &str, v: &[i64]) -> Vec<u8> {
+        let mut a = vec![];
+        bytes_field(1, name.as_bytes(), &mut a);
+        let mut packed = vec![];
+        for i in v {
+            varint(*i as u64, &mut packed);
+        }
+        bytes_field(8, &packed, &mut a);
+        int_field(20, 7, &mut a);
+        a
+    }
+
+    fn node(op: &str, inputs: &[&str], outputs: &[&str], attrs: &[Vec<u8>]) -> Vec<u8> {
+        let mut n = vec![];
+        for i in inputs {
+            bytes_field(1, i.as_bytes(), &mut n);
+        }
+        for o in outputs {
+            bytes_field(2, o.as_bytes(), &mut n);
+        }
+        bytes_field(4, op.as_bytes(), &mut n);
+        for a in attrs {
+            bytes_field(5, a, &mut n);
+        }
+        n
+    }
+
+    fn model(
+        nodes: &[Vec<u8>],
+        initializers: &[Vec<u8>],
+        inputs: &[Vec<u8>],
+        outputs: &[&str],
+    ) -> Vec<u8> {
+        let mut graph = vec![];
+        for n in nodes {
+            bytes_field(1, n, &mut graph);
+        }
+        for i in initializers {
+            bytes_field(5, i, &mut graph);
+        }
+        for i in inputs {
+            bytes_field(11, i, &mut graph);
+        }
+        for o in outputs {
+            bytes_field(12, &value_info(o, &[]), &mut graph);
+        }
+        let mut opset = vec![];
+        int_field(2, 17, &mut opset);
+        let mut m = vec![];
+        int_field(1, 8, &mut m);
+        bytes_field(7, &graph, &mut m);
+        bytes_field(8, &opset, &mut m);
+        m
+    }
+
+    /// Row-major matmul of [m, k] x [k, n]
+    fn matmul(a: &[f32], b: &[f32], m: usize, k: usize, n: usize) -> Vec<f32> {
+        let mut c = vec![0.; m * n];
+        for i in 0..m {
+            for j in 0..n {
+                c[i * n + j] = (0..k).map(|x| a[i * k + x] * b[x * n + j]).sum();
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn test_import_mlp() {
+        let (w1, b1, w2, b2) = (random_vec(32), random_vec(8), random_vec(24), random_vec(3));
+        let bytes = model(
+            &[
+                node("MatMul", &["x", "w1"], &["h"], &[]),
+                node("Add", &["h", "b1"], &["h1"], &[]),
+                node("Relu", &["h1"], &["h2"], &[]),
+                node(
+                    "Gemm",
+                    &["h2", "w2", "b2"],
+                    &["logits"],
+                    &[attr_int("transB", 1)],
+                ),
+                node("Softmax", &["logits"], &["probs"], &[attr_int("axis", -1)]),
+            ],
+            &[
+                float_tensor("w1", &[4, 8], &w1),
+                float_tensor("b1", &[8], &b1),
+                float_tensor("w2", &[3, 8], &w2),
+                float_tensor("b2", &[3], &b2),
+            ],
+            &[value_info("x", &[Err("batch"), Ok(4)])],
+            &["probs"],
+        );
+        let mut cx = Graph::new();
+        let model = import_onnx(&bytes, &mut cx).unwrap();
+        assert_eq!(model.dyn_dims, vec![("batch".to_string(), "batch".into())]);
+
+        let mut probs = model.output("probs").unwrap();
+        cx.compile(<(GenericCompiler, CPUCompiler)>::default(), &mut probs);
+
+        let x = random_vec(12);
+        model.set_input("x", x.clone(), &[3, 4]);
+        cx.execute();
+
+        let mut h = matmul(&x, &w1, 3, 4, 8);
+        for (i, v) in h.iter_mut().enumerate() {
+            *v = (*v + b1[i % 8]).max(0.);
+        }
+        let mut w2_t = vec![0.; 24];
+        for i in 0..3 {
+            for j in 0..8 {
+                w2_t[j * 3 + i] = w2[i * 8 + j];
+            }
+        }
+        let logits = matmul(&h, &w2_t, 3, 8, 3);
+        let mut expected = vec![];
+        for row in logits.chunks(3) {
+            let exps = row
+                .iter()
+                .enumerate()
+                .map(|(i, l)| (l + b2[i]).exp())
+                .collect::<Vec<_>>();
+            let sum = exps.iter().sum::<f32>();
+            expected.extend(exps.iter().map(|e| e / sum));
+        }
+        assert_close(&probs.data(), &expected);
+    }
+
+    #[test]
+    fn test_import_movement() {
+        // The reshape target is computed from the input's (symbolic) shape, like exported models do
+        let bytes = model(
+            &[
+                node(
+                    "Transpose",
+                    &["x"],
+                    &["t"],
+                    &[attr_ints("perm", &[0, 2, 1])],
+                ),
+                node("Shape", &["t"], &["shape"], &[]),
+                node(
+                    "Gather",
+                    &["shape", "zero"],
+                    &["batch"],
+                    &[attr_int("axis", 0)],
+                ),
+                node("Unsqueeze", &["batch", "zero_axis"], &["batch_1"], &[]),
+                node(
+                    "Concat",
+                    &["batch_1", "minus_one"],
+                    &["target"],
+                    &[attr_int("axis", 0)],
+                ),
+                node("Reshape", &["t", "target"], &["r"], &[]),
+                node("Slice", &["r", "starts", "ends", "one_axis"], &["s"], &[]),
+                node("Concat", &["s", "s"], &["c"], &[attr_int("axis", 1)]),
+                node(
+                    "ReduceMax",
+                    &["c"],
+                    &["m"],
+                    &[attr_ints("axes", &[1]), attr_int("keepdims", 0)],
+                ),
+                node("Neg", &["m"], &["out"], &[]),
+            ],
+            &[
+                int_tensor("zero", &[], &[0]),
+                int_tensor("zero_axis", &[1], &[0]),
+                int_tensor("minus_one", &[1], &[-1]),
+                int_tensor("starts", &[1], &[2]),
+                int_tensor("ends", &[1], &[-1]),
+                int_tensor("one_axis", &[1], &[1]),
+            ],
+            &[value_info("x", &[Err("batch"), Ok(3), Ok(4)])],
+            &["c", "out"],
+        );
+        let mut cx = Graph::new();
+        let model = import_onnx(&bytes, &mut cx).unwrap();
+        let x = random_vec(24);
+        model.set_input("x", x.clone(), &[2, 3, 4]);
+        cx.execute();
+
+        let mut expected_c = vec![];
+        let mut expected_out = vec![];
+        for b in 0..2 {
+            // Transposed row, flattened
+            let mut row = vec![];
+            for j in 0..4 {
+                for i in 0..3 {
+                    row.push(x[b * 12 + i * 4 + j]);
+                }
+            }
+            let sliced = row[2..11].to_vec();
+            expected_c.extend(sliced.iter().chain(&sliced));
+            expected_out.push(-sliced.iter().cloned().fold(f32::MIN, f32::max));
+        }
+        assert_close(&model.output("c").unwrap().data(), &expected_c);
+        assert_close(&model.output("out").unwrap().data(), &expected_out);
+    }
+
+    #[test]
+    fn test_import_high_rank() {
+        // Rank 11 input, unsqueezed to rank 12, so every op works on spilled shapes
+        let mut x_dims = [Ok(1); MAX_DIMS + 1];
+        x_dims[0] = Ok(2);
+        x_dims[2] = Ok(3);
+        x_dims[MAX_DIMS] = Ok(2);
+        let mut expand = vec![1; MAX_DIMS + 2];
+        expand[0] = 2;
+        expand[MAX_DIMS - 2] = 3;
+        expand[MAX_DIMS - 1] = 2;
+        expand[MAX_DIMS] = 2;
+        let bytes = model(
+            &[
+                node("Unsqueeze", &["x", "zero_axis"], &["u"], &[]),
+                node("Transpose", &["u"], &["t"], &[]),
+                node("Expand", &["t", "expand"], &["e"], &[]),
+                node("Reshape", &["e", "target"], &["out"], &[]),
+            ],
+            &[
+                int_tensor("zero_axis", &[1], &[0]),
+                int_tensor("expand", &[expand.len() as i64], &expand),
+                int_tensor("target", &[2], &[2, -1]),
+            ],
+            &[value_info("x", &x_dims)],
+            &["out"],
+        );
+        let mut cx = Graph::new();
+        let model = import_onnx(&bytes, &mut cx).unwrap();
+        let x = random_vec(12);
+        let mut in_dims = vec![1; MAX_DIMS + 1];
+        in_dims[0] = 2;
+        in_dims[2] = 3;
+        in_dims[MAX_DIMS] = 2;
+        model.set_input("x", x.clone(), &in_dims);
+        cx.execute();
+
+        // The transposed dims are (a, 1.., c, expanded, b, 1)
+        let mut expected = vec![];
+        for a in 0..2 {
+            for c in 0..3 {
+                for _ in 0..2 {
+                    for b in 0..2 {
+                        expected.push(x[b * 6 + c * 2 + a]);
+                    }
+                }
+            }
+        }
+        assert_close(&model.output("out").unwrap().data(), &expected);
+    }
+
+    #[test]
+    fn test_import_conv() {
+        let (x, w, b) = (random_vec(50), random_vec(54), random_vec(3));
+        let bytes = model(
+            &[node(
+                "Conv",
+                &["x", "w", "b"],
+                &["y"],
+                &[
+                    attr_ints("kernel_shape", &[3, 3]),
+                    attr_ints("pads", &[1, 0, 1, 0]),
+                    attr_ints("strides", &[2, 1]),
+                ],
+            )],
+            &[
+                float_tensor("w", &[3, 2, 3, 3], &w),
+                float_tensor("b", &[3], &b),
+            ],
+            &[value_info("x", &[Ok(1), Ok(2), Ok(5), Ok(5)])],
+            &["y"],
+        );
+        let mut cx = Graph::new();
+        let model = import_onnx(&bytes, &mut cx).unwrap();
+        model.set_input("x", x.clone(), &[1, 2, 5, 5]);
+        cx.execute();
+
+        // Padded height is 7, so 3 rows of windows with a stride of 2. Width has 3 windows
+        let mut expected = vec![];
+        for o in 0..3 {
+            for oh in 0..3 {
+                for ow in 0..3 {
+                    let mut sum = b[o];
+                    for c in 0..2 {
+                        for kh in 0..3 {
+                            for kw in 0..3 {
+                                let h = (oh * 2 + kh) as i32 - 1;
+                                if (0..5).contains(&h) {
+                                    sum += x[c * 25 + h as usize * 5 + ow + kw]
+                                        * w[o * 18 + c * 9 + kh * 3 + kw];
+                                }
+                            }
+                        }
+                    }
+                    expected.push(sum);
+                }
+            }
+        }
+        let y = model.output("y").unwrap();
+        assert_eq!(dims(&y), [1, 3, 3, 3].map(Expression::from).to_vec());
+        assert_close(&y.data(), &expected);
+    }
+
+    #[test]
+    fn test_import_embedding_layer_norm() {
+        let (embed, scale, bias) = (random_vec(20), random_vec(4), random_vec(4));
+        let bytes = model(
+            &[
+                node("Gather", &["embed", "ids"], &["e"], &[]),
+                node(
+                    "LayerNormalization",
+                    &["e", "scale", "bias"],
+                    &["y"],
+                    &[attr_float("epsilon", 1e-5)],
+                ),
+            ],
+            &[
+                float_tensor("embed", &[5, 4], &embed),
+                float_tensor("scale", &[4], &scale),
+                float_tensor("bias", &[4], &bias),
+            ],
+            &[value_info("ids", &[Err("seq")])],
+            &["y"],
+        );
+        let mut cx = Graph::new();
+        let model = import_onnx(&bytes, &mut cx).unwrap();
+        model.set_input("ids", vec![3., 0., 3.], &[3]);
+        cx.execute();
+
+        let mut expected = vec![];
+        for id in [3, 0, 3] {
+            let row = &embed[id * 4..id * 4 + 4];
+            let mean = row.iter().sum::<f32>() / 4.;
+            let var = row.iter().map(|r| (r - mean).powi(2)).sum::<f32>() / 4.;
+            for i in 0..4 {
+                expected.push((row[i] - mean) / (var + 1e-5).sqrt() * scale[i] + bias[i]);
+            }
+        }
+        assert_close(&model.output("y").unwrap().data(), &expected);
+    }
+
+    #[test]
+    fn test_import_cast() {
+        let bytes = model(
+            &[
+                node("Cast", &["x"], &["int"], &[attr_int("to", 7)]),
+                node("Cast", &["x"], &["bool"], &[attr_int("to", 9)]),
+                node("Cast", &["x"], &["float"], &[attr_int("to", 1)]),
+            ],
+            &[],
+            &[value_info("x", &[Ok(4)])],
+            &["int", "bool", "float"],
+        );
+        let mut cx = Graph::new();
+        let model = import_onnx(&bytes, &mut cx).unwrap();
+        model.set_input("x", vec![1.7, -2.5, 0., 0.3], &[4]);
+        cx.execute();
+
+        assert_eq!(model.output("int").unwrap().data(), [1., -2., 0., 0.]);
+        assert_eq!(model.output("bool").unwrap().data(), [1., 1., 0., 1.]);
+        assert_eq!(model.output("float").unwrap().dtype(), DType::F32);
+        assert_eq!(model.output("float").unwrap().data(), [1.7, -2.5, 0., 0.3]);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let bytes = model(
+            &[
+                node("Foo", &["x"], &["a"], &[]),
+                node("Relu", &["a"], &["b"], &[]),
+                node("Bar", &["b"], &["c"], &[]),
+                node("Foo", &["c"], &["d"], &[]),
+            ],
+            &[],
+            &[value_info("x", &[Ok(2)])],
+            &["d"],
+        );
+        let mut cx = Graph::new();
+        match import_onnx(&bytes, &mut cx) {
+            Err(OnnxError::UnsupportedOps(ops)) => assert_eq!(ops, ["Foo", "Bar"]),
+            r => panic!("Expected unsupported ops, got {r:?}"),
+        }
+
+        let bytes = model(
+            &[node("Reshape", &["x", "shape"], &["y"], &[])],
+            &[],
+            &[value_info("x", &[Ok(2)]), value_info("shape", &[Ok(1)])],
+            &["y"],
+        );
+        assert!(matches!(
+            import_onnx(&bytes, &mut Graph::new()),
+            Err(OnnxError::Unsupported { .. })
+        ));
+        assert!(matches!(
+            import_onnx(&bytes[..bytes.len() - 3], &mut Graph::new()),
+            Err(OnnxError::Decode(_))
+        ));
+
+        // Malformed ops are reported instead of panicking
+        let invalid = [
+            node("Cast", &["x"], &["y"], &[attr_int("to", 8)]),
+            node("Conv", &["x", "w"], &["y"], &[]),
+            node("Conv", &["x4", "w4"], &["y"], &[attr_ints("strides", &[1])]),
+            node("Conv", &["x4", "w4"], &["y"], &[attr_ints("pads", &[1, 1])]),
+            node("Reshape", &["x", "zeros"], &["y"], &[]),
+        ];
+        for n in invalid {
+            let bytes = model(
+                &[n],
+                &[
+                    float_tensor("w", &[2, 2], &[0.; 4]),
+                    float_tensor("w4", &[1, 1, 1, 1], &[0.]),
+                    int_tensor("zeros", &[2], &[0, 0]),
+                ],
+                &[
+                    value_info("x", &[Ok(2)]),
+                    value_info("x4", &[Ok(1), Ok(1), Ok(2), Ok(2)]),
+                ],
+                &["y"],
+            );
+            assert!(matches!(
+                import_onnx(&bytes, &mut Graph::new()),
+                Err(OnnxError::Unsupported { .. })
+            ));
+        }
+    }
+}
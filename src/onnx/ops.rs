@@ -0,0 +1,343 @@
+//! Ops on tensors whose shapes are only known at import time.
+//!
+//! The typed hl_ops need their shapes at compile time, so these mirror them using only the runtime shape tracker.
+
+use crate::{
+    op::{self, Operator},
+    prelude::{
+        symbolic::{BigExpression, Expression},
+        *,
+    },
+};
+
+/// A tensor with a runtime shape
+pub type Value = GraphTensor<()>;
+
+/// The logical dims of a value
+pub(crate) fn dims(v: &Value) -> Vec<Expression> {
+    v.shape.shape().into_iter().map(Expression::from).collect()
+}
+
+fn with_shape(id: petgraph::stable_graph::NodeIndex, dims: &[Expression], like: &Value) -> Value {
+    GraphTensor::from_id(id, ShapeTracker::new(dims), like.graph_ref)
+}
+
+/// A constant broadcasted to a shape
+pub(crate) fn constant(cx: &mut Graph, value: f32, dims: &[Expression]) -> Value {
+    let id = cx.constant(value).id;
+    GraphTensor::from_id(id, ShapeTracker::fake(dims), cx)
+}
+
+fn scalar(like: &Value, value: f32) -> Value {
+    constant(like.graph(), value, &[])
+}
+
+/// Numpy-style broadcast a value to a shape
+fn broadcast_to(mut v: Value, out: &[Expression]) -> Value {
+    let rank = v.shape.len();
+    for d in out[..out.len() - rank].iter().rev() {
+        v.shape.expand(0, *d);
+    }
+    for (i, d) in dims(&v).into_iter().enumerate() {
+        if d == 1.into() && out[i] != 1.into() {
+            if v.shape.is_sliced() || v.shape.is_padded() {
+                v = v.contiguous();
+            }
+            v.shape.remove_dim(i);
+            v.shape.expand(i, out[i]);
+        }
+    }
+    v
+}
+
+fn broadcast_shape(a: &[Expression], b: &[Expression]) -> Vec<Expression> {
+    let rank = a.len().max(b.len());
+    (0..rank)
+        .map(|i| {
+            let a = (i + a.len() >= rank).then(|| a[i + a.len() - rank]);
+            let b = (i + b.len() >= rank).then(|| b[i + b.len() - rank]);
+            match (a, b) {
+                (Some(a), Some(b)) if a == 1.into() => b,
+                (Some(a), _) => a,
+                (None, Some(b)) => b,
+                (None, None) => unreachable!(),
+            }
+        })
+        .collect()
+}
+
+/// Broadcast a value to a shape
+pub(crate) fn expand(a: Value, shape: &[Expression]) -> Value {
+    let out = broadcast_shape(&dims(&a), shape);
+    broadcast_to(a, &out)
+}
+
+fn binary<O: Operator + 'static>(op: O, a: Value, b: Value) -> Value {
+    let out = broadcast_shape(&dims(&a), &dims(&b));
+    let (a, b) = (broadcast_to(a, &out), broadcast_to(b, &out));
+    let id = a
+        .graph()
+        .add_op(op)
+        .input(a.id, 0, a.shape)
+        .input(b.id, 0, b.shape)
+        .finish();
+    with_shape(id, &out, &a)
+}
+
+fn unary<O: Operator + 'static>(op: O, a: Value) -> Value {
+    let id = a.graph().add_op(op).input(a.id, 0, a.shape).finish();
+    with_shape(id, &dims(&a), &a)
+}
+
+pub(crate) fn add(a: Value, b: Value) -> Value {
+    binary(op::Add, a, b)
+}
+
+pub(crate) fn mul(a: Value, b: Value) -> Value {
+    binary(op::Mul, a, b)
+}
+
+pub(crate) fn less_than(a: Value, b: Value) -> Value {
+    binary(op::LessThan, a, b)
+}
+
+pub(crate) fn neg(a: Value) -> Value {
+    mul(a, scalar(&a, -1.))
+}
+
+pub(crate) fn sub(a: Value, b: Value) -> Value {
+    add(a, neg(b))
+}
+
+pub(crate) fn div(a: Value, b: Value) -> Value {
+    mul(a, recip(b))
+}
+
+pub(crate) fn add_f32(a: Value, b: f32) -> Value {
+    add(a, scalar(&a, b))
+}
+
+pub(crate) fn mul_f32(a: Value, b: f32) -> Value {
+    mul(a, scalar(&a, b))
+}
+
+pub(crate) fn recip(a: Value) -> Value {
+    unary(op::Recip, a)
+}
+
+pub(crate) fn sqrt(a: Value) -> Value {
+    unary(op::Sqrt, a)
+}
+
+pub(crate) fn sin(a: Value) -> Value {
+    unary(op::Sin, a)
+}
+
+pub(crate) fn cos(a: Value) -> Value {
+    sin(add_f32(a, std::f32::consts::PI / 2.))
+}
+
+pub(crate) fn exp(a: Value) -> Value {
+    unary(op::Exp2, mul_f32(a, 1. / std::f32::consts::LN_2))
+}
+
+pub(crate) fn ln(a: Value) -> Value {
+    mul_f32(unary(op::Log2, a), std::f32::consts::LN_2)
+}
+
+pub(crate) fn max(a: Value, b: Value) -> Value {
+    let a_less = less_than(a, b);
+    add(mul(a_less, b), mul(add_f32(neg(a_less), 1.), a))
+}
+
+pub(crate) fn min(a: Value, b: Value) -> Value {
+    neg(max(neg(a), neg(b)))
+}
+
+pub(crate) fn relu(a: Value) -> Value {
+    max(a, scalar(&a, 0.))
+}
+
+pub(crate) fn abs(a: Value) -> Value {
+    add(relu(a), relu(neg(a)))
+}
+
+pub(crate) fn sigmoid(a: Value) -> Value {
+    recip(add_f32(exp(neg(a)), 1.))
+}
+
+pub(crate) fn tanh(a: Value) -> Value {
+    add_f32(mul_f32(sigmoid(mul_f32(a, 2.)), 2.), -1.)
+}
+
+/// Reduce over axes, optionally keeping the reduced dims with size 1
+fn reduce<O: Operator + 'static>(
+    a: Value,
+    axes: &[usize],
+    keep_dims: bool,
+    op: impl Fn(usize) -> O,
+) -> Value {
+    let mut axes = axes.to_vec();
+    axes.sort();
+    axes.dedup();
+    let mut r = a;
+    for axis in axes.iter().rev() {
+        let id = a.graph().add_op(op(*axis)).input(r.id, 0, r.shape).finish();
+        let mut d = dims(&r);
+        d.remove(*axis);
+        r = with_shape(id, &d, &a);
+    }
+    if keep_dims {
+        for axis in axes {
+            r.shape.add_dim(axis, 1.into());
+        }
+    }
+    r
+}
+
+pub(crate) fn sum_reduce(a: Value, axes: &[usize], keep_dims: bool) -> Value {
+    reduce(a, axes, keep_dims, op::SumReduce)
+}
+
+pub(crate) fn max_reduce(a: Value, axes: &[usize], keep_dims: bool) -> Value {
+    reduce(a, axes, keep_dims, op::MaxReduce)
+}
+
+pub(crate) fn mean_reduce(a: Value, axes: &[usize], keep_dims: bool) -> Value {
+    let d = dims(&a);
+    let n = axes
+        .iter()
+        .map(|i| BigExpression::from(d[*i]))
+        .product::<BigExpression>();
+    let n = a.graph().constant_expr(n).id;
+    let n = GraphTensor::from_id(n, ShapeTracker::new(&[]), a.graph_ref);
+    div(sum_reduce(a, axes, keep_dims), n)
+}
+
+pub(crate) fn softmax(a: Value, axis: usize) -> Value {
+    let m = exp(sub(a, max_reduce(a, &[axis], true)));
+    div(m, sum_reduce(m, &[axis], true))
+}
+
+pub(crate) fn permute(mut a: Value, axes: &[usize]) -> Value {
+    a.shape.permute(axes);
+    a
+}
+
+pub(crate) fn reshape(a: Value, dims: &[Expression]) -> Value {
+    let a = a.contiguous();
+    GraphTensor::from_id(a.id, ShapeTracker::new(dims), a.graph_ref)
+}
+
+pub(crate) fn unsqueeze(mut a: Value, axis: usize) -> Value {
+    a.shape.add_dim(axis, 1.into());
+    a
+}
+
+pub(crate) fn squeeze(mut a: Value, axis: usize) -> Value {
+    let index = a.shape.indexes[axis];
+    if a.shape.dims[index] != 1.into()
+        || a.shape.slices[index] != (0.into(), i32::MAX.into())
+        || a.shape.padding[index] != (0.into(), 0.into())
+    {
+        a = a.contiguous();
+    }
+    a.shape.remove_dim(axis);
+    a
+}
+
+/// Slice each dim to a range
+pub(crate) fn slice(mut a: Value, ranges: &[(Expression, Expression)]) -> Value {
+    // Padding and slicing the same dimension isn't supported, and slices don't compose
+    if ranges.iter().enumerate().any(|(i, range)| {
+        let index = a.shape.indexes[i];
+        *range != (0.into(), i32::MAX.into())
+            && (a.shape.padding[index] != (0.into(), 0.into())
+                || a.shape.slices[index] != (0.into(), i32::MAX.into()))
+    }) {
+        a = a.contiguous();
+    }
+    a.shape.slice(ranges);
+    a
+}
+
+pub(crate) fn pad(a: Value, ranges: &[(Expression, Expression)]) -> Value {
+    a.pad::<(), _, _>(ranges)
+}
+
+pub(crate) fn concat(values: &[Value], axis: usize) -> Value {
+    let mut r = values[0];
+    for v in &values[1..] {
+        let zero = (Expression::from(0), Expression::from(0));
+        let mut r_padding = vec![zero; r.shape.len()];
+        r_padding[axis].1 = dims(v)[axis];
+        let mut v_padding = vec![zero; v.shape.len()];
+        v_padding[axis].0 = dims(&r)[axis];
+        r = add(pad(r, &r_padding), pad(*v, &v_padding));
+    }
+    r
+}
+
+/// Numpy-style matmul, with batch dims broadcasted
+pub(crate) fn matmul(mut a: Value, mut b: Value) -> Value {
+    let (a_vec, b_vec) = (a.shape.len() == 1, b.shape.len() == 1);
+    if a_vec {
+        a = unsqueeze(a, 0);
+    }
+    if b_vec {
+        b = unsqueeze(b, 1);
+    }
+    let (ra, rb) = (a.shape.len(), b.shape.len());
+    let (m, n) = (dims(&a)[ra - 2], dims(&b)[rb - 1]);
+    // [..., M, K] -> [..., M, N, K]
+    a.shape.expand(ra - 1, n);
+    // [..., K, N] -> [..., N, K] -> [..., M, N, K]
+    let mut axes = (0..rb).collect::<Vec<_>>();
+    axes.swap(rb - 2, rb - 1);
+    b = permute(b, &axes);
+    b.shape.expand(rb - 2, m);
+    let mut r = sum_reduce(mul(a, b), &[ra.max(rb)], false);
+    if b_vec {
+        r = squeeze(r, r.shape.len() - 1);
+    }
+    if a_vec {
+        r = squeeze(r, r.shape.len() - if b_vec { 1 } else { 2 });
+    }
+    r
+}
+
+/// 0 to n along a single dim
+pub(crate) fn arange(cx: &mut Graph, n: Expression) -> Value {
+    if n == 1.into() {
+        constant(cx, 0., &[n])
+    } else {
+        add_f32(constant(cx, 1., &[n]).cumsum_last_dim(), -1.)
+    }
+}
+
+/// Gather slices along an axis with a tensor of (float) indexes
+pub(crate) fn gather(data: Value, indexes: Value, axis: usize) -> Value {
+    let data_dims = dims(&data);
+    let n_indexes = indexes.shape.len();
+    // One-hot encode the indexes: [indexes..., N]
+    let range = arange(data.graph(), data_dims[axis]);
+    let mut one_hot = equals(range, unsqueeze(indexes, n_indexes));
+    // Line up as [pre..., indexes..., N, post...]
+    for d in data_dims[..axis].iter().rev() {
+        one_hot.shape.expand(0, *d);
+    }
+    for d in &data_dims[axis + 1..] {
+        let len = one_hot.shape.len();
+        one_hot.shape.expand(len, *d);
+    }
+    let mut data = data;
+    for (i, d) in dims(&indexes).into_iter().enumerate() {
+        data.shape.expand(axis + i, d);
+    }
+    sum_reduce(mul(one_hot, data), &[axis + n_indexes], false)
+}
+
+pub(crate) fn equals(a: Value, b: Value) -> Value {
+    let not_equal = add(less_than(a, b), less_than(b, a));
+    add_f32(neg(not_equal), 1.)
+}
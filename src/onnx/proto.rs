@@ -0,0 +1,516 @@
+//! A minimal protobuf decoder for the parts of the ONNX schema the importer uses
+
+use super::OnnxError;
+
+pub(crate) mod data_type {
+    pub const FLOAT: i32 = 1;
+    pub const UINT8: i32 = 2;
+    pub const INT8: i32 = 3;
+    pub const INT32: i32 = 6;
+    pub const INT64: i32 = 7;
+    pub const BOOL: i32 = 9;
+    pub const FLOAT16: i32 = 10;
+    pub const DOUBLE: i32 = 11;
+    pub const BFLOAT16: i32 = 16;
+}
+
+enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+fn decode_error(message: &str) -> OnnxError {
+    OnnxError::Decode(message.to_string())
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64, OnnxError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (byte, rest) = self
+                .buf
+                .split_first()
+                .ok_or_else(|| decode_error("Truncated varint"))?;
+            self.buf = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(decode_error("Varint too long"))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], OnnxError> {
+        if self.buf.len() < n {
+            return Err(decode_error("Truncated field"));
+        }
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(taken)
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u64, Field<'a>)>, OnnxError> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = match key & 7 {
+            0 => Field::Varint(self.varint()?),
+            1 => Field::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => {
+                let len = self.varint()? as usize;
+                Field::Bytes(self.take(len)?)
+            }
+            5 => Field::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+            w => return Err(OnnxError::Decode(format!("Unsupported wire type {w}"))),
+        };
+        Ok(Some((key >> 3, field)))
+    }
+}
+
+/// Iterate over the fields of a message
+fn fields(
+    buf: &[u8],
+    mut f: impl FnMut(u64, Field) -> Result<(), OnnxError>,
+) -> Result<(), OnnxError> {
+    let mut reader = Reader { buf };
+    while let Some((number, field)) = reader.next_field()? {
+        f(number, field)?;
+    }
+    Ok(())
+}
+
+fn string(field: Field) -> Result<String, OnnxError> {
+    match field {
+        Field::Bytes(b) => {
+            String::from_utf8(b.to_vec()).map_err(|_| decode_error("Invalid UTF-8 string"))
+        }
+        _ => Err(decode_error("Expected a string")),
+    }
+}
+
+fn int(field: Field) -> Result<i64, OnnxError> {
+    match field {
+        Field::Varint(v) => Ok(v as i64),
+        _ => Err(decode_error("Expected an integer")),
+    }
+}
+
+/// Read a repeated integer field, which can be packed or not
+fn ints(field: Field, out: &mut Vec<i64>) -> Result<(), OnnxError> {
+    match field {
+        Field::Varint(v) => out.push(v as i64),
+        Field::Bytes(b) => {
+            let mut reader = Reader { buf: b };
+            while !reader.buf.is_empty() {
+                out.push(reader.varint()? as i64);
+            }
+        }
+        _ => return Err(decode_error("Expected integers")),
+    }
+    Ok(())
+}
+
+fn floats(field: Field, out: &mut Vec<f32>) -> Result<(), OnnxError> {
+    match field {
+        Field::Fixed32(v) => out.push(f32::from_bits(v)),
+        Field::Bytes(b) if b.len() % 4 == 0 => out.extend(
+            b.chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap())),
+        ),
+        _ => return Err(decode_error("Expected floats")),
+    }
+    Ok(())
+}
+
+fn doubles(field: Field, out: &mut Vec<f64>) -> Result<(), OnnxError> {
+    match field {
+        Field::Fixed64(v) => out.push(f64::from_bits(v)),
+        Field::Bytes(b) if b.len() % 8 == 0 => out.extend(
+            b.chunks_exact(8)
+                .map(|c| f64::from_le_bytes(c.try_into().unwrap())),
+        ),
+        _ => return Err(decode_error("Expected doubles")),
+    }
+    Ok(())
+}
+
+fn bytes(field: Field<'_>) -> Result<&[u8], OnnxError> {
+    match field {
+        Field::Bytes(b) => Ok(b),
+        _ => Err(decode_error("Expected bytes")),
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ModelProto {
+    /// The opset version of the default domain
+    pub opset: i64,
+    pub graph: GraphProto,
+}
+
+impl ModelProto {
+    pub fn decode(buf: &[u8]) -> Result<Self, OnnxError> {
+        let mut model = Self::default();
+        let mut graph = None;
+        fields(buf, |number, field| {
+            match number {
+                7 => graph = Some(GraphProto::decode(bytes(field)?)?),
+                8 => {
+                    let (mut domain, mut version) = (String::new(), 0);
+                    fields(bytes(field)?, |n, f| {
+                        match n {
+                            1 => domain = string(f)?,
+                            2 => version = int(f)?,
+                            _ => {}
+                        }
+                        Ok(())
+                    })?;
+                    if domain.is_empty() || domain == "ai.onnx" {
+                        model.opset = version;
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        })?;
+        model.graph = graph.ok_or_else(|| decode_error("Model has no graph"))?;
+        Ok(model)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct GraphProto {
+    pub nodes: Vec<NodeProto>,
+    pub initializers: Vec<TensorProto>,
+    pub inputs: Vec<ValueInfoProto>,
+    pub outputs: Vec<ValueInfoProto>,
+}
+
+impl GraphProto {
+    fn decode(buf: &[u8]) -> Result<Self, OnnxError> {
+        let mut graph = Self::default();
+        fields(buf, |number, field| {
+            match number {
+                1 => graph.nodes.push(NodeProto::decode(bytes(field)?)?),
+                5 => graph.initializers.push(TensorProto::decode(bytes(field)?)?),
+                11 => graph.inputs.push(ValueInfoProto::decode(bytes(field)?)?),
+                12 => graph.outputs.push(ValueInfoProto::decode(bytes(field)?)?),
+                _ => {}
+            }
+            Ok(())
+        })?;
+        Ok(graph)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct NodeProto {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub name: String,
+    pub op_type: String,
+    pub domain: String,
+    pub attributes: Vec<AttributeProto>,
+}
+
+impl NodeProto {
+    fn decode(buf: &[u8]) -> Result<Self, OnnxError> {
+        let mut node = Self::default();
+        fields(buf, |number, field| {
+            match number {
+                1 => node.inputs.push(string(field)?),
+                2 => node.outputs.push(string(field)?),
+                3 => node.name = string(field)?,
+                4 => node.op_type = string(field)?,
+                5 => node.attributes.push(AttributeProto::decode(bytes(field)?)?),
+                7 => node.domain = string(field)?,
+                _ => {}
+            }
+            Ok(())
+        })?;
+        Ok(node)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| &a.value)
+    }
+
+    /// An input, treating empty names as missing optional inputs
+    pub fn input(&self, i: usize) -> Option<&str> {
+        self.inputs
+            .get(i)
+            .map(|s| s.as_str())
+            .filter(|s| !s.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Attribute {
+    Float(f32),
+    Int(i64),
+    String(String),
+    Tensor(TensorProto),
+    Floats(Vec<f32>),
+    Ints(Vec<i64>),
+    Other,
+}
+
+#[derive(Debug)]
+pub(crate) struct AttributeProto {
+    pub name: String,
+    pub value: Attribute,
+}
+
+impl AttributeProto {
+    fn decode(buf: &[u8]) -> Result<Self, OnnxError> {
+        let mut name = String::new();
+        let mut attr_type = 0;
+        let (mut f, mut i, mut s, mut t) = (None, None, None, None);
+        let (mut fs, mut is) = (vec![], vec![]);
+        fields(buf, |number, field| {
+            match number {
+                1 => name = string(field)?,
+                2 => {
+                    let mut v = vec![];
+                    floats(field, &mut v)?;
+                    f = v.pop();
+                }
+                3 => i = Some(int(field)?),
+                4 => s = Some(string(field)?),
+                5 => t = Some(TensorProto::decode(bytes(field)?)?),
+                7 => floats(field, &mut fs)?,
+                8 => ints(field, &mut is)?,
+                20 => attr_type = int(field)?,
+                _ => {}
+            }
+            Ok(())
+        })?;
+        let value = match attr_type {
+            1 => f.map(Attribute::Float),
+            2 => i.map(Attribute::Int),
+            3 => s.map(Attribute::String),
+            4 => t.map(Attribute::Tensor),
+            6 => Some(Attribute::Floats(fs)),
+            7 => Some(Attribute::Ints(is)),
+            // Old exporters don't always set the type
+            0 => f
+                .map(Attribute::Float)
+                .or(i.map(Attribute::Int))
+                .or(s.map(Attribute::String))
+                .or(t.map(Attribute::Tensor))
+                .or((!fs.is_empty()).then_some(Attribute::Floats(fs)))
+                .or((!is.is_empty()).then_some(Attribute::Ints(is))),
+            _ => Some(Attribute::Other),
+        };
+        Ok(Self {
+            name,
+            value: value.unwrap_or(Attribute::Other),
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct TensorProto {
+    pub name: String,
+    pub dims: Vec<i64>,
+    pub data_type: i32,
+    float_data: Vec<f32>,
+    /// Also holds int8, uint8, bool and float16 (as bits) data
+    int32_data: Vec<i64>,
+    int64_data: Vec<i64>,
+    double_data: Vec<f64>,
+    raw_data: Vec<u8>,
+    external: bool,
+}
+
+impl TensorProto {
+    fn decode(buf: &[u8]) -> Result<Self, OnnxError> {
+        let mut tensor = Self::default();
+        fields(buf, |number, field| {
+            match number {
+                1 => ints(field, &mut tensor.dims)?,
+                2 => tensor.data_type = int(field)? as i32,
+                4 => floats(field, &mut tensor.float_data)?,
+                5 => ints(field, &mut tensor.int32_data)?,
+                7 => ints(field, &mut tensor.int64_data)?,
+                8 => tensor.name = string(field)?,
+                9 => tensor.raw_data = bytes(field)?.to_vec(),
+                10 => doubles(field, &mut tensor.double_data)?,
+                14 => tensor.external = int(field)? == 1,
+                _ => {}
+            }
+            Ok(())
+        })?;
+        Ok(tensor)
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self.data_type,
+            data_type::FLOAT | data_type::FLOAT16 | data_type::DOUBLE
+        )
+    }
+
+    pub fn n_elements(&self) -> usize {
+        self.dims.iter().product::<i64>().max(0) as usize
+    }
+
+    fn check_external(&self) -> Result<(), OnnxError> {
+        if self.external {
+            return Err(OnnxError::Unsupported {
+                node: self.name.clone(),
+                message: "Tensors stored in external files aren't supported".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn unsupported_type(&self) -> OnnxError {
+        OnnxError::Unsupported {
+            node: self.name.clone(),
+            message: format!("Unsupported tensor data type {}", self.data_type),
+        }
+    }
+
+    /// Read the data, converting to f32
+    pub fn to_f32(&self) -> Result<Vec<f32>, OnnxError> {
+        self.check_external()?;
+        let raw = &self.raw_data;
+        let data = match self.data_type {
+            data_type::FLOAT if raw.is_empty() => self.float_data.clone(),
+            data_type::FLOAT => raw
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+            data_type::FLOAT16 if raw.is_empty() => self
+                .int32_data
+                .iter()
+                .map(|b| half::f16::from_bits(*b as u16).to_f32())
+                .collect(),
+            data_type::FLOAT16 => raw
+                .chunks_exact(2)
+                .map(|c| half::f16::from_le_bytes(c.try_into().unwrap()).to_f32())
+                .collect(),
+            data_type::DOUBLE if raw.is_empty() => {
+                self.double_data.iter().map(|d| *d as f32).collect()
+            }
+            data_type::DOUBLE => raw
+                .chunks_exact(8)
+                .map(|c| f64::from_le_bytes(c.try_into().unwrap()) as f32)
+                .collect(),
+            _ => self.to_i64()?.into_iter().map(|i| i as f32).collect(),
+        };
+        if data.len() != self.n_elements() {
+            return Err(OnnxError::Decode(format!(
+                "Tensor {} has {} elements but its shape needs {}",
+                self.name,
+                data.len(),
+                self.n_elements()
+            )));
+        }
+        Ok(data)
+    }
+
+    /// Read integer data
+    pub fn to_i64(&self) -> Result<Vec<i64>, OnnxError> {
+        self.check_external()?;
+        let raw = &self.raw_data;
+        let data = match self.data_type {
+            data_type::INT64 if raw.is_empty() => self.int64_data.clone(),
+            data_type::INT64 => raw
+                .chunks_exact(8)
+                .map(|c| i64::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+            data_type::INT32 | data_type::INT8 | data_type::UINT8 | data_type::BOOL
+                if raw.is_empty() =>
+            {
+                self.int32_data.clone()
+            }
+            data_type::INT32 => raw
+                .chunks_exact(4)
+                .map(|c| i32::from_le_bytes(c.try_into().unwrap()) as i64)
+                .collect(),
+            data_type::INT8 => raw.iter().map(|b| *b as i8 as i64).collect(),
+            data_type::UINT8 | data_type::BOOL => raw.iter().map(|b| *b as i64).collect(),
+            _ => return Err(self.unsupported_type()),
+        };
+        if data.len() != self.n_elements() {
+            return Err(OnnxError::Decode(format!(
+                "Tensor {} has {} elements but its shape needs {}",
+                self.name,
+                data.len(),
+                self.n_elements()
+            )));
+        }
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Dim {
+    Value(i64),
+    Param(String),
+    Unknown,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ValueInfoProto {
+    pub name: String,
+    /// None if the value doesn't have a known shape
+    pub dims: Option<Vec<Dim>>,
+}
+
+impl ValueInfoProto {
+    fn decode(buf: &[u8]) -> Result<Self, OnnxError> {
+        let mut info = Self::default();
+        fields(buf, |number, field| {
+            match number {
+                1 => info.name = string(field)?,
+                // TypeProto -> TypeProto.Tensor -> TensorShapeProto -> Dimension
+                2 => fields(bytes(field)?, |n, f| {
+                    if n == 1 {
+                        fields(bytes(f)?, |n, f| {
+                            if n == 2 {
+                                let dims = info.dims.get_or_insert_with(Vec::new);
+                                fields(bytes(f)?, |n, f| {
+                                    if n == 1 {
+                                        dims.push(decode_dim(bytes(f)?)?);
+                                    }
+                                    Ok(())
+                                })?;
+                            }
+                            Ok(())
+                        })?;
+                    }
+                    Ok(())
+                })?,
+                _ => {}
+            }
+            Ok(())
+        })?;
+        Ok(info)
+    }
+}
+
+fn decode_dim(buf: &[u8]) -> Result<Dim, OnnxError> {
+    let mut dim = Dim::Unknown;
+    fields(buf, |number, field| {
+        match number {
+            1 => dim = Dim::Value(int(field)?),
+            2 => dim = Dim::Param(string(field)?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(dim)
+}
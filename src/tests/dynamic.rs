@@ -10,7 +10,7 @@ use dfdx::{
 
 use crate::{
     nn::{activation::ReLU, linear::Linear},
-    prelude::*,
+    prelude::{symbolic::Expression, *},
 };
 
 use super::assert_close;
@@ -140,3 +140,93 @@ fn test_feedforward() {
 
     assert_close(&unoptimized_batch_out, &out.as_vec());
 }
+
+#[test]
+fn test_dyn_dim_ranges() {
+    let mut cx = Graph::new();
+    cx.set_dyn_dim_range('s', 1..=4096);
+    cx.set_dyn_dim_multiple('s', 8);
+    let a = cx.tensor::<(Dyn<'s'>, Const<4>)>();
+    let b = a.permute::<_, Axes2<1, 0>>();
+    // Every logical index of the permuted view is in bounds
+    assert_ne!(b.shape.valid_expression(), 1.into());
+    assert_eq!(b.shape.valid_expression_bounded(&cx.dyn_ranges), 1.into());
+    let c = a.slice((..Expression::from(4096), ..));
+    assert_eq!(c.shape.valid_expression_bounded(&cx.dyn_ranges), 1.into());
+    // Padding still needs to be checked
+    let d = a.pad::<(Dyn<'s'>, Const<5>), _, _>(&[(0, 0), (0, 1)]);
+    assert_ne!(d.shape.valid_expression_bounded(&cx.dyn_ranges), 1.into());
+
+    cx.set_dyn_dim('s', 16);
+}
+
+#[test]
+#[should_panic(expected = "outside of its declared range")]
+fn test_dyn_dim_out_of_range() {
+    let mut cx = Graph::new();
+    cx.set_dyn_dim_range('s', 1..=4096);
+    cx.set_dyn_dim_multiple('s', 8);
+    cx.set_dyn_dim('s', 12);
+}
+
+#[test]
+fn test_named_dims() {
+    let mut cx = Graph::new();
+    let a = cx
+        .tensor::<(Dyn<'-'>, Const<2>)>()
+        .dyn_reshape::<(Dyn<'-'>, Const<2>)>(vec!["batch_size".into(), 2.into()]);
+    let b = cx
+        .tensor::<(Dyn<'-'>, Const<2>)>()
+        .dyn_reshape::<(Dyn<'-'>, Const<2>)>(vec!['b'.into(), 2.into()]);
+    a.set_dyn(vec![1., 2., 3., 4., 5., 6.], &[3, 2]);
+    b.set_dyn(vec![1., 1.], &[1, 2]);
+    let c = a.sum_reduce::<_, Axis<0>>().retrieve();
+    let d = b.sum_reduce::<_, Axis<0>>().retrieve();
+    let n = cx
+        .constant_expr(Expression::from("batch_size") * 'b')
+        .retrieve();
+    cx.execute();
+
+    assert_close(&c.data(), &[9., 12.]);
+    assert_close(&d.data(), &[1., 1.]);
+    assert_close(&n.data(), &[3.]);
+}
+
+#[test]
+fn test_set_dyn_compound_dims() {
+    let mut cx = Graph::new();
+    let a = cx
+        .tensor::<(Dyn<'-'>, Dyn<'-'>)>()
+        .dyn_reshape::<(Dyn<'-'>, Dyn<'-'>)>(vec!['s'.into(), Expression::from('s') * 2]);
+    a.set_dyn(vec![0.; 8], &[2, 4]);
+    // Only the bare dim is bound
+    assert_eq!(cx.dyn_map[&'s'.into()], 2);
+}
+
+#[test]
+#[should_panic(expected = "but was set to 5")]
+fn test_set_dyn_compound_dim_mismatch() {
+    let mut cx = Graph::new();
+    let a = cx
+        .tensor::<(Dyn<'-'>, Dyn<'-'>)>()
+        .dyn_reshape::<(Dyn<'-'>, Dyn<'-'>)>(vec!['s'.into(), Expression::from('s') * 2]);
+    a.set_dyn(vec![0.; 10], &[2, 5]);
+}
+
+#[test]
+#[should_panic(expected = "Can't infer")]
+fn test_set_dyn_unsolved_dim() {
+    let mut cx = Graph::new();
+    let a = cx
+        .tensor::<(Dyn<'-'>,)>()
+        .dyn_reshape::<(Dyn<'-'>,)>(vec![Expression::from('s') + 1]);
+    a.set_dyn(vec![0.; 3], &[3]);
+}
+
+#[test]
+#[should_panic(expected = "outside of its declared range")]
+fn test_set_dyn_out_of_range() {
+    let mut cx = Graph::new();
+    cx.set_dyn_dim_range('s', ..=4);
+    cx.tensor::<(Dyn<'s'>,)>().set_dyn(vec![0.; 8], &[8]);
+}
@@ -0,0 +1,629 @@
+//! Differential fuzzing for compilers.
+//!
+//! Random primop graphs are executed as built and again after compiling, and the results compared. When a graph gives
+//! different results (or the compiler panics), it's shrunk down to the smallest graph that still fails.
+
+use std::{
+    fmt::Display,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    op::{self, ConstantValue, Operator},
+    prelude::{
+        symbolic::{Expression, Symbol},
+        *,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unary {
+    Log2,
+    Exp2,
+    Sin,
+    Sqrt,
+    Recip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binary {
+    Add,
+    Mul,
+    Mod,
+    LessThan,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reduce {
+    Sum,
+    Max,
+}
+
+/// A change to how a tensor is viewed, which doesn't add a node
+#[derive(Debug, Clone, PartialEq)]
+pub enum View {
+    Permute(Vec<usize>),
+    /// Dim, start, end
+    Slice(usize, usize, usize),
+    /// Dim, before, after
+    Pad(usize, usize, usize),
+    /// Axis, size
+    Expand(usize, Expression),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FuzzNode {
+    /// Random positive values from a seed
+    Input(Vec<Expression>, u64),
+    /// A scalar expanded to a shape
+    Constant(f32, Vec<Expression>),
+    Unary(Unary, usize),
+    Binary(Binary, usize, usize),
+    Reduce(Reduce, usize, usize),
+    Contiguous(usize),
+    View(usize, View),
+}
+
+impl FuzzNode {
+    fn inputs(&self) -> Vec<usize> {
+        match self {
+            FuzzNode::Input(..) | FuzzNode::Constant(..) => vec![],
+            FuzzNode::Binary(_, a, b) => vec![*a, *b],
+            FuzzNode::Unary(_, a)
+            | FuzzNode::Reduce(_, a, _)
+            | FuzzNode::Contiguous(a)
+            | FuzzNode::View(a, _) => vec![*a],
+        }
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut usize> {
+        match self {
+            FuzzNode::Input(..) | FuzzNode::Constant(..) => vec![],
+            FuzzNode::Binary(_, a, b) => vec![a, b],
+            FuzzNode::Unary(_, a)
+            | FuzzNode::Reduce(_, a, _)
+            | FuzzNode::Contiguous(a)
+            | FuzzNode::View(a, _) => vec![a],
+        }
+    }
+}
+
+/// A graph of primops that can be rebuilt as many times as needed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzGraph {
+    pub dyn_dims: Vec<(char, usize)>,
+    pub nodes: Vec<FuzzNode>,
+    pub outputs: Vec<usize>,
+}
+
+/// The dims a tracker is viewed as
+fn logical_dims(tracker: &ShapeTracker) -> Vec<Expression> {
+    tracker.contiguous().dims.to_vec()
+}
+
+/// Slices and padding only compose on dims that haven't been sliced, padded or expanded yet
+fn can_slice(tracker: &ShapeTracker, dim: usize) -> bool {
+    let i = tracker.indexes[dim];
+    can_pad(tracker, dim) && tracker.padding[i] == (0.into(), 0.into())
+}
+
+fn can_pad(tracker: &ShapeTracker, dim: usize) -> bool {
+    let i = tracker.indexes[dim];
+    !tracker.fake[i] && tracker.slices[i] == (0.into(), i32::MAX.into())
+}
+
+impl FuzzGraph {
+    fn dyn_map(&self) -> FxHashMap<Symbol, usize> {
+        self.dyn_dims.iter().map(|&(c, n)| (c.into(), n)).collect()
+    }
+
+    /// The shape tracker each node is read through, or None if the graph isn't valid
+    pub fn trackers(&self) -> Option<Vec<ShapeTracker>> {
+        let mut trackers: Vec<ShapeTracker> = vec![];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.inputs().iter().any(|x| *x >= i) {
+                return None;
+            }
+            let tracker = match node {
+                FuzzNode::Input(dims, _) => ShapeTracker::new(dims),
+                FuzzNode::Constant(_, dims) => ShapeTracker::fake(dims),
+                // Unary ops work on the physical buffer, so the view carries through
+                FuzzNode::Unary(_, x) => trackers[*x].clone(),
+                FuzzNode::Contiguous(x) => trackers[*x].contiguous(),
+                FuzzNode::Binary(_, a, b) => {
+                    if logical_dims(&trackers[*a]) != logical_dims(&trackers[*b]) {
+                        return None;
+                    }
+                    trackers[*a].contiguous()
+                }
+                FuzzNode::Reduce(_, x, dim) => {
+                    let mut tracker = trackers[*x].clone();
+                    if *dim >= tracker.len() {
+                        return None;
+                    }
+                    tracker.remove_dim(*dim);
+                    tracker.contiguous()
+                }
+                FuzzNode::View(x, view) => {
+                    let mut tracker = trackers[*x].clone();
+                    let rank = tracker.len();
+                    match view {
+                        View::Permute(axes) => {
+                            let mut sorted = axes.clone();
+                            sorted.sort();
+                            if sorted != (0..rank).collect::<Vec<_>>() {
+                                return None;
+                            }
+                            tracker.permute(axes);
+                        }
+                        View::Slice(dim, start, end) => {
+                            if *dim >= rank || start >= end || !can_slice(&tracker, *dim) {
+                                return None;
+                            }
+                            let mut slices = vec![(0.into(), i32::MAX.into()); rank];
+                            slices[*dim] = ((*start).into(), (*end).into());
+                            tracker.slice(&slices);
+                        }
+                        View::Pad(dim, before, after) => {
+                            if *dim >= rank || !can_pad(&tracker, *dim) {
+                                return None;
+                            }
+                            let mut padding = vec![(0.into(), 0.into()); rank];
+                            padding[*dim] = ((*before).into(), (*after).into());
+                            tracker.pad(&padding);
+                        }
+                        View::Expand(axis, size) => {
+                            if *axis > rank || rank >= 5 {
+                                return None;
+                            }
+                            tracker.expand(*axis, *size);
+                        }
+                    }
+                    tracker
+                }
+            };
+            trackers.push(tracker);
+        }
+        self.outputs
+            .iter()
+            .all(|o| *o < trackers.len())
+            .then_some(trackers)
+    }
+
+    /// Generate a random graph of inputs, constants, unary, binary and reduce ops, views and dyn dims
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let mut graph = FuzzGraph {
+            dyn_dims: vec![('a', rng.gen_range(1..=4)), ('b', rng.gen_range(1..=4))],
+            nodes: vec![],
+            outputs: vec![],
+        };
+        let dyn_map = graph.dyn_map();
+        let random_dim = |rng: &mut dyn rand::RngCore| -> Expression {
+            if rng.gen_bool(0.25) {
+                (*['a', 'b'].choose(rng).unwrap()).into()
+            } else {
+                rng.gen_range(1..=4).into()
+            }
+        };
+        for _ in 0..rng.gen_range(1..=3) {
+            let dims = (0..rng.gen_range(1..=3)).map(|_| random_dim(rng)).collect();
+            graph.nodes.push(FuzzNode::Input(dims, rng.gen()));
+        }
+        for _ in 0..rng.gen_range(2..=12) {
+            let trackers = graph.trackers().unwrap();
+            let n_nodes = graph.nodes.len();
+            let mut x = rng.gen_range(0..graph.nodes.len());
+            let tracker = trackers[x].clone();
+            let rank = tracker.len();
+            let node = match rng.gen_range(0..7) {
+                0 => FuzzNode::Unary(
+                    *[
+                        Unary::Log2,
+                        Unary::Exp2,
+                        Unary::Sin,
+                        Unary::Sqrt,
+                        Unary::Recip,
+                    ]
+                    .choose(rng)
+                    .unwrap(),
+                    x,
+                ),
+                1 | 2 => {
+                    let dims = logical_dims(&tracker);
+                    let partners = (0..graph.nodes.len())
+                        .filter(|y| logical_dims(&trackers[*y]) == dims)
+                        .collect::<Vec<_>>();
+                    let y = if rng.gen_bool(0.7) {
+                        *partners.choose(rng).unwrap()
+                    } else {
+                        graph.nodes.push(if rng.gen_bool(0.5) {
+                            FuzzNode::Input(dims, rng.gen())
+                        } else {
+                            FuzzNode::Constant(*[0., 1., -1., 0.5, 2.].choose(rng).unwrap(), dims)
+                        });
+                        graph.nodes.len() - 1
+                    };
+                    let (a, b) = if rng.gen() { (x, y) } else { (y, x) };
+                    let op = *[Binary::Add, Binary::Mul, Binary::Mod, Binary::LessThan]
+                        .choose(rng)
+                        .unwrap();
+                    FuzzNode::Binary(op, a, b)
+                }
+                3 if rank > 0 => FuzzNode::Reduce(
+                    *[Reduce::Sum, Reduce::Max].choose(rng).unwrap(),
+                    x,
+                    rng.gen_range(0..rank),
+                ),
+                4 => FuzzNode::Contiguous(x),
+                _ if rank == 0 || rng.gen_bool(0.25) => {
+                    FuzzNode::View(x, View::Expand(rng.gen_range(0..=rank), random_dim(rng)))
+                }
+                _ => {
+                    let dim = rng.gen_range(0..rank);
+                    let view = match rng.gen_range(0..3) {
+                        0 => {
+                            let mut axes = (0..rank).collect::<Vec<_>>();
+                            axes.shuffle(rng);
+                            View::Permute(axes)
+                        }
+                        1 => {
+                            let size = logical_dims(&tracker)[dim].exec(&dyn_map).unwrap();
+                            let start = rng.gen_range(0..size);
+                            View::Slice(dim, start, rng.gen_range(start + 1..=size))
+                        }
+                        _ => View::Pad(dim, rng.gen_range(0..=2), rng.gen_range(0..=2)),
+                    };
+                    let clean = match view {
+                        View::Slice(..) => can_slice(&tracker, dim),
+                        View::Pad(..) => can_pad(&tracker, dim),
+                        _ => true,
+                    };
+                    if !clean {
+                        graph.nodes.push(FuzzNode::Contiguous(x));
+                        x = graph.nodes.len() - 1;
+                    }
+                    FuzzNode::View(x, view)
+                }
+            };
+            graph.nodes.push(node);
+            // Undo anything that isn't valid, like expanding past the max rank
+            if graph.trackers().is_none() {
+                graph.nodes.truncate(n_nodes);
+            }
+        }
+        // Every node without consumers is an output, so shrinking can drop any part of the graph
+        let consumed = graph
+            .nodes
+            .iter()
+            .flat_map(|n| n.inputs())
+            .collect::<Vec<_>>();
+        graph.outputs = (0..graph.nodes.len())
+            .filter(|n| !consumed.contains(n))
+            .collect();
+        if rng.gen_bool(0.5) {
+            graph.outputs.push(rng.gen_range(0..graph.nodes.len()));
+        }
+        graph
+    }
+
+    /// Build the graph, returning the output tensors
+    pub fn build(&self, cx: &mut Graph) -> Vec<GraphTensor<()>> {
+        let trackers = self.trackers().expect("Invalid fuzz graph");
+        for (dim, size) in &self.dyn_dims {
+            cx.set_dyn_dim(*dim, *size);
+        }
+        let mut ids = vec![];
+        for (node, tracker) in self.nodes.iter().zip(&trackers) {
+            let op: Box<dyn Operator> = match node {
+                FuzzNode::Input(_, seed) => {
+                    let n = tracker.n_elements().exec(&cx.dyn_map).unwrap();
+                    let mut rng = StdRng::seed_from_u64(*seed);
+                    let data = (0..n).map(|_| rng.gen_range(0.5..2.)).collect::<Vec<f32>>();
+                    Box::new(op::Function(
+                        "Fuzz Input".to_string(),
+                        Box::new(move |_| Ok(vec![Tensor::new(data.clone())])),
+                        DType::F32,
+                    ))
+                }
+                FuzzNode::Constant(v, _) => {
+                    Box::new(op::Constant(ConstantValue::Float(*v), &cx.dyn_map))
+                }
+                FuzzNode::Unary(Unary::Log2, _) => Box::new(op::Log2),
+                FuzzNode::Unary(Unary::Exp2, _) => Box::new(op::Exp2),
+                FuzzNode::Unary(Unary::Sin, _) => Box::new(op::Sin),
+                FuzzNode::Unary(Unary::Sqrt, _) => Box::new(op::Sqrt),
+                FuzzNode::Unary(Unary::Recip, _) => Box::new(op::Recip),
+                FuzzNode::Binary(Binary::Add, ..) => Box::new(op::Add),
+                FuzzNode::Binary(Binary::Mul, ..) => Box::new(op::Mul),
+                FuzzNode::Binary(Binary::Mod, ..) => Box::new(op::Mod),
+                FuzzNode::Binary(Binary::LessThan, ..) => Box::new(op::LessThan),
+                FuzzNode::Reduce(Reduce::Sum, _, dim) => Box::new(op::SumReduce(*dim)),
+                FuzzNode::Reduce(Reduce::Max, _, dim) => Box::new(op::MaxReduce(*dim)),
+                FuzzNode::Contiguous(_) => Box::new(op::Contiguous),
+                FuzzNode::View(x, _) => {
+                    ids.push(ids[*x]);
+                    continue;
+                }
+            };
+            let id = cx.graph.add_node(op);
+            for (i, x) in node.inputs().into_iter().enumerate() {
+                cx.graph.add_edge(
+                    ids[x],
+                    id,
+                    Dependency::Data {
+                        input_order: i as u8,
+                        output_order: 0,
+                        shape: trackers[x].clone(),
+                        dtype: DType::F32,
+                    },
+                );
+            }
+            ids.push(id);
+        }
+        self.outputs
+            .iter()
+            .map(|o| GraphTensor::from_id(ids[*o], trackers[*o].clone(), cx).retrieve())
+            .collect()
+    }
+
+    /// Run the graph as built and after compiling
+    pub fn check(&self, compiler: &dyn DynCompiler) -> Outcome {
+        if self.trackers().is_none() {
+            return Outcome::Invalid;
+        }
+        let Ok((expected, finite)) = catch_unwind(AssertUnwindSafe(|| {
+            let mut cx = Graph::new();
+            let outputs = self.build(&mut cx);
+            let nodes = cx.graph.node_indices().collect::<Vec<_>>();
+            cx.no_delete.extend(nodes);
+            cx.execute();
+            let finite = cx.tensors.values().all(|t| {
+                t.data
+                    .as_any()
+                    .downcast_ref::<Vec<f32>>()
+                    .map(|d| d.iter().all(|v| v.is_finite()))
+                    .unwrap_or(true)
+            });
+            (outputs.iter().map(|o| o.data()).collect::<Vec<_>>(), finite)
+        })) else {
+            return Outcome::Invalid;
+        };
+        // Compilers can apply identities that only hold on part of the domain (`x * recip(x) => 1`), so graphs that
+        // leave it anywhere can't be compared
+        if !finite {
+            return Outcome::Invalid;
+        }
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let mut cx = Graph::new();
+            let mut outputs = self.build(&mut cx);
+            compiler.compile_dyn(&mut cx, &mut outputs);
+            cx.execute();
+            outputs.iter().map(|o| o.data()).collect::<Vec<_>>()
+        }));
+        let Ok(results) = result else {
+            return Outcome::Failed("Panicked while compiling or executing".to_string());
+        };
+        for (output, (a, b)) in expected.iter().zip(&results).enumerate() {
+            if a.len() != b.len() {
+                return Outcome::Failed(format!(
+                    "Output {output} has {} elements, expected {}",
+                    b.len(),
+                    a.len()
+                ));
+            }
+            if let Some(i) = (0..a.len()).find(|i| !close(a[*i], b[*i])) {
+                return Outcome::Failed(format!(
+                    "Output {output} is {:?}, expected {:?} (index {i})",
+                    b, a
+                ));
+            }
+        }
+        Outcome::Passed
+    }
+
+    /// Drop the nodes the outputs don't depend on
+    fn remove_unused(mut self) -> Self {
+        let mut used = vec![false; self.nodes.len()];
+        let mut stack = self.outputs.clone();
+        while let Some(n) = stack.pop() {
+            if !used[n] {
+                used[n] = true;
+                stack.extend(self.nodes[n].inputs());
+            }
+        }
+        let mut remap = vec![0; self.nodes.len()];
+        let mut nodes = vec![];
+        for (i, node) in self.nodes.into_iter().enumerate() {
+            if used[i] {
+                remap[i] = nodes.len();
+                nodes.push(node);
+            }
+        }
+        for node in &mut nodes {
+            for x in node.inputs_mut() {
+                *x = remap[*x];
+            }
+        }
+        self.nodes = nodes;
+        for o in &mut self.outputs {
+            *o = remap[*o];
+        }
+        self
+    }
+
+    /// Smaller versions of this graph: with an output dropped, a node replaced by an input, or a node skipped over
+    fn shrink_candidates(&self) -> Vec<FuzzGraph> {
+        let mut candidates = vec![];
+        if self.outputs.len() > 1 {
+            for i in 0..self.outputs.len() {
+                let mut c = self.clone();
+                c.outputs.remove(i);
+                candidates.push(c);
+            }
+        }
+        let Some(trackers) = self.trackers() else {
+            return candidates;
+        };
+        for i in (0..self.nodes.len()).rev() {
+            if matches!(self.nodes[i], FuzzNode::Input(..) | FuzzNode::Constant(..)) {
+                continue;
+            }
+            for x in self.nodes[i].inputs() {
+                let mut c = self.clone();
+                for node in &mut c.nodes[i + 1..] {
+                    for input in node.inputs_mut() {
+                        if *input == i {
+                            *input = x;
+                        }
+                    }
+                }
+                for o in &mut c.outputs {
+                    if *o == i {
+                        *o = x;
+                    }
+                }
+                candidates.push(c);
+            }
+            let mut c = self.clone();
+            c.nodes[i] = FuzzNode::Input(logical_dims(&trackers[i]), i as u64);
+            candidates.push(c);
+        }
+        candidates
+            .into_iter()
+            .map(FuzzGraph::remove_unused)
+            .filter(|c| c.trackers().is_some())
+            .collect()
+    }
+
+    /// Shrink the graph while it keeps failing
+    pub fn shrink(mut self, fails: impl Fn(&FuzzGraph) -> bool) -> FuzzGraph {
+        while let Some(smaller) = self.shrink_candidates().into_iter().find(|c| fails(c)) {
+            self = smaller;
+        }
+        self
+    }
+}
+
+impl Display for FuzzGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (dim, size) in &self.dyn_dims {
+            writeln!(f, "{dim} = {size}")?;
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            writeln!(f, "{i}: {node:?}")?;
+        }
+        write!(f, "outputs: {:?}", self.outputs)
+    }
+}
+
+/// Whether an output matches the expected value, relative to its size
+fn close(expected: f32, b: f32) -> bool {
+    (expected - b).abs() <= 1e-3 * expected.abs().max(b.abs()).max(1.)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    /// The graph can't be built or run without compiling, or produces NaNs or infinities
+    Invalid,
+}
+
+/// Check random graphs against a compiler, returning the smallest failing graph found
+pub fn find_failure(
+    compiler: &dyn DynCompiler,
+    cases: usize,
+    seed: u64,
+) -> Option<(FuzzGraph, String)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let failing = (0..cases)
+        .map(|_| FuzzGraph::random(&mut rng))
+        .find(|g| matches!(g.check(compiler), Outcome::Failed(_)))?;
+    let graph = failing.shrink(|g| matches!(g.check(compiler), Outcome::Failed(_)));
+    let Outcome::Failed(message) = graph.check(compiler) else {
+        unreachable!()
+    };
+    Some((graph, message))
+}
+
+/// Check random graphs against a compiler, panicking with the smallest failing graph found
+pub fn fuzz_compiler(compiler: &dyn DynCompiler, cases: usize, seed: u64) {
+    if let Some((graph, message)) = find_failure(compiler, cases, seed) {
+        panic!("{message}\n{graph}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::visit::EdgeRef;
+
+    use super::*;
+
+    #[test]
+    fn test_fuzz_generic() {
+        fuzz_compiler(&GenericCompiler::default(), 300, 0);
+    }
+
+    #[test]
+    fn test_fuzz_cpu() {
+        fuzz_compiler(&<(GenericCompiler, CPUCompiler)>::default(), 300, 1);
+    }
+
+    #[test]
+    fn test_random_graphs_are_valid() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let graphs = (0..100)
+            .map(|_| FuzzGraph::random(&mut rng))
+            .collect::<Vec<_>>();
+        assert!(graphs.iter().all(|g| g.trackers().is_some()));
+        // Only a few should leave the domain of their ops
+        let valid = graphs
+            .iter()
+            .filter(|g| g.check(&()) == Outcome::Passed)
+            .count();
+        assert!(valid > 80, "Only {valid} graphs could be checked");
+    }
+
+    /// Removes contiguous ops without carrying their view over to the consumers
+    #[derive(Debug, Default)]
+    struct BrokenContiguousElimination;
+
+    impl Compiler for BrokenContiguousElimination {
+        fn compile<T: ToIdsMut>(&self, graph: &mut Graph, mut remap: T) {
+            for node in graph.graph.node_indices().collect::<Vec<_>>() {
+                if !graph.graph[node].as_any().is::<op::Contiguous>() {
+                    continue;
+                }
+                let src = graph
+                    .graph
+                    .edges_directed(node, petgraph::Direction::Incoming)
+                    .next()
+                    .unwrap()
+                    .source();
+                move_outgoing_edge(node, src, &mut graph.graph);
+                move_references(
+                    &mut remap,
+                    &mut graph.no_delete,
+                    &mut graph.to_retrieve,
+                    node,
+                    src,
+                );
+                graph.graph.remove_node(node);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shrinks_failures() {
+        let (graph, _) = find_failure(&BrokenContiguousElimination, 300, 3).unwrap();
+        // All that's needed is an input, a view of it, and the contiguous op
+        assert!(graph.nodes.len() <= 3, "{graph}");
+        assert!(graph
+            .nodes
+            .iter()
+            .any(|n| matches!(n, FuzzNode::Contiguous(_))));
+    }
+}
@@ -1,56 +1,62 @@
-use petgraph::stable_graph::NodeIndex;
-
 use crate::prelude::*;
 
-use super::{assert_close, assert_exact};
+use super::{assert_close, assert_exact, test_graphs};
+
+/// Builds a graph in the one it's given, returning the tensors to compare
+pub type TestGraph = fn(&mut Graph) -> Vec<GraphTensor<()>>;
+
+pub fn test_compilers_exact(graphs: &[TestGraph], compilers: &[Box<dyn DynCompiler>]) {
+    test_compilers(graphs, compilers, assert_exact)
+}
 
-// #[allow(clippy::type_complexity)]
-// pub fn test_compilers_exact(
-//     graphs: &[fn() -> (Graph, Vec<GraphTensor<()>>)],
-//     compilers: &[Box<dyn Compiler>],
-// ) {
-//     test_compilers(graphs, compilers, assert_exact)
-// }
+pub fn test_compilers_close(graphs: &[TestGraph], compilers: &[Box<dyn DynCompiler>]) {
+    test_compilers(graphs, compilers, assert_close)
+}
 
-// #[allow(clippy::type_complexity)]
-// pub fn test_compilers_close(
-//     graphs: &[fn() -> (Graph, Vec<GraphTensor<()>>)],
-//     compilers: &[Box<dyn Compiler>],
-// ) {
-//     test_compilers(graphs, compilers, assert_close)
-// }
+/// Check that each compiler gives the same results as the uncompiled graphs
+fn test_compilers<F: Fn(&[f32], &[f32])>(
+    graphs: &[TestGraph],
+    compilers: &[Box<dyn DynCompiler>],
+    condition: F,
+) {
+    for build in graphs {
+        let mut cx = Graph::new();
+        let unopt_results = build(&mut cx);
+        cx.execute();
+        let unopt_results = unopt_results.iter().map(|t| t.data()).collect::<Vec<_>>();
 
-// #[allow(clippy::type_complexity)]
-// fn test_compilers<F: Fn(&[f32], &[f32])>(
-//     graphs: &[fn() -> (Graph, Vec<GraphTensor<()>>)],
-//     compilers: &[Box<dyn Compiler>],
-//     condition: F,
-// ) {
-//     for create_graph in graphs {
-//         let (mut cx, result_tensors) = create_graph();
-//         cx.execute();
-//         let unopt_results = result_tensors
-//             .into_iter()
-//             .map(|mut gt| {
-//                 gt.graph_ref = &mut cx;
-//                 gt.data()
-//             })
-//             .collect::<Vec<_>>();
+        for compiler in compilers {
+            let mut cx = Graph::new();
+            let mut results = build(&mut cx);
+            cx.compile(compiler, &mut results);
+            cx.execute();
+            for (a, b) in unopt_results.iter().zip(&results) {
+                condition(&b.data(), a);
+            }
+        }
+    }
+}
 
-//         for compiler in compilers {
-//             let (mut cx, result_tensors) = create_graph();
-//             compiler.compile(&mut cx);
-//             cx.execute();
-//             let opt_results = result_tensors
-//                 .into_iter()
-//                 .map(|mut gt| {
-//                     gt.graph_ref = &mut cx;
-//                     gt.data()
-//                 })
-//                 .collect::<Vec<_>>();
-//             for (a, b) in unopt_results.iter().zip(opt_results.iter()) {
-//                 condition(a, b);
-//             }
-//         }
-//     }
-// }
+#[test]
+fn test_all_compilers() {
+    let registry = CompilerRegistry::default();
+    test_compilers_close(
+        &[
+            test_graphs::matmul,
+            test_graphs::batch_matmul,
+            test_graphs::feedforward,
+            test_graphs::transformer,
+        ],
+        &[
+            Box::new(GenericCompiler::default()),
+            Box::new(<(GenericCompiler, CPUCompiler)>::default()),
+            Box::new(
+                CompilerPipeline::from_spec(
+                    "loop(ArithmeticElimination, CSE), ConstantFolding, CPUCompiler",
+                    &registry,
+                )
+                .unwrap(),
+            ),
+        ],
+    );
+}
@@ -3,6 +3,8 @@
 #[cfg(test)]
 mod dynamic;
 #[cfg(test)]
+pub mod fuzz;
+#[cfg(test)]
 pub mod harness;
 pub mod test_graphs;
 
@@ -140,14 +142,162 @@ macro_rules! test_imports {
                 Axis as LAxis, Const as LConst, *,
             },
             tests::{
-                assert_close,
-                assert_close_precision,
-                assert_exact,
-                // harness::{test_compilers_close, test_compilers_exact},
-                random_vec,
-                random_vec_rng,
-                test_graphs,
+                assert_close, assert_close_precision, assert_exact,
+                harness::{test_compilers_close, test_compilers_exact},
+                random_vec, random_vec_rng, test_graphs,
             },
         };
     };
 }
+
+#[test]
+fn test_execute_parallel() {
+    let mut cx = Graph::new();
+    let model: crate::nn::transformer::attention::MultiHeadSelfAttention<4, 4, 4, 2> =
+        InitModule::initialize(&mut cx);
+    let a = cx
+        .tensor::<(Dyn<'s'>, Const<4>)>()
+        .set_dyn(random_vec(12), &[3, 4]);
+    let mut b = model.forward(a).retrieve();
+    // Keep a tensor used by multiple branches around
+    let mut c = (a.exp2() + a.sin()).retrieve().keep();
+
+    cx.execute();
+    let (seq_b, seq_c) = (b.data(), c.data());
+    b.drop();
+    c.drop();
+    cx.execute_parallel_threads(4);
+    assert_exact(&b.data(), &seq_b);
+    assert_exact(&c.data(), &seq_c);
+
+    cx.compile(GenericCompiler::default(), (&mut b, &mut c));
+    cx.execute();
+    let (seq_b, seq_c) = (b.data(), c.data());
+    // Run several times, since scheduling differs from run to run
+    for _ in 0..10 {
+        b.drop();
+        c.drop();
+        cx.execute_parallel();
+        assert_exact(&b.data(), &seq_b);
+        assert_exact(&c.data(), &seq_c);
+        // Only kept tensors should be left around
+        assert!(cx.tensors.keys().all(|(n, _)| cx.no_delete.contains(n)));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Failing;
+
+/// Data that isn't stored on the CPU
+#[derive(Debug, Clone)]
+struct NotCpu;
+
+impl crate::tensor::Data for NotCpu {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl crate::op::Operator for Failing {
+    fn process(&mut self, inp: Vec<(crate::op::InputTensor, ShapeTracker)>) -> Vec<Tensor> {
+        self.try_process(inp).unwrap()
+    }
+
+    fn try_process(
+        &mut self,
+        inp: Vec<(crate::op::InputTensor, ShapeTracker)>,
+    ) -> Result<Vec<Tensor>, crate::op::OpError> {
+        let data = crate::op::try_get_vec_from_tensor(&inp[0].0, 0)?;
+        if data.iter().any(|f| f.is_nan()) {
+            return Err(crate::op::OpError::Other("Input contains nan".to_string()));
+        }
+        Ok(vec![inp.into_iter().next().unwrap().0.cloned()])
+    }
+}
+
+#[test]
+fn test_try_execute() {
+    let mut cx = Graph::new();
+    let a = cx.tensor::<(Dyn<'s'>, Const<2>)>();
+    let b = cx.named_tensor::<R1<2>>("B");
+    let c = (a + b.expand()).retrieve();
+    let failing = cx.add_op(Failing).input(c.id, 0, c.shape).finish();
+    let d = GraphTensor::<(Dyn<'s'>, Const<2>)>::from_id(failing, c.shape, &mut cx).retrieve();
+
+    assert!(matches!(
+        cx.try_execute(),
+        Err(ExecutionError::UnsetInput { node, .. }) if node == a.id || node == b.id
+    ));
+    a.set_dyn(vec![1., 2., 3., 4.], &[2, 2]);
+    b.set(vec![1., 2.]);
+    assert!(cx.try_execute().is_ok());
+    assert_exact(&d.data(), &[2., 4., 4., 6.]);
+
+    // Ops reporting errors name themselves and their input shapes
+    b.set(vec![f32::NAN, 0.]);
+    c.drop();
+    d.drop();
+    let err = cx.try_execute().unwrap_err();
+    assert_eq!(
+        err,
+        ExecutionError::Op {
+            node: failing,
+            op: "Failing".to_string(),
+            shapes: vec![vec![2, 2]],
+            error: crate::op::OpError::Other("Input contains nan".to_string()),
+        }
+    );
+    assert!(err.to_string().contains("Input contains nan"));
+    // Only kept tensors are left around after a failed run
+    assert!(cx.tensors.keys().all(|(n, _)| cx.no_delete.contains(n)));
+
+    cx.dyn_map.clear();
+    c.drop();
+    assert!(matches!(
+        cx.try_execute(),
+        Err(ExecutionError::UnboundDim { dim, .. }) if dim == 's'
+    ));
+}
+
+#[test]
+fn test_try_execute_non_cpu_input() {
+    let mut cx = Graph::new();
+    let a = cx.tensor::<R1<2>>().set(vec![1., 2.]);
+    let b = cx.named_tensor::<R1<2>>("B");
+    cx.graph
+        .node_weight_mut(b.id)
+        .unwrap()
+        .as_any_mut()
+        .downcast_mut::<crate::op::Function>()
+        .unwrap()
+        .1 = Box::new(|_| Ok(vec![Tensor::new(NotCpu)]));
+    let c = (a + b).retrieve();
+
+    assert!(matches!(
+        cx.try_execute(),
+        Err(ExecutionError::Op {
+            node,
+            error: crate::op::OpError::InputType { input: 1, .. },
+            ..
+        }) if node == c.id
+    ));
+}
+
+#[test]
+fn test_try_execute_missing_weights() {
+    let mut cx = Graph::new();
+    let model = <crate::nn::linear::Linear<2, 2>>::initialize(&mut cx);
+    let a = cx.tensor::<R1<2>>().set(vec![1., 2.]);
+    model.forward(a).retrieve();
+    SafeTensorLoader::new(&["not_a_real_file.safetensors"]).load(&model, &mut cx);
+
+    let err = cx.try_execute().unwrap_err();
+    assert!(matches!(
+        &err,
+        ExecutionError::Load { node, error: crate::op::OpError::Io(message), .. }
+            if *node == model.weight.id && message.starts_with("Couldn't open not_a_real_file.safetensors")
+    ));
+}
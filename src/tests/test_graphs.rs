@@ -7,47 +7,43 @@ use crate::{
 
 use super::random_vec_rng;
 
-pub fn matmul() -> (Graph, Vec<GraphTensor<()>>) {
+pub fn matmul(cx: &mut Graph) -> Vec<GraphTensor<()>> {
     let mut rng = StdRng::seed_from_u64(0);
-    let mut cx = Graph::new();
     let a = cx
         .tensor::<(Dyn<'a'>, Const<3>)>()
         .set_dyn(random_vec_rng(2 * 3, &mut rng), &[2, 3]);
     let b = cx.tensor::<R2<3, 3>>().set(random_vec_rng(3 * 3, &mut rng));
     let c = a.matmul(b).retrieve();
-    (cx, vec![c.no_shape()])
+    vec![c.no_shape()]
 }
 
-pub fn batch_matmul() -> (Graph, Vec<GraphTensor<()>>) {
+pub fn batch_matmul(cx: &mut Graph) -> Vec<GraphTensor<()>> {
     let mut rng = StdRng::seed_from_u64(0);
-    let mut cx = Graph::new();
     let a = cx
         .tensor::<(Dyn<'a'>, Dyn<'b'>, Const<2>)>()
         .set_dyn(random_vec_rng(2 * 3 * 2, &mut rng), &[2, 3, 2]);
     let b = cx.tensor::<R2<2, 4>>().set(random_vec_rng(2 * 4, &mut rng));
     let c = a.matmul(b).retrieve();
-    (cx, vec![c.no_shape()])
+    vec![c.no_shape()]
 }
 
-pub fn feedforward() -> (Graph, Vec<GraphTensor<()>>) {
+pub fn feedforward(cx: &mut Graph) -> Vec<GraphTensor<()>> {
     let mut rng = StdRng::seed_from_u64(0);
     // Test single and batch, unoptimized and optimized
-    let mut cx = Graph::new();
     let batch = cx
         .tensor::<(Dyn<'a'>, Const<3>)>()
         .set_dyn(random_vec_rng(2 * 3, &mut rng), &[2, 3]);
-    let model: (Linear<3, 4>, ReLU, Linear<4, 2>) = InitModule::initialize(&mut cx);
+    let model: (Linear<3, 4>, ReLU, Linear<4, 2>) = InitModule::initialize(cx);
     model.0.weight.set(random_vec_rng(3 * 4, &mut rng));
     model.2.weight.set(random_vec_rng(4 * 2, &mut rng));
     let batch_out = model.forward(batch).retrieve();
 
-    (cx, vec![batch_out.no_shape()])
+    vec![batch_out.no_shape()]
 }
 
-pub fn transformer() -> (Graph, Vec<GraphTensor<()>>) {
+pub fn transformer(cx: &mut Graph) -> Vec<GraphTensor<()>> {
     let mut rng = StdRng::seed_from_u64(0);
-    let mut cx = Graph::new();
-    let model: Transformer<3, 4, 1, 1, 1, 1> = InitModule::initialize(&mut cx);
+    let model: Transformer<3, 4, 1, 1, 1, 1> = InitModule::initialize(cx);
     model.decoder.layers[0]
         .self_attention
         .w_k
@@ -137,5 +133,5 @@ pub fn transformer() -> (Graph, Vec<GraphTensor<()>>) {
     e.set_dyn(random_vec_rng(3 * 3, &mut rng), &[3, 3]);
     b.retrieve();
 
-    (cx, vec![b.no_shape()])
+    vec![b.no_shape()]
 }